@@ -1,6 +1,10 @@
 use crate::db::models::ClientHistoryEvent;
 use serde::{Deserialize, Serialize};
 use rusqlite::params;
+use crate::db::DbPool;
+use crate::db::money::Money;
+use tauri::State;
+use crate::error::AppError;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ClientFrontend {
@@ -11,18 +15,17 @@ pub struct ClientFrontend {
     pub phone: Option<String>,
     pub address: Option<String>,
     pub notes: Option<String>,
-    pub outstanding_balance: f64,
+    pub outstanding_balance: Money,
     pub status: String,
     pub created_at: String,
     pub updated_at: String,
 }
 
 #[tauri::command]
-pub fn get_clients() -> Result<Vec<ClientFrontend>, String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+pub fn get_clients(pool: State<'_, DbPool>) -> Result<Vec<ClientFrontend>, AppError> {
+    let conn = pool.get()?;
     let mut stmt = conn
-        .prepare("SELECT id, name, contact_name, email, phone, address, notes, credit_balance, active, created_at, updated_at FROM clients")
-        .map_err(|e| e.to_string())?;
+        .prepare_cached("SELECT id, name, contact_name, email, phone, address, notes, credit_balance, active, created_at, updated_at FROM clients")?;
     
     let clients = stmt
         .query_map([], |row| {
@@ -35,13 +38,12 @@ pub fn get_clients() -> Result<Vec<ClientFrontend>, String> {
                 phone: row.get(4).ok(),
                 address: row.get(5).ok(),
                 notes: row.get(6).ok(),
-                outstanding_balance: row.get(7).unwrap_or(0.0),
+                outstanding_balance: row.get(7).unwrap_or(Money::ZERO),
                 status: if active == 1 { "active".to_string() } else { "inactive".to_string() },
                 created_at: row.get(9)?,
                 updated_at: row.get(10)?,
             })
-        })
-        .map_err(|e| e.to_string())?
+        })?
         .filter_map(|res| res.ok())
         .collect();
     
@@ -49,11 +51,10 @@ pub fn get_clients() -> Result<Vec<ClientFrontend>, String> {
 }
 
 #[tauri::command]
-pub fn get_client_by_id(client_id: String) -> Result<Option<ClientFrontend>, String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+pub fn get_client_by_id(pool: State<'_, DbPool>, client_id: String) -> Result<Option<ClientFrontend>, AppError> {
+    let conn = pool.get()?;
     let mut stmt = conn
-        .prepare("SELECT id, name, contact_name, email, phone, address, notes, credit_balance, active, created_at, updated_at FROM clients WHERE id = ?1")
-        .map_err(|e| e.to_string())?;
+        .prepare_cached("SELECT id, name, contact_name, email, phone, address, notes, credit_balance, active, created_at, updated_at FROM clients WHERE id = ?1")?;
     
     let client = stmt.query_row(params![client_id], |row| {
         let active: i32 = row.get(8)?;
@@ -65,7 +66,7 @@ pub fn get_client_by_id(client_id: String) -> Result<Option<ClientFrontend>, Str
             phone: row.get(4).ok(),
             address: row.get(5).ok(),
             notes: row.get(6).ok(),
-            outstanding_balance: row.get(7).unwrap_or(0.0),
+            outstanding_balance: row.get(7).unwrap_or(Money::ZERO),
             status: if active == 1 { "active".to_string() } else { "inactive".to_string() },
             created_at: row.get(9)?,
             updated_at: row.get(10)?,
@@ -76,8 +77,8 @@ pub fn get_client_by_id(client_id: String) -> Result<Option<ClientFrontend>, Str
 }
 
 #[tauri::command]
-pub fn insert_client(client: ClientFrontend) -> Result<(), String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+pub fn insert_client(pool: State<'_, DbPool>, client: ClientFrontend) -> Result<(), AppError> {
+    let conn = pool.get()?;
     conn.execute(
         "INSERT INTO clients (id, name, contact_name, email, phone, address, notes, credit_balance, active, created_at, updated_at) 
          VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
@@ -94,13 +95,13 @@ pub fn insert_client(client: ClientFrontend) -> Result<(), String> {
             client.created_at,
             client.updated_at
         ],
-    ).map_err(|e| e.to_string())?;
+    )?;
     Ok(())
 }
 
 #[tauri::command]
-pub fn update_client(client: ClientFrontend) -> Result<(), String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+pub fn update_client(pool: State<'_, DbPool>, client: ClientFrontend) -> Result<(), AppError> {
+    let conn = pool.get()?;
     conn.execute(
         "UPDATE clients SET name = ?2, contact_name = ?3, email = ?4, phone = ?5, address = ?6, notes = ?7, active = ?8, updated_at = ?9 WHERE id = ?1",
         params![
@@ -114,33 +115,32 @@ pub fn update_client(client: ClientFrontend) -> Result<(), String> {
             if client.status == "active" { 1 } else { 0 },
             chrono::Utc::now().to_rfc3339()
         ],
-    ).map_err(|e| e.to_string())?;
+    )?;
     Ok(())
 }
 
 #[tauri::command]
-pub fn delete_client(client_id: String) -> Result<(), String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
-    conn.execute("DELETE FROM clients WHERE id = ?1", params![client_id])
-        .map_err(|e| e.to_string())?;
+pub fn delete_client(pool: State<'_, DbPool>, client_id: String) -> Result<(), AppError> {
+    let conn = pool.get()?;
+    conn.execute("DELETE FROM clients WHERE id = ?1", params![client_id])?;
     Ok(())
 }
 
 #[tauri::command]
 pub fn add_client_payment(
+    pool: State<'_, DbPool>,
     id: String,
     client_id: String,
-    amount: f64,
+    amount: Money,
     method: String,
     notes: Option<String>,
     session_id: Option<String>,
-) -> Result<(), String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+) -> Result<(), AppError> {
+    let conn = pool.get()?;
     conn.execute(
         "INSERT INTO client_payments (id, client_id, amount, method, date, notes, session_id) VALUES (?1, ?2, ?3, ?4, datetime('now'), ?5, ?6)",
         params![id, client_id, amount, method, notes, session_id],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
 
     // Update balance
     conn.execute(
@@ -159,21 +159,21 @@ pub fn add_client_payment(
 }
 
 #[tauri::command]
-pub fn update_client_payment(id: String, amount: f64, method: String) -> Result<(), String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+pub fn update_client_payment(pool: State<'_, DbPool>, id: String, amount: Money, method: String) -> Result<(), AppError> {
+    let conn = pool.get()?;
 
     // Get old info
-    let (client_id, old_amount): (String, f64) = conn.query_row(
+    let (client_id, old_amount): (String, Money) = conn.query_row(
         "SELECT client_id, amount FROM client_payments WHERE id = ?1",
         params![id],
         |row| Ok((row.get(0)?, row.get(1)?))
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     // Update record
     conn.execute(
         "UPDATE client_payments SET amount = ?1, method = ?2 WHERE id = ?3",
         params![amount, method, id],
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     // Adjust balance: Refund old, apply new
     let balance_adj = old_amount - amount;
@@ -193,18 +193,18 @@ pub fn update_client_payment(id: String, amount: f64, method: String) -> Result<
 }
 
 #[tauri::command]
-pub fn delete_client_payment(id: String) -> Result<(), String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+pub fn delete_client_payment(pool: State<'_, DbPool>, id: String) -> Result<(), AppError> {
+    let conn = pool.get()?;
 
     // Get info
-    let (client_id, amount): (String, f64) = conn.query_row(
+    let (client_id, amount): (String, Money) = conn.query_row(
         "SELECT client_id, amount FROM client_payments WHERE id = ?1",
         params![id],
         |row| Ok((row.get(0)?, row.get(1)?))
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     // Delete record
-    conn.execute("DELETE FROM client_payments WHERE id = ?1", params![id]).map_err(|e| e.to_string())?;
+    conn.execute("DELETE FROM client_payments WHERE id = ?1", params![id])?;
 
     // Reverse balance: Refund everything
     conn.execute(
@@ -224,19 +224,19 @@ pub fn delete_client_payment(id: String) -> Result<(), String> {
 
 #[tauri::command]
 pub fn adjust_client_balance(
+    pool: State<'_, DbPool>,
     client_id: String,
-    amount: f64,
+    amount: Money,
     notes: Option<String>,
-) -> Result<(), String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+) -> Result<(), AppError> {
+    let conn = pool.get()?;
 
     conn.execute(
         "UPDATE clients SET credit_balance = COALESCE(credit_balance, 0) + ?1 WHERE id = ?2",
         params![amount, client_id],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
 
-    if amount.abs() > 0.001 {
+    if !amount.is_zero() {
         let history_id = uuid::Uuid::new_v4().to_string();
         conn.execute(
             "INSERT INTO client_history (id, client_id, date, type, notes, amount)
@@ -249,18 +249,17 @@ pub fn adjust_client_balance(
                 notes.unwrap_or_else(|| "Manual entry".to_string()),
                 amount,
             ],
-        ).map_err(|e| e.to_string())?;
+        )?;
     }
 
     Ok(())
 }
 
 #[tauri::command]
-pub fn get_client_history(client_id: String) -> Result<Vec<ClientHistoryEvent>, String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+pub fn get_client_history(pool: State<'_, DbPool>, client_id: String) -> Result<Vec<ClientHistoryEvent>, AppError> {
+    let conn = pool.get()?;
     let mut stmt = conn
-        .prepare("SELECT id, client_id, date, type, notes, amount FROM client_history WHERE client_id = ?1 ORDER BY date DESC")
-        .map_err(|e| e.to_string())?;
+        .prepare_cached("SELECT id, client_id, date, type, notes, amount FROM client_history WHERE client_id = ?1 ORDER BY date DESC")?;
     
     let history = stmt
         .query_map(params![client_id], |row| {
@@ -270,11 +269,10 @@ pub fn get_client_history(client_id: String) -> Result<Vec<ClientHistoryEvent>,
                 date: row.get(2)?,
                 event_type: row.get(3)?,
                 notes: row.get(4).ok(),
-                amount: row.get::<_, f64>(5).unwrap_or(0.0),
+                amount: row.get::<_, Money>(5).unwrap_or(Money::ZERO),
                 changed_by: None,
             })
-        })
-        .map_err(|e| e.to_string())?
+        })?
         .filter_map(|res| res.ok())
         .collect();
     
@@ -282,8 +280,8 @@ pub fn get_client_history(client_id: String) -> Result<Vec<ClientHistoryEvent>,
 }
 
 #[tauri::command]
-pub fn insert_client_history(event: ClientHistoryEvent) -> Result<(), String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+pub fn insert_client_history(pool: State<'_, DbPool>, event: ClientHistoryEvent) -> Result<(), AppError> {
+    let conn = pool.get()?;
     conn.execute(
         "INSERT INTO client_history (id, client_id, date, type, notes, amount) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
         params![
@@ -294,6 +292,6 @@ pub fn insert_client_history(event: ClientHistoryEvent) -> Result<(), String> {
             event.notes,
             event.amount,
         ],
-    ).map_err(|e| e.to_string())?;
+    )?;
     Ok(())
 }
@@ -0,0 +1,284 @@
+use crate::db::models::CostLayer;
+use crate::db::money::Money;
+use crate::db::product;
+use crate::db::DbPool;
+use crate::error::AppError;
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use tauri::State;
+use uuid::Uuid;
+
+pub(crate) const WEIGHTED_AVERAGE: &str = "WeightedAverage";
+pub(crate) const FIFO: &str = "FIFO";
+
+/// How sold stock is costed: at the item's moving average cost (the default) or at the
+/// cost of the oldest layers it is taken from.
+pub(crate) fn costing_method(conn: &Connection) -> Result<String, AppError> {
+    let method: Option<String> = conn
+        .query_row(
+            "SELECT value FROM app_settings WHERE key = 'costing_method'",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(method.unwrap_or_else(|| WEIGHTED_AVERAGE.to_string()))
+}
+
+/// `amount` spread over `quantity` units, to the nearest minor unit.
+pub(crate) fn per_unit(amount: Money, quantity: i64) -> Money {
+    if quantity == 0 {
+        return amount;
+    }
+    Money::from_minor((amount.minor() as f64 / quantity as f64).round() as i64)
+}
+
+/// What one unit of an item costs on average; for a bundle, what its components cost.
+pub(crate) fn average_cost(conn: &Connection, item_id: &str) -> Result<Money, AppError> {
+    let components = product::bundle_components(conn, item_id)?;
+    if !components.is_empty() {
+        let mut cost = Money::ZERO;
+        for (component_id, per_bundle) in components {
+            cost += average_cost(conn, &component_id)? * per_bundle;
+        }
+        return Ok(cost);
+    }
+    let cost: Option<Money> = conn
+        .query_row(
+            "SELECT COALESCE(average_cost, buying_price, 0) FROM inventory_items WHERE id = ?1",
+            params![item_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(cost.unwrap_or(Money::ZERO))
+}
+
+fn on_hand(conn: &Connection, item_id: &str) -> Result<i64, AppError> {
+    let qty: Option<i64> = conn
+        .query_row(
+            "SELECT COALESCE(quantity_in_stock, 0) FROM inventory_items WHERE id = ?1",
+            params![item_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(qty.unwrap_or(0))
+}
+
+fn set_average_cost(conn: &Connection, item_id: &str, cost: Money) -> Result<(), AppError> {
+    conn.execute(
+        "UPDATE inventory_items SET average_cost = ?2 WHERE id = ?1",
+        params![item_id, cost],
+    )?;
+    Ok(())
+}
+
+/// Use up to `qty` units of the open layers: those received from `preferred_source`
+/// first, then the oldest. Returns how many units were found and what they cost.
+fn consume_layers(
+    conn: &Connection,
+    item_id: &str,
+    qty: i64,
+    preferred_source: Option<&str>,
+) -> Result<(i64, Money), AppError> {
+    let layers: Vec<(String, i64, Money)> = {
+        let mut stmt = conn.prepare_cached(
+            "SELECT id, remaining, unit_cost FROM cost_layers
+             WHERE item_id = ?1 AND remaining > 0
+             ORDER BY CASE WHEN ?2 IS NOT NULL AND source_id = ?2 THEN 0 ELSE 1 END, created_at, rowid",
+        )?;
+        let rows = stmt.query_map(params![item_id, preferred_source], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?;
+        rows.collect::<Result<_, _>>()?
+    };
+
+    let mut units = 0;
+    let mut cost = Money::ZERO;
+    for (layer_id, remaining, unit_cost) in layers {
+        if units == qty {
+            break;
+        }
+        let take = remaining.min(qty - units);
+        conn.execute(
+            "UPDATE cost_layers SET remaining = remaining - ?2 WHERE id = ?1",
+            params![layer_id, take],
+        )?;
+        units += take;
+        cost += unit_cost * take;
+    }
+    Ok((units, cost))
+}
+
+/// Record `qty` units arriving at `unit_cost` as a new layer and move the item's average
+/// cost. Call after the stock has been added. A bundle's cost is shared among its
+/// components in proportion to what they cost on average.
+pub(crate) fn receive_stock(
+    conn: &Connection,
+    item_id: &str,
+    qty: i64,
+    unit_cost: Money,
+    source_type: &str,
+    source_id: &str,
+) -> Result<(), AppError> {
+    if qty <= 0 {
+        return Ok(());
+    }
+    let components = product::bundle_components(conn, item_id)?;
+    if !components.is_empty() {
+        let mut costs = Vec::new();
+        for (component_id, per_bundle) in &components {
+            costs.push(average_cost(conn, component_id)? * *per_bundle);
+        }
+        let total: Money = costs.iter().copied().sum();
+        for ((component_id, per_bundle), cost) in components.iter().zip(costs) {
+            let share = if total == Money::ZERO {
+                per_unit(unit_cost, components.len() as i64)
+            } else {
+                Money::from_minor((unit_cost.minor() as f64 * cost.minor() as f64 / total.minor() as f64).round() as i64)
+            };
+            receive_stock(conn, component_id, qty * per_bundle, per_unit(share, *per_bundle), source_type, source_id)?;
+        }
+        return Ok(());
+    }
+    conn.execute(
+        "INSERT INTO cost_layers (id, item_id, source_type, source_id, quantity, remaining, unit_cost, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?5, ?6, ?7)",
+        params![
+            Uuid::new_v4().to_string(),
+            item_id,
+            source_type,
+            source_id,
+            qty,
+            unit_cost,
+            Utc::now().to_rfc3339(),
+        ],
+    )?;
+
+    // Stock oversold below zero has no value to average with
+    let before = (on_hand(conn, item_id)? - qty).max(0);
+    let value = average_cost(conn, item_id)?.minor() as f64 * before as f64 + unit_cost.minor() as f64 * qty as f64;
+    set_average_cost(conn, item_id, Money::from_minor((value / (before + qty) as f64).round() as i64))
+}
+
+/// Use up `qty` units, oldest layers first, and return the cost of one unit by the
+/// costing method. Units beyond the layers (oversold stock) are costed at the average.
+pub(crate) fn issue_stock(conn: &Connection, item_id: &str, qty: i64) -> Result<Money, AppError> {
+    let average = average_cost(conn, item_id)?;
+    if qty <= 0 {
+        return Ok(average);
+    }
+    let components = product::bundle_components(conn, item_id)?;
+    if !components.is_empty() {
+        let mut cost = Money::ZERO;
+        for (component_id, per_bundle) in components {
+            cost += issue_stock(conn, &component_id, qty * per_bundle)? * per_bundle;
+        }
+        return Ok(cost);
+    }
+    let (units, cost) = consume_layers(conn, item_id, qty, None)?;
+    if costing_method(conn)? == FIFO {
+        Ok(per_unit(cost + average * (qty - units), qty))
+    } else {
+        Ok(average)
+    }
+}
+
+/// Take back `qty` units received from `source_id`, e.g. a cancelled or returned
+/// purchase, using up its own layers first. Call after the stock has been removed.
+pub(crate) fn reverse_receipt(conn: &Connection, item_id: &str, qty: i64, source_id: &str) -> Result<(), AppError> {
+    if qty <= 0 {
+        return Ok(());
+    }
+    let components = product::bundle_components(conn, item_id)?;
+    if !components.is_empty() {
+        for (component_id, per_bundle) in components {
+            reverse_receipt(conn, &component_id, qty * per_bundle, source_id)?;
+        }
+        return Ok(());
+    }
+    let average = average_cost(conn, item_id)?;
+    let (units, cost) = consume_layers(conn, item_id, qty, Some(source_id))?;
+    let removed = cost + average * (qty - units);
+
+    let after = on_hand(conn, item_id)?;
+    if after > 0 {
+        let value = (average * (after + qty) - removed).max(Money::ZERO);
+        set_average_cost(conn, item_id, per_unit(value, after))?;
+    }
+    Ok(())
+}
+
+/// Keep the layers in step with stock changed by hand: extra units come in at the
+/// average cost, missing ones are used up oldest first. Call after the change.
+pub(crate) fn adjust_stock(conn: &Connection, item_id: &str, qty_change: i64) -> Result<(), AppError> {
+    let components = product::bundle_components(conn, item_id)?;
+    if !components.is_empty() {
+        for (component_id, per_bundle) in components {
+            adjust_stock(conn, &component_id, qty_change * per_bundle)?;
+        }
+        return Ok(());
+    }
+    if qty_change > 0 {
+        let average = average_cost(conn, item_id)?;
+        receive_stock(conn, item_id, qty_change, average, "Adjustment", item_id)
+    } else {
+        consume_layers(conn, item_id, -qty_change, None)?;
+        Ok(())
+    }
+}
+
+// ======================
+// COSTING SETTINGS
+// ======================
+
+#[tauri::command]
+pub fn get_costing_method(pool: State<'_, DbPool>) -> Result<String, AppError> {
+    let conn = pool.get()?;
+    costing_method(&conn)
+}
+
+/// Choose how sold stock is costed from now on. Lines already sold keep their cost.
+#[tauri::command]
+pub fn set_costing_method(pool: State<'_, DbPool>, method: String) -> Result<(), AppError> {
+    if method != WEIGHTED_AVERAGE && method != FIFO {
+        return Err(AppError::Validation(format!(
+            "Unknown costing method '{}', expected {} or {}",
+            method, WEIGHTED_AVERAGE, FIFO
+        )));
+    }
+    let conn = pool.get()?;
+    conn.execute(
+        "INSERT OR REPLACE INTO app_settings (key, value) VALUES ('costing_method', ?1)",
+        params![method],
+    )?;
+    Ok(())
+}
+
+/// Cost layers of an item, oldest first. Used-up layers are left out unless asked for.
+#[tauri::command]
+pub fn get_cost_layers(
+    pool: State<'_, DbPool>,
+    item_id: String,
+    include_used: Option<bool>,
+) -> Result<Vec<CostLayer>, AppError> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare_cached(
+        "SELECT id, item_id, source_type, source_id, quantity, remaining, unit_cost, created_at
+         FROM cost_layers WHERE item_id = ?1 AND (?2 OR remaining > 0)
+         ORDER BY created_at, rowid",
+    )?;
+    let layers = stmt
+        .query_map(params![item_id, include_used.unwrap_or(false)], |row| {
+            Ok(CostLayer {
+                id: row.get(0)?,
+                item_id: row.get(1)?,
+                source_type: row.get(2)?,
+                source_id: row.get(3)?,
+                quantity: row.get(4)?,
+                remaining: row.get(5)?,
+                unit_cost: row.get(6)?,
+                created_at: row.get(7)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(layers)
+}
@@ -0,0 +1,312 @@
+use crate::db::models::{Currency, ExchangeRate, SessionCurrencyTotal};
+use crate::db::money::{currency, Money};
+use crate::db::DbPool;
+use crate::error::AppError;
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use tauri::State;
+use uuid::Uuid;
+
+/// An amount as tendered and its value in the base currency.
+pub(crate) struct Tendered {
+    pub currency_code: String,
+    pub original_amount: Money,
+    pub exchange_rate: f64,
+    pub amount: Money,
+}
+
+/// Work out the base-currency value of a payment or expense.
+///
+/// Without a `currency_code` (or with the base one) the amount is already in the base
+/// currency. Otherwise `original_amount` is converted with the rate in effect on `date`.
+pub(crate) fn tendered_amount(
+    conn: &Connection,
+    currency_code: Option<&str>,
+    original_amount: Option<Money>,
+    amount: Money,
+    date: &str,
+) -> Result<Tendered, AppError> {
+    let base = currency().code;
+    let code = currency_code.map(|c| c.trim().to_uppercase()).unwrap_or_else(|| base.clone());
+
+    if code == base {
+        let original = original_amount.unwrap_or(amount);
+        return Ok(Tendered {
+            currency_code: code,
+            original_amount: original,
+            exchange_rate: 1.0,
+            amount: original,
+        });
+    }
+
+    let original = original_amount.ok_or_else(|| {
+        AppError::Validation(format!("The amount tendered in {} is required", code))
+    })?;
+    let rate = rate_on(conn, &code, date)?;
+    Ok(Tendered {
+        currency_code: code,
+        original_amount: original,
+        exchange_rate: rate,
+        amount: convert(original, rate),
+    })
+}
+
+/// Re-value an edited payment: for one tendered in another currency `amount` is the
+/// new tendered amount and is converted at the rate stored with the payment.
+/// Returns the base amount and the tendered amount.
+pub(crate) fn edited_amount(
+    currency_code: Option<&str>,
+    exchange_rate: Option<f64>,
+    amount: Money,
+) -> (Money, Money) {
+    match (currency_code, exchange_rate) {
+        (Some(code), Some(rate)) if code != currency().code => (convert(amount, rate), amount),
+        _ => (amount, amount),
+    }
+}
+
+/// Value of a foreign `amount` in the base currency at `rate`.
+pub(crate) fn convert(amount: Money, rate: f64) -> Money {
+    Money::from_minor((amount.minor() as f64 * rate).round() as i64)
+}
+
+/// The latest rate for `code` effective on or before `date` (RFC 3339 or YYYY-MM-DD).
+fn rate_on(conn: &Connection, code: &str, date: &str) -> Result<f64, AppError> {
+    let day = if date.len() >= 10 {
+        date[..10].to_string()
+    } else {
+        Utc::now().format("%Y-%m-%d").to_string()
+    };
+
+    let active: Option<bool> = conn
+        .query_row(
+            "SELECT active FROM currencies WHERE code = ?1",
+            params![code],
+            |row| row.get(0),
+        )
+        .optional()?;
+    match active {
+        None => return Err(AppError::Validation(format!("Unknown currency {}", code))),
+        Some(false) => return Err(AppError::Validation(format!("Currency {} is not accepted", code))),
+        Some(true) => {}
+    }
+
+    conn.query_row(
+        "SELECT rate FROM exchange_rates WHERE currency_code = ?1 AND effective_date <= ?2
+         ORDER BY effective_date DESC LIMIT 1",
+        params![code, day],
+        |row| row.get(0),
+    )
+    .optional()?
+    .ok_or_else(|| AppError::Validation(format!("No exchange rate for {} on {}", code, day)))
+}
+
+// ======================
+// CURRENCIES
+// ======================
+
+#[tauri::command]
+pub fn get_currencies(pool: State<'_, DbPool>) -> Result<Vec<Currency>, AppError> {
+    let conn = pool.get()?;
+    let base = currency().code;
+    let mut stmt = conn
+        .prepare_cached("SELECT code, name, symbol, minor_units, active FROM currencies ORDER BY code")?;
+    let currencies = stmt
+        .query_map([], |row| {
+            let code: String = row.get(0)?;
+            Ok(Currency {
+                is_base: code == base,
+                code,
+                name: row.get(1)?,
+                symbol: row.get(2)?,
+                minor_units: row.get(3)?,
+                active: row.get(4)?,
+            })
+        })?
+        .filter_map(|res| res.ok())
+        .collect();
+    Ok(currencies)
+}
+
+/// Add a currency or update its name, symbol and whether it is accepted.
+#[tauri::command]
+pub fn save_currency(pool: State<'_, DbPool>, currency: Currency) -> Result<(), AppError> {
+    let conn = pool.get()?;
+    let code = currency.code.trim().to_uppercase();
+    if code.len() != 3 || !code.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err(AppError::Validation(format!("'{}' is not a 3-letter currency code", currency.code)));
+    }
+    if code == crate::db::money::currency().code && !currency.active {
+        return Err(AppError::Validation("The base currency cannot be deactivated".to_string()));
+    }
+
+    conn.execute(
+        "INSERT INTO currencies (code, name, symbol, minor_units, active) VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT(code) DO UPDATE SET name = ?2, symbol = ?3, minor_units = ?4, active = ?5",
+        params![code, currency.name, currency.symbol, currency.minor_units, currency.active],
+    )?;
+    Ok(())
+}
+
+// ======================
+// EXCHANGE RATES
+// ======================
+
+/// Record how many base-currency units one unit of `currency_code` is worth from
+/// `effective_date` (today when omitted). Replaces a rate already set for that day.
+#[tauri::command]
+pub fn set_exchange_rate(
+    pool: State<'_, DbPool>,
+    currency_code: String,
+    rate: f64,
+    effective_date: Option<String>,
+) -> Result<ExchangeRate, AppError> {
+    let conn = pool.get()?;
+    let code = currency_code.trim().to_uppercase();
+    if code == currency().code {
+        return Err(AppError::Validation("The base currency always has a rate of 1".to_string()));
+    }
+    if !rate.is_finite() || rate <= 0.0 {
+        return Err(AppError::Validation("Exchange rate must be greater than 0".to_string()));
+    }
+    let effective_date = match effective_date {
+        Some(d) => chrono::NaiveDate::parse_from_str(&d[..d.len().min(10)], "%Y-%m-%d")
+            .map_err(|e| AppError::Validation(format!("Invalid effective date: {}", e)))?
+            .format("%Y-%m-%d")
+            .to_string(),
+        None => Utc::now().format("%Y-%m-%d").to_string(),
+    };
+
+    let exchange_rate = ExchangeRate {
+        id: Uuid::new_v4().to_string(),
+        currency_code: code,
+        rate,
+        effective_date,
+        created_at: Utc::now().to_rfc3339(),
+    };
+    conn.execute(
+        "INSERT INTO exchange_rates (id, currency_code, rate, effective_date, created_at) VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT(currency_code, effective_date) DO UPDATE SET rate = ?3, created_at = ?5",
+        params![
+            exchange_rate.id,
+            exchange_rate.currency_code,
+            exchange_rate.rate,
+            exchange_rate.effective_date,
+            exchange_rate.created_at,
+        ],
+    )?;
+    Ok(exchange_rate)
+}
+
+#[tauri::command]
+pub fn get_exchange_rates(
+    pool: State<'_, DbPool>,
+    currency_code: Option<String>,
+) -> Result<Vec<ExchangeRate>, AppError> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare_cached(
+        "SELECT id, currency_code, rate, effective_date, created_at FROM exchange_rates
+         WHERE ?1 IS NULL OR currency_code = ?1
+         ORDER BY effective_date DESC, currency_code",
+    )?;
+    let rates = stmt
+        .query_map(params![currency_code.map(|c| c.to_uppercase())], |row| {
+            Ok(ExchangeRate {
+                id: row.get(0)?,
+                currency_code: row.get(1)?,
+                rate: row.get(2)?,
+                effective_date: row.get(3)?,
+                created_at: row.get(4)?,
+            })
+        })?
+        .filter_map(|res| res.ok())
+        .collect();
+    Ok(rates)
+}
+
+/// Preview what an amount in `currency_code` is worth in the base currency.
+#[tauri::command]
+pub fn convert_to_base(
+    pool: State<'_, DbPool>,
+    currency_code: String,
+    amount: Money,
+    date: Option<String>,
+) -> Result<Money, AppError> {
+    let conn = pool.get()?;
+    let date = date.unwrap_or_else(|| Utc::now().to_rfc3339());
+    Ok(tendered_amount(&conn, Some(&currency_code), Some(amount), amount, &date)?.amount)
+}
+
+// ======================
+// CASH DRAWER
+// ======================
+
+/// Money in and out of a session (the open one when `session_id` is omitted), per
+/// currency and payment method, in the currency it was tendered in.
+#[tauri::command]
+pub fn get_session_currency_breakdown(
+    pool: State<'_, DbPool>,
+    session_id: Option<String>,
+) -> Result<Vec<SessionCurrencyTotal>, AppError> {
+    let conn = pool.get()?;
+
+    let session_id = match session_id {
+        Some(id) => id,
+        None => match conn
+            .query_row("SELECT id FROM daily_sessions WHERE status = 'open' LIMIT 1", [], |row| {
+                row.get::<_, String>(0)
+            })
+            .optional()?
+        {
+            Some(id) => id,
+            None => return Ok(Vec::new()),
+        },
+    };
+
+    // direction: 1 = into the drawer, -1 = out of it
+    let mut stmt = conn.prepare_cached(
+        "SELECT currency_code, method,
+                SUM(CASE WHEN direction > 0 THEN original_amount ELSE 0 END),
+                SUM(CASE WHEN direction < 0 THEN original_amount ELSE 0 END),
+                SUM(direction * amount)
+         FROM (
+             SELECT ?2 AS currency_code, method, amount AS original_amount, amount, 1 AS direction
+             FROM sale_payments WHERE session_id = ?1
+             UNION ALL
+             SELECT COALESCE(currency_code, ?2), method, COALESCE(original_amount, amount), amount, 1
+             FROM repair_payments WHERE session_id = ?1
+             UNION ALL
+             SELECT ?2, method, amount, amount, 1
+             FROM client_payments WHERE session_id = ?1
+             UNION ALL
+             SELECT ?2, method, amount, amount, -1
+             FROM supplier_payments WHERE session_id = ?1
+             UNION ALL
+             SELECT COALESCE(p.currency_code, ?2), p.method, COALESCE(p.original_amount, p.amount), p.amount,
+                    CASE WHEN t.transaction_type IN ('Sale', 'PurchaseReturn') THEN 1 ELSE -1 END
+             FROM transaction_payments p JOIN transactions t ON p.transaction_id = t.id
+             WHERE p.session_id = ?1
+             UNION ALL
+             SELECT COALESCE(currency_code, ?2), 'Cash', COALESCE(original_amount, amount), amount, -1
+             FROM expenses WHERE session_id = ?1
+         )
+         GROUP BY currency_code, method
+         ORDER BY currency_code = ?2 DESC, currency_code, method",
+    )?;
+    let totals = stmt
+        .query_map(params![session_id, currency().code], |row| {
+            let total_in: Money = row.get(2)?;
+            let total_out: Money = row.get(3)?;
+            Ok(SessionCurrencyTotal {
+                currency_code: row.get(0)?,
+                method: row.get(1)?,
+                total_in,
+                total_out,
+                net: total_in - total_out,
+                net_base: row.get(4)?,
+            })
+        })?
+        .filter_map(|res| res.ok())
+        .collect();
+    Ok(totals)
+}
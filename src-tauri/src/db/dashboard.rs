@@ -1,50 +1,72 @@
-use crate::db;
+use crate::db::DbPool;
 use crate::db::models::{RevenueData, RevenueBreakdown, DashboardStats};
+use crate::db::money::Money;
 use rusqlite::{params, Result, Connection};
 use chrono::{Utc, Duration, NaiveDate};
+use tauri::State;
+use crate::error::AppError;
 
 /// Helper to calculate Cost of Goods Sold for a given period
-fn calculate_cogs(conn: &Connection, start_iso: &str, end_iso: &str) -> f64 {
-    // 1. COGS from Repairs (linked via repair_used_parts to inventory_items)
+fn calculate_cogs(conn: &Connection, start_iso: &str, end_iso: &str) -> Money {
+    // Every line stores the unit cost it was issued at, so later price changes don't
+    // rewrite past profit.
+    // 1. COGS from Repairs (parts used)
     // We use created_at to match repairs ADDED in this period
-    let repair_cogs: f64 = conn.query_row(
-        "SELECT COALESCE(SUM(p.quantity * i.buying_price), 0)
+    let repair_cogs: Money = conn.query_row(
+        "SELECT COALESCE(SUM(p.quantity * COALESCE(p.unit_cost, 0)), 0)
          FROM repair_used_parts p
          JOIN repairs r ON p.repair_id = r.id
-         JOIN inventory_items i ON p.part_id = i.id
-         WHERE REPLACE(r.created_at, ' ', 'T') >= ?1 AND REPLACE(r.created_at, ' ', 'T') <= ?2 AND r.status != 'Cancelled'",
+         WHERE REPLACE(r.created_at, ' ', 'T') >= ?1 AND REPLACE(r.created_at, ' ', 'T') <= ?2 AND r.status NOT IN (SELECT name FROM repair_statuses WHERE kind = 'Void')",
         params![start_iso, end_iso],
         |row| row.get(0)
-    ).unwrap_or(0.0);
+    ).unwrap_or(Money::ZERO);
 
-    // 2. COGS from Sales (linked via sale_items to inventory_items)
-    let sale_cogs: f64 = conn.query_row(
-        "SELECT COALESCE(SUM(si.quantity * i.buying_price), 0)
+    // 2. COGS from Sales
+    let sale_cogs: Money = conn.query_row(
+        "SELECT COALESCE(SUM(si.quantity * COALESCE(si.unit_cost, 0)), 0)
          FROM sale_items si
          JOIN customer_sales s ON si.sale_id = s.id
-         JOIN inventory_items i ON si.item_id = i.id
          WHERE REPLACE(s.created_at, ' ', 'T') >= ?1 AND REPLACE(s.created_at, ' ', 'T') <= ?2 AND s.status = 'completed'",
         params![start_iso, end_iso],
         |row| row.get(0)
-    ).unwrap_or(0.0);
+    ).unwrap_or(Money::ZERO);
 
-    // 3. COGS from Unified Transactions (linked via transaction_items to inventory_items)
-    let tx_cogs: f64 = conn.query_row(
-        "SELECT COALESCE(SUM(ti.quantity * i.buying_price), 0)
+    // 3. COGS from Unified Transactions, less the cost of units customers returned
+    let tx_cogs: Money = conn.query_row(
+        "SELECT COALESCE(SUM(CASE WHEN t.transaction_type = 'Return' THEN -1 ELSE 1 END * ti.quantity * COALESCE(ti.unit_cost, 0)), 0)
          FROM transaction_items ti
          JOIN transactions t ON ti.transaction_id = t.id
-         JOIN inventory_items i ON ti.item_id = i.id
-         WHERE REPLACE(t.created_at, ' ', 'T') >= ?1 AND REPLACE(t.created_at, ' ', 'T') <= ?2 AND t.status = 'Completed' AND t.transaction_type = 'Sale'",
+         WHERE REPLACE(t.created_at, ' ', 'T') >= ?1 AND REPLACE(t.created_at, ' ', 'T') <= ?2 AND t.status = 'Completed' AND t.transaction_type IN ('Sale', 'Return')",
         params![start_iso, end_iso],
         |row| row.get(0)
-    ).unwrap_or(0.0);
+    ).unwrap_or(Money::ZERO);
 
     repair_cogs + sale_cogs + tx_cogs
 }
 
+/// Helper to total the discounts given in a period: line and document discounts on
+/// completed sales (less the share handed back on returns) and on repairs
+fn calculate_discounts_given(conn: &Connection, start_iso: &str, end_iso: &str) -> Money {
+    conn.query_row(
+        "SELECT
+            (SELECT COALESCE(SUM(CASE WHEN t.transaction_type = 'Return' THEN -1 ELSE 1 END * (ti.discount_amount + ti.document_discount_amount)), 0)
+             FROM transaction_items ti
+             JOIN transactions t ON ti.transaction_id = t.id
+             WHERE REPLACE(t.created_at, ' ', 'T') >= ?1 AND REPLACE(t.created_at, ' ', 'T') <= ?2 AND t.status = 'Completed' AND t.transaction_type IN ('Sale', 'Return')) +
+            (SELECT COALESCE(SUM(si.discount_amount), 0)
+             FROM sale_items si
+             JOIN customer_sales s ON si.sale_id = s.id
+             WHERE REPLACE(s.created_at, ' ', 'T') >= ?1 AND REPLACE(s.created_at, ' ', 'T') <= ?2 AND s.status = 'completed') +
+            (SELECT COALESCE(SUM(discount_amount), 0) FROM customer_sales WHERE REPLACE(created_at, ' ', 'T') >= ?1 AND REPLACE(created_at, ' ', 'T') <= ?2 AND status = 'completed') +
+            (SELECT COALESCE(SUM(discount_amount), 0) FROM repairs WHERE REPLACE(created_at, ' ', 'T') >= ?1 AND REPLACE(created_at, ' ', 'T') <= ?2 AND status NOT IN (SELECT name FROM repair_statuses WHERE kind = 'Void'))",
+        params![start_iso, end_iso],
+        |row| row.get(0)
+    ).unwrap_or(Money::ZERO)
+}
+
 #[tauri::command]
-pub fn get_revenue_history_by_range(start_date: String, end_date: String) -> Result<Vec<RevenueData>, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn get_revenue_history_by_range(pool: State<'_, DbPool>, start_date: String, end_date: String) -> Result<Vec<RevenueData>, AppError> {
+    let conn = pool.get()?;
     
     // Parse start and end dates (assuming YYYY-MM-DD)
     // If they have time, we strip it or handle it.
@@ -52,9 +74,9 @@ pub fn get_revenue_history_by_range(start_date: String, end_date: String) -> Res
     // If we receive ISO strings like 2023-01-01T00:00:00Z, we need to parse them.
     
     let start = NaiveDate::parse_from_str(&start_date.split('T').next().unwrap_or(&start_date), "%Y-%m-%d")
-        .map_err(|e| format!("Invalid start date: {}", e))?;
+        .map_err(|e| AppError::Validation(format!("Invalid start date: {}", e)))?;
     let end = NaiveDate::parse_from_str(&end_date.split('T').next().unwrap_or(&end_date), "%Y-%m-%d")
-        .map_err(|e| format!("Invalid end date: {}", e))?;
+        .map_err(|e| AppError::Validation(format!("Invalid end date: {}", e)))?;
 
     let mut history = Vec::new();
     let mut current = start;
@@ -63,35 +85,35 @@ pub fn get_revenue_history_by_range(start_date: String, end_date: String) -> Res
         let date_str = current.format("%Y-%m-%d").to_string();
         
         // Revenue from Sales (recorded in sale_payments)
-        let sale_revenue: f64 = conn.query_row(
+        let sale_revenue: Money = conn.query_row(
             "SELECT COALESCE(SUM(amount), 0) FROM sale_payments WHERE date LIKE ?1",
             params![format!("{}%", date_str)],
             |row| row.get(0)
-        ).unwrap_or(0.0);
+        ).unwrap_or(Money::ZERO);
 
         // Revenue from Repairs (recorded in repair_payments)
-        let repair_revenue: f64 = conn.query_row(
+        let repair_revenue: Money = conn.query_row(
             "SELECT COALESCE(SUM(amount), 0) FROM repair_payments WHERE date LIKE ?1",
             params![format!("{}%", date_str)],
             |row| row.get(0)
-        ).unwrap_or(0.0);
+        ).unwrap_or(Money::ZERO);
 
         // Revenue from Transaction Payments (Sales type)
-        let tx_revenue: f64 = conn.query_row(
+        let tx_revenue: Money = conn.query_row(
             "SELECT COALESCE(SUM(p.amount), 0) 
              FROM transaction_payments p 
              JOIN transactions t ON p.transaction_id = t.id 
              WHERE t.transaction_type = 'Sale' AND p.date LIKE ?1",
             params![format!("{}%", date_str)],
             |row| row.get(0)
-        ).unwrap_or(0.0);
+        ).unwrap_or(Money::ZERO);
 
         // Direct Client Payments
-        let client_payments: f64 = conn.query_row(
+        let client_payments: Money = conn.query_row(
             "SELECT COALESCE(SUM(amount), 0) FROM client_payments WHERE date LIKE ?1",
             params![format!("{}%", date_str)],
             |row| row.get(0)
-        ).unwrap_or(0.0);
+        ).unwrap_or(Money::ZERO);
 
         let total_revenue = sale_revenue + repair_revenue + tx_revenue + client_payments;
 
@@ -100,14 +122,14 @@ pub fn get_revenue_history_by_range(start_date: String, end_date: String) -> Res
         let day_end = format!("{}T23:59:59.999", date_str);
         
         // Use accrual revenue for the chart profit (Total Sale Value of everything COMPLETED this day)
-        let day_accrual_rev: f64 = conn.query_row(
+        let day_accrual_rev: Money = conn.query_row(
             "SELECT 
                 (SELECT COALESCE(SUM(total_amount), 0) FROM customer_sales WHERE created_at LIKE ?1 AND status = 'completed') +
-                (SELECT COALESCE(SUM(total_amount), 0) FROM transactions WHERE created_at LIKE ?1 AND status = 'Completed' AND transaction_type = 'Sale') +
-                (SELECT COALESCE(SUM(estimated_cost), 0) FROM repairs WHERE updated_at LIKE ?1 AND status IN ('Completed', 'Delivered'))",
+                (SELECT COALESCE(SUM(CASE WHEN transaction_type = 'Return' THEN -total_amount ELSE total_amount END), 0) FROM transactions WHERE created_at LIKE ?1 AND status = 'Completed' AND transaction_type IN ('Sale', 'Return')) +
+                (SELECT COALESCE(SUM(estimated_cost - discount_amount), 0) FROM repairs WHERE updated_at LIKE ?1 AND status IN (SELECT name FROM repair_statuses WHERE kind = 'Done'))",
             params![format!("{}%", date_str)],
             |row| row.get(0)
-        ).unwrap_or(0.0);
+        ).unwrap_or(Money::ZERO);
 
         let cogs = calculate_cogs(&conn, &day_start, &day_end);
         let profit = day_accrual_rev - cogs; 
@@ -118,15 +140,17 @@ pub fn get_revenue_history_by_range(start_date: String, end_date: String) -> Res
             profit,
         });
 
-        current = current.succ_opt().ok_or("Date calculation error")?;
+        current = current
+            .succ_opt()
+            .ok_or_else(|| AppError::Validation("Date calculation error".to_string()))?;
     }
 
     Ok(history)
 }
 
 #[tauri::command]
-pub fn get_revenue_history(days: i32) -> Result<Vec<RevenueData>, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn get_revenue_history(pool: State<'_, DbPool>, days: i32) -> Result<Vec<RevenueData>, AppError> {
+    let conn = pool.get()?;
     
     let mut history = Vec::new();
     let today = Utc::now();
@@ -136,47 +160,47 @@ pub fn get_revenue_history(days: i32) -> Result<Vec<RevenueData>, String> {
         let date_str = date.format("%Y-%m-%d").to_string();
         
         // Revenue from Sales (recorded in sale_payments)
-        let sale_revenue: f64 = conn.query_row(
+        let sale_revenue: Money = conn.query_row(
             "SELECT COALESCE(SUM(amount), 0) FROM sale_payments WHERE date LIKE ?1",
             params![format!("{}%", date_str)],
             |row| row.get(0)
-        ).unwrap_or(0.0);
+        ).unwrap_or(Money::ZERO);
 
         // Revenue from Repairs (recorded in repair_payments)
-        let repair_revenue: f64 = conn.query_row(
+        let repair_revenue: Money = conn.query_row(
             "SELECT COALESCE(SUM(amount), 0) FROM repair_payments WHERE date LIKE ?1",
             params![format!("{}%", date_str)],
             |row| row.get(0)
-        ).unwrap_or(0.0);
+        ).unwrap_or(Money::ZERO);
 
         // Revenue from Transaction Payments (Sales type)
-        let tx_revenue: f64 = conn.query_row(
+        let tx_revenue: Money = conn.query_row(
             "SELECT COALESCE(SUM(p.amount), 0) 
              FROM transaction_payments p 
              JOIN transactions t ON p.transaction_id = t.id 
              WHERE t.transaction_type = 'Sale' AND p.date LIKE ?1",
             params![format!("{}%", date_str)],
             |row| row.get(0)
-        ).unwrap_or(0.0);
+        ).unwrap_or(Money::ZERO);
 
         // Direct Client Payments
-        let client_payments: f64 = conn.query_row(
+        let client_payments: Money = conn.query_row(
             "SELECT COALESCE(SUM(amount), 0) FROM client_payments WHERE date LIKE ?1",
             params![format!("{}%", date_str)],
             |row| row.get(0)
-        ).unwrap_or(0.0);
+        ).unwrap_or(Money::ZERO);
 
         let total_revenue = sale_revenue + repair_revenue + tx_revenue + client_payments;
 
         // Use accrual revenue for the chart profit
-        let day_accrual_rev: f64 = conn.query_row(
+        let day_accrual_rev: Money = conn.query_row(
             "SELECT 
                 (SELECT COALESCE(SUM(total_amount), 0) FROM customer_sales WHERE created_at LIKE ?1 AND status = 'completed') +
-                (SELECT COALESCE(SUM(total_amount), 0) FROM transactions WHERE created_at LIKE ?1 AND status = 'Completed' AND transaction_type = 'Sale') +
-                (SELECT COALESCE(SUM(estimated_cost), 0) FROM repairs WHERE updated_at LIKE ?1 AND status IN ('Completed', 'Delivered'))",
+                (SELECT COALESCE(SUM(CASE WHEN transaction_type = 'Return' THEN -total_amount ELSE total_amount END), 0) FROM transactions WHERE created_at LIKE ?1 AND status = 'Completed' AND transaction_type IN ('Sale', 'Return')) +
+                (SELECT COALESCE(SUM(estimated_cost - discount_amount), 0) FROM repairs WHERE updated_at LIKE ?1 AND status IN (SELECT name FROM repair_statuses WHERE kind = 'Done'))",
             params![format!("{}%", date_str)],
             |row| row.get(0)
-        ).unwrap_or(0.0);
+        ).unwrap_or(Money::ZERO);
 
         let day_start = format!("{}T00:00:00", date_str);
         let day_end = format!("{}T23:59:59.999", date_str);
@@ -194,29 +218,29 @@ pub fn get_revenue_history(days: i32) -> Result<Vec<RevenueData>, String> {
 }
 
 #[tauri::command]
-pub fn get_revenue_breakdown(days: i32) -> Result<Vec<RevenueBreakdown>, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn get_revenue_breakdown(pool: State<'_, DbPool>, days: i32) -> Result<Vec<RevenueBreakdown>, AppError> {
+    let conn = pool.get()?;
     let start_date = (Utc::now() - Duration::days(days as i64)).to_rfc3339();
 
-    let sale_rev: f64 = conn.query_row(
+    let sale_rev: Money = conn.query_row(
         "SELECT COALESCE(SUM(amount), 0) FROM sale_payments WHERE date >= ?1",
         params![start_date],
         |row| row.get(0)
-    ).unwrap_or(0.0);
+    ).unwrap_or(Money::ZERO);
 
-    let repair_rev: f64 = conn.query_row(
+    let repair_rev: Money = conn.query_row(
         "SELECT COALESCE(SUM(amount), 0) FROM repair_payments WHERE date >= ?1",
         params![start_date],
         |row| row.get(0)
-    ).unwrap_or(0.0);
+    ).unwrap_or(Money::ZERO);
 
-    let other_rev: f64 = conn.query_row(
+    let other_rev: Money = conn.query_row(
         "SELECT COALESCE(SUM(amount), 0) FROM transaction_payments p 
          JOIN transactions t ON p.transaction_id = t.id 
          WHERE t.transaction_type = 'Sale' AND REPLACE(p.date, ' ', 'T') >= ?1",
         params![start_date],
         |row| row.get(0)
-    ).unwrap_or(0.0);
+    ).unwrap_or(Money::ZERO);
 
     Ok(vec![
         RevenueBreakdown { category: "Sales".to_string(), amount: sale_rev },
@@ -226,12 +250,12 @@ pub fn get_revenue_breakdown(days: i32) -> Result<Vec<RevenueBreakdown>, String>
 }
 
 #[tauri::command]
-pub fn get_dashboard_stats() -> Result<DashboardStats, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn get_dashboard_stats(pool: State<'_, DbPool>) -> Result<DashboardStats, AppError> {
+    let conn = pool.get()?;
     
     // Total Revenue (All time or current month? Let's do current month)
     let this_month = Utc::now().format("%Y-%m").to_string();
-    let total_revenue: f64 = conn.query_row(
+    let total_revenue: Money = conn.query_row(
         "SELECT 
             (SELECT COALESCE(SUM(amount), 0) FROM sale_payments WHERE date LIKE ?1) +
             (SELECT COALESCE(SUM(amount), 0) FROM repair_payments WHERE date LIKE ?1) +
@@ -239,17 +263,17 @@ pub fn get_dashboard_stats() -> Result<DashboardStats, String> {
             (SELECT COALESCE(SUM(p.amount), 0) FROM transaction_payments p JOIN transactions t ON p.transaction_id = t.id WHERE t.transaction_type = 'Sale' AND p.date LIKE ?1)",
         params![format!("{}%", this_month)],
         |row| row.get(0)
-    ).unwrap_or(0.0);
+    ).unwrap_or(Money::ZERO);
 
     // Active Repairs
     let active_repairs: i32 = conn.query_row(
-        "SELECT COUNT(*) FROM repairs WHERE status IN ('Pending', 'In Progress', 'Waiting for Parts')",
+        "SELECT COUNT(*) FROM repairs WHERE status IN (SELECT name FROM repair_statuses WHERE kind = 'Open')",
         [],
         |row| row.get(0)
     ).unwrap_or(0);
 
     let completed_repairs: i32 = conn.query_row(
-        "SELECT COUNT(*) FROM repairs WHERE status IN ('Completed', 'Delivered')",
+        "SELECT COUNT(*) FROM repairs WHERE status IN (SELECT name FROM repair_statuses WHERE kind = 'Done')",
         [],
         |row| row.get(0)
     ).unwrap_or(0);
@@ -274,58 +298,60 @@ pub fn get_dashboard_stats() -> Result<DashboardStats, String> {
     let start_of_month = format!("{}-01T00:00:00", this_month);
     let end_of_month = format!("{}-31T23:59:59", this_month); 
 
-    let repair_revenue: f64 = conn.query_row(
-        "SELECT COALESCE(SUM(estimated_cost), 0) FROM repairs WHERE created_at LIKE ?1 AND status != 'Cancelled'",
+    let repair_revenue: Money = conn.query_row(
+        "SELECT COALESCE(SUM(estimated_cost - discount_amount), 0) FROM repairs WHERE created_at LIKE ?1 AND status NOT IN (SELECT name FROM repair_statuses WHERE kind = 'Void')",
         params![format!("{}%", this_month)],
         |row| row.get(0)
-    ).unwrap_or(0.0);
+    ).unwrap_or(Money::ZERO);
 
-    let accrual_revenue: f64 = conn.query_row(
+    let accrual_revenue: Money = conn.query_row(
         "SELECT 
             (SELECT COALESCE(SUM(total_amount), 0) FROM customer_sales WHERE created_at LIKE ?1 AND status = 'completed') +
-            (SELECT COALESCE(SUM(total_amount), 0) FROM transactions WHERE created_at LIKE ?1 AND status = 'Completed' AND transaction_type = 'Sale') +
+            (SELECT COALESCE(SUM(CASE WHEN transaction_type = 'Return' THEN -total_amount ELSE total_amount END), 0) FROM transactions WHERE created_at LIKE ?1 AND status = 'Completed' AND transaction_type IN ('Sale', 'Return')) +
             ?2",
         params![format!("{}%", this_month), repair_revenue],
         |row| row.get(0)
-    ).unwrap_or(0.0);
+    ).unwrap_or(Money::ZERO);
 
     let cogs = calculate_cogs(&conn, &start_of_month, &end_of_month);
     
     // Calculate REPAIR specifically
     // Note: calculate_cogs already filters by created_at in my updated version
-    let repair_cogs_only: f64 = conn.query_row(
-        "SELECT COALESCE(SUM(p.quantity * i.buying_price), 0)
+    let repair_cogs_only: Money = conn.query_row(
+        "SELECT COALESCE(SUM(p.quantity * COALESCE(p.unit_cost, 0)), 0)
          FROM repair_used_parts p
          JOIN repairs r ON p.repair_id = r.id
-         JOIN inventory_items i ON p.part_id = i.id
-         WHERE r.created_at LIKE ?1 AND r.status != 'Cancelled'",
+         WHERE r.created_at LIKE ?1 AND r.status NOT IN (SELECT name FROM repair_statuses WHERE kind = 'Void')",
         params![format!("{}%", this_month)],
         |row| row.get(0)
-    ).unwrap_or(0.0);
+    ).unwrap_or(Money::ZERO);
     let repair_profit = repair_revenue - repair_cogs_only;
 
-    let expenses: f64 = conn.query_row(
+    let expenses: Money = conn.query_row(
         "SELECT COALESCE(SUM(amount), 0) FROM expenses WHERE date LIKE ?1",
         params![format!("{}%", this_month)],
         |row| row.get(0)
-    ).unwrap_or(0.0);
+    ).unwrap_or(Money::ZERO);
     let net_profit = accrual_revenue - cogs - expenses;
 
     // Net Cash (Flow for the month)
-    let supplier_payments: f64 = conn.query_row(
+    let supplier_payments: Money = conn.query_row(
         "SELECT COALESCE(SUM(amount), 0) FROM supplier_payments WHERE date LIKE ?1",
         params![format!("{}%", this_month)],
         |row| row.get(0)
-    ).unwrap_or(0.0);
+    ).unwrap_or(Money::ZERO);
 
-    let other_debits: f64 = conn.query_row(
-        "SELECT COALESCE(SUM(p.amount), 0) FROM transaction_payments p JOIN transactions t ON p.transaction_id = t.id WHERE t.transaction_type != 'Sale' AND p.date LIKE ?1",
+    // Less what suppliers refunded on purchase returns, which comes back in
+    let other_debits: Money = conn.query_row(
+        "SELECT COALESCE(SUM(CASE WHEN t.transaction_type = 'PurchaseReturn' THEN -p.amount ELSE p.amount END), 0) FROM transaction_payments p JOIN transactions t ON p.transaction_id = t.id WHERE t.transaction_type != 'Sale' AND p.date LIKE ?1",
         params![format!("{}%", this_month)],
         |row| row.get(0)
-    ).unwrap_or(0.0);
+    ).unwrap_or(Money::ZERO);
 
     let net_cash = total_revenue - expenses - supplier_payments - other_debits;
 
+    let discounts_given = calculate_discounts_given(&conn, &start_of_month, &end_of_month);
+
     Ok(DashboardStats {
         total_revenue,
         net_cash,
@@ -336,12 +362,13 @@ pub fn get_dashboard_stats() -> Result<DashboardStats, String> {
         out_of_stock,
         revenue_change,
         repair_profit,
+        discounts_given,
     })
 }
 
 #[tauri::command]
-pub fn get_dashboard_transactions_by_range(start_date: String, end_date: String) -> Result<Vec<crate::db::models::DashboardTransaction>, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn get_dashboard_transactions_by_range(pool: State<'_, DbPool>, start_date: String, end_date: String) -> Result<Vec<crate::db::models::DashboardTransaction>, AppError> {
+    let conn = pool.get()?;
     
     // Ensure accurate range
     // For Start Date: Use the raw "YYYY-MM-DD" string.
@@ -361,20 +388,20 @@ pub fn get_dashboard_transactions_by_range(start_date: String, end_date: String)
     let mut all_tx = Vec::new();
 
     // 1. Sale Payments
-    let mut stmt = conn.prepare("
+    let mut stmt = conn.prepare_cached("
         SELECT p.id, p.amount, p.date, p.method, s.sale_number 
         FROM sale_payments p 
         JOIN customer_sales s ON p.sale_id = s.id 
         WHERE REPLACE(p.date, ' ', 'T') >= ?1 AND REPLACE(p.date, ' ', 'T') <= ?2
-    ").map_err(|e| e.to_string())?;
+    ")?;
     
-    let mut rows = stmt.query(params![start_iso, end_iso]).map_err(|e| e.to_string())?;
-    while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+    let mut rows = stmt.query(params![start_iso, end_iso])?;
+    while let Some(row) = rows.next()? {
         all_tx.push(crate::db::models::DashboardTransaction {
             id: row.get(0).unwrap_or_default(),
             tx_type: "credit".to_string(),
             category: "Sale Payment".to_string(),
-            amount: row.get(1).unwrap_or(0.0),
+            amount: row.get(1).unwrap_or(Money::ZERO),
             description: format!("Sale payment for {}", row.get::<_, String>(4).unwrap_or_default()),
             time: row.get(2).unwrap_or_default(),
             status: "completed".to_string(),
@@ -382,20 +409,20 @@ pub fn get_dashboard_transactions_by_range(start_date: String, end_date: String)
     }
 
     // 2. Repair Payments
-    let mut stmt = conn.prepare("
+    let mut stmt = conn.prepare_cached("
         SELECT p.id, p.amount, p.date, p.method, r.customer_name 
         FROM repair_payments p 
         JOIN repairs r ON p.repair_id = r.id 
         WHERE REPLACE(p.date, ' ', 'T') >= ?1 AND REPLACE(p.date, ' ', 'T') <= ?2
-    ").map_err(|e| e.to_string())?;
+    ")?;
     
-    let mut rows = stmt.query(params![start_iso, end_iso]).map_err(|e| e.to_string())?;
-    while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+    let mut rows = stmt.query(params![start_iso, end_iso])?;
+    while let Some(row) = rows.next()? {
         all_tx.push(crate::db::models::DashboardTransaction {
             id: row.get(0).unwrap_or_default(),
             tx_type: "credit".to_string(),
             category: "Repair Payment".to_string(),
-            amount: row.get(1).unwrap_or(0.0),
+            amount: row.get(1).unwrap_or(Money::ZERO),
             description: format!("Repair payment from {}", row.get::<_, String>(4).unwrap_or_default()),
             time: row.get(2).unwrap_or_default(),
             status: "completed".to_string(),
@@ -403,20 +430,20 @@ pub fn get_dashboard_transactions_by_range(start_date: String, end_date: String)
     }
 
     // 3. Client Payments
-    let mut stmt = conn.prepare("
+    let mut stmt = conn.prepare_cached("
         SELECT p.id, p.amount, p.date, p.method, c.name 
         FROM client_payments p 
         JOIN clients c ON p.client_id = c.id 
         WHERE REPLACE(p.date, ' ', 'T') >= ?1 AND REPLACE(p.date, ' ', 'T') <= ?2
-    ").map_err(|e| e.to_string())?;
+    ")?;
     
-    let mut rows = stmt.query(params![start_iso, end_iso]).map_err(|e| e.to_string())?;
-    while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+    let mut rows = stmt.query(params![start_iso, end_iso])?;
+    while let Some(row) = rows.next()? {
         all_tx.push(crate::db::models::DashboardTransaction {
             id: row.get(0).unwrap_or_default(),
             tx_type: "credit".to_string(),
             category: "Client Payment".to_string(),
-            amount: row.get(1).unwrap_or(0.0),
+            amount: row.get(1).unwrap_or(Money::ZERO),
             description: format!("Direct payment from {}", row.get::<_, String>(4).unwrap_or_default()),
             time: row.get(2).unwrap_or_default(),
             status: "completed".to_string(),
@@ -424,19 +451,19 @@ pub fn get_dashboard_transactions_by_range(start_date: String, end_date: String)
     }
 
     // 4. Expenses
-    let mut stmt = conn.prepare("
+    let mut stmt = conn.prepare_cached("
         SELECT id, amount, date, reason, category 
         FROM expenses 
         WHERE REPLACE(date, ' ', 'T') >= ?1 AND REPLACE(date, ' ', 'T') <= ?2
-    ").map_err(|e| e.to_string())?;
+    ")?;
     
-    let mut rows = stmt.query(params![start_iso, end_iso]).map_err(|e| e.to_string())?;
-    while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+    let mut rows = stmt.query(params![start_iso, end_iso])?;
+    while let Some(row) = rows.next()? {
         all_tx.push(crate::db::models::DashboardTransaction {
             id: row.get(0).unwrap_or_default(),
             tx_type: "debit".to_string(),
             category: "Expense".to_string(),
-            amount: row.get(1).unwrap_or(0.0),
+            amount: row.get(1).unwrap_or(Money::ZERO),
             description: row.get(3).unwrap_or_default(),
             time: row.get(2).unwrap_or_default(),
             status: "completed".to_string(),
@@ -444,20 +471,20 @@ pub fn get_dashboard_transactions_by_range(start_date: String, end_date: String)
     }
 
     // 5. Supplier Payments
-    let mut stmt = conn.prepare("
+    let mut stmt = conn.prepare_cached("
         SELECT p.id, p.amount, p.date, p.method, s.name 
         FROM supplier_payments p 
         JOIN suppliers s ON p.supplier_id = s.id 
         WHERE REPLACE(p.date, ' ', 'T') >= ?1 AND REPLACE(p.date, ' ', 'T') <= ?2
-    ").map_err(|e| e.to_string())?;
+    ")?;
     
-    let mut rows = stmt.query(params![start_iso, end_iso]).map_err(|e| e.to_string())?;
-    while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+    let mut rows = stmt.query(params![start_iso, end_iso])?;
+    while let Some(row) = rows.next()? {
         all_tx.push(crate::db::models::DashboardTransaction {
             id: row.get(0).unwrap_or_default(),
             tx_type: "debit".to_string(),
             category: "Supplier Payment".to_string(),
-            amount: row.get(1).unwrap_or(0.0),
+            amount: row.get(1).unwrap_or(Money::ZERO),
             description: format!("Payment to {}", row.get::<_, String>(4).unwrap_or_default()),
             time: row.get(2).unwrap_or_default(),
             status: "completed".to_string(),
@@ -465,22 +492,22 @@ pub fn get_dashboard_transactions_by_range(start_date: String, end_date: String)
     }
 
     // 6. Generic Transaction Payments
-    let mut stmt = conn.prepare("
+    let mut stmt = conn.prepare_cached("
         SELECT p.id, p.amount, p.date, p.method, t.transaction_number, t.transaction_type 
         FROM transaction_payments p 
         JOIN transactions t ON p.transaction_id = t.id 
         WHERE REPLACE(p.date, ' ', 'T') >= ?1 AND REPLACE(p.date, ' ', 'T') <= ?2
-    ").map_err(|e| e.to_string())?;
+    ")?;
     
-    let mut rows = stmt.query(params![start_iso, end_iso]).map_err(|e| e.to_string())?;
-    while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+    let mut rows = stmt.query(params![start_iso, end_iso])?;
+    while let Some(row) = rows.next()? {
         let tx_type_label: String = row.get(5).unwrap_or_default();
-        let direction = if tx_type_label == "Sale" { "credit" } else { "debit" };
+        let direction = if tx_type_label == "Sale" || tx_type_label == "PurchaseReturn" { "credit" } else { "debit" };
         all_tx.push(crate::db::models::DashboardTransaction {
             id: row.get(0).unwrap_or_default(),
             tx_type: direction.to_string(),
             category: format!("{} Payment", tx_type_label),
-            amount: row.get(1).unwrap_or(0.0),
+            amount: row.get(1).unwrap_or(Money::ZERO),
             description: format!("Payment for {} {}", tx_type_label, row.get::<_, String>(4).unwrap_or_default()),
             time: row.get(2).unwrap_or_default(),
             status: "completed".to_string(),
@@ -494,8 +521,8 @@ pub fn get_dashboard_transactions_by_range(start_date: String, end_date: String)
 }
 
 #[tauri::command]
-pub fn get_dashboard_stats_by_range(start_date: String, end_date: String) -> Result<DashboardStats, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn get_dashboard_stats_by_range(pool: State<'_, DbPool>, start_date: String, end_date: String) -> Result<DashboardStats, AppError> {
+    let conn = pool.get()?;
     
     // Ensure accurate range
     // For Start Date: Use the raw "YYYY-MM-DD" string.
@@ -508,23 +535,23 @@ pub fn get_dashboard_stats_by_range(start_date: String, end_date: String) -> Res
     };
 
     // Accrual Revenue for profit calculation (Total value of sales made)
-    let repair_revenue: f64 = conn.query_row(
-        "SELECT COALESCE(SUM(estimated_cost), 0) FROM repairs WHERE REPLACE(created_at, ' ', 'T') >= ?1 AND REPLACE(created_at, ' ', 'T') <= ?2 AND status != 'Cancelled'",
+    let repair_revenue: Money = conn.query_row(
+        "SELECT COALESCE(SUM(estimated_cost - discount_amount), 0) FROM repairs WHERE REPLACE(created_at, ' ', 'T') >= ?1 AND REPLACE(created_at, ' ', 'T') <= ?2 AND status NOT IN (SELECT name FROM repair_statuses WHERE kind = 'Void')",
         params![start_iso, end_iso],
         |row| row.get(0)
-    ).unwrap_or(0.0);
+    ).unwrap_or(Money::ZERO);
 
-    let accrual_revenue: f64 = conn.query_row(
+    let accrual_revenue: Money = conn.query_row(
         "SELECT 
             (SELECT COALESCE(SUM(total_amount), 0) FROM customer_sales WHERE REPLACE(created_at, ' ', 'T') >= ?1 AND REPLACE(created_at, ' ', 'T') <= ?2 AND status = 'completed') +
-            (SELECT COALESCE(SUM(total_amount), 0) FROM transactions WHERE REPLACE(created_at, ' ', 'T') >= ?1 AND REPLACE(created_at, ' ', 'T') <= ?2 AND status = 'Completed' AND transaction_type = 'Sale') +
+            (SELECT COALESCE(SUM(CASE WHEN transaction_type = 'Return' THEN -total_amount ELSE total_amount END), 0) FROM transactions WHERE REPLACE(created_at, ' ', 'T') >= ?1 AND REPLACE(created_at, ' ', 'T') <= ?2 AND status = 'Completed' AND transaction_type IN ('Sale', 'Return')) +
             ?3",
         params![start_iso, end_iso, repair_revenue],
         |row| row.get(0)
-    ).unwrap_or(0.0);
+    ).unwrap_or(Money::ZERO);
 
     // Total Revenue (Cash-based for liquidity check)
-    let total_revenue: f64 = conn.query_row(
+    let total_revenue: Money = conn.query_row(
         "SELECT 
             (SELECT COALESCE(SUM(amount), 0) FROM sale_payments WHERE REPLACE(date, ' ', 'T') >= ?1 AND REPLACE(date, ' ', 'T') <= ?2) +
             (SELECT COALESCE(SUM(amount), 0) FROM repair_payments WHERE REPLACE(date, ' ', 'T') >= ?1 AND REPLACE(date, ' ', 'T') <= ?2) +
@@ -532,17 +559,17 @@ pub fn get_dashboard_stats_by_range(start_date: String, end_date: String) -> Res
             (SELECT COALESCE(SUM(p.amount), 0) FROM transaction_payments p JOIN transactions t ON p.transaction_id = t.id WHERE t.transaction_type = 'Sale' AND REPLACE(p.date, ' ', 'T') >= ?1 AND REPLACE(p.date, ' ', 'T') <= ?2)",
         params![start_iso, end_iso],
         |row| row.get(0)
-    ).unwrap_or(0.0);
+    ).unwrap_or(Money::ZERO);
 
     // Active Repairs (Snapshot - doesn't really depend on range, but current state)
     let active_repairs: i32 = conn.query_row(
-        "SELECT COUNT(*) FROM repairs WHERE status IN ('Pending', 'In Progress', 'Waiting for Parts')",
+        "SELECT COUNT(*) FROM repairs WHERE status IN (SELECT name FROM repair_statuses WHERE kind = 'Open')",
         [],
         |row| row.get(0)
     ).unwrap_or(0);
 
     let completed_repairs: i32 = conn.query_row(
-        "SELECT COUNT(*) FROM repairs WHERE status IN ('Completed', 'Delivered') AND REPLACE(updated_at, ' ', 'T') >= ?1 AND REPLACE(updated_at, ' ', 'T') <= ?2",
+        "SELECT COUNT(*) FROM repairs WHERE status IN (SELECT name FROM repair_statuses WHERE kind = 'Done') AND REPLACE(updated_at, ' ', 'T') >= ?1 AND REPLACE(updated_at, ' ', 'T') <= ?2",
         params![start_iso, end_iso],
         |row| row.get(0)
     ).unwrap_or(0);
@@ -567,23 +594,24 @@ pub fn get_dashboard_stats_by_range(start_date: String, end_date: String) -> Res
     // In - Out
     // In = total_revenue (Payments collected)
     // Out = Expenses + Supplier Payments + Debit Transactions
-    let expenses: f64 = conn.query_row(
+    let expenses: Money = conn.query_row(
         "SELECT COALESCE(SUM(amount), 0) FROM expenses WHERE REPLACE(date, ' ', 'T') >= ?1 AND REPLACE(date, ' ', 'T') <= ?2",
         params![start_iso, end_iso],
         |row| row.get(0)
-    ).unwrap_or(0.0);
+    ).unwrap_or(Money::ZERO);
 
-    let supplier_payments: f64 = conn.query_row(
+    let supplier_payments: Money = conn.query_row(
         "SELECT COALESCE(SUM(amount), 0) FROM supplier_payments WHERE REPLACE(date, ' ', 'T') >= ?1 AND REPLACE(date, ' ', 'T') <= ?2",
         params![start_iso, end_iso],
         |row| row.get(0)
-    ).unwrap_or(0.0);
+    ).unwrap_or(Money::ZERO);
 
-    let other_debits: f64 = conn.query_row(
-        "SELECT COALESCE(SUM(p.amount), 0) FROM transaction_payments p JOIN transactions t ON p.transaction_id = t.id WHERE t.transaction_type != 'Sale' AND REPLACE(p.date, ' ', 'T') >= ?1 AND REPLACE(p.date, ' ', 'T') <= ?2",
+    // Less what suppliers refunded on purchase returns, which comes back in
+    let other_debits: Money = conn.query_row(
+        "SELECT COALESCE(SUM(CASE WHEN t.transaction_type = 'PurchaseReturn' THEN -p.amount ELSE p.amount END), 0) FROM transaction_payments p JOIN transactions t ON p.transaction_id = t.id WHERE t.transaction_type != 'Sale' AND REPLACE(p.date, ' ', 'T') >= ?1 AND REPLACE(p.date, ' ', 'T') <= ?2",
         params![start_iso, end_iso],
         |row| row.get(0)
-    ).unwrap_or(0.0);
+    ).unwrap_or(Money::ZERO);
 
     let total_out = expenses + supplier_payments + other_debits;
     let net_cash = total_revenue - total_out;
@@ -592,19 +620,20 @@ pub fn get_dashboard_stats_by_range(start_date: String, end_date: String) -> Res
     let cogs = calculate_cogs(&conn, &start_iso, &end_iso);
     
     // Repair profit specifically for the range
-    let repair_cogs: f64 = conn.query_row(
-        "SELECT COALESCE(SUM(p.quantity * i.buying_price), 0)
+    let repair_cogs: Money = conn.query_row(
+        "SELECT COALESCE(SUM(p.quantity * COALESCE(p.unit_cost, 0)), 0)
          FROM repair_used_parts p
          JOIN repairs r ON p.repair_id = r.id
-         JOIN inventory_items i ON p.part_id = i.id
-         WHERE REPLACE(r.created_at, ' ', 'T') >= ?1 AND REPLACE(r.created_at, ' ', 'T') <= ?2 AND r.status != 'Cancelled'",
+         WHERE REPLACE(r.created_at, ' ', 'T') >= ?1 AND REPLACE(r.created_at, ' ', 'T') <= ?2 AND r.status NOT IN (SELECT name FROM repair_statuses WHERE kind = 'Void')",
         params![start_iso, end_iso],
         |row| row.get(0)
-    ).unwrap_or(0.0);
+    ).unwrap_or(Money::ZERO);
     let repair_profit = repair_revenue - repair_cogs;
 
     let net_profit = accrual_revenue - cogs - expenses;
 
+    let discounts_given = calculate_discounts_given(&conn, &start_iso, &end_iso);
+
     Ok(DashboardStats {
         total_revenue,
         net_cash,
@@ -615,5 +644,6 @@ pub fn get_dashboard_stats_by_range(start_date: String, end_date: String) -> Res
         out_of_stock,
         revenue_change,
         repair_profit,
+        discounts_given,
     })
 }
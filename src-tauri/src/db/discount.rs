@@ -0,0 +1,154 @@
+use crate::db::models::{Discount, TransactionItem};
+use crate::db::money::Money;
+use crate::db::tax::compute_item_tax;
+use crate::error::AppError;
+
+/// What `discount` takes off `base`, the amount it applies to. A discount must say why
+/// it was given and by whom, and can't take off more than there is.
+pub(crate) fn discount_amount(discount: Option<&Discount>, base: Money) -> Result<Money, AppError> {
+    let discount = match discount {
+        Some(d) => d,
+        None => return Ok(Money::ZERO),
+    };
+    if discount.reason.trim().is_empty() {
+        return Err(AppError::Validation("A discount needs a reason".to_string()));
+    }
+    if discount.applied_by.trim().is_empty() {
+        return Err(AppError::Validation("A discount needs the cashier who gave it".to_string()));
+    }
+    if !discount.value.is_finite() || discount.value < 0.0 {
+        return Err(AppError::Validation("Discount must be 0 or more".to_string()));
+    }
+
+    let amount = match discount.kind.as_str() {
+        "Percent" => {
+            if discount.value > 100.0 {
+                return Err(AppError::Validation("A discount can't be more than 100%".to_string()));
+            }
+            Money::from_minor((base.minor() as f64 * discount.value / 100.0).round() as i64)
+        }
+        "Fixed" => Money::from_major(discount.value),
+        other => {
+            return Err(AppError::Validation(format!(
+                "Unknown discount type '{}', expected Percent or Fixed",
+                other
+            )))
+        }
+    };
+    if amount > base.abs() {
+        return Err(AppError::Validation(format!(
+            "Discount of {} is more than the {} it applies to",
+            amount, base
+        )));
+    }
+    Ok(amount)
+}
+
+/// Split `amount` over `bases` in proportion to each. Rounding leftovers go to the
+/// largest base so the shares always add up to `amount`.
+pub(crate) fn allocate_discount(amount: Money, bases: &[Money]) -> Vec<Money> {
+    let total: i64 = bases.iter().map(|b| b.minor()).sum();
+    if total <= 0 || amount.is_zero() {
+        return vec![Money::ZERO; bases.len()];
+    }
+
+    let mut shares: Vec<Money> = bases
+        .iter()
+        .map(|b| Money::from_minor((amount.minor() as i128 * b.minor() as i128 / total as i128) as i64))
+        .collect();
+    let leftover = amount - shares.iter().copied().sum::<Money>();
+    if let Some((largest, _)) = bases.iter().enumerate().max_by_key(|(_, b)| **b) {
+        shares[largest] += leftover;
+    }
+    shares
+}
+
+/// Take each line's own discount, spread the document discount over what is left of
+/// the lines, and tax the rest. Rates must already be on the lines. Returns the
+/// document discount.
+pub(crate) fn apply_discounts(
+    discount: Option<&Discount>,
+    items: &mut [TransactionItem],
+) -> Result<Money, AppError> {
+    let mut bases = Vec::with_capacity(items.len());
+    for item in items.iter_mut() {
+        let gross = item.unit_price * item.quantity as i64;
+        item.discount_amount = discount_amount(item.discount.as_ref(), gross)?;
+        bases.push(gross - item.discount_amount);
+    }
+
+    let document_discount = discount_amount(discount, bases.iter().copied().sum::<Money>())?;
+    for (item, share) in items.iter_mut().zip(allocate_discount(document_discount, &bases)) {
+        item.document_discount_amount = share;
+        compute_item_tax(item);
+    }
+    Ok(document_discount)
+}
+
+/// Values for the `discount_type, discount_value, discount_reason, discount_by` columns.
+pub(crate) fn discount_columns(
+    discount: &Option<Discount>,
+) -> (Option<String>, Option<f64>, Option<String>, Option<String>) {
+    match discount {
+        Some(d) => (
+            Some(d.kind.clone()),
+            Some(d.value),
+            Some(d.reason.trim().to_string()),
+            Some(d.applied_by.trim().to_string()),
+        ),
+        None => (None, None, None, None),
+    }
+}
+
+/// Read a discount from the four discount columns starting at `first`.
+pub(crate) fn discount_from_row(row: &rusqlite::Row, first: usize) -> rusqlite::Result<Option<Discount>> {
+    let kind: Option<String> = row.get(first)?;
+    Ok(match kind {
+        Some(kind) => Some(Discount {
+            kind,
+            value: row.get::<_, Option<f64>>(first + 1)?.unwrap_or(0.0),
+            reason: row.get::<_, Option<String>>(first + 2)?.unwrap_or_default(),
+            applied_by: row.get::<_, Option<String>>(first + 3)?.unwrap_or_default(),
+        }),
+        None => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn money(minor: &[i64]) -> Vec<Money> {
+        minor.iter().copied().map(Money::from_minor).collect()
+    }
+
+    #[test]
+    fn shares_add_up_to_the_discount() {
+        let cases: &[(i64, &[i64])] = &[
+            (100, &[1, 1, 1]),
+            (1000, &[333, 333, 334]),
+            (999, &[1999, 250, 4, 7]),
+            (1, &[5000, 5000]),
+            (12_345, &[1, 2, 3, 5, 8, 13, 21]),
+        ];
+        for &(amount, bases) in cases {
+            let shares = allocate_discount(Money::from_minor(amount), &money(bases));
+            assert_eq!(shares.len(), bases.len());
+            assert_eq!(shares.iter().copied().sum::<Money>(), Money::from_minor(amount));
+        }
+    }
+
+    #[test]
+    fn the_rounding_leftover_goes_to_the_largest_line() {
+        // 30.3, 50.5 and 20.2 round down to 100, the missing 1 lands on the 500 line
+        let shares = allocate_discount(Money::from_minor(101), &money(&[300, 500, 200]));
+        assert_eq!(shares, money(&[30, 51, 20]));
+    }
+
+    #[test]
+    fn nothing_to_split_over_gives_no_shares() {
+        let shares = allocate_discount(Money::from_minor(500), &money(&[0, 0, 0]));
+        assert_eq!(shares, money(&[0, 0, 0]));
+        assert!(allocate_discount(Money::from_minor(500), &[]).is_empty());
+    }
+}
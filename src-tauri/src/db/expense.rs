@@ -1,12 +1,15 @@
-use crate::db;
+use crate::db::DbPool;
+use crate::db::currency::tendered_amount;
 use crate::db::models::Expense;
 use rusqlite::{params, Result};
 use uuid::Uuid;
 use chrono::Utc;
+use tauri::State;
+use crate::error::AppError;
 
 #[tauri::command]
-pub fn add_expense(mut expense: Expense) -> Result<Expense, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn add_expense(pool: State<'_, DbPool>, mut expense: Expense) -> Result<Expense, AppError> {
+    let conn = pool.get()?;
 
     if expense.id.is_empty() {
         expense.id = Uuid::new_v4().to_string();
@@ -15,9 +18,21 @@ pub fn add_expense(mut expense: Expense) -> Result<Expense, String> {
         expense.date = Utc::now().to_rfc3339();
     }
 
+    let tendered = tendered_amount(
+        &conn,
+        expense.currency_code.as_deref(),
+        expense.original_amount,
+        expense.amount,
+        &expense.date,
+    )?;
+    expense.amount = tendered.amount;
+    expense.currency_code = Some(tendered.currency_code);
+    expense.original_amount = Some(tendered.original_amount);
+    expense.exchange_rate = Some(tendered.exchange_rate);
+
     conn.execute(
-        "INSERT INTO expenses (id, amount, reason, date, session_id, category, created_by)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        "INSERT INTO expenses (id, amount, reason, date, session_id, category, created_by, currency_code, original_amount, exchange_rate)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
         params![
             expense.id,
             expense.amount,
@@ -26,24 +41,25 @@ pub fn add_expense(mut expense: Expense) -> Result<Expense, String> {
             expense.session_id,
             expense.category,
             expense.created_by,
+            expense.currency_code,
+            expense.original_amount,
+            expense.exchange_rate,
         ],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
 
     Ok(expense)
 }
 
 #[tauri::command]
-pub fn get_today_expenses() -> Result<Vec<Expense>, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn get_today_expenses(pool: State<'_, DbPool>) -> Result<Vec<Expense>, AppError> {
+    let conn = pool.get()?;
     
     // Get expenses from the last 24 hours or linked to current open session
     // For simplicity, let's get expenses for today's date
     let today = Utc::now().format("%Y-%m-%d").to_string();
     
     let mut stmt = conn
-        .prepare("SELECT id, amount, reason, date, session_id, category, created_by FROM expenses WHERE date LIKE ?1 ORDER BY date DESC")
-        .map_err(|e| e.to_string())?;
+        .prepare_cached("SELECT id, amount, reason, date, session_id, category, created_by, currency_code, original_amount, exchange_rate FROM expenses WHERE date LIKE ?1 ORDER BY date DESC")?;
 
     let expenses = stmt
         .query_map(params![format!("{}%", today)], |row| {
@@ -55,9 +71,11 @@ pub fn get_today_expenses() -> Result<Vec<Expense>, String> {
                 session_id: row.get(4).ok(),
                 category: row.get(5).ok(),
                 created_by: row.get(6).ok(),
+                currency_code: row.get(7).ok(),
+                original_amount: row.get(8).ok(),
+                exchange_rate: row.get(9).ok(),
             })
-        })
-        .map_err(|e| e.to_string())?
+        })?
         .filter_map(|res| res.ok())
         .collect();
 
@@ -65,12 +83,11 @@ pub fn get_today_expenses() -> Result<Vec<Expense>, String> {
 }
 
 #[tauri::command]
-pub fn get_expenses_by_session(session_id: String) -> Result<Vec<Expense>, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn get_expenses_by_session(pool: State<'_, DbPool>, session_id: String) -> Result<Vec<Expense>, AppError> {
+    let conn = pool.get()?;
     
     let mut stmt = conn
-        .prepare("SELECT id, amount, reason, date, session_id, category, created_by FROM expenses WHERE session_id = ?1 ORDER BY date DESC")
-        .map_err(|e| e.to_string())?;
+        .prepare_cached("SELECT id, amount, reason, date, session_id, category, created_by, currency_code, original_amount, exchange_rate FROM expenses WHERE session_id = ?1 ORDER BY date DESC")?;
 
     let expenses = stmt
         .query_map(params![session_id], |row| {
@@ -82,9 +99,11 @@ pub fn get_expenses_by_session(session_id: String) -> Result<Vec<Expense>, Strin
                 session_id: row.get(4).ok(),
                 category: row.get(5).ok(),
                 created_by: row.get(6).ok(),
+                currency_code: row.get(7).ok(),
+                original_amount: row.get(8).ok(),
+                exchange_rate: row.get(9).ok(),
             })
-        })
-        .map_err(|e| e.to_string())?
+        })?
         .filter_map(|res| res.ok())
         .collect();
 
@@ -0,0 +1,317 @@
+use crate::db::costing;
+use crate::db::inventory;
+use crate::db::location;
+use crate::db::models::{GoodsReceipt, GoodsReceiptLine};
+use crate::db::money::Money;
+use crate::db::order;
+use crate::db::price_list;
+use crate::db::shopping_list;
+use crate::db::DbPool;
+use crate::error::AppError;
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use tauri::State;
+use uuid::Uuid;
+
+// An order line is outstanding for `quantity - received_quantity - cancelled_quantity`
+// units. Each goods receipt posts stock and a cost layer for what arrived only; the
+// order is `partially_received` until nothing is outstanding and then completes,
+// which is when the supplier's balance is charged.
+
+/// Generate the next receipt number (e.g., GR-2025-001)
+fn generate_receipt_number(conn: &Connection) -> Result<String, AppError> {
+    let year = Utc::now().format("%Y").to_string();
+    let last: Option<String> = conn
+        .query_row(
+            "SELECT receipt_number FROM goods_receipts WHERE receipt_number LIKE ?1 ORDER BY receipt_number DESC LIMIT 1",
+            params![format!("GR-{}-%", year)],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let next = last
+        .and_then(|number| number.rsplit('-').next().and_then(|n| n.parse::<i64>().ok()))
+        .unwrap_or(0)
+        + 1;
+    Ok(format!("GR-{}-{:03}", year, next))
+}
+
+/// Lines of an order that still have units to come, each for everything outstanding.
+pub(crate) fn outstanding_lines(conn: &Connection, order_id: &str) -> Result<Vec<GoodsReceiptLine>, AppError> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT id, item_id, item_name, quantity - received_quantity - cancelled_quantity, unit_price
+         FROM order_items
+         WHERE order_id = ?1 AND quantity - received_quantity - cancelled_quantity > 0",
+    )?;
+    let lines = stmt
+        .query_map(params![order_id], |row| {
+            Ok(GoodsReceiptLine {
+                id: String::new(),
+                order_item_id: row.get(0)?,
+                item_id: row.get(1)?,
+                item_name: row.get(2)?,
+                quantity: row.get(3)?,
+                unit_cost: row.get(4)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(lines)
+}
+
+/// Record a delivery against an order inside the caller's SQL transaction: stock and
+/// cost are posted for the received quantities, and the order completes once nothing
+/// is outstanding.
+pub(crate) fn post_receipt(
+    conn: &Connection,
+    order_id: &str,
+    lines: &[GoodsReceiptLine],
+    received_by: Option<String>,
+    notes: Option<String>,
+) -> Result<GoodsReceipt, AppError> {
+    let (order_number, status, supplier_id): (String, String, String) = conn
+        .query_row(
+            "SELECT order_number, status, supplier_id FROM orders WHERE id = ?1",
+            params![order_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound(format!("Order {} not found", order_id)))?;
+    if status == "completed" {
+        return Err(AppError::Conflict(format!("Order {} is already completed", order_number)));
+    }
+    if lines.is_empty() {
+        return Err(AppError::Validation("Nothing was received".to_string()));
+    }
+
+    let location_id = location::document_location(conn, "orders", order_id)?;
+    let mut receipt = GoodsReceipt {
+        id: Uuid::new_v4().to_string(),
+        receipt_number: generate_receipt_number(conn)?,
+        order_id: order_id.to_string(),
+        location_id: location_id.clone(),
+        received_by,
+        notes,
+        created_at: Utc::now().to_rfc3339(),
+        lines: Vec::new(),
+    };
+    conn.execute(
+        "INSERT INTO goods_receipts (id, receipt_number, order_id, location_id, received_by, notes, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            receipt.id,
+            receipt.receipt_number,
+            receipt.order_id,
+            receipt.location_id,
+            receipt.received_by,
+            receipt.notes,
+            receipt.created_at,
+        ],
+    )?;
+
+    let mut units = 0i64;
+    for line in lines {
+        if line.quantity <= 0 {
+            return Err(AppError::Validation("Received quantities must be positive".to_string()));
+        }
+        let (item_id, item_name, outstanding, unit_cost): (Option<String>, String, i32, Money) = conn
+            .query_row(
+                "SELECT item_id, item_name, quantity - received_quantity - cancelled_quantity, unit_price
+                 FROM order_items WHERE id = ?1 AND order_id = ?2",
+                params![line.order_item_id, order_id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .optional()?
+            .ok_or_else(|| {
+                AppError::NotFound(format!("Line {} is not on order {}", line.order_item_id, order_number))
+            })?;
+        if line.quantity > outstanding {
+            return Err(AppError::Validation(format!(
+                "Only {} of {} are outstanding on order {}",
+                outstanding.max(0),
+                item_name,
+                order_number
+            )));
+        }
+
+        let received = GoodsReceiptLine {
+            id: Uuid::new_v4().to_string(),
+            order_item_id: line.order_item_id.clone(),
+            item_id,
+            item_name,
+            quantity: line.quantity,
+            unit_cost,
+        };
+        conn.execute(
+            "INSERT INTO goods_receipt_lines (id, receipt_id, order_item_id, item_id, item_name, quantity, unit_cost)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                received.id,
+                receipt.id,
+                received.order_item_id,
+                received.item_id,
+                received.item_name,
+                received.quantity,
+                received.unit_cost,
+            ],
+        )?;
+        conn.execute(
+            "UPDATE order_items SET received_quantity = received_quantity + ?2 WHERE id = ?1",
+            params![received.order_item_id, received.quantity],
+        )?;
+
+        if let Some(item_id) = &received.item_id {
+            let quantity = received.quantity as i64;
+            inventory::apply_stock_change(conn, item_id, location_id.as_deref(), quantity, false)?;
+            inventory::log_stock_event(
+                conn,
+                item_id,
+                location_id.as_deref(),
+                "Purchased",
+                quantity,
+                &format!("Received on {} for order {}", receipt.receipt_number, order_number),
+                order_id,
+            )?;
+            // Each delivery is a cost layer at the price paid
+            costing::receive_stock(conn, item_id, quantity, unit_cost, "Order", order_id)?;
+            price_list::record_purchase(conn, &supplier_id, item_id, unit_cost)?;
+        }
+        units += received.quantity as i64;
+        receipt.lines.push(received);
+    }
+
+    order::log_order_event(
+        conn,
+        order_id,
+        "goods_received",
+        &format!("{} units received on {}", units, receipt.receipt_number),
+        receipt.received_by.clone(),
+    )?;
+
+    if outstanding_lines(conn, order_id)?.is_empty() {
+        order::mark_order_completed(conn, order_id)?;
+    } else {
+        conn.execute(
+            "UPDATE orders SET status = 'partially_received', updated_at = ?2 WHERE id = ?1",
+            params![order_id, Utc::now().to_rfc3339()],
+        )?;
+    }
+
+    Ok(receipt)
+}
+
+/// Record a delivery of some or all of an order's outstanding quantities.
+#[tauri::command]
+pub fn receive_order_goods(
+    pool: State<'_, DbPool>,
+    order_id: String,
+    lines: Vec<GoodsReceiptLine>,
+    received_by: Option<String>,
+    notes: Option<String>,
+) -> Result<GoodsReceipt, AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    let receipt = post_receipt(&tx, &order_id, &lines, received_by, notes)?;
+    tx.commit()?;
+    Ok(receipt)
+}
+
+/// Get the goods receipts of an order, oldest first.
+#[tauri::command]
+pub fn get_goods_receipts(pool: State<'_, DbPool>, order_id: String) -> Result<Vec<GoodsReceipt>, AppError> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare_cached(
+        "SELECT id, receipt_number, order_id, location_id, received_by, notes, created_at
+         FROM goods_receipts WHERE order_id = ?1 ORDER BY created_at, receipt_number",
+    )?;
+    let mut receipts = stmt
+        .query_map(params![order_id], |row| {
+            Ok(GoodsReceipt {
+                id: row.get(0)?,
+                receipt_number: row.get(1)?,
+                order_id: row.get(2)?,
+                location_id: row.get(3)?,
+                received_by: row.get(4)?,
+                notes: row.get(5)?,
+                created_at: row.get(6)?,
+                lines: Vec::new(),
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut lines_stmt = conn.prepare_cached(
+        "SELECT id, order_item_id, item_id, item_name, quantity, unit_cost
+         FROM goods_receipt_lines WHERE receipt_id = ?1 ORDER BY rowid",
+    )?;
+    for receipt in &mut receipts {
+        receipt.lines = lines_stmt
+            .query_map(params![receipt.id], |row| {
+                Ok(GoodsReceiptLine {
+                    id: row.get(0)?,
+                    order_item_id: row.get(1)?,
+                    item_id: row.get(2)?,
+                    item_name: row.get(3)?,
+                    quantity: row.get(4)?,
+                    unit_cost: row.get(5)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+    }
+    Ok(receipts)
+}
+
+/// Stop waiting for the rest of a partially received order. Outstanding quantities
+/// are cancelled, line totals drop to what arrived and the order completes, charging
+/// the supplier for the received goods only.
+#[tauri::command]
+pub fn cancel_order_remainder(
+    pool: State<'_, DbPool>,
+    order_id: String,
+    reason: Option<String>,
+    changed_by: Option<String>,
+) -> Result<(), AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+
+    let (order_number, status): (String, String) = tx
+        .query_row(
+            "SELECT order_number, status FROM orders WHERE id = ?1",
+            params![order_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound(format!("Order {} not found", order_id)))?;
+    if status != "partially_received" {
+        return Err(AppError::Conflict(format!(
+            "Order {} has no partial delivery; only the remainder of a partially received order can be cancelled",
+            order_number
+        )));
+    }
+
+    let outstanding = outstanding_lines(&tx, &order_id)?;
+    let units: i64 = outstanding.iter().map(|line| line.quantity as i64).sum();
+    tx.execute(
+        "UPDATE order_items SET cancelled_quantity = quantity - received_quantity,
+            total_price = unit_price * received_quantity
+         WHERE order_id = ?1",
+        params![order_id],
+    )?;
+
+    // Shopping list items nothing arrived for are back to be ordered again
+    let unreceived: Vec<String> = tx
+        .prepare_cached("SELECT id FROM order_items WHERE order_id = ?1 AND received_quantity = 0")?
+        .query_map(params![order_id], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    for order_item_id in unreceived {
+        shopping_list::release_order_item(&tx, &order_item_id)?;
+    }
+
+    order::recalculate_order_total(&tx, &order_id)?;
+    let details = match reason.as_deref().map(str::trim).filter(|reason| !reason.is_empty()) {
+        Some(reason) => format!("{} outstanding units cancelled: {}", units, reason),
+        None => format!("{} outstanding units cancelled", units),
+    };
+    order::log_order_event(&tx, &order_id, "remainder_cancelled", &details, changed_by)?;
+    order::mark_order_completed(&tx, &order_id)?;
+
+    tx.commit()?;
+    Ok(())
+}
@@ -1,7 +1,15 @@
 // Inventory table logic will go here.
-use crate::db;
-use rusqlite::{params, Result};
+use crate::db::{fts_prefix_query, DbPool};
+use crate::db::costing;
+use crate::db::location;
+use crate::db::models::{BundleComponent, ItemAttribute};
+use crate::db::money::Money;
+use crate::db::product;
+use crate::db::serial;
+use rusqlite::{params, Connection, OptionalExtension, Result};
 use serde::{Deserialize, Serialize};
+use tauri::State;
+use crate::error::AppError;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct InventoryItem {
@@ -9,17 +17,111 @@ pub struct InventoryItem {
     pub item_name: String,
     pub phone_brand: String,
     pub item_type: String,
-    pub buying_price: f64,
-    pub selling_price: f64,
+    pub buying_price: Money,
+    pub selling_price: Money,
     pub quantity_in_stock: Option<i64>,
     pub low_stock_threshold: Option<i64>,
     pub supplier_info: Option<String>,
     pub barcode: Option<String>,
+    #[serde(default)]
+    pub tax_rate_id: Option<String>, // rate applied when the item is sold or bought
+    #[serde(default)]
+    pub average_cost: Option<Money>, // moving average of what stock on hand cost, kept by costing
+    #[serde(default)]
+    pub reserved_quantity: i64, // held for draft sales and open repairs
+    #[serde(default)]
+    pub available_quantity: Option<i64>, // quantity_in_stock less reserved_quantity
+    #[serde(default)]
+    pub location_quantity: Option<i64>, // stock at the location asked about, if any
+    #[serde(default)]
+    pub serial_type: Option<String>, // "IMEI" or "Serial" when every unit is tracked, None otherwise
+    #[serde(default)]
+    pub product_id: Option<String>, // the product this item is a variant of
+    #[serde(default)]
+    pub variant_label: Option<String>, // attribute values joined, e.g. "iPhone 13 / Black / OEM"; derived
+    #[serde(default)]
+    pub attributes: Vec<ItemAttribute>,
+    #[serde(default)]
+    pub is_bundle: bool, // sold as a kit of `components`, with no stock of its own
+    #[serde(default)]
+    pub components: Vec<BundleComponent>,
+    #[serde(default)]
+    pub preferred_supplier_id: Option<String>, // who reorders of this item go to
+}
+
+const ITEM_COLUMNS: &str = "id, item_name, phone_brand, item_type, buying_price, selling_price, quantity_in_stock, low_stock_threshold, supplier_info, barcode, tax_rate_id, average_cost, reserved_quantity, serial_type, product_id, variant_label, is_bundle, preferred_supplier_id";
+
+/// Map a row selected with `ITEM_COLUMNS`.
+fn item_from_row(row: &rusqlite::Row) -> rusqlite::Result<InventoryItem> {
+    let quantity_in_stock: Option<i64> = row.get(6).ok();
+    let reserved_quantity: i64 = row.get(12)?;
+    Ok(InventoryItem {
+        id: row.get(0)?,
+        item_name: row.get(1)?,
+        phone_brand: row.get(2)?,
+        item_type: row.get(3)?,
+        buying_price: row.get(4)?,
+        selling_price: row.get(5)?,
+        quantity_in_stock,
+        low_stock_threshold: row.get(7).ok(),
+        supplier_info: row.get(8).ok(),
+        barcode: row.get(9).ok(),
+        tax_rate_id: row.get(10)?,
+        average_cost: row.get(11)?,
+        reserved_quantity,
+        available_quantity: quantity_in_stock.map(|qty| qty - reserved_quantity),
+        location_quantity: None,
+        serial_type: row.get(13)?,
+        product_id: row.get(14)?,
+        variant_label: row.get(15)?,
+        attributes: Vec::new(),
+        is_bundle: row.get(16)?,
+        components: Vec::new(),
+        preferred_supplier_id: row.get(17)?,
+    })
+}
+
+/// Fill in the attributes and bundle components of items read with `item_from_row`.
+fn with_details(conn: &Connection, mut items: Vec<InventoryItem>) -> Result<Vec<InventoryItem>, AppError> {
+    for item in items.iter_mut() {
+        product::fill_item_details(conn, item)?;
+    }
+    Ok(items)
+}
+
+/// Items matching a `WHERE` clause, with their details.
+pub(crate) fn items_where<P: rusqlite::Params>(conn: &Connection, clause: &str, params: P) -> Result<Vec<InventoryItem>, AppError> {
+    let mut stmt = conn.prepare_cached(&format!("SELECT {} FROM inventory_items WHERE {}", ITEM_COLUMNS, clause))?;
+    let items = stmt
+        .query_map(params, item_from_row)?
+        .filter_map(|res| res.ok())
+        .collect();
+    with_details(conn, items)
+}
+
+/// Check a new or edited item's serial type, attributes and components, and set its
+/// variant label. A bundle's stock is its components', so it keeps none itself.
+fn check_item(conn: &Connection, item: &mut InventoryItem) -> Result<(), AppError> {
+    serial::check_serial_type(item.serial_type.as_deref())?;
+    product::check_bundle(conn, item)?;
+    if item.is_bundle {
+        item.quantity_in_stock = None;
+    }
+    item.product_id = item.product_id.take().filter(|id| !id.is_empty());
+    item.preferred_supplier_id = item.preferred_supplier_id.take().filter(|id| !id.is_empty());
+    item.variant_label = product::variant_label(conn, item.product_id.as_deref(), &mut item.attributes)?;
+    product::check_variant_unique(conn, &item.id, item.product_id.as_deref(), item.variant_label.as_deref())
+}
+
+/// Store an item's attributes and components once its row is written.
+fn save_item_details(conn: &Connection, item: &InventoryItem) -> Result<(), AppError> {
+    product::save_attributes(conn, &item.id, &item.attributes)?;
+    product::save_components(conn, &item.id, &item.components)
 }
 
 // #[tauri::command]
-// pub fn init_inventory_table() -> Result<(), String> {
-//     let conn = db::get_connection().map_err(|e| e.to_string())?;
+// pub fn init_inventory_table() -> Result<(), AppError> {
+//     let conn = pool.get()?;
 //     conn.execute(
 //         "CREATE TABLE IF NOT EXISTS inventory_items (
 //             id TEXT PRIMARY KEY,
@@ -34,25 +136,26 @@ pub struct InventoryItem {
 //             created_at DATETIME DEFAULT CURRENT_TIMESTAMP
 //         )",
 //         [],
-//     )
-//     .map_err(|e| e.to_string())?;
+//     )?;
 
 //     Ok(())
 // }
 
 // Add more CRUD functions like insert_item(), get_items() etc.
 #[tauri::command]
-pub fn insert_item(item: InventoryItem) -> Result<(), String> {
+pub fn insert_item(pool: State<'_, DbPool>, mut item: InventoryItem) -> Result<(), AppError> {
     println!("Attempting to insert item: {:?}", item);
 
     // Make sure this doesn't panic
-    let conn = match crate::db::get_connection() {
+    let mut conn = match pool.get() {
         Ok(conn) => conn,
-        Err(e) => return Err(format!("Database connection failed: {}", e)),
+        Err(e) => return Err(AppError::Database(format!("Database connection failed: {}", e))),
     };
+    let tx = conn.transaction()?;
+    check_item(&tx, &mut item)?;
 
-    conn.execute(
-        "INSERT INTO inventory_items (id, item_name, phone_brand, item_type, buying_price, selling_price, quantity_in_stock, low_stock_threshold, supplier_info, barcode) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+    tx.execute(
+        "INSERT INTO inventory_items (id, item_name, phone_brand, item_type, buying_price, selling_price, quantity_in_stock, low_stock_threshold, supplier_info, barcode, tax_rate_id, serial_type, product_id, variant_label, is_bundle, preferred_supplier_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
         params![
             item.id,
             item.item_name,
@@ -63,65 +166,50 @@ pub fn insert_item(item: InventoryItem) -> Result<(), String> {
             item.quantity_in_stock,
             item.low_stock_threshold,
             item.supplier_info,
-            item.barcode
+            item.barcode,
+            item.tax_rate_id,
+            item.serial_type,
+            item.product_id,
+            item.variant_label,
+            item.is_bundle,
+            item.preferred_supplier_id
         ],
-    ).map_err(|e| e.to_string())?;
+    )?;
+    save_item_details(&tx, &item)?;
+
+    // Stock the item starts with is valued at its buying price
+    let opening = item.quantity_in_stock.unwrap_or(0);
+    costing::receive_stock(&tx, &item.id, opening, item.buying_price, "Opening", &item.id)?;
+    tx.commit()?;
     Ok(())
 }
 
 #[tauri::command]
-pub fn get_items() -> Result<Vec<InventoryItem>, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
-    let mut stmt = conn.prepare("SELECT id, item_name, phone_brand, item_type, buying_price, selling_price, quantity_in_stock, low_stock_threshold, supplier_info, barcode FROM inventory_items").map_err(|e| e.to_string())?;
-    let items = stmt
-        .query_map([], |row| {
-            Ok(InventoryItem {
-                id: row.get(0)?,
-                item_name: row.get(1)?,
-                phone_brand: row.get(2)?,
-                item_type: row.get(3)?,
-                buying_price: row.get(4)?,
-                selling_price: row.get(5)?,
-                quantity_in_stock: row.get(6).ok(),
-                low_stock_threshold: row.get(7).ok(),
-                supplier_info: row.get(8).ok(),
-                barcode: row.get(9).ok(),
-            })
-        })
-        .map_err(|e| e.to_string())?
-        .filter_map(|res| res.ok())
-        .collect();
-    Ok(items)
+pub fn get_items(pool: State<'_, DbPool>) -> Result<Vec<InventoryItem>, AppError> {
+    let conn = pool.get()?;
+    items_where(&conn, "1 = 1", [])
 }
 
 #[tauri::command]
-pub fn get_item_by_id(item_id: String) -> Result<Option<InventoryItem>, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
-    let mut stmt = conn.prepare("SELECT id, item_name, phone_brand, item_type, buying_price, selling_price, quantity_in_stock, low_stock_threshold, supplier_info, barcode FROM inventory_items WHERE id = ?1").map_err(|e| e.to_string())?;
-    let mut rows = stmt.query(params![item_id]).map_err(|e| e.to_string())?;
-    if let Some(row) = rows.next().map_err(|e| e.to_string())? {
-        Ok(Some(InventoryItem {
-            id: row.get(0).map_err(|e| e.to_string())?,
-            item_name: row.get(1).map_err(|e| e.to_string())?,
-            phone_brand: row.get(2).map_err(|e| e.to_string())?,
-            item_type: row.get(3).map_err(|e| e.to_string())?,
-            buying_price: row.get(4).map_err(|e| e.to_string())?,
-            selling_price: row.get(5).map_err(|e| e.to_string())?,
-            quantity_in_stock: row.get(6).ok(),
-            low_stock_threshold: row.get(7).ok(),
-            supplier_info: row.get(8).ok(),
-            barcode: row.get(9).ok(),
-        }))
-    } else {
-        Ok(None)
-    }
+pub fn get_item_by_id(pool: State<'_, DbPool>, item_id: String) -> Result<Option<InventoryItem>, AppError> {
+    let conn = pool.get()?;
+    Ok(items_where(&conn, "id = ?1", params![item_id])?.pop())
 }
 
 #[tauri::command]
-pub fn update_item(item: InventoryItem) -> Result<(), String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
-    conn.execute(
-        "UPDATE inventory_items SET item_name = ?2, phone_brand = ?3, item_type = ?4, buying_price = ?5, selling_price = ?6, quantity_in_stock = ?7, low_stock_threshold = ?8, supplier_info = ?9, barcode = ?10 WHERE id = ?1",
+pub fn update_item(pool: State<'_, DbPool>, mut item: InventoryItem) -> Result<(), AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    check_item(&tx, &mut item)?;
+    let old_quantity = stock_on_hand(&tx, &item.id)?;
+    if item.is_bundle && old_quantity != 0 {
+        return Err(AppError::Conflict(format!(
+            "'{}' has {} units in stock; adjust them to zero before making it a bundle",
+            item.item_name, old_quantity
+        )));
+    }
+    tx.execute(
+        "UPDATE inventory_items SET item_name = ?2, phone_brand = ?3, item_type = ?4, buying_price = ?5, selling_price = ?6, quantity_in_stock = ?7, low_stock_threshold = ?8, supplier_info = ?9, barcode = ?10, tax_rate_id = ?11, serial_type = ?12, product_id = ?13, variant_label = ?14, is_bundle = ?15, preferred_supplier_id = ?16 WHERE id = ?1",
         params![
             item.id,
             item.item_name,
@@ -132,95 +220,173 @@ pub fn update_item(item: InventoryItem) -> Result<(), String> {
             item.quantity_in_stock,
             item.low_stock_threshold,
             item.supplier_info,
-            item.barcode
+            item.barcode,
+            item.tax_rate_id,
+            item.serial_type,
+            item.product_id,
+            item.variant_label,
+            item.is_bundle,
+            item.preferred_supplier_id
         ],
-    ).map_err(|e| e.to_string())?;
+    )?;
+    save_item_details(&tx, &item)?;
+    if !item.is_bundle {
+        costing::adjust_stock(&tx, &item.id, item.quantity_in_stock.unwrap_or(0) - old_quantity)?;
+    }
+    tx.commit()?;
     Ok(())
 }
 
 #[tauri::command]
-pub fn delete_item(item_id: String) -> Result<(), String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn delete_item(pool: State<'_, DbPool>, item_id: String) -> Result<(), AppError> {
+    let conn = pool.get()?;
+    let bundle_name: Option<String> = conn
+        .query_row(
+            "SELECT i.item_name FROM bundle_components b JOIN inventory_items i ON i.id = b.bundle_id
+             WHERE b.component_id = ?1 LIMIT 1",
+            params![item_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(bundle_name) = bundle_name {
+        return Err(AppError::Conflict(format!(
+            "This item is a component of '{}'; remove it from the bundle first",
+            bundle_name
+        )));
+    }
     conn.execute(
         "DELETE FROM inventory_items WHERE id = ?1",
         params![item_id],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
     Ok(())
 }
 
 #[tauri::command]
-pub fn update_item_quantity(item_id: String, new_quantity: i64) -> Result<(), String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn update_item_quantity(pool: State<'_, DbPool>, item_id: String, new_quantity: i64) -> Result<(), AppError> {
+    let conn = pool.get()?;
+    if !product::bundle_components(&conn, &item_id)?.is_empty() {
+        return Err(AppError::Validation(
+            "A bundle's stock is that of its components; adjust those instead".to_string(),
+        ));
+    }
+    let old_quantity = stock_on_hand(&conn, &item_id)?;
     conn.execute(
         "UPDATE inventory_items SET quantity_in_stock = ?2 WHERE id = ?1",
         params![item_id, new_quantity],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
+    costing::adjust_stock(&conn, &item_id, new_quantity - old_quantity)?;
     Ok(())
 }
 
+/// Current `quantity_in_stock` of an item, 0 when unset or missing.
+fn stock_on_hand(conn: &Connection, item_id: &str) -> Result<i64, AppError> {
+    let qty: Option<Option<i64>> = conn
+        .query_row(
+            "SELECT quantity_in_stock FROM inventory_items WHERE id = ?1",
+            params![item_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(qty.flatten().unwrap_or(0))
+}
+
+/// Items at or below their low-stock threshold. With a `location_id` the stock of the
+/// items kept at that location is checked against its own threshold (or the item's) and
+/// returned in `location_quantity`.
 #[tauri::command]
-pub fn get_low_stock_items() -> Result<Vec<InventoryItem>, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
-    let mut stmt = conn.prepare(
-        "SELECT id, item_name, phone_brand, item_type, buying_price, selling_price, quantity_in_stock, low_stock_threshold, supplier_info, barcode 
+pub fn get_low_stock_items(pool: State<'_, DbPool>, location_id: Option<String>) -> Result<Vec<InventoryItem>, AppError> {
+    let conn = pool.get()?;
+    if let Some(location_id) = location_id {
+        let mut stmt = conn.prepare_cached(&format!(
+            "SELECT {}, v.quantity
+             FROM inventory_items
+             JOIN (
+                 SELECT item_id, quantity, low_stock_threshold AS threshold
+                 FROM location_stock_levels WHERE location_id = ?1 AND tracked
+             ) v ON v.item_id = inventory_items.id
+             WHERE v.threshold IS NOT NULL
+             AND NOT inventory_items.is_bundle
+             AND v.quantity <= v.threshold
+             ORDER BY item_name",
+            ITEM_COLUMNS
+        ))?;
+        let items = stmt
+            .query_map(params![location_id], |row| {
+                let mut item = item_from_row(row)?;
+                item.location_quantity = Some(row.get(18)?);
+                Ok(item)
+            })?
+            .filter_map(|res| res.ok())
+            .collect();
+        return with_details(&conn, items);
+    }
+
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT {} 
          FROM inventory_items 
          WHERE quantity_in_stock IS NOT NULL 
          AND low_stock_threshold IS NOT NULL 
-         AND quantity_in_stock <= low_stock_threshold"
-    ).map_err(|e| e.to_string())?;
+         AND quantity_in_stock <= low_stock_threshold
+         AND NOT is_bundle",
+        ITEM_COLUMNS
+    ))?;
 
     let items = stmt
-        .query_map([], |row| {
-            Ok(InventoryItem {
-                id: row.get(0)?,
-                item_name: row.get(1)?,
-                phone_brand: row.get(2)?,
-                item_type: row.get(3)?,
-                buying_price: row.get(4)?,
-                selling_price: row.get(5)?,
-                quantity_in_stock: row.get(6).ok(),
-                low_stock_threshold: row.get(7).ok(),
-                supplier_info: row.get(8).ok(),
-                barcode: row.get(9).ok(),
-            })
-        })
-        .map_err(|e| e.to_string())?
+        .query_map([], item_from_row)?
         .filter_map(|res| res.ok())
         .collect();
-    Ok(items)
+    with_details(&conn, items)
 }
 
+/// Search inventory by name, brand, type, barcode, supplier and variant attributes.
+///
+/// Every word of the query must match the start of a word in one of those fields,
+/// so "ip 13 scr" finds "iPhone 13 Screen" and "case black" its black variant. An
+/// exact barcode match always comes first; the rest is ranked by relevance, name and
+/// brand weighing most.
 #[tauri::command]
-pub fn search_items(query: String) -> Result<Vec<InventoryItem>, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
-    let search_pattern = format!("%{}%", query);
-    let mut stmt = conn.prepare(
-        "SELECT id, item_name, phone_brand, item_type, buying_price, selling_price, quantity_in_stock, low_stock_threshold, supplier_info, barcode 
-         FROM inventory_items 
-         WHERE item_name LIKE ?1 OR phone_brand LIKE ?1 OR item_type LIKE ?1 OR barcode = ?2"
-    ).map_err(|e| e.to_string())?;
+pub fn search_items(pool: State<'_, DbPool>, query: String) -> Result<Vec<InventoryItem>, AppError> {
+    let conn = pool.get()?;
+    let query = query.trim();
 
-    let items = stmt
-        .query_map(params![search_pattern, query], |row| {
-            Ok(InventoryItem {
-                id: row.get(0)?,
-                item_name: row.get(1)?,
-                phone_brand: row.get(2)?,
-                item_type: row.get(3)?,
-                buying_price: row.get(4)?,
-                selling_price: row.get(5)?,
-                quantity_in_stock: row.get(6).ok(),
-                low_stock_threshold: row.get(7).ok(),
-                supplier_info: row.get(8).ok(),
-                barcode: row.get(9).ok(),
-            })
-        })
-        .map_err(|e| e.to_string())?
-        .filter_map(|res| res.ok())
-        .collect();
-    Ok(items)
+    let items = match fts_prefix_query(query) {
+        Some(match_query) => {
+            let mut stmt = conn.prepare_cached(&format!(
+                "SELECT {}
+                 FROM inventory_items i
+                 JOIN (
+                     SELECT id AS item_id, -1e9 AS rank FROM inventory_items WHERE barcode = ?1
+                     UNION ALL
+                     SELECT item_id, bm25(inventory_fts, 0.0, 10.0, 5.0, 2.0, 10.0, 1.0, 5.0) AS rank
+                     FROM inventory_fts WHERE inventory_fts MATCH ?2
+                 ) m ON m.item_id = i.id
+                 GROUP BY i.id
+                 ORDER BY MIN(m.rank), i.item_name",
+                ITEM_COLUMNS
+            ))?;
+            let items = stmt
+                .query_map(params![query, match_query], item_from_row)?
+                .filter_map(|res| res.ok())
+                .collect();
+            items
+        }
+        // No words to search for: list everything, or match a barcode like "--" as is
+        None => {
+            let mut stmt = conn.prepare_cached(&format!(
+                "SELECT {}
+                 FROM inventory_items
+                 WHERE ?1 = '' OR barcode = ?1
+                 ORDER BY item_name",
+                ITEM_COLUMNS
+            ))?;
+            let items = stmt
+                .query_map(params![query], item_from_row)?
+                .filter_map(|res| res.ok())
+                .collect();
+            items
+        }
+    };
+    with_details(&conn, items)
 }
 
 #[derive(Serialize, Deserialize)]
@@ -232,11 +398,13 @@ pub struct InventoryHistoryEvent {
     pub quantity_change: i64,
     pub notes: Option<String>,
     pub related_id: Option<String>,
+    #[serde(default)]
+    pub location_id: Option<String>, // where the stock moved, the default location when None
 }
 
 // #[tauri::command]
-// pub fn init_history_table() -> Result<(), String> {
-//     let conn = db::get_connection().map_err(|e| e.to_string())?;
+// pub fn init_history_table() -> Result<(), AppError> {
+//     let conn = pool.get()?;
 //     conn.execute(
 //         "CREATE TABLE IF NOT EXISTS inventory_history (
 //             id TEXT PRIMARY KEY,
@@ -249,16 +417,15 @@ pub struct InventoryHistoryEvent {
 //             FOREIGN KEY(item_id) REFERENCES inventory_items(id)
 //         )",
 //         [],
-//     )
-//     .map_err(|e| e.to_string())?;
+//     )?;
 //     Ok(())
 // }
 
 #[tauri::command]
-pub fn insert_history_event(event: InventoryHistoryEvent) -> Result<(), String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn insert_history_event(pool: State<'_, DbPool>, event: InventoryHistoryEvent) -> Result<(), AppError> {
+    let conn = pool.get()?;
     conn.execute(
-        "INSERT INTO inventory_history (id, item_id, date, event_type, quantity_change, notes, related_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        "INSERT INTO inventory_history (id, item_id, date, event_type, quantity_change, notes, related_id, location_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
         params![
             event.id,
             event.item_id,
@@ -266,18 +433,19 @@ pub fn insert_history_event(event: InventoryHistoryEvent) -> Result<(), String>
             event.event_type,
             event.quantity_change,
             event.notes,
-            event.related_id
+            event.related_id,
+            event.location_id
         ],
-    ).map_err(|e| e.to_string())?;
+    )?;
     Ok(())
 }
 
 #[tauri::command]
-pub fn get_history_for_item(item_id: String) -> Result<Vec<InventoryHistoryEvent>, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
-    let mut stmt = conn.prepare(
-        "SELECT id, item_id, date, event_type, quantity_change, notes, related_id FROM inventory_history WHERE item_id = ?1 ORDER BY date DESC"
-    ).map_err(|e| e.to_string())?;
+pub fn get_history_for_item(pool: State<'_, DbPool>, item_id: String) -> Result<Vec<InventoryHistoryEvent>, AppError> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare_cached(
+        "SELECT id, item_id, date, event_type, quantity_change, notes, related_id, location_id FROM inventory_history WHERE item_id = ?1 ORDER BY date DESC"
+    )?;
     let events = stmt
         .query_map(params![item_id], |row| {
             Ok(InventoryHistoryEvent {
@@ -288,10 +456,105 @@ pub fn get_history_for_item(item_id: String) -> Result<Vec<InventoryHistoryEvent
                 quantity_change: row.get(4)?,
                 notes: row.get(5).ok(),
                 related_id: row.get(6).ok(),
+                location_id: row.get(7)?,
             })
-        })
-        .map_err(|e| e.to_string())?
+        })?
         .filter_map(|res| res.ok())
         .collect();
     Ok(events)
 }
+
+// ======================
+// STOCK MOVEMENTS
+// ======================
+
+/// Change an item's stock by `qty_change` at a location (the default one when `None`)
+/// inside the caller's SQL transaction. Decreases that would dip into stock reserved for
+/// other drafts and repairs, or take more than the location has, fail with
+/// `InsufficientStock` unless `allow_oversell` is set. Convert the caller's own
+/// reservation first. A bundle's change is made to each of its components.
+pub(crate) fn apply_stock_change(
+    conn: &Connection,
+    item_id: &str,
+    location_id: Option<&str>,
+    qty_change: i64,
+    allow_oversell: bool,
+) -> Result<(), AppError> {
+    let components = product::bundle_components(conn, item_id)?;
+    if !components.is_empty() {
+        for (component_id, per_bundle) in components {
+            apply_stock_change(conn, &component_id, location_id, qty_change * per_bundle, allow_oversell)?;
+        }
+        return Ok(());
+    }
+
+    let (item_name, available): (String, i64) = conn
+        .query_row(
+            "SELECT item_name, COALESCE(quantity_in_stock, 0) - reserved_quantity FROM inventory_items WHERE id = ?1",
+            params![item_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound(format!("Inventory item {} not found", item_id)))?;
+
+    if qty_change < 0 && !allow_oversell && available + qty_change < 0 {
+        return Err(AppError::InsufficientStock {
+            item_id: item_id.to_string(),
+            item_name,
+            requested: -qty_change,
+            available: available.max(0),
+        });
+    }
+
+    location::move_location_stock(conn, item_id, location_id, qty_change, allow_oversell)?;
+    conn.execute(
+        "UPDATE inventory_items SET quantity_in_stock = COALESCE(quantity_in_stock, 0) + ?1 WHERE id = ?2",
+        params![qty_change, item_id],
+    )?;
+    Ok(())
+}
+
+/// Record a stock movement in `inventory_history`, at the default location when
+/// `location_id` is `None`. A bundle's movement is recorded against its components.
+pub(crate) fn log_stock_event(
+    conn: &Connection,
+    item_id: &str,
+    location_id: Option<&str>,
+    event_type: &str,
+    qty_change: i64,
+    notes: &str,
+    related_id: &str,
+) -> Result<(), AppError> {
+    let components = product::bundle_components(conn, item_id)?;
+    if !components.is_empty() {
+        let bundle_name: String = conn.query_row(
+            "SELECT item_name FROM inventory_items WHERE id = ?1",
+            params![item_id],
+            |row| row.get(0),
+        )?;
+        let notes = format!("{} (in {} x {})", notes, qty_change.abs(), bundle_name);
+        for (component_id, per_bundle) in components {
+            log_stock_event(conn, &component_id, location_id, event_type, qty_change * per_bundle, &notes, related_id)?;
+        }
+        return Ok(());
+    }
+    let location_id = match location_id.filter(|id| !id.is_empty()) {
+        Some(id) => id.to_string(),
+        None => location::default_location_id(conn)?,
+    };
+    conn.execute(
+        "INSERT INTO inventory_history (id, item_id, date, event_type, quantity_change, notes, related_id, location_id)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            uuid::Uuid::new_v4().to_string(),
+            item_id,
+            chrono::Utc::now().to_rfc3339(),
+            event_type,
+            qty_change,
+            notes,
+            related_id,
+            location_id,
+        ],
+    )?;
+    Ok(())
+}
@@ -0,0 +1,525 @@
+use crate::db::inventory;
+use crate::db::models::{Location, LocationStockLevel, StockTransfer, StockTransferItem, StockTransferWithItems};
+use crate::db::DbPool;
+use crate::error::AppError;
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use tauri::State;
+use uuid::Uuid;
+
+// Stock at the default location isn't stored: it is `quantity_in_stock` less what is
+// booked to the other locations in `location_stock`, whose row for the default location
+// only carries a threshold. Stock moved without naming a location therefore lands there,
+// and `location_stock_levels` gives the quantity at every location.
+
+const LOCATION_TYPES: [&str; 4] = ["Shop", "Storage", "Bench", "Branch"];
+
+const LOCATION_COLUMNS: &str = "id, name, location_type, is_default, active, notes, created_at";
+
+/// Map a row selected with `LOCATION_COLUMNS`.
+fn location_from_row(row: &rusqlite::Row) -> rusqlite::Result<Location> {
+    Ok(Location {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        location_type: row.get(2)?,
+        is_default: row.get(3)?,
+        active: row.get(4)?,
+        notes: row.get(5)?,
+        created_at: row.get(6)?,
+    })
+}
+
+const LEVEL_COLUMNS: &str = "v.location_id, l.name, i.id, i.item_name, i.phone_brand, i.item_type, i.barcode, v.quantity, v.low_stock_threshold";
+
+/// Map a row selected with `LEVEL_COLUMNS` from `location_stock_levels v` joined to the
+/// location `l` and item `i`.
+fn level_from_row(row: &rusqlite::Row) -> rusqlite::Result<LocationStockLevel> {
+    Ok(LocationStockLevel {
+        location_id: row.get(0)?,
+        location_name: row.get(1)?,
+        item_id: row.get(2)?,
+        item_name: row.get(3)?,
+        phone_brand: row.get(4)?,
+        item_type: row.get(5)?,
+        barcode: row.get(6)?,
+        quantity: row.get(7)?,
+        low_stock_threshold: row.get(8)?,
+    })
+}
+
+fn get_location_internal(conn: &Connection, id: &str) -> Result<Location, AppError> {
+    conn.query_row(
+        &format!("SELECT {} FROM locations WHERE id = ?1", LOCATION_COLUMNS),
+        params![id],
+        location_from_row,
+    )
+    .optional()?
+    .ok_or_else(|| AppError::NotFound(format!("Location {} not found", id)))
+}
+
+pub(crate) fn default_location_id(conn: &Connection) -> Result<String, AppError> {
+    conn.query_row("SELECT id FROM locations WHERE is_default = 1", [], |row| row.get(0))
+        .optional()?
+        .ok_or_else(|| AppError::NotFound("No default location is set".to_string()))
+}
+
+/// The location a new document moves stock at: the one picked, which must be active, or
+/// the default location when none is.
+pub(crate) fn resolve_location(conn: &Connection, location_id: Option<&str>) -> Result<String, AppError> {
+    match location_id.filter(|id| !id.is_empty()) {
+        Some(id) => {
+            let location = get_location_internal(conn, id)?;
+            if !location.active {
+                return Err(AppError::Validation(format!(
+                    "Location '{}' is inactive",
+                    location.name
+                )));
+            }
+            Ok(location.id)
+        }
+        None => default_location_id(conn),
+    }
+}
+
+/// Location stored on a document row, e.g. `document_location(conn, "orders", id)`.
+pub(crate) fn document_location(conn: &Connection, table: &str, id: &str) -> Result<Option<String>, AppError> {
+    let location_id: Option<Option<String>> = conn
+        .query_row(
+            &format!("SELECT location_id FROM {} WHERE id = ?1", table),
+            params![id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(location_id.flatten())
+}
+
+/// Units of an item on hand at a location.
+pub(crate) fn stock_at(conn: &Connection, location_id: &str, item_id: &str) -> Result<i64, AppError> {
+    let qty: Option<i64> = conn
+        .query_row(
+            "SELECT quantity FROM location_stock_levels WHERE location_id = ?1 AND item_id = ?2",
+            params![location_id, item_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(qty.unwrap_or(0))
+}
+
+/// Book `qty_change` units of an item in or out of a location (the default one when
+/// `None`). Leaves `quantity_in_stock` to `inventory::apply_stock_change`, which calls
+/// this. Taking out more than the location has fails unless `allow_oversell` is set.
+pub(crate) fn move_location_stock(
+    conn: &Connection,
+    item_id: &str,
+    location_id: Option<&str>,
+    qty_change: i64,
+    allow_oversell: bool,
+) -> Result<(), AppError> {
+    let default_id = default_location_id(conn)?;
+    let location_id = location_id.filter(|id| !id.is_empty()).unwrap_or(&default_id);
+
+    if qty_change < 0 && !allow_oversell {
+        let available = stock_at(conn, location_id, item_id)?;
+        if available + qty_change < 0 {
+            let item_name: String = conn.query_row(
+                "SELECT item_name FROM inventory_items WHERE id = ?1",
+                params![item_id],
+                |row| row.get(0),
+            )?;
+            let location_name: String = conn.query_row(
+                "SELECT name FROM locations WHERE id = ?1",
+                params![location_id],
+                |row| row.get(0),
+            )?;
+            return Err(AppError::InsufficientStock {
+                item_id: item_id.to_string(),
+                item_name: format!("{} at {}", item_name, location_name),
+                requested: -qty_change,
+                available: available.max(0),
+            });
+        }
+    }
+
+    if location_id != default_id {
+        conn.execute(
+            "INSERT INTO location_stock (location_id, item_id, quantity) VALUES (?1, ?2, ?3)
+             ON CONFLICT(location_id, item_id) DO UPDATE SET quantity = quantity + ?3",
+            params![location_id, item_id, qty_change],
+        )?;
+    }
+    Ok(())
+}
+
+/// Generate a unique transfer number (e.g., TRF-2025-001)
+fn generate_transfer_number(conn: &Connection) -> Result<String, AppError> {
+    let year = Utc::now().format("%Y").to_string();
+    let last: Option<String> = conn
+        .query_row(
+            "SELECT transfer_number FROM stock_transfers WHERE transfer_number LIKE ?1 ORDER BY transfer_number DESC LIMIT 1",
+            params![format!("TRF-{}-%", year)],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let next_number = last
+        .and_then(|number| number.split('-').next_back().and_then(|n| n.parse::<i32>().ok()))
+        .map_or(1, |n| n + 1);
+    Ok(format!("TRF-{}-{:03}", year, next_number))
+}
+
+fn get_transfer_internal(conn: &Connection, id: &str) -> Result<StockTransferWithItems, AppError> {
+    let (transfer, from_location_name, to_location_name) = conn
+        .query_row(
+            "SELECT t.id, t.transfer_number, t.from_location_id, t.to_location_id, t.notes, t.created_at, t.created_by, f.name, d.name
+             FROM stock_transfers t
+             JOIN locations f ON f.id = t.from_location_id
+             JOIN locations d ON d.id = t.to_location_id
+             WHERE t.id = ?1",
+            params![id],
+            |row| {
+                Ok((
+                    StockTransfer {
+                        id: row.get(0)?,
+                        transfer_number: row.get(1)?,
+                        from_location_id: row.get(2)?,
+                        to_location_id: row.get(3)?,
+                        notes: row.get(4)?,
+                        created_at: row.get(5)?,
+                        created_by: row.get(6)?,
+                    },
+                    row.get(7)?,
+                    row.get(8)?,
+                ))
+            },
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound(format!("Stock transfer {} not found", id)))?;
+
+    let mut stmt = conn.prepare_cached(
+        "SELECT id, transfer_id, item_id, item_name, quantity FROM stock_transfer_items WHERE transfer_id = ?1 ORDER BY item_name",
+    )?;
+    let items = stmt
+        .query_map(params![id], |row| {
+            Ok(StockTransferItem {
+                id: row.get(0)?,
+                transfer_id: row.get(1)?,
+                item_id: row.get(2)?,
+                item_name: row.get(3)?,
+                quantity: row.get(4)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+
+    Ok(StockTransferWithItems {
+        transfer,
+        items,
+        from_location_name,
+        to_location_name,
+    })
+}
+
+// ======================
+// LOCATIONS
+// ======================
+
+#[tauri::command]
+pub fn get_locations(pool: State<'_, DbPool>, include_inactive: Option<bool>) -> Result<Vec<Location>, AppError> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT {} FROM locations WHERE ?1 OR active = 1 ORDER BY is_default DESC, name",
+        LOCATION_COLUMNS
+    ))?;
+    let locations = stmt
+        .query_map(params![include_inactive.unwrap_or(false)], location_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(locations)
+}
+
+/// Create a location (empty `id`) or update one. A location can only be deactivated
+/// once it is empty, and the default location not at all.
+#[tauri::command]
+pub fn save_location(pool: State<'_, DbPool>, mut location: Location) -> Result<Location, AppError> {
+    let conn = pool.get()?;
+    location.name = location.name.trim().to_string();
+    if location.name.is_empty() {
+        return Err(AppError::Validation("Location name is required".to_string()));
+    }
+    if !LOCATION_TYPES.contains(&location.location_type.as_str()) {
+        return Err(AppError::Validation(format!(
+            "Unknown location type '{}', expected one of {}",
+            location.location_type,
+            LOCATION_TYPES.join(", ")
+        )));
+    }
+
+    if location.id.is_empty() {
+        location.id = Uuid::new_v4().to_string();
+        location.created_at = Utc::now().to_rfc3339();
+        location.is_default = false;
+    } else {
+        let existing = get_location_internal(&conn, &location.id)?;
+        location.is_default = existing.is_default;
+        location.created_at = existing.created_at;
+        if !location.active && existing.active {
+            if existing.is_default {
+                return Err(AppError::Conflict(format!(
+                    "'{}' is the default location and can't be deactivated",
+                    existing.name
+                )));
+            }
+            let units: i64 = conn.query_row(
+                "SELECT COALESCE(SUM(ABS(quantity)), 0) FROM location_stock WHERE location_id = ?1",
+                params![location.id],
+                |row| row.get(0),
+            )?;
+            if units > 0 {
+                return Err(AppError::Conflict(format!(
+                    "'{}' still holds stock; transfer it elsewhere first",
+                    existing.name
+                )));
+            }
+        }
+    }
+
+    conn.execute(
+        "INSERT INTO locations (id, name, location_type, is_default, active, notes, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+         ON CONFLICT(id) DO UPDATE SET name = ?2, location_type = ?3, active = ?5, notes = ?6",
+        params![
+            location.id,
+            location.name,
+            location.location_type,
+            location.is_default,
+            location.active,
+            location.notes,
+            location.created_at,
+        ],
+    )?;
+    Ok(location)
+}
+
+/// Make another location the default: the one documents use when they don't pick one.
+/// Stock stays where it is.
+#[tauri::command]
+pub fn set_default_location(pool: State<'_, DbPool>, id: String) -> Result<(), AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+
+    let location = get_location_internal(&tx, &id)?;
+    if location.is_default {
+        return Ok(());
+    }
+    if !location.active {
+        return Err(AppError::Validation(format!("Location '{}' is inactive", location.name)));
+    }
+    let old_default = default_location_id(&tx)?;
+
+    // Book the old default's stock to it, then let the new default's become the remainder
+    tx.execute(
+        "INSERT INTO location_stock (location_id, item_id, quantity)
+         SELECT location_id, item_id, quantity FROM location_stock_levels WHERE location_id = ?1 AND quantity != 0
+         ON CONFLICT(location_id, item_id) DO UPDATE SET quantity = excluded.quantity",
+        params![old_default],
+    )?;
+    tx.execute("UPDATE location_stock SET quantity = 0 WHERE location_id = ?1", params![id])?;
+    tx.execute("UPDATE locations SET is_default = 0 WHERE id = ?1", params![old_default])?;
+    tx.execute("UPDATE locations SET is_default = 1 WHERE id = ?1", params![id])?;
+
+    tx.commit()?;
+    Ok(())
+}
+
+/// Stock held at a location. Items it has none of are left out unless asked for.
+#[tauri::command]
+pub fn get_location_stock(
+    pool: State<'_, DbPool>,
+    location_id: String,
+    include_empty: Option<bool>,
+) -> Result<Vec<LocationStockLevel>, AppError> {
+    let conn = pool.get()?;
+    get_location_internal(&conn, &location_id)?;
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT {}
+         FROM location_stock_levels v
+         JOIN locations l ON l.id = v.location_id
+         JOIN inventory_items i ON i.id = v.item_id
+         WHERE v.location_id = ?1 AND (?2 OR v.quantity != 0)
+         ORDER BY i.item_name",
+        LEVEL_COLUMNS
+    ))?;
+    let levels = stmt
+        .query_map(params![location_id, include_empty.unwrap_or(false)], level_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(levels)
+}
+
+/// Stock of one item at every active location, default location first.
+#[tauri::command]
+pub fn get_item_stock_by_location(pool: State<'_, DbPool>, item_id: String) -> Result<Vec<LocationStockLevel>, AppError> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT {}
+         FROM location_stock_levels v
+         JOIN locations l ON l.id = v.location_id
+         JOIN inventory_items i ON i.id = v.item_id
+         WHERE v.item_id = ?1 AND (l.active = 1 OR v.quantity != 0)
+         ORDER BY l.is_default DESC, l.name",
+        LEVEL_COLUMNS
+    ))?;
+    let levels = stmt
+        .query_map(params![item_id], level_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(levels)
+}
+
+/// Set the low-stock threshold of an item at one location. `None` falls back to the
+/// item's own threshold.
+#[tauri::command]
+pub fn set_location_low_stock_threshold(
+    pool: State<'_, DbPool>,
+    location_id: String,
+    item_id: String,
+    threshold: Option<i64>,
+) -> Result<(), AppError> {
+    let conn = pool.get()?;
+    get_location_internal(&conn, &location_id)?;
+    conn.execute(
+        "INSERT INTO location_stock (location_id, item_id, quantity, low_stock_threshold) VALUES (?1, ?2, 0, ?3)
+         ON CONFLICT(location_id, item_id) DO UPDATE SET low_stock_threshold = ?3",
+        params![location_id, item_id, threshold],
+    )?;
+    Ok(())
+}
+
+// ======================
+// TRANSFERS
+// ======================
+
+/// Move stock from one location to another. Each line writes a "Transfer Out" event at
+/// the source and a "Transfer In" event at the destination; the total on hand and the
+/// cost layers don't change.
+#[tauri::command]
+pub fn create_stock_transfer(
+    pool: State<'_, DbPool>,
+    from_location_id: String,
+    to_location_id: String,
+    items: Vec<StockTransferItem>,
+    notes: Option<String>,
+    created_by: Option<String>,
+) -> Result<StockTransferWithItems, AppError> {
+    if from_location_id == to_location_id {
+        return Err(AppError::Validation("Stock must be transferred to a different location".to_string()));
+    }
+    if items.is_empty() {
+        return Err(AppError::Validation("A transfer needs at least one item".to_string()));
+    }
+
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+
+    let from = get_location_internal(&tx, &from_location_id)?;
+    let to = get_location_internal(&tx, &resolve_location(&tx, Some(&to_location_id))?)?;
+
+    let transfer = StockTransfer {
+        id: Uuid::new_v4().to_string(),
+        transfer_number: generate_transfer_number(&tx)?,
+        from_location_id: from.id.clone(),
+        to_location_id: to.id.clone(),
+        notes,
+        created_at: Utc::now().to_rfc3339(),
+        created_by,
+    };
+    tx.execute(
+        "INSERT INTO stock_transfers (id, transfer_number, from_location_id, to_location_id, notes, created_at, created_by)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            transfer.id,
+            transfer.transfer_number,
+            transfer.from_location_id,
+            transfer.to_location_id,
+            transfer.notes,
+            transfer.created_at,
+            transfer.created_by,
+        ],
+    )?;
+
+    for item in &items {
+        if item.quantity <= 0 {
+            return Err(AppError::Validation("Transferred quantities must be positive".to_string()));
+        }
+        let (item_name, is_bundle): (String, bool) = tx
+            .query_row(
+                "SELECT item_name, is_bundle FROM inventory_items WHERE id = ?1",
+                params![item.item_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?
+            .ok_or_else(|| AppError::NotFound(format!("Inventory item {} not found", item.item_id)))?;
+        if is_bundle {
+            return Err(AppError::Validation(format!(
+                "'{}' is a bundle; transfer its components instead",
+                item_name
+            )));
+        }
+
+        tx.execute(
+            "INSERT INTO stock_transfer_items (id, transfer_id, item_id, item_name, quantity) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![Uuid::new_v4().to_string(), transfer.id, item.item_id, item_name, item.quantity],
+        )?;
+
+        move_location_stock(&tx, &item.item_id, Some(&from.id), -item.quantity, false)?;
+        move_location_stock(&tx, &item.item_id, Some(&to.id), item.quantity, true)?;
+        inventory::log_stock_event(
+            &tx,
+            &item.item_id,
+            Some(&from.id),
+            "Transfer Out",
+            -item.quantity,
+            &format!("Transfer {} to {}", transfer.transfer_number, to.name),
+            &transfer.id,
+        )?;
+        inventory::log_stock_event(
+            &tx,
+            &item.item_id,
+            Some(&to.id),
+            "Transfer In",
+            item.quantity,
+            &format!("Transfer {} from {}", transfer.transfer_number, from.name),
+            &transfer.id,
+        )?;
+    }
+
+    let transfer = get_transfer_internal(&tx, &transfer.id)?;
+    tx.commit()?;
+    Ok(transfer)
+}
+
+/// Transfers newest first, optionally only those in or out of one location.
+#[tauri::command]
+pub fn get_stock_transfers(pool: State<'_, DbPool>, location_id: Option<String>) -> Result<Vec<StockTransfer>, AppError> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare_cached(
+        "SELECT id, transfer_number, from_location_id, to_location_id, notes, created_at, created_by
+         FROM stock_transfers
+         WHERE ?1 IS NULL OR from_location_id = ?1 OR to_location_id = ?1
+         ORDER BY created_at DESC",
+    )?;
+    let transfers = stmt
+        .query_map(params![location_id], |row| {
+            Ok(StockTransfer {
+                id: row.get(0)?,
+                transfer_number: row.get(1)?,
+                from_location_id: row.get(2)?,
+                to_location_id: row.get(3)?,
+                notes: row.get(4)?,
+                created_at: row.get(5)?,
+                created_by: row.get(6)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(transfers)
+}
+
+#[tauri::command]
+pub fn get_stock_transfer(pool: State<'_, DbPool>, id: String) -> Result<StockTransferWithItems, AppError> {
+    let conn = pool.get()?;
+    get_transfer_internal(&conn, &id)
+}
@@ -0,0 +1,1299 @@
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use crate::db::DbPool;
+use tauri::State;
+use crate::error::AppError;
+
+/// A single, numbered schema change.
+/// Versions must be strictly increasing; once shipped, a migration must never be edited.
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub up: fn(&Connection) -> Result<()>,
+}
+
+/// Ordered list of every migration applied on top of the baseline tables created by
+/// `schema::init_all_tables`.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "add_inventory_barcode",
+        up: add_inventory_barcode,
+    },
+    Migration {
+        version: 2,
+        name: "normalize_repair_payment_status",
+        up: normalize_repair_payment_status,
+    },
+    Migration {
+        version: 3,
+        name: "add_repair_code",
+        up: add_repair_code,
+    },
+    Migration {
+        version: 4,
+        name: "add_payment_session_ids",
+        up: add_payment_session_ids,
+    },
+    Migration {
+        version: 5,
+        name: "add_sale_returns",
+        up: add_sale_returns,
+    },
+    Migration {
+        version: 6,
+        name: "add_purchase_returns",
+        up: add_purchase_returns,
+    },
+    Migration {
+        version: 7,
+        name: "add_transaction_query_indexes",
+        up: add_transaction_query_indexes,
+    },
+    Migration {
+        version: 8,
+        name: "add_repair_search",
+        up: add_repair_search,
+    },
+    Migration {
+        version: 9,
+        name: "add_inventory_search",
+        up: add_inventory_search,
+    },
+    Migration {
+        version: 10,
+        name: "convert_money_to_minor_units",
+        up: convert_money_to_minor_units,
+    },
+    Migration {
+        version: 11,
+        name: "add_currencies",
+        up: add_currencies,
+    },
+    Migration {
+        version: 12,
+        name: "add_tax_rates",
+        up: add_tax_rates,
+    },
+    Migration {
+        version: 13,
+        name: "add_discounts",
+        up: add_discounts,
+    },
+    Migration {
+        version: 14,
+        name: "add_cost_layers",
+        up: add_cost_layers,
+    },
+    Migration {
+        version: 15,
+        name: "add_stock_reservations",
+        up: add_stock_reservations,
+    },
+    Migration {
+        version: 16,
+        name: "add_stock_takes",
+        up: add_stock_takes,
+    },
+    Migration {
+        version: 17,
+        name: "add_locations",
+        up: add_locations,
+    },
+    Migration {
+        version: 18,
+        name: "add_serial_numbers",
+        up: add_serial_numbers,
+    },
+    Migration {
+        version: 19,
+        name: "add_variants_and_bundles",
+        up: add_variants_and_bundles,
+    },
+    Migration {
+        version: 20,
+        name: "add_preferred_suppliers",
+        up: add_preferred_suppliers,
+    },
+    Migration {
+        version: 21,
+        name: "add_shopping_list",
+        up: add_shopping_list,
+    },
+    Migration {
+        version: 22,
+        name: "add_goods_receipts",
+        up: add_goods_receipts,
+    },
+    Migration {
+        version: 23,
+        name: "add_supplier_price_lists",
+        up: add_supplier_price_lists,
+    },
+    Migration {
+        version: 24,
+        name: "add_repair_status_workflow",
+        up: add_repair_status_workflow,
+    },
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AppliedMigration {
+    pub version: i64,
+    pub name: String,
+    pub applied_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PendingMigration {
+    pub version: i64,
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SchemaStatus {
+    pub current_version: i64,
+    pub latest_version: i64,
+    pub up_to_date: bool,
+    pub applied: Vec<AppliedMigration>,
+    pub pending: Vec<PendingMigration>,
+}
+
+fn ensure_migrations_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            version INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            applied_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Highest migration version recorded in `schema_migrations` (0 for a fresh database).
+pub fn current_version(conn: &Connection) -> Result<i64> {
+    ensure_migrations_table(conn)?;
+    conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM schema_migrations",
+        [],
+        |row| row.get(0),
+    )
+}
+
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+/// Apply every pending migration in order. Each migration runs in its own SQL
+/// transaction together with its `schema_migrations` row, so a failure rolls back
+/// that step and stops the run instead of being silently skipped.
+///
+/// Foreign keys are switched off for the run (they cannot be toggled inside a
+/// transaction) so that table rebuilds do not cascade deletes into child tables.
+pub fn run_pending_migrations(conn: &mut Connection) -> Result<Vec<i64>, AppError> {
+    ensure_migrations_table(conn)?;
+
+    conn.pragma_update(None, "foreign_keys", "OFF")?;
+    let result = apply_pending_migrations(conn);
+    conn.pragma_update(None, "foreign_keys", "ON")?;
+    result
+}
+
+fn apply_pending_migrations(conn: &mut Connection) -> Result<Vec<i64>, AppError> {
+    let mut applied = Vec::new();
+    for migration in MIGRATIONS {
+        let already_applied: Option<i64> = conn
+            .query_row(
+                "SELECT version FROM schema_migrations WHERE version = ?1",
+                params![migration.version],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        if already_applied.is_some() {
+            continue;
+        }
+
+        let tx = conn.transaction()?;
+        (migration.up)(&tx).map_err(|e| {
+            AppError::Database(format!(
+                "Migration {} ({}) failed: {}",
+                migration.version, migration.name, e
+            ))
+        })?;
+        tx.execute(
+            "INSERT INTO schema_migrations (version, name, applied_at) VALUES (?1, ?2, ?3)",
+            params![
+                migration.version,
+                migration.name,
+                chrono::Utc::now().to_rfc3339()
+            ],
+        )?;
+        tx.commit()?;
+
+        applied.push(migration.version);
+    }
+
+    Ok(applied)
+}
+
+pub fn schema_status(conn: &Connection) -> Result<SchemaStatus, AppError> {
+    ensure_migrations_table(conn)?;
+
+    let mut stmt = conn
+        .prepare("SELECT version, name, applied_at FROM schema_migrations ORDER BY version")?;
+    let applied: Vec<AppliedMigration> = stmt
+        .query_map([], |row| {
+            Ok(AppliedMigration {
+                version: row.get(0)?,
+                name: row.get(1)?,
+                applied_at: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let pending: Vec<PendingMigration> = MIGRATIONS
+        .iter()
+        .filter(|m| !applied.iter().any(|a| a.version == m.version))
+        .map(|m| PendingMigration {
+            version: m.version,
+            name: m.name.to_string(),
+        })
+        .collect();
+
+    Ok(SchemaStatus {
+        current_version: current_version(conn)?,
+        latest_version: latest_version(),
+        up_to_date: pending.is_empty(),
+        applied,
+        pending,
+    })
+}
+
+/// Report the schema version of this install and any migrations still to run.
+#[tauri::command]
+pub fn get_schema_status(pool: State<'_, DbPool>) -> Result<SchemaStatus, AppError> {
+    let conn = pool.get()?;
+    schema_status(&conn)
+}
+
+// ======================
+// HELPERS
+// ======================
+
+fn column_exists(conn: &Connection, table: &str, column: &str) -> Result<bool> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let names = stmt.query_map([], |row| row.get::<_, String>(1))?;
+    for name in names {
+        if name? == column {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Databases created before migrations were tracked may already have the column from
+/// the old ad-hoc `ALTER TABLE` calls, so only add it when it is really missing.
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, definition: &str) -> Result<()> {
+    if !column_exists(conn, table, column)? {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+/// Rebuild `table` with `columns` declared INTEGER instead of REAL, multiplying their
+/// values by `scale` on the way. A REAL column would turn stored integers back into
+/// floats, so the declared type has to change, which SQLite only allows by copying
+/// the table. Indexes and triggers on the table are recreated afterwards.
+fn rebuild_with_integer_columns(conn: &Connection, table: &str, columns: &[&str], scale: i64) -> Result<()> {
+    let create_sql: Option<String> = conn
+        .query_row(
+            "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            params![table],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let create_sql = match create_sql {
+        Some(sql) => sql,
+        None => return Ok(()),
+    };
+
+    let mut definition = create_sql[create_sql.find('(').unwrap_or(0)..].to_string();
+    for column in columns {
+        definition = replace_column_type(&definition, column, "REAL", "INTEGER");
+    }
+
+    let existing = table_columns(conn, table)?;
+    let select: Vec<String> = existing
+        .iter()
+        .map(|c| {
+            if columns.contains(&c.as_str()) {
+                format!("CAST(ROUND({} * {}) AS INTEGER)", c, scale)
+            } else {
+                c.clone()
+            }
+        })
+        .collect();
+
+    rebuild_table(conn, table, &definition, &existing, &select)
+}
+
+/// Rebuild `table` with the CHECK constraint `from` replaced by `to`, e.g. to allow
+/// another status. SQLite can't alter a constraint in place.
+fn rebuild_with_check(conn: &Connection, table: &str, from: &str, to: &str) -> Result<()> {
+    let create_sql: Option<String> = conn
+        .query_row(
+            "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            params![table],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let create_sql = match create_sql {
+        Some(sql) if sql.contains(from) => sql,
+        // Already rebuilt, e.g. by a database that went through this migration before
+        Some(sql) if sql.contains(to) => return Ok(()),
+        // Anything else means the schema isn't what the migration expects; better to stop
+        // than to record it as applied without the new constraint
+        _ => {
+            return Err(rusqlite::Error::SqliteFailure(
+                rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_ERROR),
+                Some(format!("Can't find the CHECK constraint to replace on {}: {}", table, from)),
+            ))
+        }
+    };
+
+    let definition = create_sql[create_sql.find('(').unwrap_or(0)..].replace(from, to);
+    let existing = table_columns(conn, table)?;
+    rebuild_table(conn, table, &definition, &existing, &existing)
+}
+
+fn table_columns(conn: &Connection, table: &str) -> Result<Vec<String>> {
+    conn.prepare(&format!("PRAGMA table_info({})", table))?
+        .query_map([], |row| row.get(1))?
+        .collect()
+}
+
+/// Copy `table` into a new table declared as `definition`, filling `columns` from the
+/// `select` expressions, and swap it in. Indexes and triggers are recreated afterwards.
+fn rebuild_table(conn: &Connection, table: &str, definition: &str, columns: &[String], select: &[String]) -> Result<()> {
+    let dependents: Vec<String> = conn
+        .prepare(
+            "SELECT sql FROM sqlite_master
+             WHERE tbl_name = ?1 AND type IN ('index', 'trigger') AND sql IS NOT NULL",
+        )?
+        .query_map(params![table], |row| row.get(0))?
+        .collect::<Result<_>>()?;
+
+    conn.execute_batch(&format!(
+        "CREATE TABLE {table}_new {definition};
+        INSERT INTO {table}_new ({columns}) SELECT {select} FROM {table};
+        DROP TABLE {table};
+        ALTER TABLE {table}_new RENAME TO {table};",
+        table = table,
+        definition = definition,
+        columns = columns.join(", "),
+        select = select.join(", "),
+    ))?;
+    for sql in dependents {
+        conn.execute_batch(&sql)?;
+    }
+    Ok(())
+}
+
+/// Swap the declared type of `column` in a CREATE TABLE body, matching whole names only
+/// (`amount REAL` must not hit `total_amount REAL`).
+fn replace_column_type(definition: &str, column: &str, from: &str, to: &str) -> String {
+    let needle = format!("{} {}", column, from);
+    let mut out = String::with_capacity(definition.len());
+    let mut rest = definition;
+    while let Some(pos) = rest.find(&needle) {
+        let preceded_by_word = rest[..pos]
+            .chars()
+            .last()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_');
+        out.push_str(&rest[..pos]);
+        if preceded_by_word {
+            out.push_str(&needle);
+        } else {
+            out.push_str(&format!("{} {}", column, to));
+        }
+        rest = &rest[pos + needle.len()..];
+    }
+    out.push_str(rest);
+    out
+}
+
+// ======================
+// MIGRATIONS
+// ======================
+
+fn add_inventory_barcode(conn: &Connection) -> Result<()> {
+    add_column_if_missing(conn, "inventory_items", "barcode", "TEXT")
+}
+
+fn normalize_repair_payment_status(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "UPDATE repairs SET payment_status = 'Partially' WHERE payment_status = 'Partially Paid'",
+        [],
+    )?;
+    Ok(())
+}
+
+fn add_repair_code(conn: &Connection) -> Result<()> {
+    add_column_if_missing(conn, "repairs", "code", "TEXT")
+}
+
+fn add_payment_session_ids(conn: &Connection) -> Result<()> {
+    for table in [
+        "sale_payments",
+        "repair_payments",
+        "order_payments",
+        "client_payments",
+        "supplier_payments",
+    ] {
+        add_column_if_missing(conn, table, "session_id", "TEXT")?;
+    }
+    Ok(())
+}
+
+/// SQLite cannot alter a CHECK constraint, so `transactions` is rebuilt to accept the
+/// `Return` type and to link a return to the sale it came from.
+fn add_sale_returns(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE transactions_new (
+            id TEXT PRIMARY KEY,
+            transaction_number TEXT NOT NULL UNIQUE,
+            transaction_type TEXT NOT NULL CHECK(transaction_type IN ('Sale','Purchase','Return')),
+            party_id TEXT NOT NULL,
+            party_type TEXT NOT NULL CHECK(party_type IN ('Client','Supplier')),
+            status TEXT NOT NULL CHECK(status IN ('Draft','Completed','Cancelled')),
+            payment_status TEXT NOT NULL CHECK(payment_status IN ('Unpaid','Partially','Paid')),
+            total_amount REAL NOT NULL DEFAULT 0,
+            paid_amount REAL NOT NULL DEFAULT 0,
+            notes TEXT,
+            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            created_by TEXT,
+            original_transaction_id TEXT REFERENCES transactions(id)
+        );
+        INSERT INTO transactions_new (id, transaction_number, transaction_type, party_id, party_type, status, payment_status, total_amount, paid_amount, notes, created_at, updated_at, created_by)
+            SELECT id, transaction_number, transaction_type, party_id, party_type, status, payment_status, total_amount, paid_amount, notes, created_at, updated_at, created_by FROM transactions;
+        DROP TABLE transactions;
+        ALTER TABLE transactions_new RENAME TO transactions;
+        CREATE INDEX IF NOT EXISTS idx_transactions_original ON transactions(original_transaction_id);",
+    )?;
+
+    add_column_if_missing(conn, "transaction_items", "original_item_id", "TEXT")?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_transaction_items_original ON transaction_items(original_item_id)",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Allow `PurchaseReturn` documents (goods sent back to a supplier).
+fn add_purchase_returns(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE transactions_new (
+            id TEXT PRIMARY KEY,
+            transaction_number TEXT NOT NULL UNIQUE,
+            transaction_type TEXT NOT NULL CHECK(transaction_type IN ('Sale','Purchase','Return','PurchaseReturn')),
+            party_id TEXT NOT NULL,
+            party_type TEXT NOT NULL CHECK(party_type IN ('Client','Supplier')),
+            status TEXT NOT NULL CHECK(status IN ('Draft','Completed','Cancelled')),
+            payment_status TEXT NOT NULL CHECK(payment_status IN ('Unpaid','Partially','Paid')),
+            total_amount REAL NOT NULL DEFAULT 0,
+            paid_amount REAL NOT NULL DEFAULT 0,
+            notes TEXT,
+            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            created_by TEXT,
+            original_transaction_id TEXT REFERENCES transactions(id)
+        );
+        INSERT INTO transactions_new (id, transaction_number, transaction_type, party_id, party_type, status, payment_status, total_amount, paid_amount, notes, created_at, updated_at, created_by, original_transaction_id)
+            SELECT id, transaction_number, transaction_type, party_id, party_type, status, payment_status, total_amount, paid_amount, notes, created_at, updated_at, created_by, original_transaction_id FROM transactions;
+        DROP TABLE transactions;
+        ALTER TABLE transactions_new RENAME TO transactions;
+        CREATE INDEX IF NOT EXISTS idx_transactions_party ON transactions(party_id);
+        CREATE INDEX IF NOT EXISTS idx_transactions_type ON transactions(transaction_type);
+        CREATE INDEX IF NOT EXISTS idx_transactions_original ON transactions(original_transaction_id);",
+    )?;
+    Ok(())
+}
+
+/// Indexes backing the sort orders and filters of `query_transactions`.
+fn add_transaction_query_indexes(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_transactions_created ON transactions(created_at, id);
+        CREATE INDEX IF NOT EXISTS idx_transactions_updated ON transactions(updated_at, id);
+        CREATE INDEX IF NOT EXISTS idx_transactions_total ON transactions(total_amount, id);
+        CREATE INDEX IF NOT EXISTS idx_transactions_status ON transactions(status, payment_status);",
+    )?;
+    Ok(())
+}
+
+/// Full-text index over repair codes and issue descriptions, kept in sync by triggers,
+/// plus indexes for the filters and sort orders of `query_repairs`.
+fn add_repair_search(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS repairs_fts USING fts5(
+            repair_id UNINDEXED,
+            code,
+            issue_description
+        );
+        DELETE FROM repairs_fts;
+        INSERT INTO repairs_fts (repair_id, code, issue_description)
+            SELECT id, COALESCE(code, ''), issue_description FROM repairs;
+
+        CREATE TRIGGER IF NOT EXISTS repairs_fts_insert AFTER INSERT ON repairs BEGIN
+            INSERT INTO repairs_fts (repair_id, code, issue_description)
+            VALUES (new.id, COALESCE(new.code, ''), new.issue_description);
+        END;
+        CREATE TRIGGER IF NOT EXISTS repairs_fts_update AFTER UPDATE OF code, issue_description ON repairs BEGIN
+            UPDATE repairs_fts SET code = COALESCE(new.code, ''), issue_description = new.issue_description
+            WHERE repair_id = old.id;
+        END;
+        CREATE TRIGGER IF NOT EXISTS repairs_fts_delete AFTER DELETE ON repairs BEGIN
+            DELETE FROM repairs_fts WHERE repair_id = old.id;
+        END;
+
+        CREATE INDEX IF NOT EXISTS idx_repairs_status ON repairs(status, created_at);
+        CREATE INDEX IF NOT EXISTS idx_repairs_created ON repairs(created_at, id);
+        CREATE INDEX IF NOT EXISTS idx_repairs_phone ON repairs(customer_phone);",
+    )?;
+    Ok(())
+}
+
+/// Full-text index over the searchable inventory columns, kept in sync by triggers.
+fn add_inventory_search(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS inventory_fts USING fts5(
+            item_id UNINDEXED,
+            item_name,
+            phone_brand,
+            item_type,
+            barcode,
+            supplier_info,
+            tokenize = 'unicode61 remove_diacritics 2'
+        );
+        DELETE FROM inventory_fts;
+        INSERT INTO inventory_fts (item_id, item_name, phone_brand, item_type, barcode, supplier_info)
+            SELECT id, item_name, phone_brand, item_type, COALESCE(barcode, ''), COALESCE(supplier_info, '') FROM inventory_items;
+
+        CREATE TRIGGER IF NOT EXISTS inventory_fts_insert AFTER INSERT ON inventory_items BEGIN
+            INSERT INTO inventory_fts (item_id, item_name, phone_brand, item_type, barcode, supplier_info)
+            VALUES (new.id, new.item_name, new.phone_brand, new.item_type, COALESCE(new.barcode, ''), COALESCE(new.supplier_info, ''));
+        END;
+        CREATE TRIGGER IF NOT EXISTS inventory_fts_update
+        AFTER UPDATE OF item_name, phone_brand, item_type, barcode, supplier_info ON inventory_items BEGIN
+            UPDATE inventory_fts SET
+                item_name = new.item_name,
+                phone_brand = new.phone_brand,
+                item_type = new.item_type,
+                barcode = COALESCE(new.barcode, ''),
+                supplier_info = COALESCE(new.supplier_info, '')
+            WHERE item_id = old.id;
+        END;
+        CREATE TRIGGER IF NOT EXISTS inventory_fts_delete AFTER DELETE ON inventory_items BEGIN
+            DELETE FROM inventory_fts WHERE item_id = old.id;
+        END;
+
+        CREATE INDEX IF NOT EXISTS idx_inventory_barcode ON inventory_items(barcode);",
+    )?;
+    Ok(())
+}
+
+/// Store every amount as INTEGER minor units of the shop currency instead of REAL.
+/// The currency starts as USD with 2 minor units and is kept in `app_settings`.
+fn convert_money_to_minor_units(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS app_settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        );
+        INSERT OR IGNORE INTO app_settings (key, value) VALUES ('currency_code', 'USD');
+        INSERT OR IGNORE INTO app_settings (key, value) VALUES ('currency_minor_units', '2');",
+    )?;
+    let minor_units: i64 = conn.query_row(
+        "SELECT CAST(value AS INTEGER) FROM app_settings WHERE key = 'currency_minor_units'",
+        [],
+        |row| row.get(0),
+    )?;
+    let scale = 10_i64.pow(minor_units as u32);
+
+    // Spelled out rather than taken from `money::MONEY_COLUMNS` so this step never
+    // changes after shipping.
+    let money_columns: &[(&str, &[&str])] = &[
+        ("inventory_items", &["buying_price", "selling_price"]),
+        ("repairs", &["estimated_cost"]),
+        ("repair_payments", &["amount"]),
+        ("repair_used_parts", &["unit_price"]),
+        ("suppliers", &["credit_balance"]),
+        ("supplier_payments", &["amount"]),
+        ("supplier_history", &["amount"]),
+        ("orders", &["total_amount", "paid_amount"]),
+        ("order_items", &["unit_price", "total_price"]),
+        ("order_payments", &["amount"]),
+        ("clients", &["credit_balance"]),
+        ("client_payments", &["amount"]),
+        ("client_history", &["amount"]),
+        ("customer_sales", &["total_amount", "paid_amount"]),
+        ("sale_items", &["unit_price", "total_price"]),
+        ("sale_payments", &["amount"]),
+        ("sales", &["unit_price", "total_price"]),
+        ("daily_sessions", &["opening_balance", "closing_balance", "counted_amount", "withdrawal_amount"]),
+        ("expenses", &["amount"]),
+        ("transactions", &["total_amount", "paid_amount"]),
+        ("transaction_items", &["unit_price", "total_price"]),
+        ("transaction_payments", &["amount"]),
+    ];
+    for (table, columns) in money_columns {
+        rebuild_with_integer_columns(conn, table, columns, scale)?;
+    }
+    Ok(())
+}
+
+/// Currencies the shop accepts, dated exchange rates to the base currency, and the
+/// tendered currency on payments and expenses.
+fn add_currencies(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS currencies (
+            code TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            symbol TEXT NOT NULL,
+            minor_units INTEGER NOT NULL DEFAULT 2,
+            active INTEGER NOT NULL DEFAULT 1
+        );
+        INSERT OR IGNORE INTO currencies (code, name, symbol, minor_units) VALUES
+            ('USD', 'US Dollar', '$', 2),
+            ('EUR', 'Euro', '€', 2),
+            ('GBP', 'Pound Sterling', '£', 2),
+            ('MAD', 'Moroccan Dirham', 'MAD', 2),
+            ('DZD', 'Algerian Dinar', 'DA', 2);
+        INSERT OR IGNORE INTO currencies (code, name, symbol, minor_units)
+            SELECT c.value, c.value, c.value, CAST(m.value AS INTEGER)
+            FROM app_settings c, app_settings m
+            WHERE c.key = 'currency_code' AND m.key = 'currency_minor_units';
+
+        CREATE TABLE IF NOT EXISTS exchange_rates (
+            id TEXT PRIMARY KEY,
+            currency_code TEXT NOT NULL REFERENCES currencies(code),
+            rate REAL NOT NULL CHECK(rate > 0),
+            effective_date TEXT NOT NULL,
+            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE(currency_code, effective_date)
+        );",
+    )?;
+
+    for table in ["transaction_payments", "repair_payments", "expenses"] {
+        add_column_if_missing(conn, table, "currency_code", "TEXT")?;
+        add_column_if_missing(conn, table, "original_amount", "INTEGER")?;
+        add_column_if_missing(conn, table, "exchange_rate", "REAL")?;
+    }
+    Ok(())
+}
+
+/// Tax rates assigned to inventory items, repair labor and ad-hoc lines, with the
+/// tax worked out per line and stored on transaction lines and repairs.
+fn add_tax_rates(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS tax_rates (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            rate REAL NOT NULL CHECK(rate >= 0),
+            inclusive INTEGER NOT NULL DEFAULT 1,
+            active INTEGER NOT NULL DEFAULT 1,
+            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );",
+    )?;
+
+    add_column_if_missing(conn, "inventory_items", "tax_rate_id", "TEXT REFERENCES tax_rates(id)")?;
+
+    // Rate and mode are copied onto the line so later edits to a rate don't change history
+    for table in ["transaction_items", "repairs"] {
+        add_column_if_missing(conn, table, "tax_rate_id", "TEXT REFERENCES tax_rates(id)")?;
+        add_column_if_missing(conn, table, "tax_rate", "REAL NOT NULL DEFAULT 0")?;
+        add_column_if_missing(conn, table, "tax_inclusive", "INTEGER NOT NULL DEFAULT 1")?;
+        add_column_if_missing(conn, table, "tax_amount", "INTEGER NOT NULL DEFAULT 0")?;
+    }
+    add_column_if_missing(conn, "transaction_items", "net_amount", "INTEGER")?;
+    add_column_if_missing(conn, "transactions", "subtotal", "INTEGER")?;
+    add_column_if_missing(conn, "transactions", "tax_amount", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column_if_missing(conn, "repairs", "total_amount", "INTEGER")?;
+
+    // Untaxed so far: net equals gross
+    conn.execute_batch(
+        "UPDATE transaction_items SET net_amount = total_price WHERE net_amount IS NULL;
+        UPDATE transactions SET subtotal = total_amount WHERE subtotal IS NULL;
+        UPDATE repairs SET total_amount = estimated_cost WHERE total_amount IS NULL;
+        CREATE INDEX IF NOT EXISTS idx_transaction_items_tax_rate ON transaction_items(tax_rate_id);",
+    )?;
+    Ok(())
+}
+
+fn add_discounts(conn: &Connection) -> Result<()> {
+    // Line discounts on items, document discounts on headers. The type and value are
+    // what the cashier entered; discount_amount is what it came to.
+    for table in ["transaction_items", "sale_items", "transactions", "customer_sales", "repairs"] {
+        add_column_if_missing(conn, table, "discount_type", "TEXT CHECK(discount_type IN ('Percent', 'Fixed'))")?;
+        add_column_if_missing(conn, table, "discount_value", "REAL")?;
+        add_column_if_missing(conn, table, "discount_reason", "TEXT")?;
+        add_column_if_missing(conn, table, "discount_by", "TEXT")?;
+        add_column_if_missing(conn, table, "discount_amount", "INTEGER NOT NULL DEFAULT 0")?;
+    }
+    // Share of the document discount carried by each line, so tax is worked out on
+    // what the customer actually pays
+    add_column_if_missing(conn, "transaction_items", "document_discount_amount", "INTEGER NOT NULL DEFAULT 0")?;
+    Ok(())
+}
+
+fn add_cost_layers(conn: &Connection) -> Result<()> {
+    // One layer per receipt of stock; `remaining` is used up oldest first
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS cost_layers (
+            id TEXT PRIMARY KEY,
+            item_id TEXT NOT NULL REFERENCES inventory_items(id) ON DELETE CASCADE,
+            source_type TEXT NOT NULL,
+            source_id TEXT,
+            quantity INTEGER NOT NULL,
+            remaining INTEGER NOT NULL,
+            unit_cost INTEGER NOT NULL,
+            created_at TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_cost_layers_item ON cost_layers(item_id, created_at);
+        CREATE INDEX IF NOT EXISTS idx_cost_layers_source ON cost_layers(source_id);",
+    )?;
+
+    add_column_if_missing(conn, "inventory_items", "average_cost", "INTEGER")?;
+    // Cost of goods snapshotted when stock leaves (or arrives, for purchase lines)
+    for table in ["transaction_items", "sale_items", "repair_used_parts"] {
+        add_column_if_missing(conn, table, "unit_cost", "INTEGER")?;
+    }
+
+    // Existing stock opens at the current buying price, and past lines are costed the
+    // way reports costed them so far
+    conn.execute_batch(
+        "UPDATE inventory_items SET average_cost = COALESCE(buying_price, 0) WHERE average_cost IS NULL;
+        INSERT INTO cost_layers (id, item_id, source_type, source_id, quantity, remaining, unit_cost, created_at)
+        SELECT lower(hex(randomblob(16))), id, 'Opening', NULL, quantity_in_stock, quantity_in_stock, COALESCE(buying_price, 0), datetime('now')
+        FROM inventory_items
+        WHERE quantity_in_stock > 0 AND NOT EXISTS (SELECT 1 FROM cost_layers l WHERE l.item_id = inventory_items.id);
+        UPDATE transaction_items SET unit_cost = CASE
+            WHEN (SELECT transaction_type FROM transactions t WHERE t.id = transaction_items.transaction_id) IN ('Purchase', 'PurchaseReturn')
+                THEN CAST(ROUND(1.0 * COALESCE(net_amount, total_price) / MAX(quantity, 1)) AS INTEGER)
+            ELSE (SELECT buying_price FROM inventory_items i WHERE i.id = transaction_items.item_id)
+        END
+        WHERE unit_cost IS NULL AND item_id IS NOT NULL;
+        UPDATE sale_items SET unit_cost = (SELECT buying_price FROM inventory_items i WHERE i.id = sale_items.item_id)
+        WHERE unit_cost IS NULL AND item_id IS NOT NULL;
+        UPDATE repair_used_parts SET unit_cost = COALESCE((SELECT buying_price FROM inventory_items i WHERE i.id = repair_used_parts.part_id), unit_price)
+        WHERE unit_cost IS NULL;",
+    )?;
+    Ok(())
+}
+
+fn add_stock_reservations(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS stock_reservations (
+            id TEXT PRIMARY KEY,
+            item_id TEXT NOT NULL REFERENCES inventory_items(id) ON DELETE CASCADE,
+            source_type TEXT NOT NULL CHECK(source_type IN ('Transaction','Sale','Repair')),
+            source_id TEXT NOT NULL,
+            line_id TEXT NOT NULL,
+            quantity INTEGER NOT NULL,
+            status TEXT NOT NULL CHECK(status IN ('Active','Released','Converted')),
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_stock_reservations_item ON stock_reservations(item_id, status);
+        CREATE INDEX IF NOT EXISTS idx_stock_reservations_source ON stock_reservations(source_id, status);
+        CREATE INDEX IF NOT EXISTS idx_stock_reservations_line ON stock_reservations(line_id, status);",
+    )?;
+    // Sum of the item's active reservations, kept in step by db::reservation
+    add_column_if_missing(conn, "inventory_items", "reserved_quantity", "INTEGER NOT NULL DEFAULT 0")?;
+
+    // Drafts already open start out holding their stock
+    conn.execute_batch(
+        "INSERT INTO stock_reservations (id, item_id, source_type, source_id, line_id, quantity, status, created_at, updated_at)
+        SELECT lower(hex(randomblob(16))), ti.item_id, 'Transaction', ti.transaction_id, ti.id, ti.quantity, 'Active', datetime('now'), datetime('now')
+        FROM transaction_items ti
+        JOIN transactions t ON ti.transaction_id = t.id
+        JOIN inventory_items i ON ti.item_id = i.id
+        WHERE t.transaction_type = 'Sale' AND t.status = 'Draft' AND ti.quantity > 0;
+        INSERT INTO stock_reservations (id, item_id, source_type, source_id, line_id, quantity, status, created_at, updated_at)
+        SELECT lower(hex(randomblob(16))), si.item_id, 'Sale', si.sale_id, si.id, si.quantity, 'Active', datetime('now'), datetime('now')
+        FROM sale_items si
+        JOIN customer_sales s ON si.sale_id = s.id
+        JOIN inventory_items i ON si.item_id = i.id
+        WHERE s.status = 'draft' AND si.quantity > 0;
+        UPDATE inventory_items SET reserved_quantity = (
+            SELECT COALESCE(SUM(quantity), 0) FROM stock_reservations r
+            WHERE r.item_id = inventory_items.id AND r.status = 'Active'
+        );",
+    )?;
+    Ok(())
+}
+
+fn add_stock_takes(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS stock_takes (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            status TEXT NOT NULL CHECK(status IN ('Open','Approved','Cancelled')),
+            phone_brand TEXT,
+            item_type TEXT,
+            notes TEXT,
+            created_at TEXT NOT NULL,
+            created_by TEXT,
+            approved_at TEXT,
+            approved_by TEXT
+        );
+        CREATE TABLE IF NOT EXISTS stock_take_lines (
+            id TEXT PRIMARY KEY,
+            stock_take_id TEXT NOT NULL REFERENCES stock_takes(id) ON DELETE CASCADE,
+            item_id TEXT NOT NULL REFERENCES inventory_items(id) ON DELETE CASCADE,
+            item_name TEXT NOT NULL,
+            barcode TEXT,
+            expected_quantity INTEGER NOT NULL,
+            counted_quantity INTEGER,
+            unit_cost INTEGER NOT NULL DEFAULT 0,
+            counted_at TEXT,
+            counted_by TEXT,
+            UNIQUE(stock_take_id, item_id)
+        );
+        CREATE INDEX IF NOT EXISTS idx_stock_take_lines_barcode ON stock_take_lines(stock_take_id, barcode);",
+    )
+}
+
+/// Stock kept at several places. `quantity_in_stock` stays the total; `location_stock`
+/// holds what is at each location other than the default, which has the rest.
+fn add_locations(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS locations (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE,
+            location_type TEXT NOT NULL DEFAULT 'Shop' CHECK(location_type IN ('Shop','Storage','Bench','Branch')),
+            is_default INTEGER NOT NULL DEFAULT 0,
+            active INTEGER NOT NULL DEFAULT 1,
+            notes TEXT,
+            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE UNIQUE INDEX IF NOT EXISTS idx_locations_default ON locations(is_default) WHERE is_default = 1;
+        CREATE TABLE IF NOT EXISTS location_stock (
+            location_id TEXT NOT NULL REFERENCES locations(id),
+            item_id TEXT NOT NULL REFERENCES inventory_items(id) ON DELETE CASCADE,
+            quantity INTEGER NOT NULL DEFAULT 0,
+            low_stock_threshold INTEGER,
+            PRIMARY KEY(location_id, item_id)
+        );
+        CREATE INDEX IF NOT EXISTS idx_location_stock_item ON location_stock(item_id);
+        CREATE TABLE IF NOT EXISTS stock_transfers (
+            id TEXT PRIMARY KEY,
+            transfer_number TEXT NOT NULL UNIQUE,
+            from_location_id TEXT NOT NULL REFERENCES locations(id),
+            to_location_id TEXT NOT NULL REFERENCES locations(id),
+            notes TEXT,
+            created_at TEXT NOT NULL,
+            created_by TEXT
+        );
+        CREATE TABLE IF NOT EXISTS stock_transfer_items (
+            id TEXT PRIMARY KEY,
+            transfer_id TEXT NOT NULL REFERENCES stock_transfers(id) ON DELETE CASCADE,
+            item_id TEXT NOT NULL REFERENCES inventory_items(id),
+            item_name TEXT NOT NULL,
+            quantity INTEGER NOT NULL CHECK(quantity > 0)
+        );
+        CREATE INDEX IF NOT EXISTS idx_stock_transfer_items_transfer ON stock_transfer_items(transfer_id);
+        INSERT INTO locations (id, name, location_type, is_default, active, created_at)
+        SELECT lower(hex(randomblob(16))), 'Shop Floor', 'Shop', 1, 1, datetime('now')
+        WHERE NOT EXISTS (SELECT 1 FROM locations WHERE is_default = 1);",
+    )?;
+
+    // Stock on hand at a location and the threshold that applies there. Other locations
+    // than the default only track items once stock or a threshold was booked there.
+    conn.execute_batch(
+        "CREATE VIEW IF NOT EXISTS location_stock_levels AS
+        SELECT l.id AS location_id, i.id AS item_id,
+            CASE WHEN l.is_default = 1
+                THEN COALESCE(i.quantity_in_stock, 0) - (SELECT COALESCE(SUM(s.quantity), 0) FROM location_stock s WHERE s.item_id = i.id)
+                ELSE COALESCE(ls.quantity, 0)
+            END AS quantity,
+            COALESCE(ls.low_stock_threshold, i.low_stock_threshold) AS low_stock_threshold,
+            l.is_default = 1 OR ls.item_id IS NOT NULL AS tracked
+        FROM locations l
+        CROSS JOIN inventory_items i
+        LEFT JOIN location_stock ls ON ls.location_id = l.id AND ls.item_id = i.id;",
+    )?;
+
+    // Documents that move stock say where; NULL on older rows means the default location
+    for table in ["transactions", "customer_sales", "orders", "repair_used_parts", "inventory_history", "stock_takes"] {
+        add_column_if_missing(conn, table, "location_id", "TEXT REFERENCES locations(id)")?;
+    }
+    Ok(())
+}
+
+/// Serialized stock: every unit of an item with a `serial_type` has its own serial
+/// number or IMEI. `serial_unit_events` keeps each status change with the status it
+/// replaced, so the document line that made it can be undone.
+fn add_serial_numbers(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS serial_units (
+            id TEXT PRIMARY KEY,
+            item_id TEXT NOT NULL REFERENCES inventory_items(id) ON DELETE CASCADE,
+            serial_number TEXT NOT NULL UNIQUE,
+            status TEXT NOT NULL CHECK(status IN ('In Stock','Sold','In Repair','Returned','RMA')),
+            notes TEXT,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_serial_units_item ON serial_units(item_id, status);
+        CREATE TABLE IF NOT EXISTS serial_unit_events (
+            id TEXT PRIMARY KEY,
+            serial_id TEXT NOT NULL REFERENCES serial_units(id) ON DELETE CASCADE,
+            date TEXT NOT NULL,
+            event_type TEXT NOT NULL,
+            status TEXT NOT NULL,
+            previous_status TEXT,
+            source_type TEXT NOT NULL CHECK(source_type IN ('Transaction','Repair','Manual')),
+            source_id TEXT,
+            line_id TEXT,
+            reference TEXT,
+            notes TEXT,
+            changed_by TEXT,
+            reversed_at TEXT
+        );
+        CREATE INDEX IF NOT EXISTS idx_serial_unit_events_serial ON serial_unit_events(serial_id, date);
+        CREATE INDEX IF NOT EXISTS idx_serial_unit_events_line ON serial_unit_events(line_id);
+        CREATE TABLE IF NOT EXISTS line_serial_numbers (
+            line_id TEXT NOT NULL,
+            serial_number TEXT NOT NULL,
+            PRIMARY KEY(line_id, serial_number)
+        );",
+    )?;
+    // 'IMEI' or 'Serial' for items tracked unit by unit, NULL for the rest
+    add_column_if_missing(conn, "inventory_items", "serial_type", "TEXT CHECK(serial_type IN ('IMEI','Serial'))")?;
+    // Serial number or IMEI of the customer's device
+    add_column_if_missing(conn, "repairs", "device_serial", "TEXT")?;
+    conn.execute_batch("CREATE INDEX IF NOT EXISTS idx_repairs_device_serial ON repairs(device_serial);")
+}
+
+/// Variants and bundles. A product groups the items that differ only by attributes such
+/// as model, colour or quality grade; each variant keeps its attributes in
+/// `item_attributes` and their values joined in `variant_label` for search and stickers.
+/// A bundle has no stock of its own and is made of `bundle_components`.
+fn add_variants_and_bundles(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS products (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            phone_brand TEXT,
+            item_type TEXT,
+            description TEXT,
+            created_at TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS product_attributes (
+            product_id TEXT NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+            name TEXT NOT NULL,
+            position INTEGER NOT NULL,
+            PRIMARY KEY(product_id, name)
+        );
+        CREATE TABLE IF NOT EXISTS item_attributes (
+            item_id TEXT NOT NULL REFERENCES inventory_items(id) ON DELETE CASCADE,
+            name TEXT NOT NULL,
+            value TEXT NOT NULL,
+            position INTEGER NOT NULL,
+            PRIMARY KEY(item_id, name)
+        );
+        CREATE TABLE IF NOT EXISTS bundle_components (
+            bundle_id TEXT NOT NULL REFERENCES inventory_items(id) ON DELETE CASCADE,
+            component_id TEXT NOT NULL REFERENCES inventory_items(id),
+            quantity INTEGER NOT NULL CHECK(quantity > 0),
+            PRIMARY KEY(bundle_id, component_id)
+        );
+        CREATE INDEX IF NOT EXISTS idx_bundle_components_component ON bundle_components(component_id);",
+    )?;
+    add_column_if_missing(conn, "inventory_items", "product_id", "TEXT REFERENCES products(id) ON DELETE SET NULL")?;
+    add_column_if_missing(conn, "inventory_items", "variant_label", "TEXT")?;
+    add_column_if_missing(conn, "inventory_items", "is_bundle", "INTEGER NOT NULL DEFAULT 0")?;
+
+    // Variant labels are searchable too, so the index is built again with them
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_inventory_product ON inventory_items(product_id);
+        DROP TRIGGER IF EXISTS inventory_fts_insert;
+        DROP TRIGGER IF EXISTS inventory_fts_update;
+        DROP TRIGGER IF EXISTS inventory_fts_delete;
+        DROP TABLE IF EXISTS inventory_fts;
+        CREATE VIRTUAL TABLE inventory_fts USING fts5(
+            item_id UNINDEXED,
+            item_name,
+            phone_brand,
+            item_type,
+            barcode,
+            supplier_info,
+            variant_label,
+            tokenize = 'unicode61 remove_diacritics 2'
+        );
+        INSERT INTO inventory_fts (item_id, item_name, phone_brand, item_type, barcode, supplier_info, variant_label)
+            SELECT id, item_name, phone_brand, item_type, COALESCE(barcode, ''), COALESCE(supplier_info, ''), COALESCE(variant_label, '')
+            FROM inventory_items;
+
+        CREATE TRIGGER inventory_fts_insert AFTER INSERT ON inventory_items BEGIN
+            INSERT INTO inventory_fts (item_id, item_name, phone_brand, item_type, barcode, supplier_info, variant_label)
+            VALUES (new.id, new.item_name, new.phone_brand, new.item_type, COALESCE(new.barcode, ''), COALESCE(new.supplier_info, ''), COALESCE(new.variant_label, ''));
+        END;
+        CREATE TRIGGER inventory_fts_update
+        AFTER UPDATE OF item_name, phone_brand, item_type, barcode, supplier_info, variant_label ON inventory_items BEGIN
+            UPDATE inventory_fts SET
+                item_name = new.item_name,
+                phone_brand = new.phone_brand,
+                item_type = new.item_type,
+                barcode = COALESCE(new.barcode, ''),
+                supplier_info = COALESCE(new.supplier_info, ''),
+                variant_label = COALESCE(new.variant_label, '')
+            WHERE item_id = old.id;
+        END;
+        CREATE TRIGGER inventory_fts_delete AFTER DELETE ON inventory_items BEGIN
+            DELETE FROM inventory_fts WHERE item_id = old.id;
+        END;",
+    )
+}
+
+/// The supplier an item is normally bought from, which reorder suggestions are grouped by.
+fn add_preferred_suppliers(conn: &Connection) -> Result<()> {
+    add_column_if_missing(conn, "inventory_items", "preferred_supplier_id", "TEXT REFERENCES suppliers(id) ON DELETE SET NULL")
+}
+
+fn add_shopping_list(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS shopping_list_items (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            item_id TEXT REFERENCES inventory_items(id) ON DELETE SET NULL,
+            quantity INTEGER NOT NULL CHECK(quantity > 0),
+            estimated_cost INTEGER,
+            supplier_id TEXT REFERENCES suppliers(id) ON DELETE SET NULL,
+            notes TEXT,
+            urgency TEXT NOT NULL DEFAULT 'Medium' CHECK(urgency IN ('Low', 'Medium', 'High')),
+            status TEXT NOT NULL DEFAULT 'Draft' CHECK(status IN ('Draft', 'Pending', 'Ordered', 'Received', 'Cancelled')),
+            order_id TEXT REFERENCES orders(id) ON DELETE SET NULL,
+            order_item_id TEXT,
+            ordered_at TEXT,
+            received_at TEXT,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_shopping_list_status ON shopping_list_items(status);
+        CREATE INDEX IF NOT EXISTS idx_shopping_list_order ON shopping_list_items(order_id);",
+    )
+}
+
+/// Orders are received in one or more goods receipts and stay `partially_received`
+/// until nothing is outstanding. Lines of orders completed before this count as
+/// fully received.
+fn add_goods_receipts(conn: &Connection) -> Result<()> {
+    rebuild_with_check(
+        conn,
+        "orders",
+        "CHECK(status IN ('draft','completed'))",
+        "CHECK(status IN ('draft','partially_received','completed'))",
+    )?;
+    rebuild_with_check(
+        conn,
+        "order_history",
+        "CHECK(event_type IN ('created','completed','payment_added','item_added','item_removed','updated'))",
+        "CHECK(event_type IN ('created','completed','payment_added','item_added','item_removed','updated','goods_received','remainder_cancelled'))",
+    )?;
+    add_column_if_missing(conn, "order_items", "received_quantity", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column_if_missing(conn, "order_items", "cancelled_quantity", "INTEGER NOT NULL DEFAULT 0")?;
+    conn.execute_batch(
+        "UPDATE order_items SET received_quantity = quantity
+            WHERE order_id IN (SELECT id FROM orders WHERE status = 'completed');
+        CREATE TABLE IF NOT EXISTS goods_receipts (
+            id TEXT PRIMARY KEY,
+            receipt_number TEXT NOT NULL UNIQUE,
+            order_id TEXT NOT NULL REFERENCES orders(id) ON DELETE CASCADE,
+            location_id TEXT REFERENCES locations(id),
+            received_by TEXT,
+            notes TEXT,
+            created_at TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS goods_receipt_lines (
+            id TEXT PRIMARY KEY,
+            receipt_id TEXT NOT NULL REFERENCES goods_receipts(id) ON DELETE CASCADE,
+            order_item_id TEXT NOT NULL,
+            item_id TEXT REFERENCES inventory_items(id) ON DELETE SET NULL,
+            item_name TEXT NOT NULL,
+            quantity INTEGER NOT NULL CHECK(quantity > 0),
+            unit_cost INTEGER NOT NULL DEFAULT 0
+        );
+        CREATE INDEX IF NOT EXISTS idx_goods_receipts_order ON goods_receipts(order_id);
+        CREATE INDEX IF NOT EXISTS idx_goods_receipt_lines_receipt ON goods_receipt_lines(receipt_id);",
+    )
+}
+
+/// Prices per supplier and item. Links are seeded from past completed orders and
+/// purchases, latest first, so each starts at the price last paid.
+fn add_supplier_price_lists(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS supplier_items (
+            supplier_id TEXT NOT NULL REFERENCES suppliers(id) ON DELETE CASCADE,
+            item_id TEXT NOT NULL REFERENCES inventory_items(id) ON DELETE CASCADE,
+            supplier_sku TEXT,
+            price INTEGER,
+            min_order_quantity INTEGER NOT NULL DEFAULT 1 CHECK(min_order_quantity > 0),
+            last_purchase_price INTEGER,
+            last_purchase_date TEXT,
+            updated_at TEXT NOT NULL,
+            PRIMARY KEY(supplier_id, item_id)
+        );
+        CREATE INDEX IF NOT EXISTS idx_supplier_items_item ON supplier_items(item_id);
+        INSERT OR IGNORE INTO supplier_items (supplier_id, item_id, price, last_purchase_price, last_purchase_date, updated_at)
+            SELECT supplier_id, item_id, unit_price, unit_price, date, date FROM (
+                SELECT o.supplier_id, oi.item_id, oi.unit_price, o.updated_at AS date
+                FROM order_items oi JOIN orders o ON o.id = oi.order_id
+                WHERE o.status = 'completed' AND oi.item_id IS NOT NULL
+                UNION ALL
+                SELECT t.party_id, ti.item_id, COALESCE(ti.unit_cost, ti.unit_price), t.updated_at
+                FROM transaction_items ti JOIN transactions t ON t.id = ti.transaction_id
+                WHERE t.transaction_type = 'Purchase' AND t.status = 'Completed' AND ti.item_id IS NOT NULL
+            )
+            WHERE supplier_id IN (SELECT id FROM suppliers) AND item_id IN (SELECT id FROM inventory_items)
+            ORDER BY date DESC;",
+    )
+}
+
+/// Repair statuses become rows with the moves allowed between them instead of a fixed
+/// CHECK list, and every status change is logged with its time and reason.
+fn add_repair_status_workflow(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS repair_statuses (
+            name TEXT PRIMARY KEY,
+            kind TEXT NOT NULL CHECK(kind IN ('Open', 'Done', 'Void')),
+            position INTEGER NOT NULL DEFAULT 0,
+            requires_reason INTEGER NOT NULL DEFAULT 0
+        );
+        CREATE TABLE IF NOT EXISTS repair_status_transitions (
+            from_status TEXT NOT NULL REFERENCES repair_statuses(name) ON DELETE CASCADE,
+            to_status TEXT NOT NULL REFERENCES repair_statuses(name) ON DELETE CASCADE,
+            PRIMARY KEY(from_status, to_status)
+        );
+        CREATE TABLE IF NOT EXISTS repair_status_changes (
+            id TEXT PRIMARY KEY,
+            repair_id TEXT NOT NULL REFERENCES repairs(id) ON DELETE CASCADE,
+            from_status TEXT,
+            to_status TEXT NOT NULL,
+            reason TEXT,
+            changed_by TEXT,
+            changed_at TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_repair_status_changes_repair ON repair_status_changes(repair_id, changed_at);
+        INSERT OR IGNORE INTO repair_statuses (name, kind, position, requires_reason) VALUES
+            ('Pending', 'Open', 0, 0),
+            ('In Progress', 'Open', 1, 0),
+            ('Waiting for Parts', 'Open', 2, 0),
+            ('Waiting for Approval', 'Open', 3, 0),
+            ('Completed', 'Done', 4, 0),
+            ('Delivered', 'Done', 5, 0),
+            ('Unrepairable', 'Void', 6, 0),
+            ('Cancelled', 'Void', 7, 1);
+        INSERT OR IGNORE INTO repair_status_transitions (from_status, to_status) VALUES
+            ('Pending', 'In Progress'),
+            ('Pending', 'Waiting for Parts'),
+            ('Pending', 'Waiting for Approval'),
+            ('Pending', 'Cancelled'),
+            ('In Progress', 'Waiting for Parts'),
+            ('In Progress', 'Waiting for Approval'),
+            ('In Progress', 'Completed'),
+            ('In Progress', 'Unrepairable'),
+            ('In Progress', 'Cancelled'),
+            ('Waiting for Parts', 'In Progress'),
+            ('Waiting for Parts', 'Cancelled'),
+            ('Waiting for Approval', 'In Progress'),
+            ('Waiting for Approval', 'Cancelled'),
+            ('Completed', 'In Progress'),
+            ('Completed', 'Delivered'),
+            ('Unrepairable', 'Delivered'),
+            ('Cancelled', 'Pending');",
+    )?;
+    rebuild_with_check(
+        conn,
+        "repairs",
+        "CHECK(status IN ('Pending','In Progress','Completed','Delivered'))",
+        "REFERENCES repair_statuses(name)",
+    )?;
+    add_column_if_missing(conn, "repairs", "status_reason", "TEXT")?;
+
+    // Whether a used part is out of stock for good, so status changes can put it back.
+    // Stocked parts not on hold have been taken out.
+    add_column_if_missing(conn, "repair_used_parts", "taken_from_stock", "INTEGER NOT NULL DEFAULT 0")?;
+    conn.execute_batch(
+        "UPDATE repair_used_parts SET taken_from_stock = 1
+         WHERE part_id IN (SELECT id FROM inventory_items)
+         AND id NOT IN (SELECT line_id FROM stock_reservations WHERE status = 'Active')",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replace_column_type_matches_whole_names() {
+        let definition = "(id TEXT, amount REAL, total_amount REAL, amount_due REAL)";
+        assert_eq!(
+            replace_column_type(definition, "amount", "REAL", "INTEGER"),
+            "(id TEXT, amount INTEGER, total_amount REAL, amount_due REAL)"
+        );
+    }
+
+    #[test]
+    fn money_columns_become_integer_minor_units() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE clients (id TEXT PRIMARY KEY, name TEXT NOT NULL, credit_balance REAL DEFAULT 0, discount_rate REAL);
+             CREATE INDEX idx_clients_name ON clients(name);
+             INSERT INTO clients VALUES ('a', 'A', 0.30000000000000004, 0.5), ('b', 'B', 12.345, NULL), ('c', 'C', -19.999, 1.25);
+             CREATE TABLE orders (id TEXT PRIMARY KEY, total_amount REAL NOT NULL, paid_amount REAL NOT NULL DEFAULT 0);
+             INSERT INTO orders VALUES ('o', 33.33 + 33.33 + 33.34, 0.1);",
+        )
+        .unwrap();
+
+        convert_money_to_minor_units(&conn).unwrap();
+
+        let clients: Vec<(i64, String, Option<f64>)> = conn
+            .prepare("SELECT credit_balance, typeof(credit_balance), discount_rate FROM clients ORDER BY id")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(
+            clients,
+            vec![
+                (30, "integer".to_string(), Some(0.5)),
+                (1235, "integer".to_string(), None),
+                (-2000, "integer".to_string(), Some(1.25)),
+            ]
+        );
+        let order: (i64, i64) = conn
+            .query_row("SELECT total_amount, paid_amount FROM orders", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!(order, (10000, 10));
+
+        // Only the money columns change type, and indexes survive the rebuild
+        let sql: String = conn
+            .query_row("SELECT sql FROM sqlite_master WHERE name = 'clients'", [], |row| row.get(0))
+            .unwrap();
+        assert!(sql.contains("credit_balance INTEGER DEFAULT 0"));
+        assert!(sql.contains("discount_rate REAL"));
+        let index: Option<String> = conn
+            .query_row("SELECT name FROM sqlite_master WHERE name = 'idx_clients_name'", [], |row| row.get(0))
+            .optional()
+            .unwrap();
+        assert!(index.is_some());
+    }
+}
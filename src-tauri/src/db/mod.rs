@@ -1,9 +1,25 @@
 pub mod inventory;
+pub mod migrations;
 pub mod models;
+pub mod money;
+pub mod currency;
+pub mod tax;
+pub mod discount;
+pub mod costing;
+pub mod reservation;
+pub mod stock_take;
+pub mod location;
+pub mod serial;
+pub mod product;
+pub mod reorder;
+pub mod shopping_list;
 pub mod repair;
+pub mod repair_status;
 pub mod schema;
 pub mod supplier;
 pub mod order;
+pub mod goods_receipt;
+pub mod price_list;
 pub mod client;
 pub mod sale;
 pub mod expense;
@@ -14,6 +30,7 @@ pub mod task;
 pub mod payment;
 
 use rusqlite::{Connection, Result};
+use std::ops::{Deref, DerefMut};
 use std::path::PathBuf;
 use std::sync::Mutex;
 
@@ -21,6 +38,11 @@ lazy_static::lazy_static! {
     static ref DB_PATH: Mutex<PathBuf> = Mutex::new(PathBuf::new());
 }
 
+/// Upper bound on idle connections kept open by the pool.
+const MAX_IDLE_CONNECTIONS: usize = 4;
+/// Number of prepared statements each pooled connection keeps cached.
+const STATEMENT_CACHE_CAPACITY: usize = 64;
+
 /// Initialize the database path.
 /// For a Tauri app, we use platform-safe application data location.
 pub fn init_db_path() {
@@ -42,23 +64,112 @@ pub fn init_db_path() {
 }
 
 /// Get the current DB path.
-fn current_db_path() -> PathBuf {
+pub fn current_db_path() -> PathBuf {
     DB_PATH.lock().unwrap().clone()
 }
 
-/// Open a connection to the SQLite database.
-pub fn get_connection() -> Result<Connection> {
-    let path = current_db_path();
-
+/// Open a new connection to the SQLite database with our standard pragmas.
+fn open_connection(path: &PathBuf) -> Result<Connection> {
     // Ensure the directory exists
     if let Some(parent) = path.parent() {
         std::fs::create_dir_all(parent).unwrap();
     }
 
-    let conn = Connection::open(&path)?;
+    let conn = Connection::open(path)?;
     // Enable WAL mode to improve concurrency
     conn.pragma_update(None, "journal_mode", "WAL")?;
     // Enable foreign key constraints
     conn.pragma_update(None, "foreign_keys", "ON")?;
+    conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
     Ok(conn)
 }
+
+/// Pool of SQLite connections held in Tauri managed state.
+/// Connections are opened once and reused, so the pragmas and the per-connection
+/// prepared statement cache (`prepare_cached`) survive across commands.
+pub struct DbPool {
+    path: PathBuf,
+    idle: Mutex<Vec<Connection>>,
+}
+
+impl DbPool {
+    pub fn new(path: PathBuf) -> Self {
+        DbPool {
+            path,
+            idle: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Check out a connection, reusing an idle one when available.
+    pub fn get(&self) -> Result<PooledConnection<'_>> {
+        let reused = self.idle.lock().unwrap_or_else(|e| e.into_inner()).pop();
+        let conn = match reused {
+            Some(conn) => conn,
+            None => open_connection(&self.path)?,
+        };
+        Ok(PooledConnection {
+            pool: self,
+            conn: Some(conn),
+        })
+    }
+
+    fn release(&self, conn: Connection) {
+        // Never hand back a connection that is stuck inside a transaction.
+        if !conn.is_autocommit() {
+            return;
+        }
+        let mut idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+        if idle.len() < MAX_IDLE_CONNECTIONS {
+            idle.push(conn);
+        }
+    }
+}
+
+/// A connection checked out of the `DbPool`; returned to the pool when dropped.
+pub struct PooledConnection<'a> {
+    pool: &'a DbPool,
+    conn: Option<Connection>,
+}
+
+impl Deref for PooledConnection<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn.as_ref().expect("connection already released")
+    }
+}
+
+impl DerefMut for PooledConnection<'_> {
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.conn.as_mut().expect("connection already released")
+    }
+}
+
+impl Drop for PooledConnection<'_> {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            self.pool.release(conn);
+        }
+    }
+}
+
+/// Turn user input into a `LIKE ... ESCAPE '\\'` pattern matching it anywhere in the text.
+pub(crate) fn contains_pattern(term: &str) -> String {
+    let escaped = term.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+/// Turn user input into an FTS5 query where every word must match as a prefix.
+/// Returns `None` when the input has no searchable words.
+pub(crate) fn fts_prefix_query(term: &str) -> Option<String> {
+    let words: Vec<String> = term
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| format!("\"{}\"*", w))
+        .collect();
+    if words.is_empty() {
+        None
+    } else {
+        Some(words.join(" "))
+    }
+}
@@ -1,4 +1,5 @@
 // This file holds your data models
+use crate::db::money::Money;
 use serde::{Deserialize, Serialize};
 
 /// REPAIRS
@@ -10,12 +11,30 @@ pub struct Repair {
     pub device_brand: String,
     pub device_model: String,
     pub issue_description: String,
-    pub estimated_cost: f64,
+    pub estimated_cost: Money,
     pub status: String,
+    #[serde(default)]
+    pub status_reason: Option<String>, // why it got its status, e.g. the cancellation reason
     pub payment_status: String,
     pub created_at: String,
     pub updated_at: String,
     pub code: Option<String>,
+    #[serde(default)]
+    pub device_serial: Option<String>, // serial number or IMEI of the customer's device
+    #[serde(default)]
+    pub tax_rate_id: Option<String>, // labor tax rate, the default labor rate when None
+    #[serde(default)]
+    pub tax_rate: f64, // percent, copied from the rate
+    #[serde(default)]
+    pub tax_inclusive: bool, // estimated_cost already includes the tax
+    #[serde(default)]
+    pub tax_amount: Money,
+    #[serde(default)]
+    pub total_amount: Money, // estimated_cost less discount plus exclusive tax: what the customer owes
+    #[serde(default)]
+    pub discount: Option<Discount>, // off the labor, before tax
+    #[serde(default)]
+    pub discount_amount: Money,
     
     // Virtual fields for full details
     #[serde(default)]
@@ -26,15 +45,51 @@ pub struct Repair {
     pub history: Vec<RepairHistory>,
 }
 
+/// Filters, sort and paging for `query_repairs`. Unset fields don't filter.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RepairQuery {
+    pub status: Option<String>,
+    pub payment_status: Option<String>,
+    pub start_date: Option<String>, // inclusive, RFC 3339 or YYYY-MM-DD
+    pub end_date: Option<String>,   // inclusive, a bare date covers the whole day
+    pub device_brand: Option<String>,
+    pub device_model: Option<String>,
+    pub customer_phone: Option<String>,
+    pub search: Option<String>, // full-text on issue_description and code
+    pub sort_by: Option<String>, // "created_at" (default), "updated_at", "estimated_cost", "customer_name" or "status"
+    pub sort_desc: Option<bool>, // defaults to newest first
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RepairStatusCount {
+    pub status: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RepairPage {
+    pub repairs: Vec<Repair>,
+    pub total_count: i64, // matches for all filters, across all pages
+    pub status_counts: Vec<RepairStatusCount>, // matches per status, ignoring the status filter
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct RepairPayment {
     pub id: String,
     pub repair_id: String,
-    pub amount: f64,
+    pub amount: Money,
     pub date: String,
     pub method: String,
     pub received_by: Option<String>,
     pub session_id: Option<String>,
+    #[serde(default)]
+    pub currency_code: Option<String>, // currency tendered, the base currency when None
+    #[serde(default)]
+    pub original_amount: Option<Money>, // amount as tendered; `amount` is always in the base currency
+    #[serde(default)]
+    pub exchange_rate: Option<f64>, // base currency units per unit of currency_code
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -45,7 +100,13 @@ pub struct RepairUsedPart {
     pub part_name: String,
     pub quantity: i32,
     #[serde(rename = "cost")]
-    pub unit_price: f64,
+    pub unit_price: Money,
+    #[serde(default)]
+    pub unit_cost: Option<Money>, // cost of goods when the part was booked
+    #[serde(default)]
+    pub location_id: Option<String>, // where the part is taken from, the default location when None
+    #[serde(default)]
+    pub serial_numbers: Vec<String>, // units fitted, one per quantity for serialized parts
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -58,20 +119,62 @@ pub struct RepairHistory {
     pub changed_by: Option<String>,
 }
 
+/// A status a repair can be in and the statuses it can move to from there.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RepairStatusDefinition {
+    pub name: String,
+    pub kind: String, // "Open" (being worked on), "Done" (parts are used) or "Void" (parts go back)
+    #[serde(default)]
+    pub position: i64, // order on the board
+    #[serde(default)]
+    pub requires_reason: bool,
+    #[serde(default)]
+    pub transitions: Vec<String>,
+}
+
+/// A stretch of time a repair spent in one status.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RepairStatusPeriod {
+    pub status: String,
+    pub entered_at: String,
+    pub left_at: Option<String>, // None while the repair is still in it
+    pub hours: f64,              // up to now for the current status
+    pub reason: Option<String>,
+    pub changed_by: Option<String>,
+}
+
+/// How long repairs stay in a status on average.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RepairStatusTurnaround {
+    pub status: String,
+    pub repairs: i64,
+    pub average_hours: f64,
+}
+
+/// Turnaround of the repairs received in a period.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RepairTurnaround {
+    pub statuses: Vec<RepairStatusTurnaround>, // time spent in each status, left statuses only
+    pub finished: i64,                          // repairs that reached a Done status
+    pub average_hours_to_done: Option<f64>,     // from intake to the first Done status
+}
+
 /// ORDERS
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Order {
     pub id: String,
     pub order_number: String,
     pub supplier_id: String,
-    pub status: String,
+    pub status: String, // "draft", "partially_received" or "completed"
     pub payment_status: String,
-    pub total_amount: f64,
-    pub paid_amount: f64,
+    pub total_amount: Money,
+    pub paid_amount: Money,
     pub notes: Option<String>,
     pub created_at: String,
     pub updated_at: String,
     pub created_by: Option<String>,
+    #[serde(default)]
+    pub location_id: Option<String>, // where the goods are received
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -81,16 +184,20 @@ pub struct OrderItem {
     pub item_id: Option<String>,
     pub item_name: String,
     pub quantity: i32,
-    pub unit_price: f64,
-    pub total_price: f64,
+    pub unit_price: Money,
+    pub total_price: Money,
     pub notes: Option<String>,
+    #[serde(default)]
+    pub received_quantity: i32, // delivered so far in goods receipts
+    #[serde(default)]
+    pub cancelled_quantity: i32, // no longer expected from the supplier
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct OrderPayment {
     pub id: String,
     pub order_id: String,
-    pub amount: f64,
+    pub amount: Money,
     pub method: String,
     pub date: String,
     pub received_by: Option<String>,
@@ -115,6 +222,60 @@ pub struct OrderWithDetails {
     pub payments: Vec<OrderPayment>,
     pub supplier_name: String,
 }
+
+/// A supplier's orders and the goods sent back to it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SupplierOrders {
+    pub orders: Vec<Order>,
+    pub returns: Vec<Transaction>, // PurchaseReturn documents, newest first
+}
+
+/// A delivery against an order: what arrived of each line.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GoodsReceipt {
+    pub id: String,
+    pub receipt_number: String,
+    pub order_id: String,
+    pub location_id: Option<String>,
+    pub received_by: Option<String>,
+    pub notes: Option<String>,
+    pub created_at: String,
+    pub lines: Vec<GoodsReceiptLine>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GoodsReceiptLine {
+    #[serde(default)]
+    pub id: String,
+    pub order_item_id: String,
+    #[serde(default)]
+    pub item_id: Option<String>,
+    #[serde(default)]
+    pub item_name: String,
+    pub quantity: i32,
+    #[serde(default)]
+    pub unit_cost: Money,
+}
+
+/// An item on a supplier's price list.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SupplierItem {
+    pub supplier_id: String,
+    #[serde(default)]
+    pub supplier_name: String,
+    pub item_id: String,
+    #[serde(default)]
+    pub item_name: String,
+    pub supplier_sku: Option<String>,
+    pub price: Option<Money>, // current price per unit
+    pub min_order_quantity: i64,
+    #[serde(default)]
+    pub last_purchase_price: Option<Money>, // set when an order or purchase completes
+    #[serde(default)]
+    pub last_purchase_date: Option<String>,
+    #[serde(default)]
+    pub updated_at: String,
+}
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ClientHistoryEvent {
     pub id: String,
@@ -122,7 +283,7 @@ pub struct ClientHistoryEvent {
     pub date: String,
     pub event_type: String, // Renamed from type_name for consistency
     pub notes: Option<String>,
-    pub amount: f64, // Made non-optional for history
+    pub amount: Money, // Made non-optional for history
     pub changed_by: Option<String>,
 }
 
@@ -133,7 +294,7 @@ pub struct SupplierHistoryEvent {
     pub date: String,
     pub event_type: String,
     pub notes: Option<String>,
-    pub amount: f64,
+    pub amount: Money,
     pub changed_by: Option<String>,
 }
 
@@ -141,7 +302,7 @@ pub struct SupplierHistoryEvent {
 pub struct ClientPayment {
     pub id: String,
     pub client_id: String,
-    pub amount: f64,
+    pub amount: Money,
     pub method: String,
     pub date: String,
     pub notes: Option<String>,
@@ -152,7 +313,7 @@ pub struct ClientPayment {
 pub struct SupplierPayment {
     pub id: String,
     pub supplier_id: String,
-    pub amount: f64,
+    pub amount: Money,
     pub method: String,
     pub date: String,
     pub notes: Option<String>,
@@ -167,12 +328,18 @@ pub struct Sale {
     pub client_id: String,
     pub status: String,
     pub payment_status: String,
-    pub total_amount: f64,
-    pub paid_amount: f64,
+    pub total_amount: Money,
+    pub paid_amount: Money,
     pub notes: Option<String>,
     pub created_at: String,
     pub updated_at: String,
     pub created_by: Option<String>,
+    #[serde(default)]
+    pub discount: Option<Discount>, // off the sum of the lines
+    #[serde(default)]
+    pub discount_amount: Money,
+    #[serde(default)]
+    pub location_id: Option<String>, // where the stock is sold from
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -182,16 +349,22 @@ pub struct SaleItem {
     pub item_id: Option<String>,
     pub item_name: String,
     pub quantity: i32,
-    pub unit_price: f64,
-    pub total_price: f64,
+    pub unit_price: Money,
+    pub total_price: Money, // unit_price * quantity less discount_amount
     pub notes: Option<String>,
+    #[serde(default)]
+    pub discount: Option<Discount>,
+    #[serde(default)]
+    pub discount_amount: Money,
+    #[serde(default)]
+    pub unit_cost: Option<Money>, // cost of goods when the sale was completed
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SalePayment {
     pub id: String,
     pub sale_id: String,
-    pub amount: f64,
+    pub amount: Money,
     pub method: String,
     pub date: String,
     pub received_by: Option<String>,
@@ -221,12 +394,18 @@ pub struct SaleWithDetails {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Expense {
     pub id: String,
-    pub amount: f64,
+    pub amount: Money,
     pub reason: String,
     pub date: String,
     pub session_id: Option<String>,
     pub category: Option<String>,
     pub created_by: Option<String>,
+    #[serde(default)]
+    pub currency_code: Option<String>, // currency tendered, the base currency when None
+    #[serde(default)]
+    pub original_amount: Option<Money>, // amount as tendered; `amount` is always in the base currency
+    #[serde(default)]
+    pub exchange_rate: Option<f64>, // base currency units per unit of currency_code
 }
 
 /// DAILY SESSIONS
@@ -235,31 +414,429 @@ pub struct DailySession {
     pub id: String,
     pub start_time: String,
     pub end_time: Option<String>,
-    pub opening_balance: f64,
-    pub closing_balance: Option<f64>,
-    pub counted_amount: Option<f64>,
-    pub withdrawal_amount: Option<f64>,
+    pub opening_balance: Money,
+    pub closing_balance: Option<Money>,
+    pub counted_amount: Option<Money>,
+    pub withdrawal_amount: Option<Money>,
     pub status: String,
     pub notes: Option<String>,
     pub created_by: Option<String>,
 }
 
+/// CURRENCIES
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Currency {
+    pub code: String, // ISO 4217, e.g. "EUR"
+    pub name: String,
+    pub symbol: String,
+    pub minor_units: i64,
+    pub active: bool,
+    #[serde(default)]
+    pub is_base: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExchangeRate {
+    pub id: String,
+    pub currency_code: String,
+    pub rate: f64, // base currency units per unit of currency_code
+    pub effective_date: String, // YYYY-MM-DD, applies until the next rate
+    pub created_at: String,
+}
+
+/// Money tendered in one currency and method during a session, for the drawer count.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SessionCurrencyTotal {
+    pub currency_code: String,
+    pub method: String,
+    pub total_in: Money,  // in currency_code
+    pub total_out: Money, // in currency_code
+    pub net: Money,       // in currency_code
+    pub net_base: Money,  // in the base currency
+}
+
+/// COSTING
+/// Stock received at one cost. Sales use up the oldest layers first.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CostLayer {
+    pub id: String,
+    pub item_id: String,
+    pub source_type: String, // "Opening", "Purchase", "Order", "Return", "Restock" or "Adjustment"
+    pub source_id: Option<String>,
+    pub quantity: i64,
+    pub remaining: i64,
+    pub unit_cost: Money,
+    pub created_at: String,
+}
+
+/// RESERVATIONS
+/// Stock held for a draft sale line or a part booked on an open repair.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StockReservation {
+    pub id: String,
+    pub item_id: String,
+    pub source_type: String, // "Transaction", "Sale" or "Repair"
+    pub source_id: String,   // the document holding the stock
+    pub line_id: String,     // its line or used part
+    pub quantity: i64,
+    pub status: String, // "Active", "Released" or "Converted"
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// STOCK TAKES
+/// A physical count of all items, or of one brand and/or type.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StockTake {
+    pub id: String,
+    pub name: String,
+    pub status: String, // "Open", "Approved" or "Cancelled"
+    pub phone_brand: Option<String>, // filter the count was started with
+    pub item_type: Option<String>,
+    #[serde(default)]
+    pub location_id: Option<String>, // location counted
+    pub notes: Option<String>,
+    pub created_at: String,
+    pub created_by: Option<String>,
+    pub approved_at: Option<String>,
+    pub approved_by: Option<String>,
+    #[serde(default)]
+    pub line_count: i64,
+    #[serde(default)]
+    pub counted_count: i64,
+}
+
+/// One item in a stock take, with the stock and cost it had when the count started.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StockTakeLine {
+    pub id: String,
+    pub stock_take_id: String,
+    pub item_id: String,
+    pub item_name: String,
+    pub barcode: Option<String>,
+    pub expected_quantity: i64,
+    pub counted_quantity: Option<i64>,
+    pub variance: Option<i64>, // counted less expected, once counted
+    pub unit_cost: Money,
+    pub variance_value: Option<Money>, // variance at unit_cost
+    pub counted_at: Option<String>,
+    pub counted_by: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StockTakeWithLines {
+    pub stock_take: StockTake,
+    pub lines: Vec<StockTakeLine>,
+}
+
+/// Counted lines that differ from the snapshot, valued at cost.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StockTakeVarianceReport {
+    pub stock_take: StockTake,
+    pub lines: Vec<StockTakeLine>,
+    pub uncounted_items: i64,
+    pub units_over: i64,
+    pub units_short: i64,
+    pub value_over: Money,
+    pub value_short: Money,
+    pub net_value: Money,
+}
+
+/// LOCATIONS
+/// A place stock is kept: the shop floor, a back room, the technician bench or a branch.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Location {
+    pub id: String,
+    pub name: String,
+    pub location_type: String, // "Shop", "Storage", "Bench" or "Branch"
+    #[serde(default)]
+    pub is_default: bool, // holds whatever stock isn't booked to another location
+    pub active: bool,
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub created_at: String,
+}
+
+/// Stock of one item at one location.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LocationStockLevel {
+    pub location_id: String,
+    pub location_name: String,
+    pub item_id: String,
+    pub item_name: String,
+    pub phone_brand: String,
+    pub item_type: String,
+    pub barcode: Option<String>,
+    pub quantity: i64,
+    pub low_stock_threshold: Option<i64>, // the location's own, else the item's
+}
+
+/// Stock moved from one location to another.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StockTransfer {
+    pub id: String,
+    pub transfer_number: String,
+    pub from_location_id: String,
+    pub to_location_id: String,
+    pub notes: Option<String>,
+    pub created_at: String,
+    pub created_by: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StockTransferItem {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub transfer_id: String,
+    pub item_id: String,
+    #[serde(default)]
+    pub item_name: String,
+    pub quantity: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StockTransferWithItems {
+    pub transfer: StockTransfer,
+    pub items: Vec<StockTransferItem>,
+    pub from_location_name: String,
+    pub to_location_name: String,
+}
+
+/// SERIAL NUMBERS
+/// One unit of a serialized item, known by its serial number or IMEI.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SerialUnit {
+    pub id: String,
+    pub item_id: String,
+    #[serde(default)]
+    pub item_name: String,
+    pub serial_number: String,
+    pub status: String, // "In Stock", "Sold", "In Repair", "Returned" or "RMA"
+    pub notes: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// A change of a unit's status and the document that made it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SerialUnitEvent {
+    pub id: String,
+    pub serial_id: String,
+    pub date: String,
+    pub event_type: String,
+    pub status: String,
+    pub previous_status: Option<String>,
+    pub source_type: String, // "Transaction", "Repair" or "Manual"
+    pub source_id: Option<String>,
+    pub reference: Option<String>, // transaction number or repair code
+    pub notes: Option<String>,
+    pub changed_by: Option<String>,
+    pub reversed_at: Option<String>, // set once the document line was undone
+}
+
+/// Everything known about one serial number: the unit and its events if it went
+/// through stock, and the repairs of a customer device carrying it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SerialHistory {
+    pub serial_number: String,
+    pub unit: Option<SerialUnit>,
+    pub events: Vec<SerialUnitEvent>,
+    pub repairs: Vec<Repair>,
+}
+
+/// PRODUCTS
+/// A product groups the inventory items that are variants of it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Product {
+    pub id: String,
+    pub name: String,
+    pub phone_brand: Option<String>,
+    pub item_type: Option<String>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub attribute_names: Vec<String>, // e.g. ["Model", "Color", "Grade"], in label order
+    #[serde(default)]
+    pub variant_count: i64,
+    #[serde(default)]
+    pub created_at: String,
+}
+
+/// One attribute of a variant, e.g. Color = Black.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ItemAttribute {
+    pub name: String,
+    pub value: String,
+}
+
+/// An item a bundle is made of and how many units of it go in one bundle.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BundleComponent {
+    pub component_id: String,
+    #[serde(default)]
+    pub component_name: String,
+    pub quantity: i64,
+}
+
+/// REORDERING
+/// How reorder quantities are worked out.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReorderSettings {
+    pub window_days: i64,    // how far back consumption is averaged over
+    pub lead_time_days: i64, // days between ordering and receiving
+    pub cover_days: i64,     // days of consumption a delivery should last once it arrives
+}
+
+/// An item that should be ordered, and why.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReorderSuggestion {
+    pub item_id: String,
+    pub item_name: String,
+    pub variant_label: Option<String>,
+    pub supplier_id: Option<String>,
+    pub on_hand: i64,
+    pub reserved: i64,
+    pub on_order: i64, // still to come on open orders, plus draft purchases
+    pub consumed: i64, // sold and used in repairs over the window, net of returns
+    pub daily_usage: f64,
+    pub reorder_point: i64, // order once available plus on order falls to this
+    pub target_level: i64, // what an order brings available plus on order up to
+    pub suggested_quantity: i64,
+    pub unit_cost: Money,
+}
+
+/// Suggestions for one supplier; `supplier_id` is None for items nobody supplies yet.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReorderGroup {
+    pub supplier_id: Option<String>,
+    pub supplier_name: Option<String>,
+    pub items: Vec<ReorderSuggestion>,
+    pub estimated_total: Money,
+}
+
+/// A line to order, usually taken from a suggestion and possibly edited.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReorderLine {
+    pub item_id: String,
+    pub supplier_id: String,
+    pub quantity: i64,
+    #[serde(default)]
+    pub unit_price: Option<Money>, // the item's buying price when None
+}
+
+/// A draft order or purchase made from reorder lines.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReorderDocument {
+    pub document_type: String, // "Order" or "Purchase"
+    pub id: String,
+    pub number: String,
+    pub supplier_id: String,
+    pub line_count: i64,
+    pub total_amount: Money,
+}
+
+/// SHOPPING LIST
+/// Something to buy, noted down before it goes on a supplier order.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ShoppingListItem {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub item_id: Option<String>, // inventory item it restocks, if any
+    pub quantity: i64,
+    #[serde(default)]
+    pub estimated_cost: Option<Money>, // for the whole quantity
+    #[serde(default)]
+    pub supplier_id: Option<String>,
+    #[serde(default)]
+    pub supplier_name: Option<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    pub urgency: String, // "Low", "Medium" or "High"
+    pub status: String,  // "Draft", "Pending", "Ordered", "Received" or "Cancelled"
+    #[serde(default)]
+    pub order_id: Option<String>, // supplier order it was put on
+    #[serde(default)]
+    pub ordered_at: Option<String>,
+    #[serde(default)]
+    pub received_at: Option<String>,
+    #[serde(default)]
+    pub created_at: String,
+    #[serde(default)]
+    pub updated_at: String,
+}
+
+/// DISCOUNTS
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Discount {
+    pub kind: String, // "Percent" or "Fixed"
+    pub value: f64,   // percent, or an amount in major units
+    pub reason: String,
+    pub applied_by: String, // cashier who gave the discount
+}
+
+/// TAXES
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TaxRate {
+    pub id: String,
+    pub name: String, // e.g. "VAT 20%"
+    pub rate: f64,    // percent
+    pub inclusive: bool, // prices entered with this rate already include the tax
+    pub active: bool,
+    pub created_at: String,
+}
+
+/// Tax of a document at one rate.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TaxLine {
+    pub tax_rate_id: Option<String>,
+    pub name: String,
+    pub rate: f64,
+    pub net_amount: Money,
+    pub tax_amount: Money,
+}
+
+/// One rate in the tax summary: tax collected on sales and repairs (net of customer
+/// returns) against tax paid on purchases (net of purchase returns).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TaxSummaryRow {
+    pub tax_rate_id: Option<String>,
+    pub name: String,
+    pub rate: f64,
+    pub sales_net: Money,
+    pub sales_tax: Money,
+    pub purchases_net: Money,
+    pub purchases_tax: Money,
+    pub net_tax: Money, // sales_tax - purchases_tax
+}
+
 /// TRANSACTIONS (Unified)
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Transaction {
     pub id: String,
     pub transaction_number: String,
-    pub transaction_type: String, // "Sale" or "Purchase"
+    pub transaction_type: String, // "Sale", "Purchase", "Return" or "PurchaseReturn"
     pub party_id: String,
     pub party_type: String, // "Client" or "Supplier"
     pub status: String,
     pub payment_status: String,
-    pub total_amount: f64,
-    pub paid_amount: f64,
+    pub total_amount: Money,
+    pub paid_amount: Money,
     pub notes: Option<String>,
     pub created_at: String,
     pub updated_at: String,
     pub created_by: Option<String>,
+    pub original_transaction_id: Option<String>, // Sale or Purchase a return was made against
+    #[serde(default)]
+    pub subtotal: Money, // sum of line net amounts
+    #[serde(default)]
+    pub tax_amount: Money, // total_amount = subtotal + tax_amount
+    #[serde(default)]
+    pub discount: Option<Discount>, // document discount, spread over the lines before tax
+    #[serde(default)]
+    pub discount_amount: Money,
+    #[serde(default)]
+    pub location_id: Option<String>, // where stock leaves or arrives, the default location when None
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -269,21 +846,57 @@ pub struct TransactionItem {
     pub item_id: Option<String>,
     pub item_name: String,
     pub quantity: i32,
-    pub unit_price: f64,
-    pub total_price: f64,
+    pub unit_price: Money,
+    pub total_price: Money,
     pub notes: Option<String>,
+    pub original_item_id: Option<String>, // Sale line a return line refers to
+    #[serde(default)]
+    pub tax_rate_id: Option<String>, // the item's rate when None
+    #[serde(default)]
+    pub tax_rate: f64, // percent, copied from the rate
+    #[serde(default)]
+    pub tax_inclusive: bool, // unit_price already includes the tax
+    #[serde(default)]
+    pub net_amount: Money, // total_price without tax
+    #[serde(default)]
+    pub tax_amount: Money, // total_price = net_amount + tax_amount
+    #[serde(default)]
+    pub discount: Option<Discount>,
+    #[serde(default)]
+    pub discount_amount: Money, // from the line's own discount
+    #[serde(default)]
+    pub document_discount_amount: Money, // share of the document discount
+    #[serde(default)]
+    pub unit_cost: Option<Money>, // cost of goods sold, or paid per unit on purchases
+    #[serde(default)]
+    pub serial_numbers: Vec<String>, // units moved, one per quantity for serialized items
+}
+
+/// One line of a customer return: how many units of an original sale line come back.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReturnItemRequest {
+    pub original_item_id: String,
+    pub quantity: i32,
+    #[serde(default)]
+    pub serial_numbers: Vec<String>, // units coming back, taken from the original line
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TransactionPayment {
     pub id: String,
     pub transaction_id: String,
-    pub amount: f64,
+    pub amount: Money,
     pub method: String,
     pub date: String,
     pub received_by: Option<String>,
     pub notes: Option<String>,
     pub session_id: Option<String>,
+    #[serde(default)]
+    pub currency_code: Option<String>, // currency tendered, the base currency when None
+    #[serde(default)]
+    pub original_amount: Option<Money>, // amount as tendered; `amount` is always in the base currency
+    #[serde(default)]
+    pub exchange_rate: Option<f64>, // base currency units per unit of currency_code
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -302,6 +915,33 @@ pub struct TransactionWithDetails {
     pub items: Vec<TransactionItem>,
     pub payments: Vec<TransactionPayment>,
     pub party_name: String,
+    #[serde(default)]
+    pub taxes: Vec<TaxLine>, // tax per rate, for receipts
+}
+
+/// Filters, sort and paging for `query_transactions`. Unset fields don't filter.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TransactionQuery {
+    pub transaction_type: Option<String>,
+    pub status: Option<String>,
+    pub payment_status: Option<String>,
+    pub party_id: Option<String>,
+    pub start_date: Option<String>, // inclusive, RFC 3339 or YYYY-MM-DD
+    pub end_date: Option<String>,   // inclusive, a bare date covers the whole day
+    pub min_amount: Option<Money>,
+    pub max_amount: Option<Money>,
+    pub search: Option<String>, // matched against transaction_number and notes
+    pub sort_by: Option<String>, // "created_at" (default), "updated_at", "transaction_number" or "total_amount"
+    pub sort_desc: Option<bool>, // defaults to newest / largest first
+    pub limit: Option<i64>,
+    pub cursor: Option<String>, // next_cursor of the previous page
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransactionPage {
+    pub transactions: Vec<Transaction>,
+    pub total_count: i64, // matches for the filters, across all pages
+    pub next_cursor: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -309,7 +949,7 @@ pub struct DashboardTransaction {
     pub id: String,
     pub tx_type: String, // "credit" or "debit"
     pub category: String,
-    pub amount: f64,
+    pub amount: Money,
     pub description: String,
     pub time: String,
     pub status: String,
@@ -318,27 +958,29 @@ pub struct DashboardTransaction {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct RevenueData {
     pub date: String,
-    pub revenue: f64,
-    pub profit: f64,
+    pub revenue: Money,
+    pub profit: Money,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct RevenueBreakdown {
     pub category: String,
-    pub amount: f64,
+    pub amount: Money,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DashboardStats {
-    pub total_revenue: f64,
-    pub net_cash: f64,
-    pub net_profit: f64,
+    pub total_revenue: Money,
+    pub net_cash: Money,
+    pub net_profit: Money,
     pub active_repairs: i32,
     pub completed_repairs: i32,
     pub stock_alerts: i32,
     pub out_of_stock: i32,
     pub revenue_change: f64,
-    pub repair_profit: f64,
+    pub repair_profit: Money,
+    #[serde(default)]
+    pub discounts_given: Money, // line and document discounts, net of returns
 }
 
 /// TASKS
@@ -359,7 +1001,7 @@ pub struct UnifiedPayment {
     pub id: String,
     pub source_id: String,
     pub source_type: String, // "Repair", "Sale", "Order", "Client", "Supplier"
-    pub amount: f64,
+    pub amount: Money,
     pub date: String,
     pub method: String,
     pub received_by: Option<String>,
@@ -0,0 +1,389 @@
+use crate::db::DbPool;
+use crate::error::AppError;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+use std::sync::RwLock;
+use tauri::State;
+
+/// Currency amounts are stored and computed in, e.g. "USD" with 2 minor units (cents).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CurrencySettings {
+    pub code: String,
+    pub minor_units: u32,
+}
+
+impl Default for CurrencySettings {
+    fn default() -> Self {
+        CurrencySettings {
+            code: "USD".to_string(),
+            minor_units: 2,
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref CURRENCY: RwLock<CurrencySettings> = RwLock::new(CurrencySettings::default());
+}
+
+/// The currency loaded from `app_settings`.
+pub fn currency() -> CurrencySettings {
+    CURRENCY.read().map(|c| c.clone()).unwrap_or_default()
+}
+
+fn minor_per_major() -> i64 {
+    10_i64.pow(currency().minor_units)
+}
+
+/// Every column holding a `Money` amount, by table. Rescaled when the number of
+/// minor units of the currency changes.
+pub(crate) const MONEY_COLUMNS: &[(&str, &[&str])] = &[
+    ("inventory_items", &["buying_price", "selling_price", "average_cost"]),
+    ("repairs", &["estimated_cost", "tax_amount", "total_amount", "discount_amount"]),
+    ("repair_payments", &["amount", "original_amount"]),
+    ("repair_used_parts", &["unit_price", "unit_cost"]),
+    ("suppliers", &["credit_balance"]),
+    ("supplier_payments", &["amount"]),
+    ("supplier_history", &["amount"]),
+    ("orders", &["total_amount", "paid_amount"]),
+    ("order_items", &["unit_price", "total_price"]),
+    ("order_payments", &["amount"]),
+    ("clients", &["credit_balance"]),
+    ("client_payments", &["amount"]),
+    ("client_history", &["amount"]),
+    ("customer_sales", &["total_amount", "paid_amount", "discount_amount"]),
+    ("sale_items", &["unit_price", "total_price", "discount_amount", "unit_cost"]),
+    ("sale_payments", &["amount"]),
+    ("sales", &["unit_price", "total_price"]),
+    ("daily_sessions", &["opening_balance", "closing_balance", "counted_amount", "withdrawal_amount"]),
+    ("expenses", &["amount", "original_amount"]),
+    ("cost_layers", &["unit_cost"]),
+    ("stock_take_lines", &["unit_cost"]),
+    ("shopping_list_items", &["estimated_cost"]),
+    ("goods_receipt_lines", &["unit_cost"]),
+    ("supplier_items", &["price", "last_purchase_price"]),
+    ("transactions", &["total_amount", "paid_amount", "subtotal", "tax_amount", "discount_amount"]),
+    ("transaction_items", &["unit_price", "total_price", "net_amount", "tax_amount", "discount_amount", "document_discount_amount", "unit_cost"]),
+    ("transaction_payments", &["amount", "original_amount"]),
+];
+
+/// An amount of money as a whole number of minor units (cents) of the shop currency.
+/// Amounts in other currencies (`original_amount` on payments) use the same scale.
+///
+/// Stored as INTEGER in SQLite so sums and comparisons are exact. Crosses the
+/// frontend boundary as a decimal number of major units (`12.5` for 12.50), so
+/// the JSON contract is the same as before amounts were integers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Money(i64);
+
+impl Money {
+    pub const ZERO: Money = Money(0);
+
+    pub fn from_minor(minor: i64) -> Money {
+        Money(minor)
+    }
+
+    /// Round a decimal amount of major units to the nearest minor unit.
+    pub fn from_major(major: f64) -> Money {
+        Money((major * minor_per_major() as f64).round() as i64)
+    }
+
+    pub fn minor(self) -> i64 {
+        self.0
+    }
+
+    pub fn to_major(self) -> f64 {
+        self.0 as f64 / minor_per_major() as f64
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn is_positive(self) -> bool {
+        self.0 > 0
+    }
+
+    pub fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+
+    pub fn abs(self) -> Money {
+        Money(self.0.abs())
+    }
+}
+
+impl Add for Money {
+    type Output = Money;
+    fn add(self, rhs: Money) -> Money {
+        Money(self.0 + rhs.0)
+    }
+}
+
+impl Sub for Money {
+    type Output = Money;
+    fn sub(self, rhs: Money) -> Money {
+        Money(self.0 - rhs.0)
+    }
+}
+
+impl Neg for Money {
+    type Output = Money;
+    fn neg(self) -> Money {
+        Money(-self.0)
+    }
+}
+
+impl AddAssign for Money {
+    fn add_assign(&mut self, rhs: Money) {
+        self.0 += rhs.0;
+    }
+}
+
+impl SubAssign for Money {
+    fn sub_assign(&mut self, rhs: Money) {
+        self.0 -= rhs.0;
+    }
+}
+
+/// Price times quantity.
+impl Mul<i64> for Money {
+    type Output = Money;
+    fn mul(self, quantity: i64) -> Money {
+        Money(self.0 * quantity)
+    }
+}
+
+impl Sum for Money {
+    fn sum<I: Iterator<Item = Money>>(iter: I) -> Money {
+        iter.fold(Money::ZERO, Add::add)
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = currency().minor_units as usize;
+        write!(f, "{:.*}", digits, self.to_major())
+    }
+}
+
+impl ToSql for Money {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.0))
+    }
+}
+
+impl FromSql for Money {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value {
+            ValueRef::Integer(i) => Ok(Money(i)),
+            // Expressions such as AVG() or arithmetic with REAL operands
+            ValueRef::Real(f) => Ok(Money(f.round() as i64)),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.to_major())
+    }
+}
+
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Money, D::Error> {
+        struct MoneyVisitor;
+
+        impl Visitor<'_> for MoneyVisitor {
+            type Value = Money;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an amount in major currency units")
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> std::result::Result<Money, E> {
+                if v.is_finite() {
+                    Ok(Money::from_major(v))
+                } else {
+                    Err(E::custom("amount must be a finite number"))
+                }
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> std::result::Result<Money, E> {
+                Ok(Money(v * minor_per_major()))
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> std::result::Result<Money, E> {
+                Ok(Money(v as i64 * minor_per_major()))
+            }
+        }
+
+        deserializer.deserialize_any(MoneyVisitor)
+    }
+}
+
+/// Load the shop currency from `app_settings` into memory. Run after migrations.
+pub fn load_currency(conn: &Connection) -> Result<()> {
+    let code: Option<String> = conn
+        .query_row("SELECT value FROM app_settings WHERE key = 'currency_code'", [], |row| row.get(0))
+        .optional()?;
+    let minor_units: Option<String> = conn
+        .query_row("SELECT value FROM app_settings WHERE key = 'currency_minor_units'", [], |row| row.get(0))
+        .optional()?;
+
+    let defaults = CurrencySettings::default();
+    let settings = CurrencySettings {
+        code: code.unwrap_or(defaults.code),
+        minor_units: minor_units.and_then(|m| m.parse().ok()).unwrap_or(defaults.minor_units),
+    };
+    if let Ok(mut current) = CURRENCY.write() {
+        *current = settings;
+    }
+    Ok(())
+}
+
+/// Rescale the amounts in `columns` from `from_minor_units` to `to_minor_units`, keeping
+/// their value in major units, rounded to the nearest new minor unit.
+fn rescale_columns(
+    conn: &Connection,
+    columns: &[(&str, &[&str])],
+    from_minor_units: u32,
+    to_minor_units: u32,
+) -> Result<()> {
+    let old_scale = 10_i64.pow(from_minor_units);
+    let new_scale = 10_i64.pow(to_minor_units);
+    for (table, columns) in columns {
+        let assignments: Vec<String> = columns
+            .iter()
+            .map(|c| format!("{c} = CAST(ROUND({c} * ?1 / ?2) AS INTEGER)"))
+            .collect();
+        conn.execute(
+            &format!("UPDATE {} SET {}", table, assignments.join(", ")),
+            params![new_scale as f64, old_scale as f64],
+        )?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_currency_settings() -> Result<CurrencySettings, AppError> {
+    Ok(currency())
+}
+
+/// Change the shop currency. When the number of minor units changes, every stored
+/// amount is rescaled so its value in major units stays the same.
+#[tauri::command]
+pub fn set_currency_settings(
+    pool: State<'_, DbPool>,
+    code: String,
+    minor_units: u32,
+) -> Result<CurrencySettings, AppError> {
+    if code.trim().is_empty() {
+        return Err(AppError::Validation("Currency code is required".to_string()));
+    }
+    if minor_units > 4 {
+        return Err(AppError::Validation("A currency can have at most 4 minor units".to_string()));
+    }
+
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    let old = currency();
+
+    if minor_units != old.minor_units {
+        rescale_columns(&tx, MONEY_COLUMNS, old.minor_units, minor_units)?;
+    }
+
+    let settings = CurrencySettings {
+        code: code.trim().to_uppercase(),
+        minor_units,
+    };
+    tx.execute(
+        "INSERT OR REPLACE INTO app_settings (key, value) VALUES ('currency_code', ?1)",
+        params![settings.code],
+    )?;
+    tx.execute(
+        "INSERT OR REPLACE INTO app_settings (key, value) VALUES ('currency_minor_units', ?1)",
+        params![settings.minor_units.to_string()],
+    )?;
+    tx.execute(
+        "INSERT OR IGNORE INTO currencies (code, name, symbol, minor_units) VALUES (?1, ?1, ?1, ?2)",
+        params![settings.code, settings.minor_units],
+    )?;
+    tx.commit()?;
+
+    if let Ok(mut current) = CURRENCY.write() {
+        *current = settings.clone();
+    }
+    Ok(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The tests use the default currency, 2 minor units; nothing here changes it.
+
+    #[test]
+    fn from_major_rounds_to_the_nearest_minor_unit() {
+        assert_eq!(Money::from_major(0.1) + Money::from_major(0.2), Money::from_major(0.3));
+        assert_eq!(Money::from_major(0.1 + 0.2), Money::from_minor(30));
+        assert_eq!(Money::from_major(2.675), Money::from_minor(268));
+        assert_eq!(Money::from_major(-1.005), Money::from_minor(-100));
+    }
+
+    #[test]
+    fn installments_add_up_to_the_total() {
+        let total = Money::from_major(100.0);
+        let installment = Money::from_major(100.0 / 3.0);
+        assert_eq!(installment, Money::from_minor(3333));
+        let last = total - installment * 2;
+        assert_eq!(last, Money::from_minor(3334));
+        assert_eq!([installment, installment, last].into_iter().sum::<Money>(), total);
+    }
+
+    #[test]
+    fn deserializes_decimal_and_whole_amounts() {
+        assert_eq!(serde_json::from_str::<Money>("12.5").unwrap(), Money::from_minor(1250));
+        assert_eq!(serde_json::from_str::<Money>("0.30000000000000004").unwrap(), Money::from_minor(30));
+        assert_eq!(serde_json::from_str::<Money>("12").unwrap(), Money::from_minor(1200));
+        assert_eq!(serde_json::from_str::<Money>("-3").unwrap(), Money::from_minor(-300));
+        assert!(serde_json::from_str::<Money>("\"12\"").is_err());
+    }
+
+    #[test]
+    fn serializes_as_major_units() {
+        assert_eq!(serde_json::to_string(&Money::from_minor(1250)).unwrap(), "12.5");
+        for minor in [0, 1, 30, 1999, -4550, 123_456_789] {
+            let json = serde_json::to_string(&Money::from_minor(minor)).unwrap();
+            assert_eq!(serde_json::from_str::<Money>(&json).unwrap(), Money::from_minor(minor));
+        }
+    }
+
+    #[test]
+    fn rescaling_keeps_the_major_amount() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE amounts (id INTEGER PRIMARY KEY, price INTEGER, cost INTEGER);
+             INSERT INTO amounts (id, price, cost) VALUES (1, 1999, 5), (2, -250, NULL);",
+        )
+        .unwrap();
+        let columns: &[(&str, &[&str])] = &[("amounts", &["price", "cost"])];
+        let read = |conn: &Connection| -> Vec<(Option<i64>, Option<i64>)> {
+            let mut stmt = conn.prepare("SELECT price, cost FROM amounts ORDER BY id").unwrap();
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+            rows.collect::<Result<_>>().unwrap()
+        };
+
+        // 2 -> 3 minor units multiplies by ten
+        rescale_columns(&conn, columns, 2, 3).unwrap();
+        assert_eq!(read(&conn), vec![(Some(19990), Some(50)), (Some(-2500), None)]);
+
+        // 3 -> 0 rounds to whole units, half away from zero
+        rescale_columns(&conn, columns, 3, 0).unwrap();
+        assert_eq!(read(&conn), vec![(Some(20), Some(0)), (Some(-3), None)]);
+    }
+}
@@ -1,16 +1,24 @@
-use crate::db;
-use crate::db::models::{Order, OrderItem, OrderPayment, OrderWithDetails};
-use rusqlite::{params, Result};
+use crate::db::costing;
+use crate::db::goods_receipt;
+use crate::db::inventory;
+use crate::db::location;
+use crate::db::price_list;
+use crate::db::shopping_list;
+use crate::db::transaction;
+use crate::db::DbPool;
+use crate::db::money::Money;
+use crate::db::models::{Order, OrderItem, OrderPayment, OrderWithDetails, SupplierOrders};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use tauri::State;
+use crate::error::AppError;
 
 /// Generate a unique order number (e.g., ORD-2025-001)
-fn generate_order_number() -> Result<String, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+fn generate_order_number(conn: &Connection) -> Result<String, AppError> {
     let year = chrono::Utc::now().format("%Y").to_string();
     
     // Get the highest order number for this year
     let mut stmt = conn
-        .prepare("SELECT order_number FROM orders WHERE order_number LIKE ?1 ORDER BY order_number DESC LIMIT 1")
-        .map_err(|e| e.to_string())?;
+        .prepare_cached("SELECT order_number FROM orders WHERE order_number LIKE ?1 ORDER BY order_number DESC LIMIT 1")?;
     
     let next_number = match stmt.query_row(params![format!("ORD-{}-%", year)], |row| {
         let order_num: String = row.get(0)?;
@@ -36,17 +44,22 @@ fn generate_order_number() -> Result<String, String> {
 
 /// Create a new order
 #[tauri::command]
-pub fn create_order(mut order: Order) -> Result<Order, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
-    
+pub fn create_order(pool: State<'_, DbPool>, order: Order) -> Result<Order, AppError> {
+    let conn = pool.get()?;
+    insert_order(&conn, order)
+}
+
+/// `create_order` inside the caller's SQL transaction.
+pub(crate) fn insert_order(conn: &Connection, mut order: Order) -> Result<Order, AppError> {
     // Generate order number if not provided or empty
     if order.order_number.is_empty() {
-        order.order_number = generate_order_number()?;
+        order.order_number = generate_order_number(conn)?;
     }
+    order.location_id = Some(location::resolve_location(conn, order.location_id.as_deref())?);
     
     conn.execute(
-        "INSERT INTO orders (id, order_number, supplier_id, status, payment_status, total_amount, paid_amount, notes, created_at, updated_at, created_by)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        "INSERT INTO orders (id, order_number, supplier_id, status, payment_status, total_amount, paid_amount, notes, created_at, updated_at, created_by, location_id)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
         params![
             order.id,
             order.order_number,
@@ -59,9 +72,9 @@ pub fn create_order(mut order: Order) -> Result<Order, String> {
             order.created_at,
             order.updated_at,
             order.created_by,
+            order.location_id,
         ],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
     
     // Log creation in history
     let history_id = uuid::Uuid::new_v4().to_string();
@@ -76,24 +89,23 @@ pub fn create_order(mut order: Order) -> Result<Order, String> {
             format!("Order {} created", order.order_number),
             order.created_by,
         ],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
     
     Ok(order)
 }
 
 /// Get all orders with optional filtering
 #[tauri::command]
-pub fn get_orders(status_filter: Option<String>) -> Result<Vec<Order>, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn get_orders(pool: State<'_, DbPool>, status_filter: Option<String>) -> Result<Vec<Order>, AppError> {
+    let conn = pool.get()?;
     
     let query = if let Some(status) = status_filter {
-        format!("SELECT id, order_number, supplier_id, status, payment_status, total_amount, paid_amount, notes, created_at, updated_at, created_by FROM orders WHERE status = '{}' ORDER BY created_at DESC", status)
+        format!("SELECT id, order_number, supplier_id, status, payment_status, total_amount, paid_amount, notes, created_at, updated_at, created_by, location_id FROM orders WHERE status = '{}' ORDER BY created_at DESC", status)
     } else {
-        "SELECT id, order_number, supplier_id, status, payment_status, total_amount, paid_amount, notes, created_at, updated_at, created_by FROM orders ORDER BY created_at DESC".to_string()
+        "SELECT id, order_number, supplier_id, status, payment_status, total_amount, paid_amount, notes, created_at, updated_at, created_by, location_id FROM orders ORDER BY created_at DESC".to_string()
     };
     
-    let mut stmt = conn.prepare(&query).map_err(|e| e.to_string())?;
+    let mut stmt = conn.prepare(&query)?;
     
     let orders = stmt
         .query_map([], |row| {
@@ -109,9 +121,9 @@ pub fn get_orders(status_filter: Option<String>) -> Result<Vec<Order>, String> {
                 created_at: row.get(8)?,
                 updated_at: row.get(9)?,
                 created_by: row.get(10).ok(),
+                location_id: row.get(11)?,
             })
-        })
-        .map_err(|e| e.to_string())?
+        })?
         .filter_map(|res| res.ok())
         .collect();
     
@@ -120,13 +132,12 @@ pub fn get_orders(status_filter: Option<String>) -> Result<Vec<Order>, String> {
 
 /// Get a single order by ID with all details
 #[tauri::command]
-pub fn get_order_by_id(order_id: String) -> Result<Option<OrderWithDetails>, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn get_order_by_id(pool: State<'_, DbPool>, order_id: String) -> Result<Option<OrderWithDetails>, AppError> {
+    let conn = pool.get()?;
     
     // Get order
     let mut stmt = conn
-        .prepare("SELECT id, order_number, supplier_id, status, payment_status, total_amount, paid_amount, notes, created_at, updated_at, created_by FROM orders WHERE id = ?1")
-        .map_err(|e| e.to_string())?;
+        .prepare_cached("SELECT id, order_number, supplier_id, status, payment_status, total_amount, paid_amount, notes, created_at, updated_at, created_by, location_id FROM orders WHERE id = ?1")?;
     
     let order = match stmt.query_row(params![order_id], |row| {
         Ok(Order {
@@ -141,6 +152,7 @@ pub fn get_order_by_id(order_id: String) -> Result<Option<OrderWithDetails>, Str
             created_at: row.get(8)?,
             updated_at: row.get(9)?,
             created_by: row.get(10).ok(),
+            location_id: row.get(11)?,
         })
     }) {
         Ok(order) => order,
@@ -158,8 +170,7 @@ pub fn get_order_by_id(order_id: String) -> Result<Option<OrderWithDetails>, Str
     
     // Get items
     let mut items_stmt = conn
-        .prepare("SELECT id, order_id, item_id, item_name, quantity, unit_price, total_price, notes FROM order_items WHERE order_id = ?1")
-        .map_err(|e| e.to_string())?;
+        .prepare_cached("SELECT id, order_id, item_id, item_name, quantity, unit_price, total_price, notes, received_quantity, cancelled_quantity FROM order_items WHERE order_id = ?1")?;
     
     let items = items_stmt
         .query_map(params![order.id], |row| {
@@ -172,16 +183,16 @@ pub fn get_order_by_id(order_id: String) -> Result<Option<OrderWithDetails>, Str
                 unit_price: row.get(5)?,
                 total_price: row.get(6)?,
                 notes: row.get(7).ok(),
+                received_quantity: row.get(8)?,
+                cancelled_quantity: row.get(9)?,
             })
-        })
-        .map_err(|e| e.to_string())?
+        })?
         .filter_map(|res| res.ok())
         .collect();
     
     // Get payments
     let mut payments_stmt = conn
-        .prepare("SELECT id, order_id, amount, method, date, received_by, notes, session_id FROM order_payments WHERE order_id = ?1")
-        .map_err(|e| e.to_string())?;
+        .prepare_cached("SELECT id, order_id, amount, method, date, received_by, notes, session_id FROM order_payments WHERE order_id = ?1")?;
     
     let payments = payments_stmt
         .query_map(params![order.id], |row| {
@@ -195,8 +206,7 @@ pub fn get_order_by_id(order_id: String) -> Result<Option<OrderWithDetails>, Str
                 notes: row.get(6).ok(),
                 session_id: row.get(7).ok(),
             })
-        })
-        .map_err(|e| e.to_string())?
+        })?
         .filter_map(|res| res.ok())
         .collect();
     
@@ -210,54 +220,78 @@ pub fn get_order_by_id(order_id: String) -> Result<Option<OrderWithDetails>, Str
 
 /// Update an existing order
 #[tauri::command]
-pub fn update_order(order: Order) -> Result<(), String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn update_order(pool: State<'_, DbPool>, order: Order) -> Result<(), AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
     
     // Get old order state to handle balance changes
-    let (old_supplier_id, old_total_amount, old_status): (String, f64, String) = conn.query_row(
-        "SELECT supplier_id, total_amount, status FROM orders WHERE id = ?1",
+    let (old_supplier_id, old_total_amount, old_status, old_location_id): (String, Money, String, Option<String>) = tx.query_row(
+        "SELECT supplier_id, total_amount, status, location_id FROM orders WHERE id = ?1",
         params![order.id],
-        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))
-    ).map_err(|e| e.to_string())?;
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+    )?;
+
+    // Goods of a completed order have already been received at its location
+    let location_id = location::resolve_location(&tx, order.location_id.as_deref())?;
+    if old_status != "draft" && old_location_id.is_some_and(|old| old != location_id) {
+        return Err(AppError::Conflict(format!(
+            "Order {} has received goods; its location can't be changed",
+            order.order_number
+        )));
+    }
+
+    // Partial deliveries move on with goods receipts or by cancelling the remainder
+    if old_status != order.status && (old_status == "partially_received" || order.status == "partially_received") {
+        return Err(AppError::Conflict(format!(
+            "Order {} is partially received; receive the rest or cancel the remainder",
+            order.order_number
+        )));
+    }
+
+    // Completing takes the goods in, which only complete_order and goods receipts do
+    if old_status != "completed" && order.status == "completed" {
+        return Err(AppError::Conflict(format!(
+            "Order {} can't be completed by editing it; complete it or receive its goods instead",
+            order.order_number
+        )));
+    }
 
     // Update order header (excluding totals which are managed by recalculate functions)
-    conn.execute(
-        "UPDATE orders SET supplier_id = ?2, status = ?3, notes = ?4, updated_at = ?5 WHERE id = ?1",
+    tx.execute(
+        "UPDATE orders SET supplier_id = ?2, status = ?3, notes = ?4, updated_at = ?5, location_id = ?6 WHERE id = ?1",
         params![
             order.id,
             order.supplier_id,
             order.status,
             order.notes,
             order.updated_at,
+            location_id,
         ],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
 
     // Handle Balance Ownership/Lifecycle Changes
     // (Actual volume changes are handled by recalculate_order_total at the end)
     
     if old_status == "completed" && order.status != "completed" {
         // Transition: Completed -> Draft (Reverse entire old balance from old supplier)
-        adjust_supplier_balance_internal(&conn, &old_supplier_id, -old_total_amount, "Order Reverted to Draft", &order.order_number)?;
-    } else if old_status != "completed" && order.status == "completed" {
-        // Transition: Draft -> Completed (Apply current balance to new supplier)
-        adjust_supplier_balance_internal(&conn, &order.supplier_id, old_total_amount, "Order Completed", &order.order_number)?;
+        adjust_supplier_balance_internal(&tx, &old_supplier_id, -old_total_amount, "Order Reverted to Draft", &order.order_number)?;
+        shopping_list::sync_order_status(&tx, &order.id, false)?;
     } else if old_status == "completed" && order.status == "completed" {
         // Transition: Remained Completed (Check if supplier changed)
         if old_supplier_id != order.supplier_id {
             // Move balance from old to new supplier
-            adjust_supplier_balance_internal(&conn, &old_supplier_id, -old_total_amount, "Order Moved to Another Supplier", &order.order_number)?;
-            adjust_supplier_balance_internal(&conn, &order.supplier_id, old_total_amount, "Order Moved from Another Supplier", &order.order_number)?;
+            adjust_supplier_balance_internal(&tx, &old_supplier_id, -old_total_amount, "Order Moved to Another Supplier", &order.order_number)?;
+            adjust_supplier_balance_internal(&tx, &order.supplier_id, old_total_amount, "Order Moved from Another Supplier", &order.order_number)?;
         }
     }
 
     // Sync everything
-    recalculate_order_total(&order.id)?;
-    recalculate_payment_status(&order.id)?;
+    recalculate_order_total(&tx, &order.id)?;
+    recalculate_payment_status(&tx, &order.id)?;
     
     // Log update in history
     let history_id = uuid::Uuid::new_v4().to_string();
-    conn.execute(
+    tx.execute(
         "INSERT INTO order_history (id, order_id, date, event_type, details, changed_by)
          VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
         params![
@@ -268,22 +302,22 @@ pub fn update_order(order: Order) -> Result<(), String> {
             format!("Order {} updated", order.order_number),
             order.created_by,
         ],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
     
+    tx.commit()?;
     Ok(())
 }
 
 /// Helper function to adjust supplier balance with history logging (Internal use)
-fn adjust_supplier_balance_internal(conn: &rusqlite::Connection, supplier_id: &str, amount: f64, note_prefix: &str, order_num: &str) -> Result<(), String> {
-    if amount.abs() < 0.001 {
+fn adjust_supplier_balance_internal(conn: &rusqlite::Connection, supplier_id: &str, amount: Money, note_prefix: &str, order_num: &str) -> Result<(), AppError> {
+    if amount.is_zero() {
         return Ok(());
     }
 
     conn.execute(
         "UPDATE suppliers SET credit_balance = COALESCE(credit_balance, 0) + ?1 WHERE id = ?2",
         params![amount, supplier_id],
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     let history_id = uuid::Uuid::new_v4().to_string();
     conn.execute(
@@ -293,21 +327,39 @@ fn adjust_supplier_balance_internal(conn: &rusqlite::Connection, supplier_id: &s
             history_id,
             supplier_id,
             chrono::Utc::now().to_rfc3339(),
-            if amount > 0.0 { "Purchase Order Created" } else { "Credit Balance Adjusted" },
+            if amount.is_positive() { "Purchase Order Created" } else { "Credit Balance Adjusted" },
             format!("{}: Order {}", note_prefix, order_num),
             amount,
             None::<String>,
         ],
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     Ok(())
 }
 
 /// Add an item to an order
 #[tauri::command]
-pub fn add_order_item(item: OrderItem) -> Result<(), String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
-    
+pub fn add_order_item(pool: State<'_, DbPool>, item: OrderItem) -> Result<(), AppError> {
+    let conn = pool.get()?;
+    insert_order_item(&conn, &item)
+}
+
+/// `add_order_item` inside the caller's SQL transaction. A line without a price gets
+/// the one on the supplier's price list.
+pub(crate) fn insert_order_item(conn: &Connection, item: &OrderItem) -> Result<(), AppError> {
+    let mut item = item.clone();
+    if let (true, Some(item_id)) = (item.unit_price.is_zero(), &item.item_id) {
+        let supplier_id: String = conn.query_row(
+            "SELECT supplier_id FROM orders WHERE id = ?1",
+            params![item.order_id],
+            |row| row.get(0)
+        )?;
+        if let Some(price) = price_list::supplier_price(conn, &supplier_id, item_id)? {
+            item.unit_price = price;
+            item.total_price = price * item.quantity as i64;
+        }
+    }
+
     conn.execute(
         "INSERT INTO order_items (id, order_id, item_id, item_name, quantity, unit_price, total_price, notes)
          VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
@@ -321,8 +373,7 @@ pub fn add_order_item(item: OrderItem) -> Result<(), String> {
             item.total_price,
             item.notes,
         ],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
 
     // If order is completed, update inventory
     let order_status: String = conn.query_row(
@@ -332,12 +383,14 @@ pub fn add_order_item(item: OrderItem) -> Result<(), String> {
     ).unwrap_or_else(|_| "draft".to_string());
 
     if order_status == "completed" {
+        conn.execute(
+            "UPDATE order_items SET received_quantity = quantity WHERE id = ?1",
+            params![item.id],
+        )?;
         if let Some(item_id) = &item.item_id {
             // Update inventory
-            conn.execute(
-                "UPDATE inventory_items SET quantity_in_stock = COALESCE(quantity_in_stock, 0) + ?1 WHERE id = ?2",
-                params![item.quantity, item_id]
-            ).map_err(|e| e.to_string())?;
+            let location_id = location::document_location(conn, "orders", &item.order_id)?;
+            inventory::apply_stock_change(conn, item_id, location_id.as_deref(), item.quantity as i64, true)?;
 
             // Log inventory history
             let order_num: String = conn.query_row(
@@ -346,25 +399,21 @@ pub fn add_order_item(item: OrderItem) -> Result<(), String> {
                 |row| row.get(0)
             ).unwrap_or_default();
 
-            let history_id = uuid::Uuid::new_v4().to_string();
-            conn.execute(
-                "INSERT INTO inventory_history (id, item_id, date, event_type, quantity_change, notes, related_id)
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-                params![
-                    history_id,
-                    item_id,
-                    chrono::Utc::now().to_rfc3339(),
-                    "Purchased",
-                    item.quantity,
-                    format!("Added item to completed order {}", order_num),
-                    item.order_id,
-                ],
-            ).map_err(|e| e.to_string())?;
+            inventory::log_stock_event(
+                conn,
+                item_id,
+                location_id.as_deref(),
+                "Purchased",
+                item.quantity as i64,
+                &format!("Added item to completed order {}", order_num),
+                &item.order_id,
+            )?;
+            costing::receive_stock(conn, item_id, item.quantity as i64, item.unit_price, "Order", &item.order_id)?;
         }
     }
     
     // Recalculate order total
-    recalculate_order_total(&item.order_id)?;
+    recalculate_order_total(conn, &item.order_id)?;
     
     // Log in history
     let history_id = uuid::Uuid::new_v4().to_string();
@@ -379,25 +428,54 @@ pub fn add_order_item(item: OrderItem) -> Result<(), String> {
             format!("Added item: {}", item.item_name),
             None::<String>,
         ],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
     
     Ok(())
 }
 
 /// Update an order item
 #[tauri::command]
-pub fn update_order_item(item: OrderItem) -> Result<(), String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
-    
-    // Get old item details for inventory adjustment if completed
-    let old_item: Option<(Option<String>, i32)> = conn.query_row(
-        "SELECT i.item_id, i.quantity FROM order_items i JOIN orders o ON i.order_id = o.id WHERE i.id = ?1 AND o.status = 'completed'",
+pub fn update_order_item(pool: State<'_, DbPool>, mut item: OrderItem) -> Result<(), AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+
+    let (old_item_id, received, cancelled, order_status, order_num): (Option<String>, i32, i32, String, String) = tx.query_row(
+        "SELECT i.item_id, i.received_quantity, i.cancelled_quantity, o.status, o.order_number
+         FROM order_items i JOIN orders o ON i.order_id = o.id WHERE i.id = ?1",
         params![item.id],
-        |row| Ok((row.get(0).ok(), row.get(1)?))
-    ).ok();
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?))
+    )?;
+
+    // Units already delivered on an open order stay as they were received
+    if order_status != "completed" && received > 0 {
+        if old_item_id != item.item_id {
+            return Err(AppError::Conflict(format!(
+                "{} has been partly received; its item can't be changed",
+                item.item_name
+            )));
+        }
+        if item.quantity < received {
+            return Err(AppError::Validation(format!(
+                "{} of {} have already been received",
+                received, item.item_name
+            )));
+        }
+    }
 
-    conn.execute(
+    // A completed line is received except for what was cancelled, and only that is charged
+    if order_status == "completed" {
+        if item.quantity < cancelled {
+            return Err(AppError::Validation(format!(
+                "{} of {} were cancelled; the quantity can't be lower",
+                cancelled, item.item_name
+            )));
+        }
+        if cancelled > 0 {
+            item.total_price = item.unit_price * (item.quantity - cancelled) as i64;
+        }
+    }
+
+    tx.execute(
         "UPDATE order_items SET item_id = ?2, item_name = ?3, quantity = ?4, unit_price = ?5, total_price = ?6, notes = ?7 WHERE id = ?1",
         params![
             item.id,
@@ -408,115 +486,112 @@ pub fn update_order_item(item: OrderItem) -> Result<(), String> {
             item.total_price,
             item.notes,
         ],
-    )
-    .map_err(|e| e.to_string())?;
-
-    // Handle inventory adjustment if completed
-    if let Some((old_item_id, old_qty)) = old_item {
-        let order_num: String = conn.query_row(
-            "SELECT order_number FROM orders WHERE id = ?1",
-            params![item.order_id],
-            |row| row.get(0)
-        ).unwrap_or_default();
-
-        // 1. Remove old quantity
-        if let Some(id) = old_item_id {
-            conn.execute(
-                "UPDATE inventory_items SET quantity_in_stock = COALESCE(quantity_in_stock, 0) - ?1 WHERE id = ?2",
-                params![old_qty, id]
-            ).ok();
-        }
+    )?;
 
-        // 2. Add new quantity
-        if let Some(new_id) = &item.item_id {
-            conn.execute(
-                "UPDATE inventory_items SET quantity_in_stock = COALESCE(quantity_in_stock, 0) + ?1 WHERE id = ?2",
-                params![item.quantity, new_id]
-            ).map_err(|e| e.to_string())?;
-
-            // Log history
-            let history_id = uuid::Uuid::new_v4().to_string();
-            conn.execute(
-                "INSERT INTO inventory_history (id, item_id, date, event_type, quantity_change, notes, related_id)
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-                params![
-                    history_id,
-                    new_id,
-                    chrono::Utc::now().to_rfc3339(),
-                    "Adjustment",
-                    item.quantity as i64 - old_qty as i64,
-                    format!("Updated item in completed order {}", order_num),
-                    item.order_id,
-                ],
-            ).map_err(|e| e.to_string())?;
+    // Move stock by what changes against the received quantity
+    if order_status == "completed" {
+        let new_received = item.quantity - cancelled;
+        tx.execute(
+            "UPDATE order_items SET received_quantity = ?2 WHERE id = ?1",
+            params![item.id, new_received],
+        )?;
+
+        let location_id = location::document_location(&tx, "orders", &item.order_id)?;
+        let note = format!("Updated item in completed order {}", order_num);
+
+        if old_item_id == item.item_id {
+            if let Some(id) = &item.item_id {
+                let delta = new_received as i64 - received as i64;
+                if delta != 0 {
+                    inventory::apply_stock_change(&tx, id, location_id.as_deref(), delta, true)?;
+                    inventory::log_stock_event(&tx, id, location_id.as_deref(), "Adjustment", delta, &note, &item.order_id)?;
+                    if delta > 0 {
+                        costing::receive_stock(&tx, id, delta, item.unit_price, "Order", &item.order_id)?;
+                    } else {
+                        costing::reverse_receipt(&tx, id, -delta, &item.order_id)?;
+                    }
+                }
+            }
+        } else {
+            // The line now stands for another item: take the old one out, bring the new one in
+            if let Some(id) = &old_item_id {
+                if received > 0 {
+                    inventory::apply_stock_change(&tx, id, location_id.as_deref(), -(received as i64), true)?;
+                    inventory::log_stock_event(&tx, id, location_id.as_deref(), "Adjustment", -(received as i64), &note, &item.order_id)?;
+                    costing::reverse_receipt(&tx, id, received as i64, &item.order_id)?;
+                }
+            }
+            if let Some(id) = &item.item_id {
+                if new_received > 0 {
+                    inventory::apply_stock_change(&tx, id, location_id.as_deref(), new_received as i64, true)?;
+                    inventory::log_stock_event(&tx, id, location_id.as_deref(), "Adjustment", new_received as i64, &note, &item.order_id)?;
+                    costing::receive_stock(&tx, id, new_received as i64, item.unit_price, "Order", &item.order_id)?;
+                }
+            }
         }
     }
     
     // Recalculate order total
-    recalculate_order_total(&item.order_id)?;
+    recalculate_order_total(&tx, &item.order_id)?;
     
+    tx.commit()?;
     Ok(())
 }
 
 /// Remove an item from an order
 #[tauri::command]
-pub fn remove_order_item(item_id: String, order_id: String) -> Result<(), String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn remove_order_item(pool: State<'_, DbPool>, item_id: String, order_id: String) -> Result<(), AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
     
-    // Get item details for inventory reversal if completed
-    let item_info: Option<(String, Option<String>, i32, String)> = conn.query_row(
-        "SELECT i.item_name, i.item_id, i.quantity, o.status FROM order_items i JOIN orders o ON i.order_id = o.id WHERE i.id = ?1",
+    // Get item details for reversing what was received of it
+    let item_info: Option<(String, Option<String>, i32)> = tx.query_row(
+        "SELECT item_name, item_id, received_quantity FROM order_items WHERE id = ?1",
         params![item_id],
-        |row| Ok((row.get(0)?, row.get(1).ok(), row.get(2)?, row.get(3)?))
-    ).ok();
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+    ).optional()?;
 
     let name_for_history = item_info.as_ref().map(|info| info.0.clone()).unwrap_or_else(|| "Unknown Item".to_string());
 
-    conn.execute(
+    tx.execute(
         "DELETE FROM order_items WHERE id = ?1",
         params![item_id],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
+    shopping_list::release_order_item(&tx, &item_id)?;
 
-    // Revert inventory if order was completed
-    if let Some((name, id_opt, qty, status)) = item_info {
-        if status == "completed" {
+    // Revert inventory for the units already received
+    if let Some((name, id_opt, qty)) = item_info {
+        if qty > 0 {
             if let Some(id) = id_opt {
-                conn.execute(
-                    "UPDATE inventory_items SET quantity_in_stock = COALESCE(quantity_in_stock, 0) - ?1 WHERE id = ?2",
-                    params![qty, id]
-                ).map_err(|e| e.to_string())?;
+                let location_id = location::document_location(&tx, "orders", &order_id)?;
+                inventory::apply_stock_change(&tx, &id, location_id.as_deref(), -(qty as i64), true)?;
+                costing::reverse_receipt(&tx, &id, qty as i64, &order_id)?;
 
-                let order_num: String = conn.query_row(
+                let order_num: String = tx.query_row(
                     "SELECT order_number FROM orders WHERE id = ?1",
                     params![order_id],
                     |row| row.get(0)
                 ).unwrap_or_default();
 
-                let history_id = uuid::Uuid::new_v4().to_string();
-                conn.execute(
-                    "INSERT INTO inventory_history (id, item_id, date, event_type, quantity_change, notes, related_id)
-                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-                    params![
-                        history_id,
-                        id,
-                        chrono::Utc::now().to_rfc3339(),
-                        "Adjustment",
-                        -qty,
-                        format!("Removed item {} from completed order {}", name, order_num),
-                        order_id,
-                    ],
-                ).map_err(|e| e.to_string())?;
+                inventory::log_stock_event(
+                    &tx,
+                    &id,
+                    location_id.as_deref(),
+                    "Adjustment",
+                    -(qty as i64),
+                    &format!("Removed item {} from order {}", name, order_num),
+                    &order_id,
+                )?;
             }
         }
     }
     
     // Recalculate order total
-    recalculate_order_total(&order_id)?;
+    recalculate_order_total(&tx, &order_id)?;
     
     // Log in history
     let history_id = uuid::Uuid::new_v4().to_string();
-    conn.execute(
+    tx.execute(
         "INSERT INTO order_history (id, order_id, date, event_type, details, changed_by)
          VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
         params![
@@ -527,48 +602,45 @@ pub fn remove_order_item(item_id: String, order_id: String) -> Result<(), String
             format!("Removed item: {}", name_for_history),
             None::<String>,
         ],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
     
+    tx.commit()?;
     Ok(())
 }
 
 /// Helper function to recalculate order total
-fn recalculate_order_total(order_id: &str) -> Result<(), String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
-    
+pub(crate) fn recalculate_order_total(conn: &Connection, order_id: &str) -> Result<(), AppError> {
     // Get current state to check if we need to adjust supplier balance
-    let (old_total, status, supplier_id, order_num): (f64, String, String, String) = conn.query_row(
+    let (old_total, status, supplier_id, order_num): (Money, String, String, String) = conn.query_row(
         "SELECT total_amount, status, supplier_id, order_number FROM orders WHERE id = ?1",
         params![order_id],
         |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     // Sum all item totals
-    let new_total: f64 = conn
+    let new_total: Money = conn
         .query_row(
             "SELECT COALESCE(SUM(total_price), 0) FROM order_items WHERE order_id = ?1",
             params![order_id],
             |row| row.get(0),
         )
-        .unwrap_or(0.0);
+        .unwrap_or(Money::ZERO);
     
     // Update order total
     conn.execute(
         "UPDATE orders SET total_amount = ?1, updated_at = ?2 WHERE id = ?3",
         params![new_total, chrono::Utc::now().to_rfc3339(), order_id],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
 
     // If the order is completed, we must adjust the supplier's credit balance
-    if status == "completed" && (new_total - old_total).abs() > 0.001 {
+    if status == "completed" && new_total != old_total {
         let diff = new_total - old_total;
         
         // Update supplier balance
         conn.execute(
             "UPDATE suppliers SET credit_balance = COALESCE(credit_balance, 0) + ?1 WHERE id = ?2",
             params![diff, supplier_id],
-        ).map_err(|e| e.to_string())?;
+        )?;
 
         // Log in supplier history
         let history_id = uuid::Uuid::new_v4().to_string();
@@ -584,19 +656,19 @@ fn recalculate_order_total(order_id: &str) -> Result<(), String> {
                 diff,
                 None::<String>,
             ],
-        ).map_err(|e| e.to_string())?;
+        )?;
     }
     
     // Recalculate payment status as total has changed
-    recalculate_payment_status(order_id)?;
+    recalculate_payment_status(conn, order_id)?;
     
     Ok(())
 }
 
 /// Add a payment to an order
 #[tauri::command]
-pub fn add_order_payment(payment: OrderPayment) -> Result<(), String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn add_order_payment(pool: State<'_, DbPool>, payment: OrderPayment) -> Result<(), AppError> {
+    let conn = pool.get()?;
     
     conn.execute(
         "INSERT INTO order_payments (id, order_id, amount, method, date, received_by, notes, session_id)
@@ -611,24 +683,23 @@ pub fn add_order_payment(payment: OrderPayment) -> Result<(), String> {
             payment.notes,
             payment.session_id,
         ],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
     
     // Recalculate payment status
-    recalculate_payment_status(&payment.order_id)?;
+    recalculate_payment_status(&conn, &payment.order_id)?;
     
     // Update supplier balance and history
     let (supplier_id, order_number): (String, String) = conn.query_row(
         "SELECT supplier_id, order_number FROM orders WHERE id = ?1",
         params![payment.order_id],
         |row| Ok((row.get(0)?, row.get(1)?))
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     // Subtract amount from supplier credit balance
     conn.execute(
         "UPDATE suppliers SET credit_balance = COALESCE(credit_balance, 0) - ?1 WHERE id = ?2",
         params![payment.amount, supplier_id],
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     // Log in supplier history
     let supplier_history_id = uuid::Uuid::new_v4().to_string();
@@ -644,7 +715,7 @@ pub fn add_order_payment(payment: OrderPayment) -> Result<(), String> {
             -payment.amount,
             payment.received_by,
         ],
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     // Log in history
     let history_id = uuid::Uuid::new_v4().to_string();
@@ -659,20 +730,18 @@ pub fn add_order_payment(payment: OrderPayment) -> Result<(), String> {
             format!("Payment of ${:.2} added", payment.amount),
             payment.received_by,
         ],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
     
     Ok(())
 }
 
 /// Get all payments for an order
 #[tauri::command]
-pub fn get_order_payments(order_id: String) -> Result<Vec<OrderPayment>, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn get_order_payments(pool: State<'_, DbPool>, order_id: String) -> Result<Vec<OrderPayment>, AppError> {
+    let conn = pool.get()?;
     
     let mut stmt = conn
-        .prepare("SELECT id, order_id, amount, method, date, received_by, notes, session_id FROM order_payments WHERE order_id = ?1 ORDER BY date DESC")
-        .map_err(|e| e.to_string())?;
+        .prepare_cached("SELECT id, order_id, amount, method, date, received_by, notes, session_id FROM order_payments WHERE order_id = ?1 ORDER BY date DESC")?;
     
     let payments = stmt
         .query_map(params![order_id], |row| {
@@ -686,8 +755,7 @@ pub fn get_order_payments(order_id: String) -> Result<Vec<OrderPayment>, String>
                 notes: row.get(6).ok(),
                 session_id: row.get(7).ok(),
             })
-        })
-        .map_err(|e| e.to_string())?
+        })?
         .filter_map(|res| res.ok())
         .collect();
     
@@ -695,11 +763,9 @@ pub fn get_order_payments(order_id: String) -> Result<Vec<OrderPayment>, String>
 }
 
 /// Helper function to recalculate payment status
-fn recalculate_payment_status(order_id: &str) -> Result<(), String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
-    
+fn recalculate_payment_status(conn: &Connection, order_id: &str) -> Result<(), AppError> {
     // Get total amount and sum of payments
-    let (total_amount, paid_amount): (f64, f64) = conn
+    let (total_amount, paid_amount): (Money, Money) = conn
         .query_row(
             "SELECT 
                 o.total_amount,
@@ -711,12 +777,12 @@ fn recalculate_payment_status(order_id: &str) -> Result<(), String> {
             params![order_id],
             |row| Ok((row.get(0)?, row.get(1)?)),
         )
-        .unwrap_or((0.0, 0.0));
+        .unwrap_or((Money::ZERO, Money::ZERO));
     
     // Determine payment status
     let payment_status = if paid_amount >= total_amount {
         "paid"
-    } else if paid_amount > 0.0 {
+    } else if paid_amount.is_positive() {
         "partial"
     } else {
         "unpaid"
@@ -726,114 +792,68 @@ fn recalculate_payment_status(order_id: &str) -> Result<(), String> {
     conn.execute(
         "UPDATE orders SET paid_amount = ?1, payment_status = ?2, updated_at = ?3 WHERE id = ?4",
         params![paid_amount, payment_status, chrono::Utc::now().to_rfc3339(), order_id],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
     
     Ok(())
 }
 
-/// Complete an order and update inventory
+/// Complete an order, receiving everything still outstanding in one goods receipt.
+/// Runs as a single SQL transaction so a failure leaves the order in its previous state.
 #[tauri::command]
-pub fn complete_order(order_id: String) -> Result<(), String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn complete_order(pool: State<'_, DbPool>, order_id: String) -> Result<(), AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
     
-    // Get order details and check status
-    let (order_number, status): (String, String) = conn
-        .query_row(
-            "SELECT order_number, status FROM orders WHERE id = ?1",
-            params![order_id],
-            |row| Ok((row.get(0)?, row.get(1)?)),
-        )
-        .map_err(|e| e.to_string())?;
+    let status: String = tx.query_row(
+        "SELECT status FROM orders WHERE id = ?1",
+        params![order_id],
+        |row| row.get(0),
+    )?;
     
     // Safety check: if already completed, don't update inventory twice
     if status == "completed" {
         return Ok(());
     }
     
-    // Get all order items
-    let mut stmt = conn
-        .prepare("SELECT id, item_id, quantity FROM order_items WHERE order_id = ?1")
-        .map_err(|e| e.to_string())?;
-    
-    let items: Vec<(String, Option<String>, i32)> = stmt
-        .query_map(params![order_id], |row| {
-            Ok((
-                row.get(0)?,  // id
-                row.get(1).ok(),  // item_id
-                row.get(2)?,  // quantity
-            ))
-        })
-        .map_err(|e| e.to_string())?
-        .filter_map(|res| res.ok())
-        .collect();
-    
-    // Update inventory for each item
-    for (_, item_id_opt, quantity) in items {
-        if let Some(item_id) = item_id_opt {
-            // Update inventory quantity
-            conn.execute(
-                "UPDATE inventory_items 
-                 SET quantity_in_stock = COALESCE(quantity_in_stock, 0) + ?1 
-                 WHERE id = ?2",
-                params![quantity, item_id],
-            )
-            .map_err(|e| e.to_string())?;
-            
-            // Log in inventory history
-            let history_id = uuid::Uuid::new_v4().to_string();
-            conn.execute(
-                "INSERT INTO inventory_history (id, item_id, date, event_type, quantity_change, notes, related_id)
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-                params![
-                    history_id,
-                    item_id,
-                    chrono::Utc::now().to_rfc3339(),
-                    "Purchased",
-                    quantity,
-                    format!("Added from order {}", order_number),
-                    order_id,
-                ],
-            )
-            .map_err(|e| e.to_string())?;
-        }
+    let outstanding = goods_receipt::outstanding_lines(&tx, &order_id)?;
+    if outstanding.is_empty() {
+        mark_order_completed(&tx, &order_id)?;
+    } else {
+        // Completes the order once the last units are in
+        goods_receipt::post_receipt(&tx, &order_id, &outstanding, None, None)?;
     }
     
+    tx.commit()?;
+    Ok(())
+}
+
+/// Mark an order completed and charge its total to the supplier's balance.
+pub(crate) fn mark_order_completed(conn: &Connection, order_id: &str) -> Result<(), AppError> {
+    let (order_number, supplier_id, total_amount): (String, String, Money) = conn.query_row(
+        "SELECT order_number, supplier_id, total_amount FROM orders WHERE id = ?1",
+        params![order_id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+    )?;
+    
     // Update order status to completed
     conn.execute(
         "UPDATE orders SET status = 'completed', updated_at = ?1 WHERE id = ?2",
         params![chrono::Utc::now().to_rfc3339(), order_id],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
     
-    // Log completion in order history
-    let history_id = uuid::Uuid::new_v4().to_string();
-    conn.execute(
-        "INSERT INTO order_history (id, order_id, date, event_type, details, changed_by)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-        params![
-            history_id,
-            order_id,
-            chrono::Utc::now().to_rfc3339(),
-            "completed",
-            format!("Order {} completed and inventory updated", order_number),
-            None::<String>,
-        ],
-    )
-    .map_err(|e| e.to_string())?;
-
-    // Update supplier balance and history
-    let (supplier_id, total_amount): (String, f64) = conn.query_row(
-        "SELECT supplier_id, total_amount FROM orders WHERE id = ?1",
-        params![order_id],
-        |row| Ok((row.get(0)?, row.get(1)?))
-    ).map_err(|e| e.to_string())?;
+    log_order_event(
+        conn,
+        order_id,
+        "completed",
+        &format!("Order {} completed and inventory updated", order_number),
+        None,
+    )?;
 
     // Add total amount to supplier credit balance
     conn.execute(
         "UPDATE suppliers SET credit_balance = COALESCE(credit_balance, 0) + ?1 WHERE id = ?2",
         params![total_amount, supplier_id],
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     // Log in supplier history
     let supplier_history_id = uuid::Uuid::new_v4().to_string();
@@ -849,22 +869,46 @@ pub fn complete_order(order_id: String) -> Result<(), String> {
             total_amount,
             None::<String>,
         ],
-    ).map_err(|e| e.to_string())?;
-    
+    )?;
+
+    // Whatever the shopping list ordered with it has now arrived
+    shopping_list::sync_order_status(conn, order_id, true)
+}
+
+/// Add an entry to an order's history.
+pub(crate) fn log_order_event(
+    conn: &Connection,
+    order_id: &str,
+    event_type: &str,
+    details: &str,
+    changed_by: Option<String>,
+) -> Result<(), AppError> {
+    let history_id = uuid::Uuid::new_v4().to_string();
+    conn.execute(
+        "INSERT INTO order_history (id, order_id, date, event_type, details, changed_by)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            history_id,
+            order_id,
+            chrono::Utc::now().to_rfc3339(),
+            event_type,
+            details,
+            changed_by,
+        ],
+    )?;
     Ok(())
 }
 
-/// Get orders for a specific supplier
+/// Get orders for a specific supplier, with the goods sent back to it
 #[tauri::command]
-pub fn get_orders_by_supplier(supplier_id: String) -> Result<Vec<Order>, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn get_orders_by_supplier(pool: State<'_, DbPool>, supplier_id: String) -> Result<SupplierOrders, AppError> {
+    let conn = pool.get()?;
     
     let mut stmt = conn
-        .prepare("SELECT id, order_number, supplier_id, status, payment_status, total_amount, paid_amount, notes, created_at, updated_at, created_by 
+        .prepare_cached("SELECT id, order_number, supplier_id, status, payment_status, total_amount, paid_amount, notes, created_at, updated_at, created_by, location_id 
                   FROM orders 
                   WHERE supplier_id = ?1 
-                  ORDER BY created_at DESC")
-        .map_err(|e| e.to_string())?;
+                  ORDER BY created_at DESC")?;
     
     let orders = stmt
         .query_map(params![supplier_id], |row| {
@@ -880,11 +924,13 @@ pub fn get_orders_by_supplier(supplier_id: String) -> Result<Vec<Order>, String>
                 created_at: row.get(8)?,
                 updated_at: row.get(9)?,
                 created_by: row.get(10).ok(),
+                location_id: row.get(11)?,
             })
-        })
-        .map_err(|e| e.to_string())?
-        .filter_map(|res| res.ok())
-        .collect();
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
     
-    Ok(orders)
+    Ok(SupplierOrders {
+        orders,
+        returns: transaction::purchase_returns_for_supplier(&conn, &supplier_id)?,
+    })
 }
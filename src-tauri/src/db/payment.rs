@@ -1,19 +1,21 @@
-use crate::db;
+use crate::db::DbPool;
 use crate::db::models::UnifiedPayment;
 use rusqlite::Result;
+use tauri::State;
+use crate::error::AppError;
 
 #[tauri::command]
-pub fn get_all_payments() -> Result<Vec<UnifiedPayment>, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn get_all_payments(pool: State<'_, DbPool>) -> Result<Vec<UnifiedPayment>, AppError> {
+    let conn = pool.get()?;
 
     let mut payments = Vec::new();
 
     // 1. Repair Payments
-    let mut stmt = conn.prepare(
+    let mut stmt = conn.prepare_cached(
         "SELECT p.id, p.repair_id, 'Repair', p.amount, p.date, p.method, p.received_by, NULL, r.code, r.customer_name
          FROM repair_payments p
          JOIN repairs r ON p.repair_id = r.id"
-    ).map_err(|e| e.to_string())?;
+    )?;
     
     let repair_payments = stmt.query_map([], |row| {
         Ok(UnifiedPayment {
@@ -28,7 +30,7 @@ pub fn get_all_payments() -> Result<Vec<UnifiedPayment>, String> {
             source_number: row.get(8).ok(),
             party_name: row.get(9).ok(),
         })
-    }).map_err(|e| e.to_string())?;
+    })?;
 
     for p in repair_payments {
         if let Ok(payment) = p {
@@ -37,7 +39,7 @@ pub fn get_all_payments() -> Result<Vec<UnifiedPayment>, String> {
     }
 
     // 2. Transaction Payments (Sales & Purchases)
-    let mut stmt = conn.prepare(
+    let mut stmt = conn.prepare_cached(
         "SELECT p.id, p.transaction_id, t.transaction_type, p.amount, p.date, p.method, p.received_by, p.notes, t.transaction_number, 
          CASE 
             WHEN t.party_type = 'Client' THEN (SELECT name FROM clients WHERE id = t.party_id)
@@ -46,7 +48,7 @@ pub fn get_all_payments() -> Result<Vec<UnifiedPayment>, String> {
          END as party_name
          FROM transaction_payments p
          JOIN transactions t ON p.transaction_id = t.id"
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     let tx_payments = stmt.query_map([], |row| {
         Ok(UnifiedPayment {
@@ -61,7 +63,7 @@ pub fn get_all_payments() -> Result<Vec<UnifiedPayment>, String> {
             source_number: row.get(8).ok(),
             party_name: row.get(9).ok(),
         })
-    }).map_err(|e| e.to_string())?;
+    })?;
 
     for p in tx_payments {
         if let Ok(payment) = p {
@@ -70,11 +72,11 @@ pub fn get_all_payments() -> Result<Vec<UnifiedPayment>, String> {
     }
 
     // 3. Client Standalone Payments
-    let mut stmt = conn.prepare(
+    let mut stmt = conn.prepare_cached(
         "SELECT p.id, p.client_id, 'Client', p.amount, p.date, p.method, NULL, p.notes, NULL, c.name
          FROM client_payments p
          JOIN clients c ON p.client_id = c.id"
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     let client_payments = stmt.query_map([], |row| {
         Ok(UnifiedPayment {
@@ -89,7 +91,7 @@ pub fn get_all_payments() -> Result<Vec<UnifiedPayment>, String> {
             source_number: row.get(8).ok(),
             party_name: row.get(9).ok(),
         })
-    }).map_err(|e| e.to_string())?;
+    })?;
 
     for p in client_payments {
         if let Ok(payment) = p {
@@ -98,11 +100,11 @@ pub fn get_all_payments() -> Result<Vec<UnifiedPayment>, String> {
     }
 
     // 4. Supplier Standalone Payments
-    let mut stmt = conn.prepare(
+    let mut stmt = conn.prepare_cached(
         "SELECT p.id, p.supplier_id, 'Supplier', p.amount, p.date, p.method, NULL, p.notes, NULL, s.name
          FROM supplier_payments p
          JOIN suppliers s ON p.supplier_id = s.id"
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     let supplier_payments = stmt.query_map([], |row| {
         Ok(UnifiedPayment {
@@ -117,7 +119,7 @@ pub fn get_all_payments() -> Result<Vec<UnifiedPayment>, String> {
             source_number: row.get(8).ok(),
             party_name: row.get(9).ok(),
         })
-    }).map_err(|e| e.to_string())?;
+    })?;
 
     for p in supplier_payments {
         if let Ok(payment) = p {
@@ -0,0 +1,177 @@
+use crate::db::models::SupplierItem;
+use crate::db::money::Money;
+use crate::db::DbPool;
+use crate::error::AppError;
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
+use tauri::State;
+
+// One row per supplier and item. `price` is what the supplier currently charges and
+// is kept at the last price paid: completing an order or a purchase sets both it and
+// `last_purchase_price`.
+
+const SUPPLIER_ITEM_COLUMNS: &str = "si.supplier_id, s.name, si.item_id, i.item_name, si.supplier_sku, si.price,
+    si.min_order_quantity, si.last_purchase_price, si.last_purchase_date, si.updated_at";
+
+const SUPPLIER_ITEM_JOINS: &str = "supplier_items si
+    JOIN suppliers s ON s.id = si.supplier_id
+    JOIN inventory_items i ON i.id = si.item_id";
+
+fn supplier_item_from_row(row: &Row) -> Result<SupplierItem> {
+    Ok(SupplierItem {
+        supplier_id: row.get(0)?,
+        supplier_name: row.get(1)?,
+        item_id: row.get(2)?,
+        item_name: row.get(3)?,
+        supplier_sku: row.get(4)?,
+        price: row.get(5)?,
+        min_order_quantity: row.get(6)?,
+        last_purchase_price: row.get(7)?,
+        last_purchase_date: row.get(8)?,
+        updated_at: row.get(9)?,
+    })
+}
+
+/// The price a supplier charges for an item, falling back to the last price paid.
+pub(crate) fn supplier_price(conn: &Connection, supplier_id: &str, item_id: &str) -> Result<Option<Money>, AppError> {
+    let price: Option<Option<Money>> = conn
+        .query_row(
+            "SELECT COALESCE(price, last_purchase_price) FROM supplier_items WHERE supplier_id = ?1 AND item_id = ?2",
+            params![supplier_id, item_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(price.flatten())
+}
+
+/// Record that `item_id` was bought from `supplier_id` at `unit_price`, linking the
+/// two if they weren't yet.
+pub(crate) fn record_purchase(
+    conn: &Connection,
+    supplier_id: &str,
+    item_id: &str,
+    unit_price: Money,
+) -> Result<(), AppError> {
+    conn.execute(
+        "INSERT INTO supplier_items (supplier_id, item_id, price, last_purchase_price, last_purchase_date, updated_at)
+         SELECT ?1, ?2, ?3, ?3, ?4, ?4
+         WHERE EXISTS(SELECT 1 FROM suppliers WHERE id = ?1)
+         ON CONFLICT(supplier_id, item_id) DO UPDATE SET
+            price = excluded.price,
+            last_purchase_price = excluded.last_purchase_price,
+            last_purchase_date = excluded.last_purchase_date,
+            updated_at = excluded.updated_at",
+        params![supplier_id, item_id, unit_price, Utc::now().to_rfc3339()],
+    )?;
+    Ok(())
+}
+
+/// Get a supplier's price list, by item name.
+#[tauri::command]
+pub fn get_supplier_price_list(
+    pool: State<'_, DbPool>,
+    supplier_id: String,
+) -> Result<Vec<SupplierItem>, AppError> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT {} FROM {} WHERE si.supplier_id = ?1 ORDER BY i.item_name COLLATE NOCASE",
+        SUPPLIER_ITEM_COLUMNS, SUPPLIER_ITEM_JOINS
+    ))?;
+    let items = stmt
+        .query_map(params![supplier_id], supplier_item_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(items)
+}
+
+/// Add an item to a supplier's price list or change its entry.
+#[tauri::command]
+pub fn save_supplier_item(pool: State<'_, DbPool>, entry: SupplierItem) -> Result<SupplierItem, AppError> {
+    let conn = pool.get()?;
+
+    if entry.price.is_some_and(|price| price.is_negative()) {
+        return Err(AppError::Validation("Price can't be negative".to_string()));
+    }
+    if entry.min_order_quantity <= 0 {
+        return Err(AppError::Validation("Minimum order quantity must be positive".to_string()));
+    }
+    let supplier_exists: Option<String> = conn
+        .query_row("SELECT id FROM suppliers WHERE id = ?1", params![entry.supplier_id], |row| row.get(0))
+        .optional()?;
+    if supplier_exists.is_none() {
+        return Err(AppError::NotFound(format!("Supplier {} not found", entry.supplier_id)));
+    }
+    let item_exists: Option<String> = conn
+        .query_row("SELECT id FROM inventory_items WHERE id = ?1", params![entry.item_id], |row| row.get(0))
+        .optional()?;
+    if item_exists.is_none() {
+        return Err(AppError::NotFound(format!("Inventory item {} not found", entry.item_id)));
+    }
+
+    let supplier_sku = entry
+        .supplier_sku
+        .as_deref()
+        .map(str::trim)
+        .filter(|sku| !sku.is_empty());
+    conn.execute(
+        "INSERT INTO supplier_items (supplier_id, item_id, supplier_sku, price, min_order_quantity, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+         ON CONFLICT(supplier_id, item_id) DO UPDATE SET
+            supplier_sku = excluded.supplier_sku,
+            price = excluded.price,
+            min_order_quantity = excluded.min_order_quantity,
+            updated_at = excluded.updated_at",
+        params![
+            entry.supplier_id,
+            entry.item_id,
+            supplier_sku,
+            entry.price,
+            entry.min_order_quantity,
+            Utc::now().to_rfc3339(),
+        ],
+    )?;
+
+    let saved = conn.query_row(
+        &format!(
+            "SELECT {} FROM {} WHERE si.supplier_id = ?1 AND si.item_id = ?2",
+            SUPPLIER_ITEM_COLUMNS, SUPPLIER_ITEM_JOINS
+        ),
+        params![entry.supplier_id, entry.item_id],
+        supplier_item_from_row,
+    )?;
+    Ok(saved)
+}
+
+/// Take an item off a supplier's price list.
+#[tauri::command]
+pub fn delete_supplier_item(pool: State<'_, DbPool>, supplier_id: String, item_id: String) -> Result<(), AppError> {
+    let conn = pool.get()?;
+    conn.execute(
+        "DELETE FROM supplier_items WHERE supplier_id = ?1 AND item_id = ?2",
+        params![supplier_id, item_id],
+    )?;
+    Ok(())
+}
+
+/// Compare the suppliers of an item, cheapest first. With a `quantity`, suppliers
+/// whose minimum order is larger come after the ones that can supply it; suppliers
+/// with no known price come last.
+#[tauri::command]
+pub fn compare_supplier_prices(
+    pool: State<'_, DbPool>,
+    item_id: String,
+    quantity: Option<i64>,
+) -> Result<Vec<SupplierItem>, AppError> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT {} FROM {} WHERE si.item_id = ?1
+         ORDER BY ?2 IS NOT NULL AND si.min_order_quantity > ?2,
+                  COALESCE(si.price, si.last_purchase_price) IS NULL,
+                  COALESCE(si.price, si.last_purchase_price),
+                  s.name COLLATE NOCASE",
+        SUPPLIER_ITEM_COLUMNS, SUPPLIER_ITEM_JOINS
+    ))?;
+    let suppliers = stmt
+        .query_map(params![item_id, quantity], supplier_item_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(suppliers)
+}
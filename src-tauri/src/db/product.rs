@@ -0,0 +1,394 @@
+use crate::db::inventory::{self, InventoryItem};
+use crate::db::models::{BundleComponent, ItemAttribute, Product};
+use crate::db::DbPool;
+use crate::error::AppError;
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use tauri::State;
+use uuid::Uuid;
+
+// A variant is an ordinary inventory item linked to a product, with its attributes in
+// `item_attributes` and their values joined into `variant_label`. A bundle is an item
+// with `is_bundle` set and no stock of its own: stock, reservation and costing calls on
+// it are passed on to its components, so every document that sells one takes the
+// components off the shelf.
+
+const PRODUCT_COLUMNS: &str = "p.id, p.name, p.phone_brand, p.item_type, p.description, p.created_at,
+    (SELECT COUNT(*) FROM inventory_items i WHERE i.product_id = p.id)";
+
+/// Map a row selected with `PRODUCT_COLUMNS`; `attribute_names` is filled in after.
+fn product_from_row(row: &rusqlite::Row) -> rusqlite::Result<Product> {
+    Ok(Product {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        phone_brand: row.get(2)?,
+        item_type: row.get(3)?,
+        description: row.get(4)?,
+        created_at: row.get(5)?,
+        variant_count: row.get(6)?,
+        attribute_names: Vec::new(),
+    })
+}
+
+fn attribute_names(conn: &Connection, product_id: &str) -> Result<Vec<String>, AppError> {
+    let mut stmt = conn.prepare_cached("SELECT name FROM product_attributes WHERE product_id = ?1 ORDER BY position")?;
+    let names = stmt
+        .query_map(params![product_id], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    Ok(names)
+}
+
+fn get_product_internal(conn: &Connection, id: &str) -> Result<Product, AppError> {
+    let mut product = conn
+        .query_row(
+            &format!("SELECT {} FROM products p WHERE p.id = ?1", PRODUCT_COLUMNS),
+            params![id],
+            product_from_row,
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound(format!("Product {} not found", id)))?;
+    product.attribute_names = attribute_names(conn, id)?;
+    Ok(product)
+}
+
+// ======================
+// VARIANTS
+// ======================
+
+/// Tidy an item's attributes and check them against its product, putting them in the
+/// product's order. Returns the variant label: the values joined with " / ".
+pub(crate) fn variant_label(
+    conn: &Connection,
+    product_id: Option<&str>,
+    attributes: &mut Vec<ItemAttribute>,
+) -> Result<Option<String>, AppError> {
+    for attribute in attributes.iter_mut() {
+        attribute.name = attribute.name.trim().to_string();
+        attribute.value = attribute.value.trim().to_string();
+        if attribute.name.is_empty() {
+            return Err(AppError::Validation("Attribute name is required".to_string()));
+        }
+    }
+    attributes.retain(|attribute| !attribute.value.is_empty());
+    for (i, attribute) in attributes.iter().enumerate() {
+        if attributes[..i].iter().any(|other| other.name.eq_ignore_ascii_case(&attribute.name)) {
+            return Err(AppError::Validation(format!("Attribute '{}' is given twice", attribute.name)));
+        }
+    }
+
+    if let Some(product_id) = product_id {
+        let product = get_product_internal(conn, product_id)?;
+        if !product.attribute_names.is_empty() {
+            let position = |name: &str| {
+                product
+                    .attribute_names
+                    .iter()
+                    .position(|known| known.eq_ignore_ascii_case(name))
+            };
+            if let Some(unknown) = attributes.iter().find(|attribute| position(&attribute.name).is_none()) {
+                return Err(AppError::Validation(format!(
+                    "'{}' is not an attribute of {}, expected one of {}",
+                    unknown.name,
+                    product.name,
+                    product.attribute_names.join(", ")
+                )));
+            }
+            attributes.sort_by_key(|attribute| position(&attribute.name));
+            // Spell the names the product's way
+            for attribute in attributes.iter_mut() {
+                if let Some(i) = position(&attribute.name) {
+                    attribute.name = product.attribute_names[i].clone();
+                }
+            }
+        }
+    }
+
+    if attributes.is_empty() {
+        return Ok(None);
+    }
+    let values: Vec<&str> = attributes.iter().map(|attribute| attribute.value.as_str()).collect();
+    Ok(Some(values.join(" / ")))
+}
+
+/// Two variants of a product can't have the same attributes.
+pub(crate) fn check_variant_unique(
+    conn: &Connection,
+    item_id: &str,
+    product_id: Option<&str>,
+    label: Option<&str>,
+) -> Result<(), AppError> {
+    let (Some(product_id), Some(label)) = (product_id, label) else {
+        return Ok(());
+    };
+    let existing: Option<String> = conn
+        .query_row(
+            "SELECT item_name FROM inventory_items WHERE product_id = ?1 AND variant_label = ?2 AND id != ?3",
+            params![product_id, label, item_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    match existing {
+        Some(name) => Err(AppError::Conflict(format!("'{}' is already the {} variant", name, label))),
+        None => Ok(()),
+    }
+}
+
+/// Replace an item's attributes.
+pub(crate) fn save_attributes(conn: &Connection, item_id: &str, attributes: &[ItemAttribute]) -> Result<(), AppError> {
+    conn.execute("DELETE FROM item_attributes WHERE item_id = ?1", params![item_id])?;
+    for (position, attribute) in attributes.iter().enumerate() {
+        conn.execute(
+            "INSERT INTO item_attributes (item_id, name, value, position) VALUES (?1, ?2, ?3, ?4)",
+            params![item_id, attribute.name, attribute.value, position as i64],
+        )?;
+    }
+    Ok(())
+}
+
+fn item_attributes(conn: &Connection, item_id: &str) -> Result<Vec<ItemAttribute>, AppError> {
+    let mut stmt = conn.prepare_cached("SELECT name, value FROM item_attributes WHERE item_id = ?1 ORDER BY position")?;
+    let attributes = stmt
+        .query_map(params![item_id], |row| {
+            Ok(ItemAttribute {
+                name: row.get(0)?,
+                value: row.get(1)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(attributes)
+}
+
+// ======================
+// BUNDLES
+// ======================
+
+/// The components of a bundle and how many of each go in one; empty for other items.
+pub(crate) fn bundle_components(conn: &Connection, item_id: &str) -> Result<Vec<(String, i64)>, AppError> {
+    let mut stmt = conn.prepare_cached("SELECT component_id, quantity FROM bundle_components WHERE bundle_id = ?1")?;
+    let components = stmt
+        .query_map(params![item_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+    Ok(components)
+}
+
+/// Check what an item is made of. Only bundles have components; neither bundles nor
+/// their components can be serialized, and a component can't be a bundle itself.
+pub(crate) fn check_bundle(conn: &Connection, item: &InventoryItem) -> Result<(), AppError> {
+    let used_in: Option<String> = conn
+        .query_row(
+            "SELECT i.item_name FROM bundle_components b JOIN inventory_items i ON i.id = b.bundle_id
+             WHERE b.component_id = ?1 LIMIT 1",
+            params![item.id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if !item.is_bundle {
+        if !item.components.is_empty() {
+            return Err(AppError::Validation("Only bundles have components".to_string()));
+        }
+        if let (Some(bundle_name), Some(_)) = (used_in, &item.serial_type) {
+            return Err(AppError::Validation(format!(
+                "'{}' is a component of '{}' and can't be serialized",
+                item.item_name, bundle_name
+            )));
+        }
+        return Ok(());
+    }
+    if item.serial_type.is_some() {
+        return Err(AppError::Validation("A bundle can't be serialized; its components carry the stock".to_string()));
+    }
+    if item.components.is_empty() {
+        return Err(AppError::Validation("A bundle needs at least one component".to_string()));
+    }
+    if let Some(bundle_name) = used_in {
+        return Err(AppError::Validation(format!(
+            "'{}' is a component of '{}' and can't be a bundle itself",
+            item.item_name, bundle_name
+        )));
+    }
+
+    for (i, component) in item.components.iter().enumerate() {
+        if component.quantity <= 0 {
+            return Err(AppError::Validation("Component quantity must be greater than zero".to_string()));
+        }
+        if component.component_id == item.id {
+            return Err(AppError::Validation("A bundle can't contain itself".to_string()));
+        }
+        if item.components[..i].iter().any(|other| other.component_id == component.component_id) {
+            return Err(AppError::Validation("A component is listed twice".to_string()));
+        }
+        let (name, is_bundle, serial_type): (String, bool, Option<String>) = conn
+            .query_row(
+                "SELECT item_name, is_bundle, serial_type FROM inventory_items WHERE id = ?1",
+                params![component.component_id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()?
+            .ok_or_else(|| AppError::NotFound(format!("Inventory item {} not found", component.component_id)))?;
+        if is_bundle {
+            return Err(AppError::Validation(format!("'{}' is a bundle; bundles can't contain bundles", name)));
+        }
+        if serial_type.is_some() {
+            return Err(AppError::Validation(format!(
+                "'{}' is serialized and can't be a bundle component",
+                name
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Replace a bundle's components.
+pub(crate) fn save_components(conn: &Connection, bundle_id: &str, components: &[BundleComponent]) -> Result<(), AppError> {
+    conn.execute("DELETE FROM bundle_components WHERE bundle_id = ?1", params![bundle_id])?;
+    for component in components {
+        conn.execute(
+            "INSERT INTO bundle_components (bundle_id, component_id, quantity) VALUES (?1, ?2, ?3)",
+            params![bundle_id, component.component_id, component.quantity],
+        )?;
+    }
+    Ok(())
+}
+
+/// Fill in an item's attributes and, for a bundle, its components and how many bundles
+/// can be made from the components available.
+pub(crate) fn fill_item_details(conn: &Connection, item: &mut InventoryItem) -> Result<(), AppError> {
+    item.attributes = item_attributes(conn, &item.id)?;
+    if !item.is_bundle {
+        return Ok(());
+    }
+    let mut stmt = conn.prepare_cached(
+        "SELECT b.component_id, i.item_name, b.quantity, COALESCE(i.quantity_in_stock, 0) - i.reserved_quantity
+         FROM bundle_components b JOIN inventory_items i ON i.id = b.component_id
+         WHERE b.bundle_id = ?1 ORDER BY i.item_name",
+    )?;
+    let rows: Vec<(BundleComponent, i64)> = stmt
+        .query_map(params![item.id], |row| {
+            Ok((
+                BundleComponent {
+                    component_id: row.get(0)?,
+                    component_name: row.get(1)?,
+                    quantity: row.get(2)?,
+                },
+                row.get(3)?,
+            ))
+        })?
+        .collect::<Result<_, _>>()?;
+    item.available_quantity = rows
+        .iter()
+        .map(|(component, available)| (*available).max(0) / component.quantity)
+        .min();
+    item.components = rows.into_iter().map(|(component, _)| component).collect();
+    Ok(())
+}
+
+// ======================
+// PRODUCTS
+// ======================
+
+#[tauri::command]
+pub fn get_products(pool: State<'_, DbPool>) -> Result<Vec<Product>, AppError> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare_cached(&format!("SELECT {} FROM products p ORDER BY p.name", PRODUCT_COLUMNS))?;
+    let mut products: Vec<Product> = stmt
+        .query_map([], product_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    for product in products.iter_mut() {
+        product.attribute_names = attribute_names(&conn, &product.id)?;
+    }
+    Ok(products)
+}
+
+/// Create a product (empty `id`) or update one. Attributes its variants already use
+/// can't be dropped from `attribute_names`; the variants' labels follow the new order.
+#[tauri::command]
+pub fn save_product(pool: State<'_, DbPool>, mut product: Product) -> Result<Product, AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+
+    product.name = product.name.trim().to_string();
+    if product.name.is_empty() {
+        return Err(AppError::Validation("Product name is required".to_string()));
+    }
+    let mut names: Vec<String> = Vec::new();
+    for name in &product.attribute_names {
+        let name = name.trim();
+        if name.is_empty() {
+            continue;
+        }
+        if names.iter().any(|known| known.eq_ignore_ascii_case(name)) {
+            return Err(AppError::Validation(format!("Attribute '{}' is given twice", name)));
+        }
+        names.push(name.to_string());
+    }
+    product.attribute_names = names;
+
+    if product.id.is_empty() {
+        product.id = Uuid::new_v4().to_string();
+        product.created_at = Utc::now().to_rfc3339();
+    } else {
+        product.created_at = get_product_internal(&tx, &product.id)?.created_at;
+    }
+    tx.execute(
+        "INSERT INTO products (id, name, phone_brand, item_type, description, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+         ON CONFLICT(id) DO UPDATE SET name = ?2, phone_brand = ?3, item_type = ?4, description = ?5",
+        params![
+            product.id,
+            product.name,
+            product.phone_brand,
+            product.item_type,
+            product.description,
+            product.created_at,
+        ],
+    )?;
+    tx.execute("DELETE FROM product_attributes WHERE product_id = ?1", params![product.id])?;
+    for (position, name) in product.attribute_names.iter().enumerate() {
+        tx.execute(
+            "INSERT INTO product_attributes (product_id, name, position) VALUES (?1, ?2, ?3)",
+            params![product.id, name, position as i64],
+        )?;
+    }
+
+    // Check the variants against the new attributes and relabel them in the new order
+    let variant_ids: Vec<String> = {
+        let mut stmt = tx.prepare_cached("SELECT id FROM inventory_items WHERE product_id = ?1")?;
+        let ids = stmt.query_map(params![product.id], |row| row.get(0))?;
+        ids.collect::<Result<_, _>>()?
+    };
+    for variant_id in &variant_ids {
+        let mut attributes = item_attributes(&tx, variant_id)?;
+        let label = variant_label(&tx, Some(&product.id), &mut attributes)?;
+        save_attributes(&tx, variant_id, &attributes)?;
+        tx.execute(
+            "UPDATE inventory_items SET variant_label = ?2 WHERE id = ?1",
+            params![variant_id, label],
+        )?;
+    }
+
+    product.variant_count = variant_ids.len() as i64;
+    tx.commit()?;
+    Ok(product)
+}
+
+/// Delete a product that has no variants left.
+#[tauri::command]
+pub fn delete_product(pool: State<'_, DbPool>, id: String) -> Result<(), AppError> {
+    let conn = pool.get()?;
+    let product = get_product_internal(&conn, &id)?;
+    if product.variant_count > 0 {
+        return Err(AppError::Conflict(format!(
+            "{} still has {} variants; move or delete them first",
+            product.name, product.variant_count
+        )));
+    }
+    conn.execute("DELETE FROM products WHERE id = ?1", params![id])?;
+    Ok(())
+}
+
+/// The variants of a product, ordered by their label.
+#[tauri::command]
+pub fn get_product_variants(pool: State<'_, DbPool>, product_id: String) -> Result<Vec<InventoryItem>, AppError> {
+    let conn = pool.get()?;
+    get_product_internal(&conn, &product_id)?;
+    inventory::items_where(&conn, "product_id = ?1 ORDER BY variant_label, item_name", params![product_id])
+}
@@ -0,0 +1,432 @@
+use crate::db::models::{
+    Order, OrderItem, ReorderDocument, ReorderGroup, ReorderLine, ReorderSettings, ReorderSuggestion, Transaction,
+    TransactionItem,
+};
+use crate::db::money::Money;
+use crate::db::order;
+use crate::db::price_list;
+use crate::db::transaction;
+use crate::db::DbPool;
+use crate::error::AppError;
+use chrono::{Duration, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use tauri::State;
+use uuid::Uuid;
+
+// Consumption is read from `inventory_history`: units sold or used in repairs, less
+// those that came back from customers or repairs and those of cancelled sales and
+// returns. Bundles show up as their components.
+
+const DEFAULT_SETTINGS: ReorderSettings = ReorderSettings {
+    window_days: 30,
+    lead_time_days: 7,
+    cover_days: 30,
+};
+
+/// Reorder lines for one supplier, with each item's name and the price to order it at.
+type SupplierLines = (String, Vec<(ReorderLine, String, Money)>);
+
+/// Ten years; longer windows or lead times make no sense and would overflow the date maths.
+const MAX_DAYS: i64 = 3650;
+
+const SETTING_KEYS: [&str; 3] = ["reorder_window_days", "reorder_lead_time_days", "reorder_cover_days"];
+
+fn reorder_settings(conn: &Connection) -> Result<ReorderSettings, AppError> {
+    let mut values = [DEFAULT_SETTINGS.window_days, DEFAULT_SETTINGS.lead_time_days, DEFAULT_SETTINGS.cover_days];
+    for (value, key) in values.iter_mut().zip(SETTING_KEYS) {
+        let stored: Option<i64> = conn
+            .query_row(
+                "SELECT CAST(value AS INTEGER) FROM app_settings WHERE key = ?1",
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(stored) = stored {
+            *value = stored;
+        }
+    }
+    Ok(ReorderSettings {
+        window_days: values[0],
+        lead_time_days: values[1],
+        cover_days: values[2],
+    })
+}
+
+fn check_settings(settings: &ReorderSettings) -> Result<(), AppError> {
+    if settings.window_days <= 0 {
+        return Err(AppError::Validation("The consumption window must be at least one day".to_string()));
+    }
+    if settings.lead_time_days < 0 || settings.cover_days < 0 {
+        return Err(AppError::Validation("Lead time and cover can't be negative".to_string()));
+    }
+    if settings.window_days > MAX_DAYS || settings.lead_time_days > MAX_DAYS || settings.cover_days > MAX_DAYS {
+        return Err(AppError::Validation(format!(
+            "The consumption window, lead time and cover can be at most {} days",
+            MAX_DAYS
+        )));
+    }
+    Ok(())
+}
+
+/// Where an item stands and what it would take to bring it back up to its target level,
+/// or None when it doesn't need ordering.
+fn suggest(
+    settings: &ReorderSettings,
+    on_hand: i64,
+    reserved: i64,
+    on_order: i64,
+    consumed: i64,
+    threshold: Option<i64>,
+) -> Option<(f64, i64, i64, i64)> {
+    let daily_usage = consumed.max(0) as f64 / settings.window_days as f64;
+    if daily_usage == 0.0 && threshold.is_none() {
+        return None;
+    }
+    let lead_time_demand = (daily_usage * settings.lead_time_days as f64).ceil() as i64;
+    let reorder_point = threshold.unwrap_or(0).max(lead_time_demand);
+    let target_level = ((daily_usage * (settings.lead_time_days + settings.cover_days) as f64).ceil() as i64)
+        .max(reorder_point + 1);
+
+    let projected = on_hand - reserved + on_order;
+    if projected > reorder_point {
+        return None;
+    }
+    Some((daily_usage, reorder_point, target_level, target_level - projected))
+}
+
+// ======================
+// REORDER SETTINGS
+// ======================
+
+#[tauri::command]
+pub fn get_reorder_settings(pool: State<'_, DbPool>) -> Result<ReorderSettings, AppError> {
+    let conn = pool.get()?;
+    reorder_settings(&conn)
+}
+
+#[tauri::command]
+pub fn set_reorder_settings(pool: State<'_, DbPool>, settings: ReorderSettings) -> Result<(), AppError> {
+    check_settings(&settings)?;
+    let conn = pool.get()?;
+    let values = [settings.window_days, settings.lead_time_days, settings.cover_days];
+    for (key, value) in SETTING_KEYS.iter().zip(values) {
+        conn.execute(
+            "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
+            params![key, value.to_string()],
+        )?;
+    }
+    Ok(())
+}
+
+// ======================
+// SUGGESTIONS
+// ======================
+
+/// Items to order, grouped by the supplier they are bought from: the item's preferred
+/// supplier, else the cheapest supplier on whose price list it is, else the supplier
+/// named in its supplier info, else whoever it was last ordered from. Items are costed
+/// at that supplier's price when it has one. Uses the stored settings unless others
+/// are given.
+#[tauri::command]
+pub fn get_reorder_suggestions(
+    pool: State<'_, DbPool>,
+    settings: Option<ReorderSettings>,
+) -> Result<Vec<ReorderGroup>, AppError> {
+    let conn = pool.get()?;
+    let settings = match settings {
+        Some(settings) => settings,
+        None => reorder_settings(&conn)?,
+    };
+    check_settings(&settings)?;
+    let since = (Utc::now() - Duration::days(settings.window_days)).to_rfc3339();
+
+    let mut stmt = conn.prepare_cached(
+        "SELECT i.id, i.item_name, i.variant_label, i.buying_price, i.low_stock_threshold,
+                COALESCE(i.quantity_in_stock, 0), i.reserved_quantity,
+                COALESCE(i.preferred_supplier_id,
+                    (SELECT si.supplier_id FROM supplier_items si WHERE si.item_id = i.id
+                     ORDER BY COALESCE(si.price, si.last_purchase_price) IS NULL, COALESCE(si.price, si.last_purchase_price)
+                     LIMIT 1),
+                    (SELECT s.id FROM suppliers s WHERE s.name = i.supplier_info COLLATE NOCASE LIMIT 1),
+                    (SELECT supplier_id FROM (
+                        SELECT o.supplier_id, o.created_at FROM order_items oi JOIN orders o ON o.id = oi.order_id
+                        WHERE oi.item_id = i.id
+                        UNION ALL
+                        SELECT t.party_id, t.created_at FROM transaction_items ti JOIN transactions t ON t.id = ti.transaction_id
+                        WHERE ti.item_id = i.id AND t.transaction_type = 'Purchase' AND t.status != 'Cancelled'
+                    ) ORDER BY created_at DESC LIMIT 1)),
+                (SELECT COALESCE(SUM(oi.quantity - oi.received_quantity - oi.cancelled_quantity), 0)
+                 FROM order_items oi JOIN orders o ON o.id = oi.order_id
+                 WHERE oi.item_id = i.id AND o.status != 'completed')
+                + (SELECT COALESCE(SUM(ti.quantity), 0) FROM transaction_items ti JOIN transactions t ON t.id = ti.transaction_id
+                 WHERE ti.item_id = i.id AND t.transaction_type = 'Purchase' AND t.status = 'Draft'),
+                COALESCE(c.consumed, 0)
+         FROM inventory_items i
+         LEFT JOIN (
+             SELECT h.item_id, -SUM(h.quantity_change) AS consumed
+             FROM inventory_history h
+             LEFT JOIN transactions t ON t.id = h.related_id
+             LEFT JOIN customer_sales s ON s.id = h.related_id
+             WHERE h.date >= ?1
+             AND (h.event_type IN ('Sold', 'Used in Repair', 'Returned', 'Return from Repair')
+                  OR (h.event_type IN ('Cancelled', 'Adjustment')
+                      AND (t.transaction_type IN ('Sale', 'Return') OR s.id IS NOT NULL)))
+             GROUP BY h.item_id
+         ) c ON c.item_id = i.id
+         WHERE i.quantity_in_stock IS NOT NULL AND NOT i.is_bundle
+         ORDER BY i.item_name",
+    )?;
+    let rows = stmt.query_map(params![since], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, Option<String>>(2)?,
+            row.get::<_, Money>(3)?,
+            row.get::<_, Option<i64>>(4)?,
+            row.get::<_, i64>(5)?,
+            row.get::<_, i64>(6)?,
+            row.get::<_, Option<String>>(7)?,
+            row.get::<_, i64>(8)?,
+            row.get::<_, i64>(9)?,
+        ))
+    })?;
+
+    let mut groups: Vec<ReorderGroup> = Vec::new();
+    for row in rows {
+        let (item_id, item_name, variant_label, unit_cost, threshold, on_hand, reserved, supplier_id, on_order, consumed) =
+            row?;
+        let Some((daily_usage, reorder_point, target_level, suggested_quantity)) =
+            suggest(&settings, on_hand, reserved, on_order, consumed, threshold)
+        else {
+            continue;
+        };
+        let unit_cost = match &supplier_id {
+            Some(supplier_id) => price_list::supplier_price(&conn, supplier_id, &item_id)?.unwrap_or(unit_cost),
+            None => unit_cost,
+        };
+        let suggestion = ReorderSuggestion {
+            item_id,
+            item_name,
+            variant_label,
+            supplier_id: supplier_id.clone(),
+            on_hand,
+            reserved,
+            on_order,
+            consumed,
+            daily_usage,
+            reorder_point,
+            target_level,
+            suggested_quantity,
+            unit_cost,
+        };
+        match groups.iter_mut().find(|group| group.supplier_id == supplier_id) {
+            Some(group) => group.items.push(suggestion),
+            None => groups.push(ReorderGroup {
+                supplier_id,
+                supplier_name: None,
+                items: vec![suggestion],
+                estimated_total: Money::ZERO,
+            }),
+        }
+    }
+
+    for group in groups.iter_mut() {
+        group.estimated_total = group.items.iter().map(|item| item.unit_cost * item.suggested_quantity).sum();
+        if let Some(supplier_id) = &group.supplier_id {
+            group.supplier_name = conn
+                .query_row("SELECT name FROM suppliers WHERE id = ?1", params![supplier_id], |row| row.get(0))
+                .optional()?;
+        }
+    }
+    // Suppliers by name, items nobody supplies last
+    groups.sort_by(|a, b| {
+        (a.supplier_name.is_none(), &a.supplier_name).cmp(&(b.supplier_name.is_none(), &b.supplier_name))
+    });
+    Ok(groups)
+}
+
+/// Turn reorder lines into one draft per supplier: an order (`document_type` "Order")
+/// or a Purchase transaction ("Purchase"). Nothing is created if any line is invalid.
+#[tauri::command]
+pub fn create_reorder_documents(
+    pool: State<'_, DbPool>,
+    lines: Vec<ReorderLine>,
+    document_type: String,
+    location_id: Option<String>,
+    created_by: Option<String>,
+) -> Result<Vec<ReorderDocument>, AppError> {
+    if document_type != "Order" && document_type != "Purchase" {
+        return Err(AppError::Validation(format!(
+            "Unknown document type '{}', expected Order or Purchase",
+            document_type
+        )));
+    }
+    if lines.is_empty() {
+        return Err(AppError::Validation("Nothing to order".to_string()));
+    }
+
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+
+    // Lines by supplier, in the order the suppliers first appear
+    let mut by_supplier: Vec<SupplierLines> = Vec::new();
+    for line in lines {
+        if line.quantity <= 0 {
+            return Err(AppError::Validation("Order quantities must be positive".to_string()));
+        }
+        let supplier_exists: Option<String> = tx
+            .query_row("SELECT id FROM suppliers WHERE id = ?1", params![line.supplier_id], |row| row.get(0))
+            .optional()?;
+        if supplier_exists.is_none() {
+            return Err(AppError::NotFound(format!("Supplier {} not found", line.supplier_id)));
+        }
+        let (item_name, buying_price): (String, Money) = tx
+            .query_row(
+                "SELECT item_name, buying_price FROM inventory_items WHERE id = ?1",
+                params![line.item_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?
+            .ok_or_else(|| AppError::NotFound(format!("Inventory item {} not found", line.item_id)))?;
+        let unit_price = match line.unit_price {
+            Some(unit_price) => unit_price,
+            None => price_list::supplier_price(&tx, &line.supplier_id, &line.item_id)?.unwrap_or(buying_price),
+        };
+        match by_supplier.iter_mut().find(|(supplier_id, _)| *supplier_id == line.supplier_id) {
+            Some((_, group)) => group.push((line, item_name, unit_price)),
+            None => by_supplier.push((line.supplier_id.clone(), vec![(line, item_name, unit_price)])),
+        }
+    }
+
+    let now = Utc::now().to_rfc3339();
+    let notes = Some("Created from reorder suggestions".to_string());
+    let mut documents = Vec::new();
+    let mut purchases = Vec::new();
+    for (supplier_id, group) in by_supplier {
+        let line_count = group.len() as i64;
+        let document = if document_type == "Order" {
+            let order = order::insert_order(
+                &tx,
+                Order {
+                    id: Uuid::new_v4().to_string(),
+                    order_number: String::new(),
+                    supplier_id: supplier_id.clone(),
+                    status: "draft".to_string(),
+                    payment_status: "unpaid".to_string(),
+                    total_amount: Money::ZERO,
+                    paid_amount: Money::ZERO,
+                    notes: notes.clone(),
+                    created_at: now.clone(),
+                    updated_at: now.clone(),
+                    created_by: created_by.clone(),
+                    location_id: location_id.clone(),
+                },
+            )?;
+            for (line, item_name, unit_price) in group {
+                order::insert_order_item(
+                    &tx,
+                    &OrderItem {
+                        id: Uuid::new_v4().to_string(),
+                        order_id: order.id.clone(),
+                        item_id: Some(line.item_id),
+                        item_name,
+                        quantity: line.quantity as i32,
+                        unit_price,
+                        total_price: unit_price * line.quantity,
+                        notes: None,
+                        received_quantity: 0,
+                        cancelled_quantity: 0,
+                    },
+                )?;
+            }
+            let total_amount: Money = tx.query_row(
+                "SELECT total_amount FROM orders WHERE id = ?1",
+                params![order.id],
+                |row| row.get(0),
+            )?;
+            ReorderDocument {
+                document_type: document_type.clone(),
+                id: order.id,
+                number: order.order_number,
+                supplier_id,
+                line_count,
+                total_amount,
+            }
+        } else {
+            let transaction_id = Uuid::new_v4().to_string();
+            let items = group
+                .into_iter()
+                .map(|(line, item_name, unit_price)| TransactionItem {
+                    id: Uuid::new_v4().to_string(),
+                    transaction_id: transaction_id.clone(),
+                    item_id: Some(line.item_id),
+                    item_name,
+                    quantity: line.quantity as i32,
+                    unit_price,
+                    total_price: unit_price * line.quantity,
+                    notes: None,
+                    original_item_id: None,
+                    tax_rate_id: None,
+                    tax_rate: 0.0,
+                    tax_inclusive: false,
+                    net_amount: Money::ZERO,
+                    tax_amount: Money::ZERO,
+                    discount: None,
+                    discount_amount: Money::ZERO,
+                    document_discount_amount: Money::ZERO,
+                    unit_cost: None,
+                    serial_numbers: Vec::new(),
+                })
+                .collect();
+            let purchase = transaction::insert_submitted_transaction(
+                &tx,
+                Transaction {
+                    id: transaction_id,
+                    transaction_number: String::new(),
+                    transaction_type: "Purchase".to_string(),
+                    party_id: supplier_id.clone(),
+                    party_type: "Supplier".to_string(),
+                    status: "Draft".to_string(),
+                    payment_status: "Unpaid".to_string(),
+                    total_amount: Money::ZERO,
+                    paid_amount: Money::ZERO,
+                    notes: notes.clone(),
+                    created_at: now.clone(),
+                    updated_at: now.clone(),
+                    created_by: created_by.clone(),
+                    original_transaction_id: None,
+                    subtotal: Money::ZERO,
+                    tax_amount: Money::ZERO,
+                    discount: None,
+                    discount_amount: Money::ZERO,
+                    location_id: location_id.clone(),
+                },
+                items,
+                Vec::new(),
+                false,
+            )?;
+            let total_amount: Money = tx.query_row(
+                "SELECT total_amount FROM transactions WHERE id = ?1",
+                params![purchase.id],
+                |row| row.get(0),
+            )?;
+            let document = ReorderDocument {
+                document_type: document_type.clone(),
+                id: purchase.id.clone(),
+                number: purchase.transaction_number.clone(),
+                supplier_id,
+                line_count,
+                total_amount,
+            };
+            purchases.push(purchase);
+            document
+        };
+        documents.push(document);
+    }
+
+    tx.commit()?;
+    for purchase in &purchases {
+        transaction::log_party_submission(&conn, purchase);
+    }
+    Ok(documents)
+}
@@ -1,7 +1,23 @@
-use super::models::{Repair, RepairHistory, RepairPayment, RepairUsedPart};
-use rusqlite::{params, Connection, OptionalExtension, Result};
+use super::models::{
+    Repair, RepairHistory, RepairPage, RepairPayment, RepairQuery, RepairStatusCount, RepairUsedPart,
+};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Result};
 use chrono::Utc;
 use uuid::Uuid;
+use crate::db::{contains_pattern, fts_prefix_query, DbPool};
+use crate::db::costing;
+use crate::db::inventory;
+use crate::db::location;
+use crate::db::repair_status;
+use crate::db::reservation;
+use crate::db::serial::{self, SerialSource};
+use crate::db::currency::{edited_amount, tendered_amount};
+use crate::db::discount::{discount_columns, discount_from_row};
+use crate::db::money::Money;
+use crate::db::tax::apply_repair_tax;
+use tauri::State;
+use crate::error::AppError;
 
 /// ======================
 /// CRUD FUNCTIONS
@@ -9,18 +25,19 @@ use uuid::Uuid;
 
 /// Insert a new repair
 #[tauri::command]
-pub fn insert_repair(mut repair: Repair) -> Result<(), String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+pub fn insert_repair(pool: State<'_, DbPool>, mut repair: Repair) -> Result<(), AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    repair_status::status_kind(&tx, &repair.status)?;
 
     // Generate readable code
-    let last_code: Option<String> = conn
+    let last_code: Option<String> = tx
         .query_row(
             "SELECT code FROM repairs WHERE code IS NOT NULL ORDER BY created_at DESC LIMIT 1",
             [],
             |row| row.get(0),
         )
-        .optional()
-        .map_err(|e| e.to_string())?;
+        .optional()?;
 
     let new_code = match last_code {
         Some(code) => {
@@ -34,10 +51,13 @@ pub fn insert_repair(mut repair: Repair) -> Result<(), String> {
         None => "REP001".to_string(),
     };
     repair.code = Some(new_code.clone());
+    repair.device_serial = serial::normalize_device_serial(repair.device_serial.as_deref())?;
+    apply_repair_tax(&tx, &mut repair)?;
+    let (discount_type, discount_value, discount_reason, discount_by) = discount_columns(&repair.discount);
 
-    conn.execute(
-        "INSERT INTO repairs (id, customer_name, customer_phone, device_brand, device_model, issue_description, estimated_cost, status, payment_status, created_at, updated_at, code)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, datetime('now'), datetime('now'), ?10)",
+    tx.execute(
+        "INSERT INTO repairs (id, customer_name, customer_phone, device_brand, device_model, issue_description, estimated_cost, status, payment_status, created_at, updated_at, code, tax_rate_id, tax_rate, tax_inclusive, tax_amount, total_amount, discount_type, discount_value, discount_reason, discount_by, discount_amount, device_serial)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, datetime('now'), datetime('now'), ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21)",
         params![
             repair.id,
             repair.customer_name,
@@ -49,64 +69,208 @@ pub fn insert_repair(mut repair: Repair) -> Result<(), String> {
             repair.status,
             repair.payment_status,
             new_code,
+            repair.tax_rate_id,
+            repair.tax_rate,
+            repair.tax_inclusive,
+            repair.tax_amount,
+            repair.total_amount,
+            discount_type,
+            discount_value,
+            discount_reason,
+            discount_by,
+            repair.discount_amount,
+            repair.device_serial,
         ],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
+    repair_status::start_status(&tx, &repair.id, &repair.status, repair.status_reason.as_deref())?;
+    tx.commit()?;
     Ok(())
 }
 
+const REPAIR_COLUMNS: &str = "id, customer_name, customer_phone, device_brand, device_model, issue_description, estimated_cost, status, payment_status, created_at, updated_at, code, tax_rate_id, tax_rate, tax_inclusive, tax_amount, total_amount, discount_type, discount_value, discount_reason, discount_by, discount_amount, device_serial, status_reason";
+
+/// Map a row selected with `REPAIR_COLUMNS`; parts, payments and history are left empty.
+fn repair_from_row(row: &rusqlite::Row) -> rusqlite::Result<Repair> {
+    Ok(Repair {
+        id: row.get(0)?,
+        customer_name: row.get(1)?,
+        customer_phone: row.get(2)?,
+        device_brand: row.get(3)?,
+        device_model: row.get(4)?,
+        issue_description: row.get(5)?,
+        estimated_cost: row.get(6)?,
+        status: row.get(7)?,
+        status_reason: row.get(23)?,
+        payment_status: row.get(8)?,
+        created_at: row.get(9)?,
+        updated_at: row.get(10)?,
+        code: row.get(11).ok(), // Optional
+        device_serial: row.get(22)?,
+        tax_rate_id: row.get(12)?,
+        tax_rate: row.get(13)?,
+        tax_inclusive: row.get(14)?,
+        tax_amount: row.get(15)?,
+        total_amount: row.get(16)?,
+        discount: discount_from_row(row, 17)?,
+        discount_amount: row.get(21)?,
+        used_parts: Vec::new(),
+        payments: Vec::new(),
+        history: Vec::new(),
+    })
+}
+
 /// Fetch all repairs
 #[tauri::command]
-pub fn get_repairs() -> Result<Vec<Repair>, String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+pub fn get_repairs(pool: State<'_, DbPool>) -> Result<Vec<Repair>, AppError> {
+    let conn = pool.get()?;
     let mut stmt = conn
-        .prepare("SELECT id, customer_name, customer_phone, device_brand, device_model, issue_description, estimated_cost, status, payment_status, created_at, updated_at, code FROM repairs ORDER BY created_at DESC")
-        .map_err(|e| e.to_string())?;
+        .prepare_cached(&format!("SELECT {} FROM repairs ORDER BY created_at DESC", REPAIR_COLUMNS))?;
     let items = stmt
-        .query_map([], |row| {
-            Ok(Repair {
-                id: row.get(0)?,
-                customer_name: row.get(1)?,
-                customer_phone: row.get(2)?,
-                device_brand: row.get(3)?,
-                device_model: row.get(4)?,
-                issue_description: row.get(5)?,
-                estimated_cost: row.get(6)?,
-                status: row.get(7)?,
-                payment_status: row.get(8)?,
-                created_at: row.get(9)?,
-                updated_at: row.get(10)?,
-                code: row.get(11).ok(), // Optional
-                used_parts: Vec::new(),
-                payments: Vec::new(),
-                history: Vec::new(),
-            })
-        })
-        .map_err(|e| e.to_string())?
+        .query_map([], repair_from_row)?
         .filter_map(|res| res.ok())
         .collect();
     Ok(items)
 }
 
+/// Repairs of the customer device with this serial number or IMEI, newest first.
+pub(crate) fn repairs_for_device(conn: &Connection, device_serial: &str) -> Result<Vec<Repair>, AppError> {
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT {} FROM repairs WHERE device_serial = ?1 ORDER BY created_at DESC",
+        REPAIR_COLUMNS
+    ))?;
+    let repairs = stmt
+        .query_map(params![device_serial], repair_from_row)?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(repairs)
+}
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 500;
+
+/// Build the WHERE clause for a repair query, leaving out the status filter so the
+/// same clause can count repairs per status. Every value is bound as a parameter.
+fn repair_filter_clause(query: &RepairQuery) -> (String, Vec<Value>) {
+    let mut clause = String::from("WHERE 1=1");
+    let mut values: Vec<Value> = Vec::new();
+
+    if let Some(payment_status) = &query.payment_status {
+        values.push(Value::Text(payment_status.clone()));
+        clause.push_str(&format!(" AND payment_status = ?{}", values.len()));
+    }
+    // created_at is stored as "YYYY-MM-DD HH:MM:SS", so normalize the bounds with datetime()
+    if let Some(start) = &query.start_date {
+        values.push(Value::Text(start.clone()));
+        clause.push_str(&format!(" AND created_at >= datetime(?{})", values.len()));
+    }
+    if let Some(end) = &query.end_date {
+        values.push(Value::Text(end.clone()));
+        if end.len() == 10 {
+            clause.push_str(&format!(" AND created_at < datetime(?{}, '+1 day')", values.len()));
+        } else {
+            clause.push_str(&format!(" AND created_at <= datetime(?{})", values.len()));
+        }
+    }
+    if let Some(brand) = query.device_brand.as_deref().filter(|s| !s.is_empty()) {
+        values.push(Value::Text(brand.to_string()));
+        clause.push_str(&format!(" AND device_brand = ?{} COLLATE NOCASE", values.len()));
+    }
+    if let Some(model) = query.device_model.as_deref().filter(|s| !s.is_empty()) {
+        values.push(Value::Text(contains_pattern(model)));
+        clause.push_str(&format!(" AND device_model LIKE ?{} ESCAPE '\\'", values.len()));
+    }
+    if let Some(phone) = query.customer_phone.as_deref().filter(|s| !s.is_empty()) {
+        values.push(Value::Text(contains_pattern(phone)));
+        clause.push_str(&format!(" AND customer_phone LIKE ?{} ESCAPE '\\'", values.len()));
+    }
+    if let Some(search) = query.search.as_deref().and_then(fts_prefix_query) {
+        values.push(Value::Text(search));
+        clause.push_str(&format!(
+            " AND id IN (SELECT repair_id FROM repairs_fts WHERE repairs_fts MATCH ?{})",
+            values.len()
+        ));
+    }
+
+    (clause, values)
+}
+
+/// Search repairs with bound filters, one page at a time, with the number of
+/// matching repairs in each status for the board's tabs.
+#[tauri::command]
+pub fn query_repairs(pool: State<'_, DbPool>, query: RepairQuery) -> Result<RepairPage, AppError> {
+    let conn = pool.get()?;
+
+    let sort_column = match query.sort_by.as_deref().unwrap_or("created_at") {
+        "created_at" => "created_at",
+        "updated_at" => "updated_at",
+        "estimated_cost" => "estimated_cost",
+        "customer_name" => "customer_name",
+        "status" => "status",
+        other => return Err(AppError::Validation(format!("Cannot sort repairs by '{}'", other))),
+    };
+    let direction = if query.sort_desc.unwrap_or(true) { "DESC" } else { "ASC" };
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let offset = query.offset.unwrap_or(0).max(0);
+
+    let (mut filter, mut values) = repair_filter_clause(&query);
+
+    // 1. Counts per status, before the status filter applies
+    let mut stmt = conn.prepare(&format!(
+        "SELECT status, COUNT(*) FROM repairs {} GROUP BY status ORDER BY status",
+        filter
+    ))?;
+    let status_counts: Vec<RepairStatusCount> = stmt
+        .query_map(params_from_iter(values.iter()), |row| {
+            Ok(RepairStatusCount {
+                status: row.get(0)?,
+                count: row.get(1)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+
+    if let Some(status) = &query.status {
+        values.push(Value::Text(status.clone()));
+        filter.push_str(&format!(" AND status = ?{}", values.len()));
+    }
+
+    // 2. Total and page
+    let total_count: i64 = conn.query_row(
+        &format!("SELECT COUNT(*) FROM repairs {}", filter),
+        params_from_iter(values.iter()),
+        |row| row.get(0),
+    )?;
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM repairs {} ORDER BY {} {}, id {} LIMIT {} OFFSET {}",
+        REPAIR_COLUMNS, filter, sort_column, direction, direction, limit, offset
+    ))?;
+    let repairs = stmt
+        .query_map(params_from_iter(values.iter()), repair_from_row)?
+        .collect::<rusqlite::Result<_>>()?;
+
+    Ok(RepairPage {
+        repairs,
+        total_count,
+        status_counts,
+    })
+}
+
 /// Fetch repair by id
 #[tauri::command]
-pub fn get_repair_by_id(repair_id: String) -> Result<Option<Repair>, String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+pub fn get_repair_by_id(pool: State<'_, DbPool>, repair_id: String) -> Result<Option<Repair>, AppError> {
+    let conn = pool.get()?;
     
     // 1. Get the base repair
     let mut stmt = conn
-        .prepare("SELECT id, customer_name, customer_phone, device_brand, device_model, issue_description, estimated_cost, status, payment_status, created_at, updated_at, code FROM repairs WHERE id = ?1")
-        .map_err(|e| e.to_string())?;
+        .prepare_cached(&format!("SELECT {} FROM repairs WHERE id = ?1", REPAIR_COLUMNS))?;
     
-    let mut rows = stmt.query(params![repair_id]).map_err(|e| e.to_string())?;
+    let mut rows = stmt.query(params![repair_id])?;
     
-    if let Some(row) = rows.next().map_err(|e| e.to_string())? {
+    if let Some(row) = rows.next()? {
         // 2. Get used parts
         let mut parts_stmt = conn
-            .prepare("SELECT id, repair_id, part_id, part_name, quantity, unit_price FROM repair_used_parts WHERE repair_id = ?1")
-            .map_err(|e| e.to_string())?;
+            .prepare_cached("SELECT id, repair_id, part_id, part_name, quantity, unit_price, unit_cost, location_id FROM repair_used_parts WHERE repair_id = ?1")?;
             
-        let used_parts: Vec<RepairUsedPart> = parts_stmt
+        let mut used_parts: Vec<RepairUsedPart> = parts_stmt
             .query_map(params![repair_id], |row| {
                 Ok(RepairUsedPart {
                     id: row.get(0)?,
@@ -115,16 +279,20 @@ pub fn get_repair_by_id(repair_id: String) -> Result<Option<Repair>, String> {
                     part_name: row.get(3)?,
                     quantity: row.get(4)?,
                     unit_price: row.get(5)?,
+                    unit_cost: row.get(6)?,
+                    location_id: row.get(7)?,
+                    serial_numbers: Vec::new(),
                 })
-            })
-            .map_err(|e| e.to_string())?
+            })?
             .filter_map(|res| res.ok())
             .collect();
+        for part in &mut used_parts {
+            part.serial_numbers = serial::line_serials(&conn, &part.id)?;
+        }
 
         // 3. Get payments
         let mut payments_stmt = conn
-            .prepare("SELECT id, repair_id, amount, date, method, received_by, session_id FROM repair_payments WHERE repair_id = ?1 ORDER BY date DESC")
-            .map_err(|e| e.to_string())?;
+            .prepare_cached("SELECT id, repair_id, amount, date, method, received_by, session_id, currency_code, original_amount, exchange_rate FROM repair_payments WHERE repair_id = ?1 ORDER BY date DESC")?;
             
         let payments: Vec<RepairPayment> = payments_stmt
             .query_map(params![repair_id], |row| {
@@ -136,16 +304,17 @@ pub fn get_repair_by_id(repair_id: String) -> Result<Option<Repair>, String> {
                     method: row.get(4)?,
                     received_by: row.get(5).ok(),
                     session_id: row.get(6).ok(),
+                    currency_code: row.get(7).ok(),
+                    original_amount: row.get(8).ok(),
+                    exchange_rate: row.get(9).ok(),
                 })
-            })
-            .map_err(|e| e.to_string())?
+            })?
             .filter_map(|res| res.ok())
             .collect();
 
         // 4. Get history
         let mut history_stmt = conn
-            .prepare("SELECT id, repair_id, date, event_type, details, changed_by FROM repair_history WHERE repair_id = ?1 ORDER BY date DESC")
-            .map_err(|e| e.to_string())?;
+            .prepare_cached("SELECT id, repair_id, date, event_type, details, changed_by FROM repair_history WHERE repair_id = ?1 ORDER BY date DESC")?;
             
         let history: Vec<RepairHistory> = history_stmt
             .query_map(params![repair_id], |row| {
@@ -157,25 +326,25 @@ pub fn get_repair_by_id(repair_id: String) -> Result<Option<Repair>, String> {
                     details: row.get(4)?,
                     changed_by: row.get(5).ok(),
                 })
-            })
-            .map_err(|e| e.to_string())?
+            })?
             .filter_map(|res| res.ok())
             .collect();
 
         // 5. Calculate computed fields
-        let estimated_cost: f64 = row.get(6).unwrap_or(0.0);
+        let estimated_cost: Money = row.get(6).unwrap_or(Money::ZERO);
 
         // 6. Return full object
         Ok(Some(Repair {
-            id: row.get(0).map_err(|e| e.to_string())?,
-            customer_name: row.get(1).map_err(|e| e.to_string())?,
-            customer_phone: row.get(2).map_err(|e| e.to_string())?,
-            device_brand: row.get(3).map_err(|e| e.to_string())?,
-            device_model: row.get(4).map_err(|e| e.to_string())?,
-            issue_description: row.get(5).map_err(|e| e.to_string())?,
+            id: row.get(0)?,
+            customer_name: row.get(1)?,
+            customer_phone: row.get(2)?,
+            device_brand: row.get(3)?,
+            device_model: row.get(4)?,
+            issue_description: row.get(5)?,
             estimated_cost,
-            status: row.get(7).map_err(|e| e.to_string())?,
-            payment_status: row.get(8).map_err(|e| e.to_string())?,
+            status: row.get(7)?,
+            status_reason: row.get(23)?,
+            payment_status: row.get(8)?,
             
             // Related entities
             used_parts,
@@ -183,9 +352,21 @@ pub fn get_repair_by_id(repair_id: String) -> Result<Option<Repair>, String> {
             history,
             
             // Dates & Code
-            created_at: row.get(9).map_err(|e| e.to_string())?,
-            updated_at: row.get(10).map_err(|e| e.to_string())?,
+            created_at: row.get(9)?,
+            updated_at: row.get(10)?,
             code: row.get(11).ok(),
+            device_serial: row.get(22)?,
+
+            // Labor tax
+            tax_rate_id: row.get(12)?,
+            tax_rate: row.get(13)?,
+            tax_inclusive: row.get(14)?,
+            tax_amount: row.get(15)?,
+            total_amount: row.get(16)?,
+
+            // Labor discount
+            discount: discount_from_row(row, 17)?,
+            discount_amount: row.get(21)?,
             
             // Note: Our Rust struct might not have totalPaid/remainingBalance locally if they are not in the struct definition in models.rs
             // Checking models.rs... they are NOT in the struct.
@@ -208,10 +389,22 @@ pub fn get_repair_by_id(repair_id: String) -> Result<Option<Repair>, String> {
 
 /// Update repair
 #[tauri::command]
-pub fn update_repair(repair: Repair) -> Result<(), String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
-    conn.execute(
-        "UPDATE repairs SET customer_name = ?2, customer_phone = ?3, device_brand = ?4, device_model = ?5, issue_description = ?6, estimated_cost = ?7, status = ?8, payment_status = ?9, updated_at = datetime('now') WHERE id = ?1",
+pub fn update_repair(pool: State<'_, DbPool>, mut repair: Repair) -> Result<(), AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    repair.device_serial = serial::normalize_device_serial(repair.device_serial.as_deref())?;
+    apply_repair_tax(&tx, &mut repair)?;
+    let (discount_type, discount_value, discount_reason, discount_by) = discount_columns(&repair.discount);
+
+    // Status changes follow the workflow like any other
+    let old_status: String = tx
+        .query_row("SELECT status FROM repairs WHERE id = ?1", params![&repair.id], |row| row.get(0))
+        .optional()?
+        .ok_or_else(|| AppError::NotFound(format!("Repair {} not found", repair.id)))?;
+    repair_status::change_status(&tx, &repair.id, &old_status, &repair.status, repair.status_reason.as_deref(), None)?;
+
+    tx.execute(
+        "UPDATE repairs SET customer_name = ?2, customer_phone = ?3, device_brand = ?4, device_model = ?5, issue_description = ?6, estimated_cost = ?7, status = ?8, payment_status = ?9, updated_at = datetime('now'), tax_rate_id = ?10, tax_rate = ?11, tax_inclusive = ?12, tax_amount = ?13, total_amount = ?14, discount_type = ?15, discount_value = ?16, discount_reason = ?17, discount_by = ?18, discount_amount = ?19, device_serial = ?20 WHERE id = ?1",
         params![
             repair.id,
             repair.customer_name,
@@ -222,53 +415,56 @@ pub fn update_repair(repair: Repair) -> Result<(), String> {
             repair.estimated_cost,
             repair.status,
             repair.payment_status,
+            repair.tax_rate_id,
+            repair.tax_rate,
+            repair.tax_inclusive,
+            repair.tax_amount,
+            repair.total_amount,
+            discount_type,
+            discount_value,
+            discount_reason,
+            discount_by,
+            repair.discount_amount,
+            repair.device_serial,
             // We usually don't update code, so I'll leave it as is or should I?
             // If code is not updating, I don't need to add it to SET.
             // But if the user edits the 'code' (not currently planned), it would be needed.
             // For now, let's NOT update code to prevent accidental changes.
         ],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
+
+    // The amount owed may have changed
+    recalculate_repair_status_internal(&tx, &repair.id)?;
+    settle_used_parts(&tx, &repair.id, &repair.status)?;
+    tx.commit()?;
     Ok(())
 }
 
-/// Update repair status
+/// Update repair status. Only the moves the workflow allows are accepted, and statuses
+/// such as Cancelled need a `reason`.
 #[tauri::command]
-pub fn update_repair_status(id: String, new_status: String) -> Result<(), String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
-    // Get the current status before updating to log the change
-    let old_status: String = conn
+pub fn update_repair_status(
+    pool: State<'_, DbPool>,
+    id: String,
+    new_status: String,
+    reason: Option<String>,
+    changed_by: Option<String>,
+) -> Result<(), AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    let old_status: String = tx
         .query_row(
             "SELECT status FROM repairs WHERE id = ?1",
             params![&id],
             |row| row.get(0),
         )
-        .map_err(|e| e.to_string())?;
-    
-    // Update the status
-    conn.execute(
-        "UPDATE repairs SET status = ?2, updated_at = datetime('now') WHERE id = ?1",
-        params![id, new_status],
-    )
-    .map_err(|e| e.to_string())?;
-    
-    // Add history entry for the status change
-    use chrono::Utc;
-    use uuid::Uuid;
-    let repair_history_id = Uuid::new_v4().to_string();
-    conn.execute(
-        "INSERT INTO repair_history (id, repair_id, date, event_type, details, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-        params![
-            repair_history_id,
-            id,
-            Utc::now().to_rfc3339(),
-            "status_change",
-            format!("Status changed from {} to {}", old_status, new_status),
-            Option::<String>::None,
-        ],
-    )
-    .map_err(|e| e.to_string())?;
-    
+        .optional()?
+        .ok_or_else(|| AppError::NotFound(format!("Repair {} not found", id)))?;
+
+    repair_status::change_status(&tx, &id, &old_status, &new_status, reason.as_deref(), changed_by.as_deref())?;
+
+    settle_used_parts(&tx, &id, &new_status)?;
+    tx.commit()?;
     Ok(())
 }
 
@@ -276,10 +472,16 @@ pub fn update_repair_status(id: String, new_status: String) -> Result<(), String
 
 /// Delete repair (cascade will clear children)
 #[tauri::command]
-pub fn delete_repair(id: String) -> Result<(), String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
-    conn.execute("DELETE FROM repairs WHERE id = ?1", params![id])
-        .map_err(|e| e.to_string())?;
+pub fn delete_repair(pool: State<'_, DbPool>, id: String) -> Result<(), AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    release_held_parts(&tx, &id)?;
+    tx.execute(
+        "DELETE FROM line_serial_numbers WHERE line_id IN (SELECT id FROM repair_used_parts WHERE repair_id = ?1)",
+        params![id],
+    )?;
+    tx.execute("DELETE FROM repairs WHERE id = ?1", params![id])?;
+    tx.commit()?;
     Ok(())
 }
 
@@ -288,22 +490,33 @@ pub fn delete_repair(id: String) -> Result<(), String> {
 /// ======================
 
 #[tauri::command]
-pub fn add_payment(payment: RepairPayment) -> Result<(), String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+pub fn add_payment(pool: State<'_, DbPool>, payment: RepairPayment) -> Result<(), AppError> {
+    let conn = pool.get()?;
+
+    let tendered = tendered_amount(
+        &conn,
+        payment.currency_code.as_deref(),
+        payment.original_amount,
+        payment.amount,
+        &payment.date,
+    )?;
 
     // Insert payment
     conn.execute(
-        "INSERT INTO repair_payments (id, repair_id, amount, date, method, received_by, session_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        "INSERT INTO repair_payments (id, repair_id, amount, date, method, received_by, session_id, currency_code, original_amount, exchange_rate) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
         params![
             payment.id,
             payment.repair_id,
-            payment.amount,
+            tendered.amount,
             payment.date,
             payment.method,
             payment.received_by,
-            payment.session_id
+            payment.session_id,
+            tendered.currency_code,
+            tendered.original_amount,
+            tendered.exchange_rate
         ],
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     // Recalculate status
     recalculate_repair_status_internal(&conn, &payment.repair_id)?;
@@ -312,21 +525,22 @@ pub fn add_payment(payment: RepairPayment) -> Result<(), String> {
 }
 
 #[tauri::command]
-pub fn update_repair_payment(id: String, amount: f64, method: String) -> Result<(), String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+pub fn update_repair_payment(pool: State<'_, DbPool>, id: String, amount: Money, method: String) -> Result<(), AppError> {
+    let conn = pool.get()?;
     
     // Get repair_id for recalculation and logging
-    let (repair_id, old_amount): (String, f64) = conn.query_row(
-        "SELECT repair_id, amount FROM repair_payments WHERE id = ?1",
+    let (repair_id, old_amount, currency_code, exchange_rate): (String, Money, Option<String>, Option<f64>) = conn.query_row(
+        "SELECT repair_id, amount, currency_code, exchange_rate FROM repair_payments WHERE id = ?1",
         params![id],
-        |row| Ok((row.get(0)?, row.get(1)?))
-    ).map_err(|e| e.to_string())?;
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+    )?;
+    let (amount, original_amount) = edited_amount(currency_code.as_deref(), exchange_rate, amount);
 
     // Update payment
     conn.execute(
-        "UPDATE repair_payments SET amount = ?2, method = ?3 WHERE id = ?1",
-        params![id, amount, method],
-    ).map_err(|e| e.to_string())?;
+        "UPDATE repair_payments SET amount = ?2, method = ?3, original_amount = ?4 WHERE id = ?1",
+        params![id, amount, method, original_amount],
+    )?;
 
     // Recalculate and update repair
     recalculate_repair_status_internal(&conn, &repair_id)?;
@@ -336,24 +550,24 @@ pub fn update_repair_payment(id: String, amount: f64, method: String) -> Result<
     conn.execute(
         "INSERT INTO repair_history (id, repair_id, date, event_type, details, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
         params![h_id, repair_id, Utc::now().to_rfc3339(), "note", format!("Payment updated: {} -> {} (Method: {})", old_amount, amount, method), None::<String>],
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     Ok(())
 }
 
 #[tauri::command]
-pub fn delete_repair_payment(id: String) -> Result<(), String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+pub fn delete_repair_payment(pool: State<'_, DbPool>, id: String) -> Result<(), AppError> {
+    let conn = pool.get()?;
     
     // Get repair_id for recalculation and logging
-    let (repair_id, amount): (String, f64) = conn.query_row(
+    let (repair_id, amount): (String, Money) = conn.query_row(
         "SELECT repair_id, amount FROM repair_payments WHERE id = ?1",
         params![id],
         |row| Ok((row.get(0)?, row.get(1)?))
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     // Delete payment
-    conn.execute("DELETE FROM repair_payments WHERE id = ?1", params![id]).map_err(|e| e.to_string())?;
+    conn.execute("DELETE FROM repair_payments WHERE id = ?1", params![id])?;
 
     // Recalculate and update repair
     recalculate_repair_status_internal(&conn, &repair_id)?;
@@ -363,34 +577,32 @@ pub fn delete_repair_payment(id: String) -> Result<(), String> {
     conn.execute(
         "INSERT INTO repair_history (id, repair_id, date, event_type, details, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
         params![h_id, repair_id, Utc::now().to_rfc3339(), "note", format!("Payment deleted: {}", amount), None::<String>],
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     Ok(())
 }
 
-fn recalculate_repair_status_internal(conn: &Connection, repair_id: &str) -> Result<(), String> {
+fn recalculate_repair_status_internal(conn: &Connection, repair_id: &str) -> Result<(), AppError> {
     // Recalculate total paid
-    let total_paid: f64 = conn
+    let total_paid: Money = conn
         .query_row(
             "SELECT COALESCE(SUM(amount), 0) FROM repair_payments WHERE repair_id = ?1",
             params![repair_id],
             |row| row.get(0),
-        )
-        .map_err(|e| e.to_string())?;
+        )?;
 
-    // Get estimated cost
-    let estimated_cost: f64 = conn
+    // Get the amount owed, labor tax included
+    let total_amount: Money = conn
         .query_row(
-            "SELECT estimated_cost FROM repairs WHERE id = ?1",
+            "SELECT total_amount FROM repairs WHERE id = ?1",
             params![repair_id],
             |row| row.get(0),
-        )
-        .map_err(|e| e.to_string())?;
+        )?;
 
     // Derive new payment status
-    let new_status = if total_paid == 0.0 {
+    let new_status = if total_paid.is_zero() {
         "Unpaid"
-    } else if total_paid >= estimated_cost {
+    } else if total_paid >= total_amount {
         "Paid"
     } else {
         "Partially"
@@ -400,18 +612,16 @@ fn recalculate_repair_status_internal(conn: &Connection, repair_id: &str) -> Res
     conn.execute(
         "UPDATE repairs SET payment_status = ?2, updated_at = datetime('now') WHERE id = ?1",
         params![repair_id, new_status],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
 
     Ok(())
 }
 
 #[tauri::command]
-pub fn get_payments_for_repair(repair_id: String) -> Result<Vec<RepairPayment>, String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+pub fn get_payments_for_repair(pool: State<'_, DbPool>, repair_id: String) -> Result<Vec<RepairPayment>, AppError> {
+    let conn = pool.get()?;
     let mut stmt = conn
-        .prepare("SELECT id, repair_id, amount, date, method, received_by, session_id FROM repair_payments WHERE repair_id = ?1 ORDER BY date DESC")
-        .map_err(|e| e.to_string())?;
+        .prepare_cached("SELECT id, repair_id, amount, date, method, received_by, session_id, currency_code, original_amount, exchange_rate FROM repair_payments WHERE repair_id = ?1 ORDER BY date DESC")?;
     let rows = stmt
         .query_map(params![repair_id], |row| {
             Ok(RepairPayment {
@@ -422,9 +632,11 @@ pub fn get_payments_for_repair(repair_id: String) -> Result<Vec<RepairPayment>,
                 method: row.get(4)?,
                 received_by: row.get(5).ok(),
                 session_id: row.get(6).ok(),
+                currency_code: row.get(7).ok(),
+                original_amount: row.get(8).ok(),
+                exchange_rate: row.get(9).ok(),
             })
-        })
-        .map_err(|e| e.to_string())?
+        })?
         .filter_map(|res| res.ok())
         .collect();
     Ok(rows)
@@ -434,80 +646,239 @@ pub fn get_payments_for_repair(repair_id: String) -> Result<Vec<RepairPayment>,
 /// USED PARTS
 /// ======================
 
+/// Take a used part out of stock at a location and return what one unit cost.
+fn take_part_from_stock(
+    conn: &Connection,
+    part_id: &str,
+    location_id: Option<&str>,
+    quantity: i64,
+    repair_id: &str,
+) -> Result<Money, AppError> {
+    inventory::apply_stock_change(conn, part_id, location_id, -quantity, false)?;
+    inventory::log_stock_event(
+        conn,
+        part_id,
+        location_id,
+        "Used in Repair",
+        -quantity,
+        &format!("Used {} units in repair", quantity),
+        repair_id,
+    )?;
+    costing::issue_stock(conn, part_id, quantity)
+}
+
+/// A used part that is a stocked item, and what it is doing to stock right now.
+struct StockedPart {
+    id: String,
+    part_id: String,
+    quantity: i64,
+    location_id: Option<String>,
+    unit_cost: Option<Money>,
+    held: bool,
+    taken: bool,
+}
+
+fn stocked_parts(conn: &Connection, repair_id: &str) -> Result<Vec<StockedPart>, AppError> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT p.id, p.part_id, p.quantity, p.location_id, p.unit_cost,
+            EXISTS (SELECT 1 FROM stock_reservations r WHERE r.line_id = p.id AND r.status = 'Active'),
+            p.taken_from_stock
+         FROM repair_used_parts p
+         JOIN inventory_items i ON i.id = p.part_id
+         WHERE p.repair_id = ?1 AND COALESCE(i.quantity_in_stock, CASE WHEN i.is_bundle THEN 0 END) IS NOT NULL",
+    )?;
+    let rows = stmt.query_map(params![repair_id], |row| {
+        Ok(StockedPart {
+            id: row.get(0)?,
+            part_id: row.get(1)?,
+            quantity: row.get(2)?,
+            location_id: row.get(3)?,
+            unit_cost: row.get(4)?,
+            held: row.get(5)?,
+            taken: row.get(6)?,
+        })
+    })?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+/// Put a used part that was taken out of stock back on the shelf, at what it cost.
+fn return_part_to_stock(conn: &Connection, part: &StockedPart, repair_id: &str, notes: &str) -> Result<(), AppError> {
+    let location_id = part.location_id.as_deref();
+    inventory::apply_stock_change(conn, &part.part_id, location_id, part.quantity, true)?;
+    inventory::log_stock_event(conn, &part.part_id, location_id, "Return from Repair", part.quantity, notes, repair_id)?;
+    let unit_cost = match part.unit_cost {
+        Some(cost) => cost,
+        None => costing::average_cost(conn, &part.part_id)?,
+    };
+    costing::receive_stock(conn, &part.part_id, part.quantity, unit_cost, "Restock", repair_id)?;
+    conn.execute(
+        "UPDATE repair_used_parts SET taken_from_stock = 0 WHERE id = ?1",
+        params![part.id],
+    )?;
+    Ok(())
+}
+
+/// Give back the parts a repair still holds, with their units back on the shelf.
+fn release_held_parts(conn: &Connection, repair_id: &str) -> Result<(), AppError> {
+    let held: Vec<String> = {
+        let mut stmt = conn.prepare_cached(
+            "SELECT line_id FROM stock_reservations WHERE source_id = ?1 AND source_type = 'Repair' AND status = 'Active'",
+        )?;
+        let rows = stmt.query_map(params![repair_id], |row| row.get(0))?;
+        rows.collect::<Result<_, _>>()?
+    };
+    for used_part_id in held {
+        serial::reverse_line_units(conn, &used_part_id, "Released from Repair", "Repair cancelled or deleted")?;
+    }
+    reservation::release_source(conn, repair_id)
+}
+
+/// Bring the stocked parts of a repair in line with its status. Open statuses hold them,
+/// also when a done or void repair is reopened. Done statuses take the held ones out of
+/// stock, while parts a void repair gave back stay given back. Void statuses give
+/// everything back, whether it was held or already taken out.
+fn settle_used_parts(conn: &Connection, repair_id: &str, status: &str) -> Result<(), AppError> {
+    let kind = repair_status::status_kind(conn, status)?;
+    let code: Option<String> = conn.query_row(
+        "SELECT code FROM repairs WHERE id = ?1",
+        params![repair_id],
+        |row| row.get(0),
+    )?;
+
+    for part in stocked_parts(conn, repair_id)? {
+        match kind.as_str() {
+            "Open" if !part.held => {
+                if part.taken {
+                    return_part_to_stock(conn, &part, repair_id, "Repair reopened")?;
+                } else {
+                    // Units given back when the repair was voided are fitted again
+                    serial::move_line_units(
+                        conn,
+                        &part.part_id,
+                        part.quantity,
+                        &serial::ON_HAND,
+                        "In Repair",
+                        "Used in Repair",
+                        &SerialSource::line("Repair", repair_id, &part.id, code.as_deref().unwrap_or_default()),
+                    )?;
+                }
+                reservation::reserve_stock(conn, &part.part_id, part.quantity, "Repair", repair_id, &part.id, false)?;
+            }
+            "Done" if part.held => {
+                reservation::convert_line(conn, &part.id)?;
+                let unit_cost = take_part_from_stock(conn, &part.part_id, part.location_id.as_deref(), part.quantity, repair_id)?;
+                conn.execute(
+                    "UPDATE repair_used_parts SET unit_cost = ?2, taken_from_stock = 1 WHERE id = ?1",
+                    params![part.id, unit_cost],
+                )?;
+            }
+            "Void" if part.held || part.taken => {
+                serial::reverse_line_units(conn, &part.id, "Released from Repair", "Repair cancelled")?;
+                if part.held {
+                    reservation::release_line(conn, &part.id)?;
+                } else {
+                    return_part_to_stock(conn, &part, repair_id, "Repair cancelled")?;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
 #[tauri::command]
-pub fn add_used_part(repair_id: String, part: RepairUsedPart) -> Result<(), String> {
-    let mut conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+pub fn add_used_part(pool: State<'_, DbPool>, repair_id: String, part: RepairUsedPart) -> Result<(), AppError> {
+    let mut conn = pool.get()?;
     let mut part = part;
 
     // Set the repair_id to ensure consistency
     part.repair_id = repair_id;
+    // Parts bought in for the job cost what was entered; stocked parts are costed below
+    part.unit_cost = Some(part.unit_price);
 
-    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let tx = conn.transaction()?;
+    let mut taken_from_stock = false;
 
     {
+        let part_item = Some(part.part_id.as_str()).filter(|id| !id.is_empty());
+        serial::set_line_serials(&tx, &part.id, part_item, part.quantity as i64, &part.serial_numbers)?;
+
         // First, check if we have enough inventory for this part
         if !part.part_id.is_empty() {
             // Get current inventory quantity
             let current_stock: Option<i64> = tx
                 .query_row(
-                    "SELECT quantity_in_stock FROM inventory_items WHERE id = ?1",
+                    "SELECT COALESCE(quantity_in_stock, CASE WHEN is_bundle THEN 0 END) FROM inventory_items WHERE id = ?1",
                     params![&part.part_id],
                     |row| row.get(0),
                 )
-                .optional()
-                .map_err(|e| e.to_string())?;
-
-            if let Some(stock) = current_stock {
-                let quantity_to_deduct = part.quantity as i64;
-                if stock < quantity_to_deduct {
-                    return Err(format!(
-                        "Not enough inventory for part '{}'. Available: {}, Requested: {}",
-                        part.part_name, stock, quantity_to_deduct
-                    ));
-                }
+                .optional()?;
 
-                // Update inventory quantity
-                let new_stock = stock - quantity_to_deduct;
-                tx.execute(
-                    "UPDATE inventory_items SET quantity_in_stock = ?2 WHERE id = ?1",
-                    params![&part.part_id, new_stock],
-                )
-                .map_err(|e| e.to_string())?;
-
-                // Add history entry for inventory deduction
-                use chrono::Utc;
-                use uuid::Uuid;
-                let history_id = Uuid::new_v4().to_string();
-                tx.execute(
-                    "INSERT INTO inventory_history (id, item_id, date, event_type, quantity_change, notes, related_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-                    params![
-                        history_id,
+            if current_stock.is_some() {
+                part.location_id = Some(location::resolve_location(&tx, part.location_id.as_deref())?);
+                let (repair_status, repair_code): (String, Option<String>) = tx.query_row(
+                    "SELECT status, code FROM repairs WHERE id = ?1",
+                    params![&part.repair_id],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )?;
+                let repair_kind = repair_status::status_kind(&tx, &repair_status)?;
+                if repair_kind == "Void" {
+                    return Err(AppError::Conflict(format!(
+                        "The repair is {}; reopen it before adding parts",
+                        repair_status
+                    )));
+                }
+                // Serialized parts name the units fitted, which leave the shelf right away
+                serial::move_line_units(
+                    &tx,
+                    &part.part_id,
+                    part.quantity as i64,
+                    &serial::ON_HAND,
+                    "In Repair",
+                    "Used in Repair",
+                    &SerialSource::line("Repair", &part.repair_id, &part.id, repair_code.as_deref().unwrap_or_default()),
+                )?;
+                if repair_kind == "Open" {
+                    // Hold the part until the repair is done; cost it at today's average meanwhile
+                    reservation::reserve_stock(
+                        &tx,
                         &part.part_id,
-                        Utc::now().to_rfc3339(),
-                        "Used in Repair",
-                        -quantity_to_deduct, // negative because we're deducting
-                        format!("Used {} units in repair", quantity_to_deduct),
-                        &part.repair_id, // link to the repair
-                    ],
-                )
-                .map_err(|e| e.to_string())?;
+                        part.quantity as i64,
+                        "Repair",
+                        &part.repair_id,
+                        &part.id,
+                        false,
+                    )?;
+                    part.unit_cost = Some(costing::average_cost(&tx, &part.part_id)?);
+                } else {
+                    part.unit_cost = Some(take_part_from_stock(
+                        &tx,
+                        &part.part_id,
+                        part.location_id.as_deref(),
+                        part.quantity as i64,
+                        &part.repair_id,
+                    )?);
+                    taken_from_stock = true;
+                }
             }
             // If current_stock is None (part_id doesn't exist in inventory), we allow the part to be added without inventory deduction
         }
 
         // Insert the used part record
         tx.execute(
-            "INSERT INTO repair_used_parts (id, repair_id, part_id, part_name, quantity, unit_price) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            "INSERT INTO repair_used_parts (id, repair_id, part_id, part_name, quantity, unit_price, unit_cost, location_id, taken_from_stock) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
             params![
                 part.id,
                 part.repair_id,
                 part.part_id,
                 part.part_name,
                 part.quantity,
-                part.unit_price
+                part.unit_price,
+                part.unit_cost,
+                part.location_id,
+                taken_from_stock
             ],
-        )
-        .map_err(|e| e.to_string())?;
+        )?;
 
         // NEW: Add history entry for the repair itself
         use chrono::Utc;
@@ -523,21 +894,19 @@ pub fn add_used_part(repair_id: String, part: RepairUsedPart) -> Result<(), Stri
                 format!("Added part: {} (Qty: {})", part.part_name, part.quantity),
                 Option::<String>::None,
             ],
-        )
-        .map_err(|e| e.to_string())?;
+        )?;
     }
 
-    tx.commit().map_err(|e| e.to_string())?;
+    tx.commit()?;
     Ok(())
 }
 
 #[tauri::command]
-pub fn get_used_parts_for_repair(repair_id: String) -> Result<Vec<RepairUsedPart>, String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+pub fn get_used_parts_for_repair(pool: State<'_, DbPool>, repair_id: String) -> Result<Vec<RepairUsedPart>, AppError> {
+    let conn = pool.get()?;
     let mut stmt = conn
-        .prepare("SELECT id, repair_id, part_id, part_name, quantity, unit_price FROM repair_used_parts WHERE repair_id = ?1")
-        .map_err(|e| e.to_string())?;
-    let rows = stmt
+        .prepare_cached("SELECT id, repair_id, part_id, part_name, quantity, unit_price, unit_cost, location_id FROM repair_used_parts WHERE repair_id = ?1")?;
+    let mut rows: Vec<RepairUsedPart> = stmt
         .query_map(params![repair_id], |row| {
             Ok(RepairUsedPart {
                 id: row.get(0)?,
@@ -546,70 +915,52 @@ pub fn get_used_parts_for_repair(repair_id: String) -> Result<Vec<RepairUsedPart
                 part_name: row.get(3)?,
                 quantity: row.get(4)?,
                 unit_price: row.get(5)?,
+                unit_cost: row.get(6)?,
+                location_id: row.get(7)?,
+                serial_numbers: Vec::new(),
             })
-        })
-        .map_err(|e| e.to_string())?
+        })?
         .filter_map(|res| res.ok())
         .collect();
+    for part in &mut rows {
+        part.serial_numbers = serial::line_serials(&conn, &part.id)?;
+    }
     Ok(rows)
 }
 
 /// Delete used part
 #[tauri::command]
-pub fn delete_used_part(id: String) -> Result<(), String> {
-    let mut conn = crate::db::get_connection().map_err(|e| e.to_string())?;
-    let tx = conn.transaction().map_err(|e| e.to_string())?;
+pub fn delete_used_part(pool: State<'_, DbPool>, id: String) -> Result<(), AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
 
     {
         // 1. Get part details before deleting to restore inventory
-        let part_data: Option<(String, i32, String, String)> = tx
+        let part_data: Option<(String, i32, String, String, Option<Money>, bool)> = tx
             .query_row(
-                "SELECT part_id, quantity, part_name, repair_id FROM repair_used_parts WHERE id = ?1",
+                "SELECT part_id, quantity, part_name, repair_id, unit_cost, taken_from_stock FROM repair_used_parts WHERE id = ?1",
                 params![id],
-                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?)),
             )
-            .optional()
-            .map_err(|e| e.to_string())?;
-
-        if let Some((part_id, quantity, part_name, repair_id)) = part_data {
-            // 2. Restore inventory if it was an inventory item
-            if !part_id.is_empty() {
-                // Check if item exists in inventory
-                let current_stock: Option<i64> = tx
-                    .query_row(
-                        "SELECT quantity_in_stock FROM inventory_items WHERE id = ?1",
-                        params![&part_id],
-                        |row| row.get(0),
-                    )
-                    .optional()
-                    .map_err(|e| e.to_string())?;
-
-                if let Some(stock) = current_stock {
-                    let new_stock = stock + quantity as i64;
-                    tx.execute(
-                        "UPDATE inventory_items SET quantity_in_stock = ?2 WHERE id = ?1",
-                        params![&part_id, new_stock],
-                    )
-                    .map_err(|e| e.to_string())?;
-
-                    // Log inventory return
-                     use chrono::Utc;
-                    use uuid::Uuid;
-                    let history_id = Uuid::new_v4().to_string();
-                    tx.execute(
-                        "INSERT INTO inventory_history (id, item_id, date, event_type, quantity_change, notes, related_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-                        params![
-                            history_id,
-                            &part_id,
-                            Utc::now().to_rfc3339(),
-                            "Return from Repair",
-                            quantity, 
-                            format!("Restored from repair deletion"),
-                            &repair_id,
-                        ],
-                    )
-                    .map_err(|e| e.to_string())?;
-                }
+            .optional()?;
+        let location_id = location::document_location(&tx, "repair_used_parts", &id)?;
+
+        if let Some((part_id, quantity, part_name, repair_id, unit_cost, taken)) = part_data {
+            // 2. Give back a part still on hold, or restore inventory if it was taken out.
+            // Parts of a void repair were given back already.
+            if reservation::is_reserved(&tx, &id)? {
+                reservation::release_line(&tx, &id)?;
+            } else if taken {
+                let part = StockedPart {
+                    id: id.clone(),
+                    part_id,
+                    quantity: quantity as i64,
+                    location_id,
+                    unit_cost,
+                    held: false,
+                    taken,
+                };
+                return_part_to_stock(&tx, &part, &repair_id, "Restored from repair deletion")?;
             }
              
              // 3. Log repair history
@@ -626,16 +977,16 @@ pub fn delete_used_part(id: String) -> Result<(), String> {
                     format!("Part removed: {} (Qty: {})", part_name, quantity),
                     Option::<String>::None,
                 ],
-            )
-            .map_err(|e| e.to_string())?;
+            )?;
         }
 
-        // 4. Delete the record
-        tx.execute("DELETE FROM repair_used_parts WHERE id = ?1", params![id])
-            .map_err(|e| e.to_string())?;
+        // 4. Put fitted units back on the shelf and delete the record
+        serial::reverse_line_units(&tx, &id, "Removed from Repair", "Part removed from repair")?;
+        serial::delete_line_serials(&tx, &id)?;
+        tx.execute("DELETE FROM repair_used_parts WHERE id = ?1", params![id])?;
     }
 
-    tx.commit().map_err(|e| e.to_string())?;
+    tx.commit()?;
     Ok(())
 }
 
@@ -644,8 +995,8 @@ pub fn delete_used_part(id: String) -> Result<(), String> {
 /// ======================
 
 #[tauri::command]
-pub fn insert_repair_history(event: RepairHistory) -> Result<(), String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+pub fn insert_repair_history(pool: State<'_, DbPool>, event: RepairHistory) -> Result<(), AppError> {
+    let conn = pool.get()?;
     conn.execute(
         "INSERT INTO repair_history (id, repair_id, date, event_type, details, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
         params![
@@ -656,17 +1007,15 @@ pub fn insert_repair_history(event: RepairHistory) -> Result<(), String> {
             event.details,
             event.changed_by
         ],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
     Ok(())
 }
 
 #[tauri::command]
-pub fn get_history_for_repair(repair_id: String) -> Result<Vec<RepairHistory>, String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+pub fn get_history_for_repair(pool: State<'_, DbPool>, repair_id: String) -> Result<Vec<RepairHistory>, AppError> {
+    let conn = pool.get()?;
     let mut stmt = conn
-        .prepare("SELECT id, repair_id, date, event_type, details, changed_by FROM repair_history WHERE repair_id = ?1 ORDER BY date DESC")
-        .map_err(|e| e.to_string())?;
+        .prepare_cached("SELECT id, repair_id, date, event_type, details, changed_by FROM repair_history WHERE repair_id = ?1 ORDER BY date DESC")?;
     let rows = stmt
         .query_map(params![repair_id], |row| {
             Ok(RepairHistory {
@@ -677,8 +1026,7 @@ pub fn get_history_for_repair(repair_id: String) -> Result<Vec<RepairHistory>, S
                 details: row.get(4)?,
                 changed_by: row.get(5).ok(),
             })
-        })
-        .map_err(|e| e.to_string())?
+        })?
         .filter_map(|res| res.ok())
         .collect();
     Ok(rows)
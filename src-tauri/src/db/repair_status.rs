@@ -0,0 +1,411 @@
+use crate::db::models::{RepairStatusDefinition, RepairStatusPeriod, RepairStatusTurnaround, RepairTurnaround};
+use crate::db::DbPool;
+use crate::error::AppError;
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use tauri::State;
+use uuid::Uuid;
+
+// Statuses are rows of `repair_statuses`; a repair can only move along the pairs in
+// `repair_status_transitions`. The kind of a status decides what happens to the parts
+// booked on the repair: Open statuses hold them, Done statuses take them out of stock
+// and Void statuses give them back. Every change is kept in `repair_status_changes`,
+// which is where turnaround times come from.
+
+const KINDS: [&str; 3] = ["Open", "Done", "Void"];
+
+/// The kind of a status: "Open", "Done" or "Void".
+pub(crate) fn status_kind(conn: &Connection, status: &str) -> Result<String, AppError> {
+    conn.query_row(
+        "SELECT kind FROM repair_statuses WHERE name = ?1",
+        params![status],
+        |row| row.get(0),
+    )
+    .optional()?
+    .ok_or_else(|| AppError::Validation(format!("Unknown repair status '{}'", status)))
+}
+
+/// The trimmed reason, failing if the status needs one and none was given.
+fn required_reason<'a>(conn: &Connection, status: &str, reason: Option<&'a str>) -> Result<Option<&'a str>, AppError> {
+    let reason = reason.map(str::trim).filter(|reason| !reason.is_empty());
+    let requires_reason: bool = conn
+        .query_row(
+            "SELECT requires_reason FROM repair_statuses WHERE name = ?1",
+            params![status],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::Validation(format!("Unknown repair status '{}'", status)))?;
+    if requires_reason && reason.is_none() {
+        return Err(AppError::Validation(format!("A reason is required to mark a repair {}", status)));
+    }
+    Ok(reason)
+}
+
+fn log_status_change(
+    conn: &Connection,
+    repair_id: &str,
+    from: Option<&str>,
+    to: &str,
+    reason: Option<&str>,
+    changed_by: Option<&str>,
+) -> Result<(), AppError> {
+    conn.execute(
+        "INSERT INTO repair_status_changes (id, repair_id, from_status, to_status, reason, changed_by, changed_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![Uuid::new_v4().to_string(), repair_id, from, to, reason, changed_by, Utc::now().to_rfc3339()],
+    )?;
+    Ok(())
+}
+
+/// Check the status a new repair starts in and its reason, and start its status log.
+/// Call once the repair row exists.
+pub(crate) fn start_status(
+    conn: &Connection,
+    repair_id: &str,
+    status: &str,
+    reason: Option<&str>,
+) -> Result<(), AppError> {
+    let reason = required_reason(conn, status, reason)?;
+    conn.execute(
+        "UPDATE repairs SET status_reason = ?2 WHERE id = ?1",
+        params![repair_id, reason],
+    )?;
+    log_status_change(conn, repair_id, None, status, reason, None)
+}
+
+/// Move a repair from `from` to `to` if the workflow allows it, recording when and why.
+/// Parts are settled by the caller.
+pub(crate) fn change_status(
+    conn: &Connection,
+    repair_id: &str,
+    from: &str,
+    to: &str,
+    reason: Option<&str>,
+    changed_by: Option<&str>,
+) -> Result<(), AppError> {
+    if from == to {
+        return Ok(());
+    }
+    let reason = required_reason(conn, to, reason)?;
+    let allowed: Option<String> = conn
+        .query_row(
+            "SELECT to_status FROM repair_status_transitions WHERE from_status = ?1 AND to_status = ?2",
+            params![from, to],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if allowed.is_none() {
+        return Err(AppError::Conflict(format!("A repair can't go from {} to {}", from, to)));
+    }
+
+    conn.execute(
+        "UPDATE repairs SET status = ?2, status_reason = ?3, updated_at = datetime('now') WHERE id = ?1",
+        params![repair_id, to, reason],
+    )?;
+    log_status_change(conn, repair_id, Some(from), to, reason, changed_by)?;
+
+    let details = match reason {
+        Some(reason) => format!("Status changed from {} to {}: {}", from, to, reason),
+        None => format!("Status changed from {} to {}", from, to),
+    };
+    conn.execute(
+        "INSERT INTO repair_history (id, repair_id, date, event_type, details, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            Uuid::new_v4().to_string(),
+            repair_id,
+            Utc::now().to_rfc3339(),
+            "status_change",
+            details,
+            changed_by,
+        ],
+    )?;
+    Ok(())
+}
+
+// ======================
+// CONFIGURATION
+// ======================
+
+fn load_statuses(conn: &Connection) -> Result<Vec<RepairStatusDefinition>, AppError> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT name, kind, position, requires_reason FROM repair_statuses ORDER BY position, name",
+    )?;
+    let mut statuses = stmt
+        .query_map([], |row| {
+            Ok(RepairStatusDefinition {
+                name: row.get(0)?,
+                kind: row.get(1)?,
+                position: row.get(2)?,
+                requires_reason: row.get(3)?,
+                transitions: Vec::new(),
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut transitions_stmt = conn.prepare_cached(
+        "SELECT t.to_status FROM repair_status_transitions t JOIN repair_statuses s ON s.name = t.to_status
+         WHERE t.from_status = ?1 ORDER BY s.position, s.name",
+    )?;
+    for status in &mut statuses {
+        status.transitions = transitions_stmt
+            .query_map(params![status.name], |row| row.get(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+    }
+    Ok(statuses)
+}
+
+/// Get the repair statuses in board order, each with the statuses it can move to.
+#[tauri::command]
+pub fn get_repair_statuses(pool: State<'_, DbPool>) -> Result<Vec<RepairStatusDefinition>, AppError> {
+    let conn = pool.get()?;
+    load_statuses(&conn)
+}
+
+/// Add a repair status or change one, replacing the statuses it can move to.
+#[tauri::command]
+pub fn save_repair_status(
+    pool: State<'_, DbPool>,
+    mut status: RepairStatusDefinition,
+) -> Result<RepairStatusDefinition, AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+
+    status.name = status.name.trim().to_string();
+    if status.name.is_empty() {
+        return Err(AppError::Validation("Status name is required".to_string()));
+    }
+    if !KINDS.contains(&status.kind.as_str()) {
+        return Err(AppError::Validation(format!(
+            "Unknown status kind '{}', expected Open, Done or Void",
+            status.kind
+        )));
+    }
+    // Parts on repairs already in the status were settled for its old kind
+    let kind: Option<String> = tx
+        .query_row("SELECT kind FROM repair_statuses WHERE name = ?1", params![status.name], |row| row.get(0))
+        .optional()?;
+    if kind.is_some_and(|kind| kind != status.kind) {
+        let in_use: i64 = tx.query_row(
+            "SELECT COUNT(*) FROM repairs WHERE status = ?1",
+            params![status.name],
+            |row| row.get(0),
+        )?;
+        if in_use > 0 {
+            return Err(AppError::Conflict(format!(
+                "{} repairs are {}; its kind can't change while they are",
+                in_use, status.name
+            )));
+        }
+    }
+
+    tx.execute(
+        "INSERT INTO repair_statuses (name, kind, position, requires_reason) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(name) DO UPDATE SET kind = excluded.kind, position = excluded.position,
+            requires_reason = excluded.requires_reason",
+        params![status.name, status.kind, status.position, status.requires_reason],
+    )?;
+    tx.execute(
+        "DELETE FROM repair_status_transitions WHERE from_status = ?1",
+        params![status.name],
+    )?;
+    status.transitions.retain(|to| *to != status.name);
+    status.transitions.dedup();
+    for to in &status.transitions {
+        status_kind(&tx, to)?;
+        tx.execute(
+            "INSERT OR IGNORE INTO repair_status_transitions (from_status, to_status) VALUES (?1, ?2)",
+            params![status.name, to],
+        )?;
+    }
+
+    tx.commit()?;
+    Ok(status)
+}
+
+/// Remove a repair status no repair is in, with the moves to and from it.
+#[tauri::command]
+pub fn delete_repair_status(pool: State<'_, DbPool>, name: String) -> Result<(), AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+
+    let in_use: i64 = tx.query_row(
+        "SELECT COUNT(*) FROM repairs WHERE status = ?1",
+        params![name],
+        |row| row.get(0),
+    )?;
+    if in_use > 0 {
+        return Err(AppError::Conflict(format!("{} repairs are {}; move them first", in_use, name)));
+    }
+    tx.execute(
+        "DELETE FROM repair_status_transitions WHERE from_status = ?1 OR to_status = ?1",
+        params![name],
+    )?;
+    tx.execute("DELETE FROM repair_statuses WHERE name = ?1", params![name])?;
+
+    tx.commit()?;
+    Ok(())
+}
+
+// ======================
+// TURNAROUND
+// ======================
+
+/// Parse an RFC 3339 time or an SQLite `datetime('now')` one, which is UTC.
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+                .ok()
+                .map(|time| Utc.from_utc_datetime(&time))
+        })
+}
+
+fn hours_between(from: &str, to: Option<&str>) -> f64 {
+    let to = match to {
+        Some(to) => parse_time(to),
+        None => Some(Utc::now()),
+    };
+    match (parse_time(from), to) {
+        (Some(from), Some(to)) => (to - from).num_seconds().max(0) as f64 / 3600.0,
+        _ => 0.0,
+    }
+}
+
+/// The statuses a repair went through. Repairs from before status changes were logged
+/// start with the status their first logged change left, from the moment they were
+/// received.
+fn timeline(conn: &Connection, repair_id: &str) -> Result<Vec<RepairStatusPeriod>, AppError> {
+    let (status, created_at): (String, String) = conn
+        .query_row(
+            "SELECT status, created_at FROM repairs WHERE id = ?1",
+            params![repair_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound(format!("Repair {} not found", repair_id)))?;
+
+    // Each change starts a period; the status it left comes along for the first one
+    let changes: Vec<(Option<String>, RepairStatusPeriod)> = conn
+        .prepare_cached(
+            "SELECT from_status, to_status, changed_at, reason, changed_by FROM repair_status_changes
+             WHERE repair_id = ?1 ORDER BY changed_at, rowid",
+        )?
+        .query_map(params![repair_id], |row| {
+            Ok((
+                row.get(0)?,
+                RepairStatusPeriod {
+                    status: row.get(1)?,
+                    entered_at: row.get(2)?,
+                    left_at: None,
+                    hours: 0.0,
+                    reason: row.get(3)?,
+                    changed_by: row.get(4)?,
+                },
+            ))
+        })?
+        .collect::<Result<_, _>>()?;
+
+    let mut periods: Vec<RepairStatusPeriod> = Vec::new();
+    match changes.first() {
+        None => periods.push(RepairStatusPeriod {
+            status,
+            entered_at: created_at,
+            left_at: None,
+            hours: 0.0,
+            reason: None,
+            changed_by: None,
+        }),
+        Some((Some(from), _)) => periods.push(RepairStatusPeriod {
+            status: from.clone(),
+            entered_at: created_at,
+            left_at: None,
+            hours: 0.0,
+            reason: None,
+            changed_by: None,
+        }),
+        Some((None, _)) => {}
+    }
+    for (_, period) in changes {
+        if let Some(last) = periods.last_mut() {
+            last.left_at = Some(period.entered_at.clone());
+        }
+        periods.push(period);
+    }
+    for period in &mut periods {
+        period.hours = hours_between(&period.entered_at, period.left_at.as_deref());
+    }
+    Ok(periods)
+}
+
+/// Get the statuses a repair went through and how long it spent in each.
+#[tauri::command]
+pub fn get_repair_status_timeline(
+    pool: State<'_, DbPool>,
+    repair_id: String,
+) -> Result<Vec<RepairStatusPeriod>, AppError> {
+    let conn = pool.get()?;
+    timeline(&conn, &repair_id)
+}
+
+/// Average time spent in each status by the repairs received between `start_date` and
+/// `end_date`, and how long they took to get done.
+#[tauri::command]
+pub fn get_repair_turnaround(
+    pool: State<'_, DbPool>,
+    start_date: String,
+    end_date: String,
+) -> Result<RepairTurnaround, AppError> {
+    let conn = pool.get()?;
+    let statuses = load_statuses(&conn)?;
+
+    let repairs: Vec<(String, String)> = conn
+        .prepare_cached(
+            "SELECT id, created_at FROM repairs
+             WHERE REPLACE(created_at, ' ', 'T') >= ?1 AND REPLACE(created_at, ' ', 'T') <= ?2",
+        )?
+        .query_map(params![start_date, end_date], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+
+    // Hours and repairs per status, in board order
+    let mut totals: Vec<(String, f64, i64)> = statuses.iter().map(|s| (s.name.clone(), 0.0, 0)).collect();
+    let mut finished = 0;
+    let mut hours_to_done = 0.0;
+    for (repair_id, created_at) in repairs {
+        let periods = timeline(&conn, &repair_id)?;
+        let mut counted: Vec<&str> = Vec::new();
+        for period in periods.iter().filter(|period| period.left_at.is_some()) {
+            let Some(total) = totals.iter_mut().find(|(name, ..)| *name == period.status) else {
+                continue;
+            };
+            total.1 += period.hours;
+            if !counted.contains(&period.status.as_str()) {
+                counted.push(&period.status);
+                total.2 += 1;
+            }
+        }
+        let done = periods
+            .iter()
+            .find(|period| statuses.iter().any(|s| s.name == period.status && s.kind == "Done"));
+        if let Some(done) = done {
+            finished += 1;
+            hours_to_done += hours_between(&created_at, Some(&done.entered_at));
+        }
+    }
+
+    Ok(RepairTurnaround {
+        statuses: totals
+            .into_iter()
+            .filter(|(_, _, repairs)| *repairs > 0)
+            .map(|(status, hours, repairs)| RepairStatusTurnaround {
+                status,
+                repairs,
+                average_hours: hours / repairs as f64,
+            })
+            .collect(),
+        finished,
+        average_hours_to_done: (finished > 0).then(|| hours_to_done / finished as f64),
+    })
+}
@@ -0,0 +1,167 @@
+use crate::db::models::StockReservation;
+use crate::db::product;
+use crate::db::DbPool;
+use crate::error::AppError;
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use tauri::State;
+use uuid::Uuid;
+
+/// Hold `qty` units of an item for one line of a draft or open repair. A hold the line
+/// already has is replaced. Fails with `InsufficientStock` when fewer units are
+/// available, unless `allow_oversell` is set. A bundle holds its components.
+pub(crate) fn reserve_stock(
+    conn: &Connection,
+    item_id: &str,
+    qty: i64,
+    source_type: &str,
+    source_id: &str,
+    line_id: &str,
+    allow_oversell: bool,
+) -> Result<(), AppError> {
+    release_line(conn, line_id)?;
+    if qty <= 0 {
+        return Ok(());
+    }
+    let components = product::bundle_components(conn, item_id)?;
+    if components.is_empty() {
+        return hold_stock(conn, item_id, qty, source_type, source_id, line_id, allow_oversell);
+    }
+    for (component_id, per_bundle) in components {
+        hold_stock(conn, &component_id, qty * per_bundle, source_type, source_id, line_id, allow_oversell)?;
+    }
+    Ok(())
+}
+
+/// Add a reservation of `qty` units of one item to a line. Units available are on hand
+/// less what is held for drafts and open repairs.
+fn hold_stock(
+    conn: &Connection,
+    item_id: &str,
+    qty: i64,
+    source_type: &str,
+    source_id: &str,
+    line_id: &str,
+    allow_oversell: bool,
+) -> Result<(), AppError> {
+    let (item_name, available): (String, i64) = conn
+        .query_row(
+            "SELECT item_name, COALESCE(quantity_in_stock, 0) - reserved_quantity FROM inventory_items WHERE id = ?1",
+            params![item_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound(format!("Inventory item {} not found", item_id)))?;
+
+    if !allow_oversell && qty > available {
+        return Err(AppError::InsufficientStock {
+            item_id: item_id.to_string(),
+            item_name,
+            requested: qty,
+            available: available.max(0),
+        });
+    }
+
+    let now = Utc::now().to_rfc3339();
+    conn.execute(
+        "INSERT INTO stock_reservations (id, item_id, source_type, source_id, line_id, quantity, status, created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, 'Active', ?7, ?7)",
+        params![Uuid::new_v4().to_string(), item_id, source_type, source_id, line_id, qty, now],
+    )?;
+    conn.execute(
+        "UPDATE inventory_items SET reserved_quantity = reserved_quantity + ?2 WHERE id = ?1",
+        params![item_id, qty],
+    )?;
+    Ok(())
+}
+
+/// Give back the stock held for a line, e.g. when it is removed from the draft.
+pub(crate) fn release_line(conn: &Connection, line_id: &str) -> Result<(), AppError> {
+    close_reservations(conn, "line_id", line_id, "Released")
+}
+
+/// Give back all stock held for a document, e.g. when it is cancelled.
+pub(crate) fn release_source(conn: &Connection, source_id: &str) -> Result<(), AppError> {
+    close_reservations(conn, "source_id", source_id, "Released")
+}
+
+/// Close a line's hold because the stock is now taken out for real. Call before
+/// `inventory::apply_stock_change`, so the line's own hold doesn't count against it.
+pub(crate) fn convert_line(conn: &Connection, line_id: &str) -> Result<(), AppError> {
+    close_reservations(conn, "line_id", line_id, "Converted")
+}
+
+/// `convert_line` for every line of a document being completed.
+pub(crate) fn convert_source(conn: &Connection, source_id: &str) -> Result<(), AppError> {
+    close_reservations(conn, "source_id", source_id, "Converted")
+}
+
+/// Whether a line is holding stock that has not been taken out yet.
+pub(crate) fn is_reserved(conn: &Connection, line_id: &str) -> Result<bool, AppError> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM stock_reservations WHERE line_id = ?1 AND status = 'Active'",
+        params![line_id],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
+/// Close the active reservations where `column` (`line_id` or `source_id`) is `id`
+/// and take them off the items' `reserved_quantity`.
+fn close_reservations(conn: &Connection, column: &str, id: &str, status: &str) -> Result<(), AppError> {
+    conn.execute(
+        &format!(
+            "UPDATE inventory_items SET reserved_quantity = reserved_quantity - (
+                 SELECT COALESCE(SUM(r.quantity), 0) FROM stock_reservations r
+                 WHERE r.item_id = inventory_items.id AND r.{0} = ?1 AND r.status = 'Active'
+             )
+             WHERE id IN (SELECT item_id FROM stock_reservations WHERE {0} = ?1 AND status = 'Active')",
+            column
+        ),
+        params![id],
+    )?;
+    conn.execute(
+        &format!(
+            "UPDATE stock_reservations SET status = ?2, updated_at = ?3 WHERE {} = ?1 AND status = 'Active'",
+            column
+        ),
+        params![id, status, Utc::now().to_rfc3339()],
+    )?;
+    Ok(())
+}
+
+// ======================
+// RESERVATIONS
+// ======================
+
+/// Reservations on an item, newest first. Released and converted ones are left out
+/// unless asked for.
+#[tauri::command]
+pub fn get_stock_reservations(
+    pool: State<'_, DbPool>,
+    item_id: String,
+    include_closed: Option<bool>,
+) -> Result<Vec<StockReservation>, AppError> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare_cached(
+        "SELECT id, item_id, source_type, source_id, line_id, quantity, status, created_at, updated_at
+         FROM stock_reservations WHERE item_id = ?1 AND (?2 OR status = 'Active')
+         ORDER BY created_at DESC",
+    )?;
+    let reservations = stmt
+        .query_map(params![item_id, include_closed.unwrap_or(false)], |row| {
+            Ok(StockReservation {
+                id: row.get(0)?,
+                item_id: row.get(1)?,
+                source_type: row.get(2)?,
+                source_id: row.get(3)?,
+                line_id: row.get(4)?,
+                quantity: row.get(5)?,
+                status: row.get(6)?,
+                created_at: row.get(7)?,
+                updated_at: row.get(8)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(reservations)
+}
@@ -1,15 +1,21 @@
-use crate::db;
-use crate::db::models::{Sale, SaleItem, SalePayment, SaleWithDetails};
-use rusqlite::{params, Connection, Result};
+use crate::db::costing;
+use crate::db::discount::{discount_amount, discount_columns, discount_from_row};
+use crate::db::inventory;
+use crate::db::location;
+use crate::db::reservation;
+use crate::db::DbPool;
+use crate::db::money::Money;
+use crate::db::models::{Discount, Sale, SaleItem, SalePayment, SaleWithDetails};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use tauri::State;
+use crate::error::AppError;
 
 /// Generate a unique sale number (e.g., SALE-2025-001)
-fn generate_sale_number() -> Result<String, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+fn generate_sale_number(conn: &Connection) -> Result<String, AppError> {
     let year = chrono::Utc::now().format("%Y").to_string();
 
     let mut stmt = conn
-        .prepare("SELECT sale_number FROM customer_sales WHERE sale_number LIKE ?1 ORDER BY sale_number DESC LIMIT 1")
-        .map_err(|e| e.to_string())?;
+        .prepare_cached("SELECT sale_number FROM customer_sales WHERE sale_number LIKE ?1 ORDER BY sale_number DESC LIMIT 1")?;
 
     let next_number = match stmt.query_row(params![format!("SALE-{}-%", year)], |row| {
         let sale_num: String = row.get(0)?;
@@ -32,17 +38,62 @@ fn generate_sale_number() -> Result<String, String> {
     Ok(format!("SALE-{}-{:03}", year, next_number))
 }
 
+const SALE_COLUMNS: &str = "id, sale_number, client_id, status, payment_status, total_amount, paid_amount, notes, created_at, updated_at, created_by, discount_type, discount_value, discount_reason, discount_by, discount_amount, location_id";
+
+/// Map a row selected with `SALE_COLUMNS`.
+fn sale_from_row(row: &rusqlite::Row) -> rusqlite::Result<Sale> {
+    Ok(Sale {
+        id: row.get(0)?,
+        sale_number: row.get(1)?,
+        client_id: row.get(2)?,
+        status: row.get(3)?,
+        payment_status: row.get(4)?,
+        total_amount: row.get(5)?,
+        paid_amount: row.get(6)?,
+        notes: row.get(7).ok(),
+        created_at: row.get(8)?,
+        updated_at: row.get(9)?,
+        created_by: row.get(10).ok(),
+        discount: discount_from_row(row, 11)?,
+        discount_amount: row.get(15)?,
+        location_id: row.get(16)?,
+    })
+}
+
+/// Take the line's discount off price times quantity.
+fn price_sale_item(item: &mut SaleItem) -> Result<(), AppError> {
+    let gross = item.unit_price * item.quantity as i64;
+    item.discount_amount = discount_amount(item.discount.as_ref(), gross)?;
+    item.total_price = gross - item.discount_amount;
+    Ok(())
+}
+
+/// The sum of a sale's lines less the document discount, and that discount.
+fn sale_total(conn: &Connection, sale_id: &str, discount: Option<&Discount>) -> Result<(Money, Money), AppError> {
+    let lines_total: Money = conn.query_row(
+        "SELECT COALESCE(SUM(total_price), 0) FROM sale_items WHERE sale_id = ?1",
+        params![sale_id],
+        |row| row.get(0),
+    )?;
+    let discount = discount_amount(discount, lines_total)?;
+    Ok((lines_total - discount, discount))
+}
+
 #[tauri::command]
-pub fn create_sale(mut sale: Sale) -> Result<Sale, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn create_sale(pool: State<'_, DbPool>, mut sale: Sale) -> Result<Sale, AppError> {
+    let conn = pool.get()?;
 
     if sale.sale_number.is_empty() {
-        sale.sale_number = generate_sale_number()?;
+        sale.sale_number = generate_sale_number(&conn)?;
     }
+    // Worked out once lines are added
+    sale.discount_amount = Money::ZERO;
+    sale.location_id = Some(location::resolve_location(&conn, sale.location_id.as_deref())?);
+    let (discount_type, discount_value, discount_reason, discount_by) = discount_columns(&sale.discount);
 
     conn.execute(
-        "INSERT INTO customer_sales (id, sale_number, client_id, status, payment_status, total_amount, paid_amount, notes, created_at, updated_at, created_by)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        "INSERT INTO customer_sales (id, sale_number, client_id, status, payment_status, total_amount, paid_amount, notes, created_at, updated_at, created_by, discount_type, discount_value, discount_reason, discount_by, location_id)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
         params![
             sale.id,
             sale.sale_number,
@@ -55,9 +106,13 @@ pub fn create_sale(mut sale: Sale) -> Result<Sale, String> {
             sale.created_at,
             sale.updated_at,
             sale.created_by,
+            discount_type,
+            discount_value,
+            discount_reason,
+            discount_by,
+            sale.location_id,
         ],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
 
     let history_id = uuid::Uuid::new_v4().to_string();
     conn.execute(
@@ -71,41 +126,25 @@ pub fn create_sale(mut sale: Sale) -> Result<Sale, String> {
             format!("Sale {} created", sale.sale_number),
             sale.created_by,
         ],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
 
     Ok(sale)
 }
 
 #[tauri::command]
-pub fn get_sales(status_filter: Option<String>) -> Result<Vec<Sale>, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn get_sales(pool: State<'_, DbPool>, status_filter: Option<String>) -> Result<Vec<Sale>, AppError> {
+    let conn = pool.get()?;
 
     let query = if let Some(status) = status_filter {
-        format!("SELECT id, sale_number, client_id, status, payment_status, total_amount, paid_amount, notes, created_at, updated_at, created_by FROM customer_sales WHERE status = '{}' ORDER BY created_at DESC", status)
+        format!("SELECT {} FROM customer_sales WHERE status = '{}' ORDER BY created_at DESC", SALE_COLUMNS, status)
     } else {
-        "SELECT id, sale_number, client_id, status, payment_status, total_amount, paid_amount, notes, created_at, updated_at, created_by FROM customer_sales ORDER BY created_at DESC".to_string()
+        format!("SELECT {} FROM customer_sales ORDER BY created_at DESC", SALE_COLUMNS)
     };
 
-    let mut stmt = conn.prepare(&query).map_err(|e| e.to_string())?;
+    let mut stmt = conn.prepare(&query)?;
 
     let sales = stmt
-        .query_map([], |row| {
-            Ok(Sale {
-                id: row.get(0)?,
-                sale_number: row.get(1)?,
-                client_id: row.get(2)?,
-                status: row.get(3)?,
-                payment_status: row.get(4)?,
-                total_amount: row.get(5)?,
-                paid_amount: row.get(6)?,
-                notes: row.get(7).ok(),
-                created_at: row.get(8)?,
-                updated_at: row.get(9)?,
-                created_by: row.get(10).ok(),
-            })
-        })
-        .map_err(|e| e.to_string())?
+        .query_map([], sale_from_row)?
         .filter_map(|res| res.ok())
         .collect();
 
@@ -113,28 +152,13 @@ pub fn get_sales(status_filter: Option<String>) -> Result<Vec<Sale>, String> {
 }
 
 #[tauri::command]
-pub fn get_sale_by_id(sale_id: String) -> Result<Option<SaleWithDetails>, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn get_sale_by_id(pool: State<'_, DbPool>, sale_id: String) -> Result<Option<SaleWithDetails>, AppError> {
+    let conn = pool.get()?;
 
     let mut stmt = conn
-        .prepare("SELECT id, sale_number, client_id, status, payment_status, total_amount, paid_amount, notes, created_at, updated_at, created_by FROM customer_sales WHERE id = ?1")
-        .map_err(|e| e.to_string())?;
-
-    let sale = match stmt.query_row(params![sale_id], |row| {
-        Ok(Sale {
-            id: row.get(0)?,
-            sale_number: row.get(1)?,
-            client_id: row.get(2)?,
-            status: row.get(3)?,
-            payment_status: row.get(4)?,
-            total_amount: row.get(5)?,
-            paid_amount: row.get(6)?,
-            notes: row.get(7).ok(),
-            created_at: row.get(8)?,
-            updated_at: row.get(9)?,
-            created_by: row.get(10).ok(),
-        })
-    }) {
+        .prepare_cached(&format!("SELECT {} FROM customer_sales WHERE id = ?1", SALE_COLUMNS))?;
+
+    let sale = match stmt.query_row(params![sale_id], sale_from_row) {
         Ok(sale) => sale,
         Err(_) => return Ok(None),
     };
@@ -148,8 +172,7 @@ pub fn get_sale_by_id(sale_id: String) -> Result<Option<SaleWithDetails>, String
         .unwrap_or_else(|_| "Unknown Client".to_string());
 
     let mut items_stmt = conn
-        .prepare("SELECT id, sale_id, item_id, item_name, quantity, unit_price, total_price, notes FROM sale_items WHERE sale_id = ?1")
-        .map_err(|e| e.to_string())?;
+        .prepare_cached("SELECT id, sale_id, item_id, item_name, quantity, unit_price, total_price, notes, discount_type, discount_value, discount_reason, discount_by, discount_amount, unit_cost FROM sale_items WHERE sale_id = ?1")?;
 
     let items = items_stmt
         .query_map(params![sale.id], |row| {
@@ -162,15 +185,16 @@ pub fn get_sale_by_id(sale_id: String) -> Result<Option<SaleWithDetails>, String
                 unit_price: row.get(5)?,
                 total_price: row.get(6)?,
                 notes: row.get(7).ok(),
+                discount: discount_from_row(row, 8)?,
+                discount_amount: row.get(12)?,
+                unit_cost: row.get(13)?,
             })
-        })
-        .map_err(|e| e.to_string())?
+        })?
         .filter_map(|res| res.ok())
         .collect();
 
     let mut payments_stmt = conn
-        .prepare("SELECT id, sale_id, amount, method, date, received_by, notes FROM sale_payments WHERE sale_id = ?1")
-        .map_err(|e| e.to_string())?;
+        .prepare_cached("SELECT id, sale_id, amount, method, date, received_by, notes FROM sale_payments WHERE sale_id = ?1")?;
 
     let payments = payments_stmt
         .query_map(params![sale.id], |row| {
@@ -184,8 +208,7 @@ pub fn get_sale_by_id(sale_id: String) -> Result<Option<SaleWithDetails>, String
                 notes: row.get(6).ok(),
                 session_id: None, // Initialize session_id as None for existing payments
             })
-        })
-        .map_err(|e| e.to_string())?
+        })?
         .filter_map(|res| res.ok())
         .collect();
 
@@ -198,48 +221,81 @@ pub fn get_sale_by_id(sale_id: String) -> Result<Option<SaleWithDetails>, String
 }
 
 #[tauri::command]
-pub fn update_sale(sale: Sale) -> Result<(), String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn update_sale(pool: State<'_, DbPool>, mut sale: Sale) -> Result<(), AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
 
-    let (old_client_id, old_total_amount, old_status): (String, f64, String) = conn
+    let (old_client_id, old_total_amount, old_status, old_location_id): (String, Money, String, Option<String>) = tx
         .query_row(
-            "SELECT client_id, total_amount, status FROM customer_sales WHERE id = ?1",
+            "SELECT client_id, total_amount, status, location_id FROM customer_sales WHERE id = ?1",
             params![sale.id],
-            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
-        )
-        .map_err(|e| e.to_string())?;
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )?;
 
-    conn.execute(
-        "UPDATE customer_sales SET client_id = ?2, status = ?3, notes = ?4, updated_at = ?5 WHERE id = ?1",
+    // Completing takes the stock out, which only complete_sale does
+    if old_status != "completed" && sale.status == "completed" {
+        return Err(AppError::Conflict(format!(
+            "Sale {} can't be completed by editing it; complete it instead",
+            sale.sale_number
+        )));
+    }
+
+    // Stock of a completed sale has already left its location
+    let location_id = location::resolve_location(&tx, sale.location_id.as_deref())?;
+    if old_status == "completed" && old_location_id.is_some_and(|old| old != location_id) {
+        return Err(AppError::Conflict(format!(
+            "Sale {} is completed; its location can't be changed",
+            sale.sale_number
+        )));
+    }
+    sale.location_id = Some(location_id);
+
+    // The document discount may have changed
+    let (total_amount, discount) = sale_total(&tx, &sale.id, sale.discount.as_ref())?;
+    sale.total_amount = total_amount;
+    sale.discount_amount = discount;
+    let (discount_type, discount_value, discount_reason, discount_by) = discount_columns(&sale.discount);
+
+    tx.execute(
+        "UPDATE customer_sales SET client_id = ?2, status = ?3, notes = ?4, updated_at = ?5, total_amount = ?6, discount_type = ?7, discount_value = ?8, discount_reason = ?9, discount_by = ?10, discount_amount = ?11, location_id = ?12 WHERE id = ?1",
         params![
             sale.id,
             sale.client_id,
             sale.status,
             sale.notes,
             sale.updated_at,
+            sale.total_amount,
+            discount_type,
+            discount_value,
+            discount_reason,
+            discount_by,
+            sale.discount_amount,
+            sale.location_id,
         ],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
 
-    if old_status == "completed" && sale.status != "completed" {
+    if old_status == "completed" && sale.status == "completed" && sale.total_amount != old_total_amount {
         adjust_client_balance_internal(
-            &conn,
-            &old_client_id,
-            -old_total_amount,
-            "Sale Reverted to Draft",
+            &tx,
+            &sale.client_id,
+            sale.total_amount - old_total_amount,
+            "Balance Adjusted",
             &sale.sale_number,
         )?;
-    } else if old_status != "completed" && sale.status == "completed" {
-        // When a sale is marked completed, increase the client's balance by the sale total
+    } else if old_status == "completed" && sale.status != "completed" {
         adjust_client_balance_internal(
-            &conn,
-            &sale.client_id,
-            sale.total_amount,
-            "Sale Completed",
+            &tx,
+            &old_client_id,
+            -old_total_amount,
+            "Sale Reverted to Draft",
             &sale.sale_number,
         )?;
+        revert_sale_stock(&tx, &sale.id, &sale.sale_number, sale.location_id.as_deref())?;
     }
 
+    recalculate_payment_status(&tx, &sale.id)?;
+
+    tx.commit()?;
     Ok(())
 }
 
@@ -248,17 +304,16 @@ pub fn update_sale(sale: Sale) -> Result<(), String> {
 fn adjust_client_balance_internal(
     conn: &Connection,
     client_id: &str,
-    amount: f64,
+    amount: Money,
     event_type: &str,
     sale_number: &str,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
     conn.execute(
         "UPDATE clients SET credit_balance = COALESCE(credit_balance, 0) + ?1 WHERE id = ?2",
         params![amount, client_id],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
 
-    if amount.abs() > 0.001 {
+    if !amount.is_zero() {
         let history_id = uuid::Uuid::new_v4().to_string();
         let notes = format!("{}: {}", event_type, sale_number);
         conn.execute(
@@ -273,46 +328,42 @@ fn adjust_client_balance_internal(
                 amount,
                 None::<String>,
             ],
-        )
-        .map_err(|e| e.to_string())?;
+        )?;
     }
 
     Ok(())
 }
 
 // Recalculate sale total and adjust client balance if sale already completed
-fn recalculate_sale_total(sale_id: &str) -> Result<(), String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
-
-    let (old_total, status, client_id, sale_num): (f64, String, String, String) = conn
+fn recalculate_sale_total(conn: &Connection, sale_id: &str) -> Result<(), AppError> {
+    let (old_total, status, client_id, sale_num): (Money, String, String, String) = conn
         .query_row(
             "SELECT total_amount, status, client_id, sale_number FROM customer_sales WHERE id = ?1",
             params![sale_id],
             |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
-        )
-        .map_err(|e| e.to_string())?;
+        )?;
 
-    let new_total: f64 = conn
+    let discount: Option<Discount> = conn
         .query_row(
-            "SELECT COALESCE(SUM(total_price), 0) FROM sale_items WHERE sale_id = ?1",
+            "SELECT discount_type, discount_value, discount_reason, discount_by FROM customer_sales WHERE id = ?1",
             params![sale_id],
-            |row| row.get(0),
+            |row| discount_from_row(row, 0),
         )
-        .unwrap_or(0.0);
+        .optional()?
+        .flatten();
+    let (new_total, discount_amount) = sale_total(conn, sale_id, discount.as_ref())?;
 
     conn.execute(
-        "UPDATE customer_sales SET total_amount = ?1, updated_at = ?2 WHERE id = ?3",
-        params![new_total, chrono::Utc::now().to_rfc3339(), sale_id],
-    )
-    .map_err(|e| e.to_string())?;
+        "UPDATE customer_sales SET total_amount = ?1, updated_at = ?2, discount_amount = ?4 WHERE id = ?3",
+        params![new_total, chrono::Utc::now().to_rfc3339(), sale_id, discount_amount],
+    )?;
 
-    if status == "completed" && (new_total - old_total).abs() > 0.001 {
+    if status == "completed" && new_total != old_total {
         let diff = new_total - old_total;
         conn.execute(
             "UPDATE clients SET credit_balance = COALESCE(credit_balance, 0) + ?1 WHERE id = ?2",
             params![diff, client_id],
-        )
-        .map_err(|e| e.to_string())?;
+        )?;
 
         let history_id = uuid::Uuid::new_v4().to_string();
         conn.execute(
@@ -327,30 +378,27 @@ fn recalculate_sale_total(sale_id: &str) -> Result<(), String> {
                 diff,
                 None::<String>,
             ],
-        )
-        .map_err(|e| e.to_string())?;
+        )?;
     }
 
-    recalculate_payment_status(sale_id)?;
+    recalculate_payment_status(conn, sale_id)?;
 
     Ok(())
 }
 
 // Recalculate sale payment status
-fn recalculate_payment_status(sale_id: &str) -> Result<(), String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
-
-    let (total_amount, paid_amount): (f64, f64) = conn
+fn recalculate_payment_status(conn: &Connection, sale_id: &str) -> Result<(), AppError> {
+    let (total_amount, paid_amount): (Money, Money) = conn
         .query_row(
             "SELECT o.total_amount, COALESCE(SUM(p.amount), 0) FROM customer_sales o LEFT JOIN sale_payments p ON o.id = p.sale_id WHERE o.id = ?1 GROUP BY o.id",
             params![sale_id],
             |row| Ok((row.get(0)?, row.get(1)?)),
         )
-        .unwrap_or((0.0, 0.0));
+        .unwrap_or((Money::ZERO, Money::ZERO));
 
     let payment_status = if paid_amount >= total_amount {
         "paid"
-    } else if paid_amount > 0.0 {
+    } else if paid_amount.is_positive() {
         "partial"
     } else {
         "unpaid"
@@ -359,19 +407,67 @@ fn recalculate_payment_status(sale_id: &str) -> Result<(), String> {
     conn.execute(
         "UPDATE customer_sales SET paid_amount = ?1, payment_status = ?2, updated_at = ?3 WHERE id = ?4",
         params![paid_amount, payment_status, chrono::Utc::now().to_rfc3339(), sale_id],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
+
+    Ok(())
+}
 
+/// Put the stock of a completed sale going back to draft on the shelf, held for it again
+/// as the draft's reservation.
+fn revert_sale_stock(conn: &Connection, sale_id: &str, sale_number: &str, location_id: Option<&str>) -> Result<(), AppError> {
+    let items: Vec<(String, String, i32, Option<Money>)> = {
+        let mut stmt = conn.prepare_cached(
+            "SELECT id, item_id, quantity, unit_cost FROM sale_items WHERE sale_id = ?1 AND item_id IS NOT NULL",
+        )?;
+        let rows = stmt.query_map(params![sale_id], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })?;
+        rows.collect::<Result<_, _>>()?
+    };
+
+    for (line_id, item_id, quantity, unit_cost) in items {
+        inventory::apply_stock_change(conn, &item_id, location_id, quantity as i64, true)?;
+        inventory::log_stock_event(
+            conn,
+            &item_id,
+            location_id,
+            "Adjustment",
+            quantity as i64,
+            &format!("Sale {} reverted to draft", sale_number),
+            sale_id,
+        )?;
+        restock_sold_units(conn, &item_id, quantity as i64, unit_cost, sale_id)?;
+        conn.execute("UPDATE sale_items SET unit_cost = NULL WHERE id = ?1", params![line_id])?;
+        reservation::reserve_stock(conn, &item_id, quantity as i64, "Sale", sale_id, &line_id, false)?;
+    }
     Ok(())
 }
 
+/// Put units of a completed sale line back into the cost layers at what they were sold at.
+fn restock_sold_units(
+    conn: &Connection,
+    item_id: &str,
+    quantity: i64,
+    unit_cost: Option<Money>,
+    sale_id: &str,
+) -> Result<(), AppError> {
+    let unit_cost = match unit_cost {
+        Some(cost) => cost,
+        None => costing::average_cost(conn, item_id)?,
+    };
+    costing::receive_stock(conn, item_id, quantity, unit_cost, "Restock", sale_id)
+}
+
 /// Add an item to a sale
 #[tauri::command]
-pub fn add_sale_item(item: SaleItem) -> Result<(), String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
-
-    conn.execute(
-        "INSERT INTO sale_items (id, sale_id, item_id, item_name, quantity, unit_price, total_price, notes) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+pub fn add_sale_item(pool: State<'_, DbPool>, mut item: SaleItem) -> Result<(), AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+
+    price_sale_item(&mut item)?;
+    let (discount_type, discount_value, discount_reason, discount_by) = discount_columns(&item.discount);
+    tx.execute(
+        "INSERT INTO sale_items (id, sale_id, item_id, item_name, quantity, unit_price, total_price, notes, discount_type, discount_value, discount_reason, discount_by, discount_amount) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
         params![
             item.id,
             item.sale_id,
@@ -381,11 +477,16 @@ pub fn add_sale_item(item: SaleItem) -> Result<(), String> {
             item.unit_price,
             item.total_price,
             item.notes,
+            discount_type,
+            discount_value,
+            discount_reason,
+            discount_by,
+            item.discount_amount,
         ],
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     // If sale is completed, reduce inventory
-    let sale_status: String = conn
+    let sale_status: String = tx
         .query_row(
             "SELECT status FROM customer_sales WHERE id = ?1",
             params![item.sale_id],
@@ -393,14 +494,18 @@ pub fn add_sale_item(item: SaleItem) -> Result<(), String> {
         )
         .unwrap_or_else(|_| "draft".to_string());
 
+    if sale_status == "draft" {
+        if let Some(item_id) = &item.item_id {
+            reservation::reserve_stock(&tx, item_id, item.quantity as i64, "Sale", &item.sale_id, &item.id, false)?;
+        }
+    }
+
     if sale_status == "completed" {
         if let Some(item_id) = &item.item_id {
-            conn.execute(
-                "UPDATE inventory_items SET quantity_in_stock = COALESCE(quantity_in_stock, 0) - ?1 WHERE id = ?2",
-                params![item.quantity, item_id]
-            ).map_err(|e| e.to_string())?;
+            let location_id = location::document_location(&tx, "customer_sales", &item.sale_id)?;
+            inventory::apply_stock_change(&tx, item_id, location_id.as_deref(), -(item.quantity as i64), true)?;
 
-            let sale_num: String = conn
+            let sale_num: String = tx
                 .query_row(
                     "SELECT sale_number FROM customer_sales WHERE id = ?1",
                     params![item.sale_id],
@@ -408,26 +513,25 @@ pub fn add_sale_item(item: SaleItem) -> Result<(), String> {
                 )
                 .unwrap_or_default();
 
-            let history_id = uuid::Uuid::new_v4().to_string();
-            conn.execute(
-                "INSERT INTO inventory_history (id, item_id, date, event_type, quantity_change, notes, related_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-                params![
-                    history_id,
-                    item_id,
-                    chrono::Utc::now().to_rfc3339(),
-                    "Sold",
-                    -(item.quantity),
-                    format!("Sold as part of sale {}", sale_num),
-                    item.sale_id,
-                ],
-            ).map_err(|e| e.to_string())?;
+            inventory::log_stock_event(
+                &tx,
+                item_id,
+                location_id.as_deref(),
+                "Sold",
+                -(item.quantity as i64),
+                &format!("Sold as part of sale {}", sale_num),
+                &item.sale_id,
+            )?;
+
+            let unit_cost = costing::issue_stock(&tx, item_id, item.quantity as i64)?;
+            tx.execute("UPDATE sale_items SET unit_cost = ?2 WHERE id = ?1", params![item.id, unit_cost])?;
         }
     }
 
-    recalculate_sale_total(&item.sale_id)?;
+    recalculate_sale_total(&tx, &item.sale_id)?;
 
     let history_id = uuid::Uuid::new_v4().to_string();
-    conn.execute(
+    tx.execute(
         "INSERT INTO sale_history (id, sale_id, date, event_type, details, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
         params![
             history_id,
@@ -437,25 +541,29 @@ pub fn add_sale_item(item: SaleItem) -> Result<(), String> {
             format!("Added item: {}", item.item_name),
             None::<String>,
         ],
-    ).map_err(|e| e.to_string())?;
+    )?;
 
+    tx.commit()?;
     Ok(())
 }
 
 /// Update a sale item
 #[tauri::command]
-pub fn update_sale_item(item: SaleItem) -> Result<(), String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn update_sale_item(pool: State<'_, DbPool>, mut item: SaleItem) -> Result<(), AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    price_sale_item(&mut item)?;
+    let (discount_type, discount_value, discount_reason, discount_by) = discount_columns(&item.discount);
 
     // If sale was completed, get old item to reverse inventory
-    let old_item: Option<(Option<String>, i32)> = conn.query_row(
-        "SELECT i.item_id, i.quantity FROM sale_items i JOIN customer_sales s ON i.sale_id = s.id WHERE i.id = ?1 AND s.status = 'completed'",
+    let old_item: Option<(Option<String>, i32, Option<Money>)> = tx.query_row(
+        "SELECT i.item_id, i.quantity, i.unit_cost FROM sale_items i JOIN customer_sales s ON i.sale_id = s.id WHERE i.id = ?1 AND s.status = 'completed'",
         params![item.id],
-        |row| Ok((row.get(0).ok(), row.get(1)?))
+        |row| Ok((row.get(0).ok(), row.get(1)?, row.get(2)?))
     ).ok();
 
-    conn.execute(
-        "UPDATE sale_items SET item_id = ?2, item_name = ?3, quantity = ?4, unit_price = ?5, total_price = ?6, notes = ?7 WHERE id = ?1",
+    tx.execute(
+        "UPDATE sale_items SET item_id = ?2, item_name = ?3, quantity = ?4, unit_price = ?5, total_price = ?6, notes = ?7, discount_type = ?8, discount_value = ?9, discount_reason = ?10, discount_by = ?11, discount_amount = ?12 WHERE id = ?1",
         params![
             item.id,
             item.item_id,
@@ -464,11 +572,33 @@ pub fn update_sale_item(item: SaleItem) -> Result<(), String> {
             item.unit_price,
             item.total_price,
             item.notes,
+            discount_type,
+            discount_value,
+            discount_reason,
+            discount_by,
+            item.discount_amount,
         ],
-    ).map_err(|e| e.to_string())?;
+    )?;
+
+    // A draft line holds its new quantity instead of the old one
+    let sale_status: String = tx
+        .query_row(
+            "SELECT status FROM customer_sales WHERE id = ?1",
+            params![item.sale_id],
+            |row| row.get(0),
+        )
+        .unwrap_or_else(|_| "draft".to_string());
+    if sale_status == "draft" {
+        match &item.item_id {
+            Some(item_id) => {
+                reservation::reserve_stock(&tx, item_id, item.quantity as i64, "Sale", &item.sale_id, &item.id, false)?
+            }
+            None => reservation::release_line(&tx, &item.id)?,
+        }
+    }
 
-    if let Some((old_item_id, old_qty)) = old_item {
-        let sale_num: String = conn
+    if let Some((old_item_id, old_qty, old_cost)) = old_item {
+        let sale_num: String = tx
             .query_row(
                 "SELECT sale_number FROM customer_sales WHERE id = ?1",
                 params![item.sale_id],
@@ -476,70 +606,70 @@ pub fn update_sale_item(item: SaleItem) -> Result<(), String> {
             )
             .unwrap_or_default();
 
+        let location_id = location::document_location(&tx, "customer_sales", &item.sale_id)?;
+
         // 1. Revert old inventory
         if let Some(id) = old_item_id {
-            conn.execute(
-                "UPDATE inventory_items SET quantity_in_stock = COALESCE(quantity_in_stock, 0) + ?1 WHERE id = ?2",
-                params![old_qty, id]
-            ).ok();
+            inventory::apply_stock_change(&tx, &id, location_id.as_deref(), old_qty as i64, true)?;
+            restock_sold_units(&tx, &id, old_qty as i64, old_cost, &item.sale_id)?;
         }
 
         // 2. Apply new inventory deduction
         if let Some(new_id) = &item.item_id {
-            conn.execute(
-                "UPDATE inventory_items SET quantity_in_stock = COALESCE(quantity_in_stock, 0) - ?1 WHERE id = ?2",
-                params![item.quantity, new_id]
-            ).map_err(|e| e.to_string())?;
-
-            let history_id = uuid::Uuid::new_v4().to_string();
-            conn.execute(
-                "INSERT INTO inventory_history (id, item_id, date, event_type, quantity_change, notes, related_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-                params![
-                    history_id,
-                    new_id,
-                    chrono::Utc::now().to_rfc3339(),
-                    "Adjustment",
-                    -(item.quantity as i64 - old_qty as i64),
-                    format!("Updated item in completed sale {}", sale_num),
-                    item.sale_id,
-                ],
-            ).map_err(|e| e.to_string())?;
+            inventory::apply_stock_change(&tx, new_id, location_id.as_deref(), -(item.quantity as i64), true)?;
+            inventory::log_stock_event(
+                &tx,
+                new_id,
+                location_id.as_deref(),
+                "Adjustment",
+                -(item.quantity as i64 - old_qty as i64),
+                &format!("Updated item in completed sale {}", sale_num),
+                &item.sale_id,
+            )?;
+
+            let unit_cost = costing::issue_stock(&tx, new_id, item.quantity as i64)?;
+            tx.execute("UPDATE sale_items SET unit_cost = ?2 WHERE id = ?1", params![item.id, unit_cost])?;
         }
     }
 
-    recalculate_sale_total(&item.sale_id)?;
+    recalculate_sale_total(&tx, &item.sale_id)?;
 
+    tx.commit()?;
     Ok(())
 }
 
 /// Remove a sale item
 #[tauri::command]
-pub fn remove_sale_item(item_id: String, sale_id: String) -> Result<(), String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn remove_sale_item(pool: State<'_, DbPool>, item_id: String, sale_id: String) -> Result<(), AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
 
-    let item_info: Option<(String, Option<String>, i32, String)> = conn.query_row(
+    let item_info: Option<(String, Option<String>, i32, String)> = tx.query_row(
         "SELECT i.item_name, i.item_id, i.quantity, s.status FROM sale_items i JOIN customer_sales s ON i.sale_id = s.id WHERE i.id = ?1",
         params![item_id],
         |row| Ok((row.get(0)?, row.get(1).ok(), row.get(2)?, row.get(3)?))
     ).ok();
+    let unit_cost: Option<Money> = tx
+        .query_row("SELECT unit_cost FROM sale_items WHERE id = ?1", params![item_id], |row| row.get(0))
+        .optional()?
+        .flatten();
 
     let name_for_history = item_info
         .as_ref()
         .map(|info| info.0.clone())
         .unwrap_or_else(|| "Unknown Item".to_string());
 
-    conn.execute("DELETE FROM sale_items WHERE id = ?1", params![item_id])
-        .map_err(|e| e.to_string())?;
+    tx.execute("DELETE FROM sale_items WHERE id = ?1", params![item_id])?;
+    reservation::release_line(&tx, &item_id)?;
 
     if let Some((name, id_opt, qty, status)) = item_info {
         if status == "completed" {
             if let Some(id) = id_opt {
-                conn.execute(
-                    "UPDATE inventory_items SET quantity_in_stock = COALESCE(quantity_in_stock, 0) + ?1 WHERE id = ?2",
-                    params![qty, id]
-                ).map_err(|e| e.to_string())?;
+                let location_id = location::document_location(&tx, "customer_sales", &sale_id)?;
+                inventory::apply_stock_change(&tx, &id, location_id.as_deref(), qty as i64, true)?;
+                restock_sold_units(&tx, &id, qty as i64, unit_cost, &sale_id)?;
 
-                let sale_num: String = conn
+                let sale_num: String = tx
                     .query_row(
                         "SELECT sale_number FROM customer_sales WHERE id = ?1",
                         params![sale_id],
@@ -547,27 +677,23 @@ pub fn remove_sale_item(item_id: String, sale_id: String) -> Result<(), String>
                     )
                     .unwrap_or_default();
 
-                let history_id = uuid::Uuid::new_v4().to_string();
-                conn.execute(
-                    "INSERT INTO inventory_history (id, item_id, date, event_type, quantity_change, notes, related_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-                    params![
-                        history_id,
-                        id,
-                        chrono::Utc::now().to_rfc3339(),
-                        "Adjustment",
-                        qty,
-                        format!("Removed item {} from completed sale {}", name, sale_num),
-                        sale_id,
-                    ],
-                ).map_err(|e| e.to_string())?;
+                inventory::log_stock_event(
+                    &tx,
+                    &id,
+                    location_id.as_deref(),
+                    "Adjustment",
+                    qty as i64,
+                    &format!("Removed item {} from completed sale {}", name, sale_num),
+                    &sale_id,
+                )?;
             }
         }
     }
 
-    recalculate_sale_total(&sale_id)?;
+    recalculate_sale_total(&tx, &sale_id)?;
 
     let history_id = uuid::Uuid::new_v4().to_string();
-    conn.execute(
+    tx.execute(
         "INSERT INTO sale_history (id, sale_id, date, event_type, details, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
         params![
             history_id,
@@ -577,15 +703,16 @@ pub fn remove_sale_item(item_id: String, sale_id: String) -> Result<(), String>
             format!("Removed item: {}", name_for_history),
             None::<String>,
         ],
-    ).map_err(|e| e.to_string())?;
+    )?;
 
+    tx.commit()?;
     Ok(())
 }
 
 /// Add a payment to a sale
 #[tauri::command]
-pub fn add_sale_payment(payment: SalePayment) -> Result<(), String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn add_sale_payment(pool: State<'_, DbPool>, payment: SalePayment) -> Result<(), AppError> {
+    let conn = pool.get()?;
 
     conn.execute(
         "INSERT INTO sale_payments (id, sale_id, amount, method, date, received_by, notes, session_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
@@ -599,9 +726,9 @@ pub fn add_sale_payment(payment: SalePayment) -> Result<(), String> {
             payment.notes,
             payment.session_id,
         ],
-    ).map_err(|e| e.to_string())?;
+    )?;
 
-    recalculate_payment_status(&payment.sale_id)?;
+    recalculate_payment_status(&conn, &payment.sale_id)?;
 
     // Subtract payment from client balance
     let (client_id, sale_number): (String, String) = conn
@@ -609,14 +736,12 @@ pub fn add_sale_payment(payment: SalePayment) -> Result<(), String> {
             "SELECT client_id, sale_number FROM customer_sales WHERE id = ?1",
             params![payment.sale_id],
             |row| Ok((row.get(0)?, row.get(1)?)),
-        )
-        .map_err(|e| e.to_string())?;
+        )?;
 
     conn.execute(
         "UPDATE clients SET credit_balance = COALESCE(credit_balance, 0) - ?1 WHERE id = ?2",
         params![payment.amount, client_id],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
 
     let client_history_id = uuid::Uuid::new_v4().to_string();
     conn.execute(
@@ -630,7 +755,7 @@ pub fn add_sale_payment(payment: SalePayment) -> Result<(), String> {
             -payment.amount,
             payment.received_by,
         ],
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     let history_id = uuid::Uuid::new_v4().to_string();
     conn.execute(
@@ -643,70 +768,71 @@ pub fn add_sale_payment(payment: SalePayment) -> Result<(), String> {
             format!("Payment of ${:.2} added", payment.amount),
             payment.received_by,
         ],
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     Ok(())
 }
 
-/// Complete a sale and update inventory and client balance
+/// Complete a sale and update inventory and client balance.
+/// Runs as a single SQL transaction; fails with `InsufficientStock` if a line exceeds
+/// `quantity_in_stock` and `allow_oversell` is not set.
 #[tauri::command]
-pub fn complete_sale(sale_id: String) -> Result<(), String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
-
-    let (sale_number, status): (String, String) = conn
+pub fn complete_sale(
+    pool: State<'_, DbPool>,
+    sale_id: String,
+    allow_oversell: Option<bool>,
+) -> Result<(), AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+
+    let (sale_number, status, location_id): (String, String, Option<String>) = tx
         .query_row(
-            "SELECT sale_number, status FROM customer_sales WHERE id = ?1",
+            "SELECT sale_number, status, location_id FROM customer_sales WHERE id = ?1",
             params![sale_id],
-            |row| Ok((row.get(0)?, row.get(1)?)),
-        )
-        .map_err(|e| e.to_string())?;
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
 
     if status == "completed" {
         return Ok(());
     }
 
-    let mut stmt = conn
-        .prepare("SELECT id, item_id, quantity FROM sale_items WHERE sale_id = ?1")
-        .map_err(|e| e.to_string())?;
-    let items: Vec<(String, Option<String>, i32)> = stmt
-        .query_map(params![sale_id], |row| {
-            Ok((row.get(0)?, row.get(1).ok(), row.get(2)?))
-        })
-        .map_err(|e| e.to_string())?
-        .filter_map(|r| r.ok())
-        .collect();
+    // The stock the draft was holding is taken out for real below
+    reservation::convert_source(&tx, &sale_id)?;
+    let items: Vec<(String, Option<String>, i32)> = {
+        let mut stmt = tx
+            .prepare_cached("SELECT id, item_id, quantity FROM sale_items WHERE sale_id = ?1")?;
+        let rows = stmt.query_map(params![sale_id], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?;
+        rows.collect::<Result<_, _>>()?
+    };
 
-    for (_, item_id_opt, quantity) in items {
+    for (line_id, item_id_opt, quantity) in items {
         if let Some(item_id) = item_id_opt {
-            conn.execute(
-                "UPDATE inventory_items SET quantity_in_stock = COALESCE(quantity_in_stock, 0) - ?1 WHERE id = ?2",
-                params![quantity, item_id],
-            ).map_err(|e| e.to_string())?;
-
-            let history_id = uuid::Uuid::new_v4().to_string();
-            conn.execute(
-                "INSERT INTO inventory_history (id, item_id, date, event_type, quantity_change, notes, related_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-                params![
-                    history_id,
-                    item_id,
-                    chrono::Utc::now().to_rfc3339(),
-                    "Sold",
-                    -(quantity),
-                    format!("Sold from sale {}", sale_number),
-                    sale_id,
-                ],
-            ).map_err(|e| e.to_string())?;
+            let qty_change = -(quantity as i64);
+            inventory::apply_stock_change(&tx, &item_id, location_id.as_deref(), qty_change, allow_oversell.unwrap_or(false))?;
+            inventory::log_stock_event(
+                &tx,
+                &item_id,
+                location_id.as_deref(),
+                "Sold",
+                qty_change,
+                &format!("Sold from sale {}", sale_number),
+                &sale_id,
+            )?;
+
+            let unit_cost = costing::issue_stock(&tx, &item_id, quantity as i64)?;
+            tx.execute("UPDATE sale_items SET unit_cost = ?2 WHERE id = ?1", params![line_id, unit_cost])?;
         }
     }
 
-    conn.execute(
+    tx.execute(
         "UPDATE customer_sales SET status = 'completed', updated_at = ?1 WHERE id = ?2",
         params![chrono::Utc::now().to_rfc3339(), sale_id],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
 
     let history_id = uuid::Uuid::new_v4().to_string();
-    conn.execute(
+    tx.execute(
         "INSERT INTO sale_history (id, sale_id, date, event_type, details, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
         params![
             history_id,
@@ -716,25 +842,23 @@ pub fn complete_sale(sale_id: String) -> Result<(), String> {
             format!("Sale {} completed and inventory updated", sale_number),
             None::<String>,
         ],
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     // Update client balance
-    let (client_id, total_amount): (String, f64) = conn
+    let (client_id, total_amount): (String, Money) = tx
         .query_row(
             "SELECT client_id, total_amount FROM customer_sales WHERE id = ?1",
             params![sale_id],
             |row| Ok((row.get(0)?, row.get(1)?)),
-        )
-        .map_err(|e| e.to_string())?;
+        )?;
 
-    conn.execute(
+    tx.execute(
         "UPDATE clients SET credit_balance = COALESCE(credit_balance, 0) + ?1 WHERE id = ?2",
         params![total_amount, client_id],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
 
     let client_history_id = uuid::Uuid::new_v4().to_string();
-    conn.execute(
+    tx.execute(
         "INSERT INTO client_history (id, client_id, date, type, notes, amount, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
         params![
             client_history_id,
@@ -745,7 +869,8 @@ pub fn complete_sale(sale_id: String) -> Result<(), String> {
             total_amount,
             None::<String>,
         ],
-    ).map_err(|e| e.to_string())?;
+    )?;
 
+    tx.commit()?;
     Ok(())
 }
@@ -1,5 +1,10 @@
 use rusqlite::{Connection, Result};
+use crate::db::DbPool;
+use tauri::State;
+use crate::error::AppError;
 
+/// Create the baseline tables. Column additions and data fixes on top of this
+/// baseline live in `db::migrations` so each install records its schema version.
 pub fn init_all_tables(conn: &Connection) -> Result<()> {
     // Inventory tables
     conn.execute(
@@ -19,9 +24,6 @@ pub fn init_all_tables(conn: &Connection) -> Result<()> {
         [],
     )?;
 
-    // Migration: Add barcode column to inventory_items if it doesn't exist
-    let _ = conn.execute("ALTER TABLE inventory_items ADD COLUMN barcode TEXT", []);
-
     conn.execute(
         "CREATE TABLE IF NOT EXISTS inventory_history (
             id TEXT PRIMARY KEY,
@@ -55,13 +57,6 @@ pub fn init_all_tables(conn: &Connection) -> Result<()> {
         [],
     )?;
 
-    // Migration: Update status from 'Partially Paid' to 'Partially' for consistency
-    let _ = conn.execute("UPDATE repairs SET payment_status = 'Partially' WHERE payment_status = 'Partially Paid'", []);
-
-    // Migration: Add code column if it doesn't exist
-    // We try to add it, ignoring error if it exists (simplest migration for SQLite without dedicated migration tool)
-    let _ = conn.execute("ALTER TABLE repairs ADD COLUMN code TEXT", []);
-
     // Repair payments (supports multiple/partial payments)
     conn.execute(
         "CREATE TABLE IF NOT EXISTS repair_payments (
@@ -479,13 +474,6 @@ pub fn init_all_tables(conn: &Connection) -> Result<()> {
     conn.execute("CREATE INDEX IF NOT EXISTS idx_transaction_items_tx ON transaction_items(transaction_id)", [])?;
     conn.execute("CREATE INDEX IF NOT EXISTS idx_transaction_payments_tx ON transaction_payments(transaction_id)", [])?;
 
-    // Migrations to add session_id to payment tables (ignore errors if column exists)
-    let _ = conn.execute("ALTER TABLE sale_payments ADD COLUMN session_id TEXT", []);
-    let _ = conn.execute("ALTER TABLE repair_payments ADD COLUMN session_id TEXT", []);
-    let _ = conn.execute("ALTER TABLE order_payments ADD COLUMN session_id TEXT", []);
-    let _ = conn.execute("ALTER TABLE client_payments ADD COLUMN session_id TEXT", []);
-    let _ = conn.execute("ALTER TABLE supplier_payments ADD COLUMN session_id TEXT", []);
-
     // Tasks & Reminders table
     conn.execute(
         "CREATE TABLE IF NOT EXISTS tasks (
@@ -505,8 +493,10 @@ pub fn init_all_tables(conn: &Connection) -> Result<()> {
 }
 
 #[tauri::command]
-pub fn init_database() -> Result<(), String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
-    init_all_tables(&conn).map_err(|e| e.to_string())?;
+pub fn init_database(pool: State<'_, DbPool>) -> Result<(), AppError> {
+    let mut conn = pool.get()?;
+    init_all_tables(&conn)?;
+    crate::db::migrations::run_pending_migrations(&mut conn)?;
+    crate::db::money::load_currency(&conn)?;
     Ok(())
 }
@@ -0,0 +1,723 @@
+use crate::db::costing;
+use crate::db::inventory;
+use crate::db::models::{SerialHistory, SerialUnit, SerialUnitEvent};
+use crate::db::repair;
+use crate::db::DbPool;
+use crate::error::AppError;
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use tauri::State;
+use uuid::Uuid;
+
+// Items with a `serial_type` are tracked unit by unit. Document lines list the units
+// they move in `line_serial_numbers`, one per quantity, and once the stock moves each
+// unit's status follows. Every status change is logged in `serial_unit_events` with the
+// status it replaced, so cancelling or editing the document can put the unit back.
+
+pub const SERIAL_TYPES: [&str; 2] = ["IMEI", "Serial"];
+
+const SERIAL_STATUSES: [&str; 5] = ["In Stock", "Sold", "In Repair", "Returned", "RMA"];
+
+/// Statuses of units on the shelf, which `quantity_in_stock` counts.
+pub(crate) const ON_HAND: [&str; 2] = ["In Stock", "Returned"];
+
+const UNIT_COLUMNS: &str = "u.id, u.item_id, i.item_name, u.serial_number, u.status, u.notes, u.created_at, u.updated_at";
+
+/// Map a row selected with `UNIT_COLUMNS` from `serial_units u` joined to the item `i`.
+fn unit_from_row(row: &rusqlite::Row) -> rusqlite::Result<SerialUnit> {
+    Ok(SerialUnit {
+        id: row.get(0)?,
+        item_id: row.get(1)?,
+        item_name: row.get(2)?,
+        serial_number: row.get(3)?,
+        status: row.get(4)?,
+        notes: row.get(5)?,
+        created_at: row.get(6)?,
+        updated_at: row.get(7)?,
+    })
+}
+
+const EVENT_COLUMNS: &str = "id, serial_id, date, event_type, status, previous_status, source_type, source_id, reference, notes, changed_by, reversed_at";
+
+/// Map a row selected with `EVENT_COLUMNS`.
+fn event_from_row(row: &rusqlite::Row) -> rusqlite::Result<SerialUnitEvent> {
+    Ok(SerialUnitEvent {
+        id: row.get(0)?,
+        serial_id: row.get(1)?,
+        date: row.get(2)?,
+        event_type: row.get(3)?,
+        status: row.get(4)?,
+        previous_status: row.get(5)?,
+        source_type: row.get(6)?,
+        source_id: row.get(7)?,
+        reference: row.get(8)?,
+        notes: row.get(9)?,
+        changed_by: row.get(10)?,
+        reversed_at: row.get(11)?,
+    })
+}
+
+/// What moved a unit: a document line, or a change made by hand.
+pub(crate) struct SerialSource<'a> {
+    pub source_type: &'a str, // "Transaction", "Repair" or "Manual"
+    pub source_id: Option<&'a str>,
+    pub line_id: Option<&'a str>,
+    pub reference: Option<&'a str>, // transaction number or repair code
+    pub changed_by: Option<&'a str>,
+}
+
+impl<'a> SerialSource<'a> {
+    pub fn line(source_type: &'a str, source_id: &'a str, line_id: &'a str, reference: &'a str) -> Self {
+        SerialSource {
+            source_type,
+            source_id: Some(source_id),
+            line_id: Some(line_id),
+            reference: Some(reference),
+            changed_by: None,
+        }
+    }
+
+    fn manual(changed_by: Option<&'a str>) -> Self {
+        SerialSource {
+            source_type: "Manual",
+            source_id: None,
+            line_id: None,
+            reference: None,
+            changed_by,
+        }
+    }
+}
+
+// ======================
+// VALIDATION
+// ======================
+
+/// A 15-digit IMEI whose last digit is its Luhn check digit.
+pub fn is_valid_imei(imei: &str) -> bool {
+    if imei.len() != 15 || !imei.chars().all(|c| c.is_ascii_digit()) {
+        return false;
+    }
+    let digits: Vec<u32> = imei.chars().filter_map(|c| c.to_digit(10)).collect();
+    // Every second digit from the right of the first 14 is doubled and its digits added
+    let sum: u32 = digits[..14]
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, d)| if i % 2 == 1 { *d } else { (d * 2) / 10 + (d * 2) % 10 })
+        .sum();
+    (10 - sum % 10) % 10 == digits[14]
+}
+
+/// Serial numbers are kept upper-case without spaces. Numbers written in groups, like
+/// the IMEI "35-209900-176148-1", lose their dashes as well.
+pub(crate) fn normalize_serial(serial: &str) -> String {
+    let compact: String = serial.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_uppercase();
+    let digits: String = compact.chars().filter(|c| *c != '-').collect();
+    if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) {
+        digits
+    } else {
+        compact
+    }
+}
+
+fn check_serial(serial: &str, serial_type: &str) -> Result<(), AppError> {
+    if serial.is_empty() {
+        return Err(AppError::Validation("Serial numbers cannot be empty".to_string()));
+    }
+    if serial_type == "IMEI" && !is_valid_imei(serial) {
+        return Err(AppError::Validation(format!("{} is not a valid IMEI", serial)));
+    }
+    Ok(())
+}
+
+/// Validate an item's `serial_type`: "IMEI", "Serial", or `None` for untracked items.
+pub(crate) fn check_serial_type(serial_type: Option<&str>) -> Result<(), AppError> {
+    match serial_type {
+        Some(serial_type) if !SERIAL_TYPES.contains(&serial_type) => Err(AppError::Validation(format!(
+            "Unknown serial type '{}', expected IMEI or Serial",
+            serial_type
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Normalize the serial number of a customer's device. Fifteen digits are taken to be an
+/// IMEI and must pass the Luhn check.
+pub(crate) fn normalize_device_serial(serial: Option<&str>) -> Result<Option<String>, AppError> {
+    let serial = match serial.map(normalize_serial).filter(|s| !s.is_empty()) {
+        Some(serial) => serial,
+        None => return Ok(None),
+    };
+    if serial.len() == 15 && serial.chars().all(|c| c.is_ascii_digit()) {
+        check_serial(&serial, "IMEI")?;
+    }
+    Ok(Some(serial))
+}
+
+/// `serial_type` of an item, `None` when it isn't tracked by serial number.
+pub(crate) fn item_serial_type(conn: &Connection, item_id: &str) -> Result<Option<String>, AppError> {
+    let serial_type: Option<Option<String>> = conn
+        .query_row(
+            "SELECT serial_type FROM inventory_items WHERE id = ?1",
+            params![item_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(serial_type.flatten())
+}
+
+// ======================
+// DOCUMENT LINES
+// ======================
+
+/// Record the units picked for a document line, replacing those it had. They are checked
+/// against the item's serial type here and against the units' status once stock moves.
+pub(crate) fn set_line_serials(
+    conn: &Connection,
+    line_id: &str,
+    item_id: Option<&str>,
+    quantity: i64,
+    serials: &[String],
+) -> Result<(), AppError> {
+    delete_line_serials(conn, line_id)?;
+    if serials.is_empty() {
+        return Ok(());
+    }
+
+    let serial_type = match item_id {
+        Some(item_id) => item_serial_type(conn, item_id)?,
+        None => None,
+    }
+    .ok_or_else(|| AppError::Validation("Serial numbers can only be given for items tracked by serial number".to_string()))?;
+
+    let mut chosen: Vec<String> = Vec::new();
+    for serial in serials {
+        let serial = normalize_serial(serial);
+        check_serial(&serial, &serial_type)?;
+        if chosen.contains(&serial) {
+            return Err(AppError::Validation(format!("Serial number {} is given twice", serial)));
+        }
+        chosen.push(serial);
+    }
+    if chosen.len() as i64 > quantity.abs() {
+        return Err(AppError::Validation(format!(
+            "{} serial numbers given for a quantity of {}",
+            chosen.len(),
+            quantity
+        )));
+    }
+
+    for serial in &chosen {
+        conn.execute(
+            "INSERT INTO line_serial_numbers (line_id, serial_number) VALUES (?1, ?2)",
+            params![line_id, serial],
+        )?;
+    }
+    Ok(())
+}
+
+/// Serial numbers picked for a document line.
+pub(crate) fn line_serials(conn: &Connection, line_id: &str) -> Result<Vec<String>, AppError> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT serial_number FROM line_serial_numbers WHERE line_id = ?1 ORDER BY serial_number",
+    )?;
+    let serials = stmt
+        .query_map(params![line_id], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    Ok(serials)
+}
+
+/// Units picked for a document line that are in one of `statuses` now.
+pub(crate) fn line_units_in(conn: &Connection, line_id: &str, statuses: &[&str]) -> Result<Vec<String>, AppError> {
+    let mut units = Vec::new();
+    for serial in line_serials(conn, line_id)? {
+        if let Some(unit) = unit_by_serial(conn, &serial)? {
+            if statuses.contains(&unit.status.as_str()) {
+                units.push(serial);
+            }
+        }
+    }
+    Ok(units)
+}
+
+pub(crate) fn delete_line_serials(conn: &Connection, line_id: &str) -> Result<(), AppError> {
+    conn.execute("DELETE FROM line_serial_numbers WHERE line_id = ?1", params![line_id])?;
+    Ok(())
+}
+
+/// The units a line moves, or `None` when the item isn't serialized. A line must name
+/// one unit per unit of quantity before its stock can move.
+fn units_to_move(
+    conn: &Connection,
+    item_id: &str,
+    quantity: i64,
+    source: &SerialSource,
+) -> Result<Option<Vec<String>>, AppError> {
+    if item_serial_type(conn, item_id)?.is_none() {
+        return Ok(None);
+    }
+    let serials = line_serials(conn, source.line_id.unwrap_or_default())?;
+    if serials.len() as i64 != quantity.abs() {
+        let item_name: String = conn.query_row(
+            "SELECT item_name FROM inventory_items WHERE id = ?1",
+            params![item_id],
+            |row| row.get(0),
+        )?;
+        return Err(AppError::Validation(format!(
+            "Serial numbers picked for '{}': {} of {}",
+            item_name,
+            serials.len(),
+            quantity.abs()
+        )));
+    }
+    Ok(Some(serials))
+}
+
+fn unit_by_serial(conn: &Connection, serial_number: &str) -> Result<Option<SerialUnit>, AppError> {
+    let unit = conn
+        .query_row(
+            &format!(
+                "SELECT {} FROM serial_units u JOIN inventory_items i ON u.item_id = i.id WHERE u.serial_number = ?1",
+                UNIT_COLUMNS
+            ),
+            params![serial_number],
+            unit_from_row,
+        )
+        .optional()?;
+    Ok(unit)
+}
+
+fn log_unit_event(
+    conn: &Connection,
+    serial_id: &str,
+    event_type: &str,
+    status: &str,
+    previous_status: Option<&str>,
+    source: &SerialSource,
+    notes: Option<&str>,
+) -> Result<(), AppError> {
+    conn.execute(
+        "INSERT INTO serial_unit_events (id, serial_id, date, event_type, status, previous_status, source_type, source_id, line_id, reference, notes, changed_by)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+        params![
+            Uuid::new_v4().to_string(),
+            serial_id,
+            Utc::now().to_rfc3339(),
+            event_type,
+            status,
+            previous_status,
+            source.source_type,
+            source.source_id,
+            source.line_id,
+            source.reference,
+            notes,
+            source.changed_by,
+        ],
+    )?;
+    Ok(())
+}
+
+fn set_unit_status(conn: &Connection, serial_id: &str, status: &str) -> Result<(), AppError> {
+    conn.execute(
+        "UPDATE serial_units SET status = ?2, updated_at = ?3 WHERE id = ?1",
+        params![serial_id, status, Utc::now().to_rfc3339()],
+    )?;
+    Ok(())
+}
+
+/// Take the units of a receiving line (a purchase) into stock. New serial numbers are
+/// registered; a unit already known must be the same item and out of stock, e.g. a
+/// phone bought back from a customer.
+pub(crate) fn receive_line_units(
+    conn: &Connection,
+    item_id: &str,
+    quantity: i64,
+    event_type: &str,
+    source: &SerialSource,
+) -> Result<(), AppError> {
+    let serials = match units_to_move(conn, item_id, quantity, source)? {
+        Some(serials) => serials,
+        None => return Ok(()),
+    };
+
+    for serial in serials {
+        match unit_by_serial(conn, &serial)? {
+            Some(unit) => {
+                if unit.item_id != item_id {
+                    return Err(AppError::Validation(format!(
+                        "Serial number {} belongs to '{}'",
+                        serial, unit.item_name
+                    )));
+                }
+                if ON_HAND.contains(&unit.status.as_str()) || unit.status == "In Repair" {
+                    return Err(AppError::Conflict(format!(
+                        "Serial number {} is already {}",
+                        serial,
+                        unit.status.to_lowercase()
+                    )));
+                }
+                set_unit_status(conn, &unit.id, "In Stock")?;
+                log_unit_event(conn, &unit.id, event_type, "In Stock", Some(&unit.status), source, None)?;
+            }
+            None => {
+                let serial_id = Uuid::new_v4().to_string();
+                let now = Utc::now().to_rfc3339();
+                conn.execute(
+                    "INSERT INTO serial_units (id, item_id, serial_number, status, created_at, updated_at)
+                     VALUES (?1, ?2, ?3, 'In Stock', ?4, ?4)",
+                    params![serial_id, item_id, serial, now],
+                )?;
+                log_unit_event(conn, &serial_id, event_type, "In Stock", None, source, None)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Move the units of a line from one of the `from` statuses to `to`, e.g. from
+/// `ON_HAND` to "Sold" when a sale completes.
+pub(crate) fn move_line_units(
+    conn: &Connection,
+    item_id: &str,
+    quantity: i64,
+    from: &[&str],
+    to: &str,
+    event_type: &str,
+    source: &SerialSource,
+) -> Result<(), AppError> {
+    let serials = match units_to_move(conn, item_id, quantity, source)? {
+        Some(serials) => serials,
+        None => return Ok(()),
+    };
+
+    for serial in serials {
+        let unit = unit_by_serial(conn, &serial)?
+            .ok_or_else(|| AppError::NotFound(format!("Serial number {} is not registered", serial)))?;
+        if unit.item_id != item_id {
+            return Err(AppError::Validation(format!(
+                "Serial number {} belongs to '{}'",
+                serial, unit.item_name
+            )));
+        }
+        if !from.contains(&unit.status.as_str()) {
+            return Err(AppError::Conflict(format!(
+                "Serial number {} is {} and can't be {}",
+                serial,
+                unit.status.to_lowercase(),
+                event_type.to_lowercase()
+            )));
+        }
+        set_unit_status(conn, &unit.id, to)?;
+        log_unit_event(conn, &unit.id, event_type, to, Some(&unit.status), source, None)?;
+    }
+    Ok(())
+}
+
+/// Undo what a document line did to its units: each goes back to the status it had
+/// before, and units the line registered are removed. A unit another document has
+/// moved on since (sold again, say) can't be put back, which fails with `Conflict`.
+pub(crate) fn reverse_line_units(
+    conn: &Connection,
+    line_id: &str,
+    event_type: &str,
+    notes: &str,
+) -> Result<(), AppError> {
+    struct Applied {
+        event_id: String,
+        serial_id: String,
+        serial_number: String,
+        status: String,      // the unit's status now
+        line_status: String, // the status the line gave it
+        previous_status: Option<String>,
+        source_type: String,
+        source_id: Option<String>,
+        reference: Option<String>,
+        moved_since: bool, // another document line moved it later and still stands
+    }
+
+    let applied: Vec<Applied> = {
+        let mut stmt = conn.prepare_cached(
+            "SELECT e.id, u.id, u.serial_number, u.status, e.status, e.previous_status, e.source_type, e.source_id, e.reference,
+                EXISTS (SELECT 1 FROM serial_unit_events x
+                    WHERE x.serial_id = u.id AND x.line_id IS NOT NULL AND x.reversed_at IS NULL
+                    AND (x.date > e.date OR (x.date = e.date AND x.rowid > e.rowid)))
+             FROM serial_unit_events e
+             JOIN serial_units u ON u.id = e.serial_id
+             WHERE e.line_id = ?1 AND e.reversed_at IS NULL",
+        )?;
+        let rows = stmt.query_map(params![line_id], |row| {
+            Ok(Applied {
+                event_id: row.get(0)?,
+                serial_id: row.get(1)?,
+                serial_number: row.get(2)?,
+                status: row.get(3)?,
+                line_status: row.get(4)?,
+                previous_status: row.get(5)?,
+                source_type: row.get(6)?,
+                source_id: row.get(7)?,
+                reference: row.get(8)?,
+                moved_since: row.get(9)?,
+            })
+        })?;
+        rows.collect::<Result<_, _>>()?
+    };
+
+    let now = Utc::now().to_rfc3339();
+    for unit in applied {
+        if unit.moved_since || unit.status != unit.line_status {
+            return Err(AppError::Conflict(format!(
+                "Serial number {} is {} since {} and can't be put back",
+                unit.serial_number,
+                unit.status.to_lowercase(),
+                unit.reference.as_deref().unwrap_or("then")
+            )));
+        }
+        conn.execute(
+            "UPDATE serial_unit_events SET reversed_at = ?2 WHERE id = ?1",
+            params![unit.event_id, now],
+        )?;
+        match &unit.previous_status {
+            Some(previous_status) => {
+                set_unit_status(conn, &unit.serial_id, previous_status)?;
+                let source = SerialSource {
+                    source_type: &unit.source_type,
+                    source_id: unit.source_id.as_deref(),
+                    line_id: None,
+                    reference: unit.reference.as_deref(),
+                    changed_by: None,
+                };
+                log_unit_event(conn, &unit.serial_id, event_type, previous_status, Some(&unit.status), &source, Some(notes))?;
+            }
+            None => {
+                conn.execute("DELETE FROM serial_units WHERE id = ?1", params![unit.serial_id])?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// ======================
+// COMMANDS
+// ======================
+
+/// Units of an item, or of every item, optionally in one status.
+#[tauri::command]
+pub fn get_serial_units(
+    pool: State<'_, DbPool>,
+    item_id: Option<String>,
+    status: Option<String>,
+) -> Result<Vec<SerialUnit>, AppError> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT {} FROM serial_units u JOIN inventory_items i ON u.item_id = i.id
+         WHERE (?1 IS NULL OR u.item_id = ?1) AND (?2 IS NULL OR u.status = ?2)
+         ORDER BY i.item_name, u.serial_number",
+        UNIT_COLUMNS
+    ))?;
+    let units = stmt
+        .query_map(params![item_id, status], unit_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(units)
+}
+
+/// Register the serial numbers of units already in stock, e.g. when an item starts
+/// being tracked or stock arrived without its serials. There can't be more units on
+/// hand than `quantity_in_stock`.
+#[tauri::command]
+pub fn register_serial_units(
+    pool: State<'_, DbPool>,
+    item_id: String,
+    serial_numbers: Vec<String>,
+    notes: Option<String>,
+    created_by: Option<String>,
+) -> Result<Vec<SerialUnit>, AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+
+    let serial_type = item_serial_type(&tx, &item_id)?
+        .ok_or_else(|| AppError::Validation("Only items tracked by serial number take serial numbers".to_string()))?;
+    // Units fitted on open repairs are only held, so they still count as stock
+    let (item_name, in_stock, registered): (String, i64, i64) = tx.query_row(
+        "SELECT item_name, COALESCE(quantity_in_stock, 0),
+            (SELECT COUNT(*) FROM serial_units u WHERE u.item_id = i.id AND (
+                u.status IN ('In Stock', 'Returned')
+                OR (u.status = 'In Repair' AND EXISTS (
+                    SELECT 1 FROM serial_unit_events e
+                    JOIN stock_reservations r ON r.line_id = e.line_id AND r.status = 'Active'
+                    WHERE e.serial_id = u.id AND e.reversed_at IS NULL
+                ))
+            ))
+         FROM inventory_items i WHERE id = ?1",
+        params![item_id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    if registered + serial_numbers.len() as i64 > in_stock {
+        return Err(AppError::Validation(format!(
+            "'{}' has {} units in stock and {} of them have serial numbers",
+            item_name, in_stock, registered
+        )));
+    }
+
+    let now = Utc::now().to_rfc3339();
+    let source = SerialSource::manual(created_by.as_deref());
+    let mut serial_ids = Vec::new();
+    for serial in &serial_numbers {
+        let serial = normalize_serial(serial);
+        check_serial(&serial, &serial_type)?;
+        if let Some(unit) = unit_by_serial(&tx, &serial)? {
+            return Err(AppError::Conflict(format!(
+                "Serial number {} is already registered to '{}'",
+                serial, unit.item_name
+            )));
+        }
+        let serial_id = Uuid::new_v4().to_string();
+        tx.execute(
+            "INSERT INTO serial_units (id, item_id, serial_number, status, notes, created_at, updated_at)
+             VALUES (?1, ?2, ?3, 'In Stock', ?4, ?5, ?5)",
+            params![serial_id, item_id, serial, notes, now],
+        )?;
+        log_unit_event(&tx, &serial_id, "Registered", "In Stock", None, &source, notes.as_deref())?;
+        serial_ids.push(serial_id);
+    }
+
+    let units = {
+        let mut stmt = tx.prepare_cached(&format!(
+            "SELECT {} FROM serial_units u JOIN inventory_items i ON u.item_id = i.id WHERE u.id = ?1",
+            UNIT_COLUMNS
+        ))?;
+        serial_ids
+            .iter()
+            .map(|id| stmt.query_row(params![id], unit_from_row))
+            .collect::<Result<Vec<_>, _>>()?
+    };
+    tx.commit()?;
+    Ok(units)
+}
+
+/// Change a unit's status by hand. Units on the shelf can be marked returned or back in
+/// stock, and sent to the supplier for RMA, which takes them out of stock until they
+/// come back. Sales, repairs and returns move units through their documents instead.
+#[tauri::command]
+pub fn set_serial_unit_status(
+    pool: State<'_, DbPool>,
+    serial_number: String,
+    status: String,
+    notes: Option<String>,
+    changed_by: Option<String>,
+) -> Result<SerialUnit, AppError> {
+    if !SERIAL_STATUSES.contains(&status.as_str()) {
+        return Err(AppError::Validation(format!("Unknown serial status '{}'", status)));
+    }
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+
+    let serial_number = normalize_serial(&serial_number);
+    let unit = unit_by_serial(&tx, &serial_number)?
+        .ok_or_else(|| AppError::NotFound(format!("Serial number {} is not registered", serial_number)))?;
+    if unit.status == status {
+        return Ok(unit);
+    }
+
+    let on_hand = ON_HAND.contains(&unit.status.as_str());
+    let stock_change = match (unit.status.as_str(), status.as_str()) {
+        (_, "In Stock") | (_, "Returned") if on_hand => 0,
+        (_, "RMA") if on_hand => -1,
+        ("RMA", "In Stock") => 1,
+        (from, to) => {
+            return Err(AppError::Conflict(format!(
+                "A unit that is {} can't be set to {} by hand",
+                from.to_lowercase(),
+                to.to_lowercase()
+            )))
+        }
+    };
+    if stock_change != 0 {
+        inventory::apply_stock_change(&tx, &unit.item_id, None, stock_change, false)?;
+        inventory::log_stock_event(
+            &tx,
+            &unit.item_id,
+            None,
+            if stock_change < 0 { "Sent for RMA" } else { "Back from RMA" },
+            stock_change,
+            &format!("Serial number {}", serial_number),
+            &unit.id,
+        )?;
+        costing::adjust_stock(&tx, &unit.item_id, stock_change)?;
+    }
+
+    set_unit_status(&tx, &unit.id, &status)?;
+    let source = SerialSource::manual(changed_by.as_deref());
+    log_unit_event(&tx, &unit.id, "Status Changed", &status, Some(&unit.status), &source, notes.as_deref())?;
+
+    let unit = unit_by_serial(&tx, &serial_number)?
+        .ok_or_else(|| AppError::NotFound(format!("Serial number {} is not registered", serial_number)))?;
+    tx.commit()?;
+    Ok(unit)
+}
+
+/// Look a serial number or IMEI up: the unit with every purchase, sale, repair and
+/// return it went through, oldest first, and the repairs of a customer's device with
+/// that number.
+#[tauri::command]
+pub fn get_serial_history(pool: State<'_, DbPool>, serial_number: String) -> Result<SerialHistory, AppError> {
+    let conn = pool.get()?;
+    let serial_number = normalize_serial(&serial_number);
+    if serial_number.is_empty() {
+        return Err(AppError::Validation("Enter a serial number or IMEI".to_string()));
+    }
+
+    let unit = unit_by_serial(&conn, &serial_number)?;
+    let events = match &unit {
+        Some(unit) => conn
+            .prepare_cached(&format!(
+                "SELECT {} FROM serial_unit_events WHERE serial_id = ?1 ORDER BY date, rowid",
+                EVENT_COLUMNS
+            ))?
+            .query_map(params![unit.id], event_from_row)?
+            .collect::<Result<Vec<_>, _>>()?,
+        None => Vec::new(),
+    };
+    let repairs = repair::repairs_for_device(&conn, &serial_number)?;
+
+    Ok(SerialHistory {
+        serial_number,
+        unit,
+        events,
+        repairs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn imei_check_digit() {
+        assert!(is_valid_imei("490154203237518"));
+        assert!(is_valid_imei("352099001761481"));
+        assert!(!is_valid_imei("490154203237519"));
+        assert!(!is_valid_imei("352099001761482"));
+    }
+
+    #[test]
+    fn imei_needs_fifteen_digits() {
+        assert!(!is_valid_imei("49015420323751"));
+        assert!(!is_valid_imei("4901542032375180"));
+        assert!(!is_valid_imei("49015420323751A"));
+        assert!(!is_valid_imei(""));
+    }
+
+    #[test]
+    fn normalize_drops_spaces_and_dashes_in_numbers() {
+        assert_eq!(normalize_serial("35-209900-176148-1"), "352099001761481");
+        assert_eq!(normalize_serial(" 35 209900 176148 1 "), "352099001761481");
+        assert!(is_valid_imei(&normalize_serial("35-209900 176148-1")));
+    }
+
+    #[test]
+    fn normalize_keeps_dashes_in_other_serials() {
+        assert_eq!(normalize_serial(" f2lx-9abc d1 "), "F2LX-9ABCD1");
+        assert_eq!(normalize_serial("-"), "-");
+        assert_eq!(normalize_serial("   "), "");
+    }
+}
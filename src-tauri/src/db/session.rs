@@ -1,16 +1,20 @@
-use crate::db;
+use crate::db::DbPool;
+use crate::db::money::Money;
 use crate::db::models::{DailySession, DashboardTransaction};
 use chrono::Utc;
 use rusqlite::{params, OptionalExtension, Result};
 use uuid::Uuid;
+use tauri::State;
+use crate::error::AppError;
 
 #[tauri::command]
 pub fn start_session(
-    opening_balance: f64,
+    pool: State<'_, DbPool>,
+    opening_balance: Money,
     notes: Option<String>,
     created_by: Option<String>,
-) -> Result<DailySession, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+) -> Result<DailySession, AppError> {
+    let conn = pool.get()?;
 
     // Check if there's already an open session
     let existing_open: Option<String> = conn
@@ -22,7 +26,7 @@ pub fn start_session(
         .ok();
 
     if existing_open.is_some() {
-        return Err("A session is already open. Close it before starting a new one.".to_string());
+        return Err(AppError::Conflict("A session is already open. Close it before starting a new one.".to_string()));
     }
 
     let session = DailySession {
@@ -49,19 +53,17 @@ pub fn start_session(
             session.notes,
             session.created_by,
         ],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
 
     Ok(session)
 }
 
 #[tauri::command]
-pub fn get_current_session() -> Result<Option<DailySession>, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn get_current_session(pool: State<'_, DbPool>) -> Result<Option<DailySession>, AppError> {
+    let conn = pool.get()?;
 
     let mut stmt = conn
-        .prepare("SELECT id, start_time, end_time, opening_balance, closing_balance, counted_amount, withdrawal_amount, status, notes, created_by FROM daily_sessions WHERE status = 'open' LIMIT 1")
-        .map_err(|e| e.to_string())?;
+        .prepare_cached("SELECT id, start_time, end_time, opening_balance, closing_balance, counted_amount, withdrawal_amount, status, notes, created_by FROM daily_sessions WHERE status = 'open' LIMIT 1")?;
 
     let session = stmt
         .query_row([], |row| {
@@ -70,9 +72,9 @@ pub fn get_current_session() -> Result<Option<DailySession>, String> {
                 start_time: row.get(1)?,
                 end_time: row.get::<_, Option<String>>(2)?,
                 opening_balance: row.get(3)?,
-                closing_balance: row.get::<_, Option<f64>>(4)?,
-                counted_amount: row.get::<_, Option<f64>>(5)?,
-                withdrawal_amount: row.get::<_, Option<f64>>(6)?,
+                closing_balance: row.get::<_, Option<Money>>(4)?,
+                counted_amount: row.get::<_, Option<Money>>(5)?,
+                withdrawal_amount: row.get::<_, Option<Money>>(6)?,
                 status: row.get(7)?,
                 notes: row.get::<_, Option<String>>(8)?,
                 created_by: row.get::<_, Option<String>>(9)?,
@@ -85,12 +87,13 @@ pub fn get_current_session() -> Result<Option<DailySession>, String> {
 
 #[tauri::command]
 pub fn close_session(
+    pool: State<'_, DbPool>,
     id: String,
-    counted_amount: f64,
-    withdrawal_amount: f64,
+    counted_amount: Money,
+    withdrawal_amount: Money,
     notes: Option<String>,
-) -> Result<(), String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+) -> Result<(), AppError> {
+    let conn = pool.get()?;
 
     let end_time = Utc::now().to_rfc3339();
 
@@ -115,8 +118,7 @@ pub fn close_session(
             closing_balance,
             notes,
         ],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
 
     // Link all payments and expenses without a session_id to this session (if they happened during it)
     let _ = conn.execute(
@@ -152,8 +154,8 @@ pub fn close_session(
 }
 
 #[tauri::command]
-pub fn get_current_session_transactions() -> Result<Vec<DashboardTransaction>, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn get_current_session_transactions(pool: State<'_, DbPool>) -> Result<Vec<DashboardTransaction>, AppError> {
+    let conn = pool.get()?;
 
     // Get current open session ID
     let session_id_result: Result<Option<String>, rusqlite::Error> = conn
@@ -164,7 +166,7 @@ pub fn get_current_session_transactions() -> Result<Vec<DashboardTransaction>, S
         )
         .optional();
 
-    let session_id = match session_id_result.map_err(|e| e.to_string())? {
+    let session_id = match session_id_result? {
         Some(id) => id,
         None => return Ok(Vec::new()), // No open session
     };
@@ -172,120 +174,121 @@ pub fn get_current_session_transactions() -> Result<Vec<DashboardTransaction>, S
     let mut all_tx = Vec::new();
 
     // 1. Sale Payments
-    let mut stmt = conn.prepare("SELECT p.id, p.amount, p.date, p.method, s.sale_number FROM sale_payments p JOIN customer_sales s ON p.sale_id = s.id WHERE p.session_id = ?1").map_err(|e| e.to_string())?;
-    let mut rows = stmt.query(params![session_id]).map_err(|e| e.to_string())?;
-    while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+    let mut stmt = conn.prepare_cached("SELECT p.id, p.amount, p.date, p.method, s.sale_number FROM sale_payments p JOIN customer_sales s ON p.sale_id = s.id WHERE p.session_id = ?1")?;
+    let mut rows = stmt.query(params![session_id])?;
+    while let Some(row) = rows.next()? {
         all_tx.push(DashboardTransaction {
-            id: row.get(0).map_err(|e: rusqlite::Error| e.to_string())?,
+            id: row.get(0)?,
             tx_type: "credit".to_string(),
             category: "Sale Payment".to_string(),
-            amount: row.get(1).map_err(|e: rusqlite::Error| e.to_string())?,
+            amount: row.get(1)?,
             description: format!(
                 "Sale payment for {}",
-                row.get::<_, String>(4)
-                    .map_err(|e: rusqlite::Error| e.to_string())?
+                row.get::<_, String>(4)?
             ),
-            time: row.get(2).map_err(|e: rusqlite::Error| e.to_string())?,
+            time: row.get(2)?,
             status: "completed".to_string(),
         });
     }
 
     // 2. Repair Payments
-    let mut stmt = conn.prepare("SELECT p.id, p.amount, p.date, p.method, r.customer_name FROM repair_payments p JOIN repairs r ON p.repair_id = r.id WHERE p.session_id = ?1").map_err(|e| e.to_string())?;
-    let mut rows = stmt.query(params![session_id]).map_err(|e| e.to_string())?;
-    while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+    let mut stmt = conn.prepare_cached("SELECT p.id, p.amount, p.date, p.method, r.customer_name FROM repair_payments p JOIN repairs r ON p.repair_id = r.id WHERE p.session_id = ?1")?;
+    let mut rows = stmt.query(params![session_id])?;
+    while let Some(row) = rows.next()? {
         all_tx.push(DashboardTransaction {
-            id: row.get(0).map_err(|e: rusqlite::Error| e.to_string())?,
+            id: row.get(0)?,
             tx_type: "credit".to_string(),
             category: "Repair Payment".to_string(),
-            amount: row.get(1).map_err(|e: rusqlite::Error| e.to_string())?,
+            amount: row.get(1)?,
             description: format!(
                 "Repair payment from {}",
-                row.get::<_, String>(4)
-                    .map_err(|e: rusqlite::Error| e.to_string())?
+                row.get::<_, String>(4)?
             ),
-            time: row.get(2).map_err(|e: rusqlite::Error| e.to_string())?,
+            time: row.get(2)?,
             status: "completed".to_string(),
         });
     }
 
     // 3. Client Payments (Direct)
-    let mut stmt = conn.prepare("SELECT p.id, p.amount, p.date, p.method, c.name FROM client_payments p JOIN clients c ON p.client_id = c.id WHERE p.session_id = ?1").map_err(|e| e.to_string())?;
-    let mut rows = stmt.query(params![session_id]).map_err(|e| e.to_string())?;
-    while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+    let mut stmt = conn.prepare_cached("SELECT p.id, p.amount, p.date, p.method, c.name FROM client_payments p JOIN clients c ON p.client_id = c.id WHERE p.session_id = ?1")?;
+    let mut rows = stmt.query(params![session_id])?;
+    while let Some(row) = rows.next()? {
         all_tx.push(DashboardTransaction {
-            id: row.get(0).map_err(|e: rusqlite::Error| e.to_string())?,
+            id: row.get(0)?,
             tx_type: "credit".to_string(),
             category: "Client Payment".to_string(),
-            amount: row.get(1).map_err(|e: rusqlite::Error| e.to_string())?,
+            amount: row.get(1)?,
             description: format!(
                 "Direct payment from {}",
-                row.get::<_, String>(4)
-                    .map_err(|e: rusqlite::Error| e.to_string())?
+                row.get::<_, String>(4)?
             ),
-            time: row.get(2).map_err(|e: rusqlite::Error| e.to_string())?,
+            time: row.get(2)?,
             status: "completed".to_string(),
         });
     }
 
     // 4. Expenses
     let mut stmt = conn
-        .prepare("SELECT id, amount, date, reason, category FROM expenses WHERE session_id = ?1")
-        .map_err(|e| e.to_string())?;
-    let mut rows = stmt.query(params![session_id]).map_err(|e| e.to_string())?;
-    while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+        .prepare_cached("SELECT id, amount, date, reason, category FROM expenses WHERE session_id = ?1")?;
+    let mut rows = stmt.query(params![session_id])?;
+    while let Some(row) = rows.next()? {
         all_tx.push(DashboardTransaction {
-            id: row.get(0).map_err(|e: rusqlite::Error| e.to_string())?,
+            id: row.get(0)?,
             tx_type: "debit".to_string(),
             category: "Expense".to_string(),
-            amount: row.get(1).map_err(|e: rusqlite::Error| e.to_string())?,
-            description: row.get(3).map_err(|e: rusqlite::Error| e.to_string())?,
-            time: row.get(2).map_err(|e: rusqlite::Error| e.to_string())?,
+            amount: row.get(1)?,
+            description: row.get(3)?,
+            time: row.get(2)?,
             status: "completed".to_string(),
         });
     }
 
     // 5. Supplier Payments
-    let mut stmt = conn.prepare("SELECT p.id, p.amount, p.date, p.method, s.name FROM supplier_payments p JOIN suppliers s ON p.supplier_id = s.id WHERE p.session_id = ?1").map_err(|e| e.to_string())?;
-    let mut rows = stmt.query(params![session_id]).map_err(|e| e.to_string())?;
-    while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+    let mut stmt = conn.prepare_cached("SELECT p.id, p.amount, p.date, p.method, s.name FROM supplier_payments p JOIN suppliers s ON p.supplier_id = s.id WHERE p.session_id = ?1")?;
+    let mut rows = stmt.query(params![session_id])?;
+    while let Some(row) = rows.next()? {
         all_tx.push(DashboardTransaction {
-            id: row.get(0).map_err(|e: rusqlite::Error| e.to_string())?,
+            id: row.get(0)?,
             tx_type: "debit".to_string(),
             category: "Supplier Payment".to_string(),
-            amount: row.get(1).map_err(|e: rusqlite::Error| e.to_string())?,
+            amount: row.get(1)?,
             description: format!(
                 "Payment to {}",
-                row.get::<_, String>(4)
-                    .map_err(|e: rusqlite::Error| e.to_string())?
+                row.get::<_, String>(4)?
             ),
-            time: row.get(2).map_err(|e: rusqlite::Error| e.to_string())?,
+            time: row.get(2)?,
             status: "completed".to_string(),
         });
     }
 
     // 6. Generic Transaction Payments
-    let mut stmt = conn.prepare("SELECT p.id, p.amount, p.date, p.method, t.transaction_number, t.transaction_type FROM transaction_payments p JOIN transactions t ON p.transaction_id = t.id WHERE p.session_id = ?1").map_err(|e| e.to_string())?;
-    let mut rows = stmt.query(params![session_id]).map_err(|e| e.to_string())?;
-    while let Some(row) = rows.next().map_err(|e| e.to_string())? {
-        let tx_type_label: String = row.get(5).map_err(|e: rusqlite::Error| e.to_string())?;
-        let direction = if tx_type_label == "Sale" {
+    let mut stmt = conn.prepare_cached("SELECT p.id, p.amount, p.date, p.method, t.transaction_number, t.transaction_type FROM transaction_payments p JOIN transactions t ON p.transaction_id = t.id WHERE p.session_id = ?1")?;
+    let mut rows = stmt.query(params![session_id])?;
+    while let Some(row) = rows.next()? {
+        let tx_type_label: String = row.get(5)?;
+        // Suppliers refunding a purchase return pay into the drawer like customers do
+        let direction = if tx_type_label == "Sale" || tx_type_label == "PurchaseReturn" {
             "credit"
         } else {
             "debit"
         };
+        // Payments on a return are refunds handed back to the customer
+        let category = match tx_type_label.as_str() {
+            "Return" => "Refund".to_string(),
+            "PurchaseReturn" => "Supplier Refund".to_string(),
+            _ => format!("{} Payment", tx_type_label),
+        };
         all_tx.push(DashboardTransaction {
-            id: row.get(0).map_err(|e: rusqlite::Error| e.to_string())?,
+            id: row.get(0)?,
             tx_type: direction.to_string(),
-            category: format!("{} Payment", tx_type_label),
-            amount: row.get(1).map_err(|e: rusqlite::Error| e.to_string())?,
+            category,
+            amount: row.get(1)?,
             description: format!(
                 "Payment for {} {}",
                 tx_type_label,
-                row.get::<_, String>(4)
-                    .map_err(|e: rusqlite::Error| e.to_string())?
+                row.get::<_, String>(4)?
             ),
-            time: row.get(2).map_err(|e: rusqlite::Error| e.to_string())?,
+            time: row.get(2)?,
             status: "completed".to_string(),
         });
     }
@@ -297,16 +300,16 @@ pub fn get_current_session_transactions() -> Result<Vec<DashboardTransaction>, S
 }
 
 #[tauri::command]
-pub fn get_last_session_closing_balance() -> Result<f64, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn get_last_session_closing_balance(pool: State<'_, DbPool>) -> Result<Money, AppError> {
+    let conn = pool.get()?;
 
-    let balance: f64 = match conn.query_row(
+    let balance: Money = match conn.query_row(
         "SELECT closing_balance FROM daily_sessions WHERE status = 'closed' ORDER BY end_time DESC LIMIT 1",
         [],
-        |row| row.get::<_, Option<f64>>(0)
+        |row| row.get::<_, Option<Money>>(0)
     ) {
-        Ok(closing_balance) => closing_balance.unwrap_or(0.0),
-        Err(_) => 0.0,
+        Ok(closing_balance) => closing_balance.unwrap_or(Money::ZERO),
+        Err(_) => Money::ZERO,
     };
 
     Ok(balance)
@@ -0,0 +1,392 @@
+use crate::db::costing;
+use crate::db::models::{Order, OrderItem, ShoppingListItem};
+use crate::db::money::Money;
+use crate::db::order;
+use crate::db::price_list;
+use crate::db::DbPool;
+use crate::error::AppError;
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
+use tauri::State;
+use uuid::Uuid;
+
+// Items are noted as Draft, marked Pending once they should be bought and become
+// Ordered when put on a supplier order. Items on an order follow it: they are
+// Received when the order is completed and go back to Pending if they are taken
+// off it.
+
+const URGENCIES: [&str; 3] = ["Low", "Medium", "High"];
+
+/// Statuses an item can be moved to by hand from each status.
+const TRANSITIONS: &[(&str, &[&str])] = &[
+    ("Draft", &["Pending", "Cancelled"]),
+    ("Pending", &["Draft", "Ordered", "Cancelled"]),
+    ("Ordered", &["Received", "Cancelled"]),
+    ("Received", &[]),
+    ("Cancelled", &["Draft"]),
+];
+
+const ITEM_COLUMNS: &str = "s.id, s.name, s.item_id, s.quantity, s.estimated_cost, s.supplier_id, sup.name, s.notes,
+    s.urgency, s.status, s.order_id, s.ordered_at, s.received_at, s.created_at, s.updated_at";
+
+fn item_from_row(row: &Row) -> Result<ShoppingListItem> {
+    Ok(ShoppingListItem {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        item_id: row.get(2)?,
+        quantity: row.get(3)?,
+        estimated_cost: row.get(4)?,
+        supplier_id: row.get(5)?,
+        supplier_name: row.get(6)?,
+        notes: row.get(7)?,
+        urgency: row.get(8)?,
+        status: row.get(9)?,
+        order_id: row.get(10)?,
+        ordered_at: row.get(11)?,
+        received_at: row.get(12)?,
+        created_at: row.get(13)?,
+        updated_at: row.get(14)?,
+    })
+}
+
+fn load_item(conn: &Connection, id: &str) -> Result<ShoppingListItem, AppError> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM shopping_list_items s LEFT JOIN suppliers sup ON sup.id = s.supplier_id WHERE s.id = ?1",
+            ITEM_COLUMNS
+        ),
+        params![id],
+        item_from_row,
+    )
+    .optional()?
+    .ok_or_else(|| AppError::NotFound(format!("Shopping list item {} not found", id)))
+}
+
+fn check_supplier(conn: &Connection, supplier_id: &str) -> Result<(), AppError> {
+    let exists: Option<String> = conn
+        .query_row("SELECT id FROM suppliers WHERE id = ?1", params![supplier_id], |row| row.get(0))
+        .optional()?;
+    if exists.is_none() {
+        return Err(AppError::NotFound(format!("Supplier {} not found", supplier_id)));
+    }
+    Ok(())
+}
+
+/// Get the shopping list, most urgent first, optionally only items with one status.
+#[tauri::command]
+pub fn get_shopping_list(
+    pool: State<'_, DbPool>,
+    status: Option<String>,
+) -> Result<Vec<ShoppingListItem>, AppError> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT {} FROM shopping_list_items s LEFT JOIN suppliers sup ON sup.id = s.supplier_id
+         WHERE ?1 IS NULL OR s.status = ?1
+         ORDER BY CASE s.urgency WHEN 'High' THEN 0 WHEN 'Medium' THEN 1 ELSE 2 END, s.created_at",
+        ITEM_COLUMNS
+    ))?;
+    let items = stmt
+        .query_map(params![status], item_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(items)
+}
+
+/// Create (empty id) or edit a shopping list item. Only Draft and Pending items can
+/// be edited; the status itself is changed with `set_shopping_list_status`.
+#[tauri::command]
+pub fn save_shopping_list_item(
+    pool: State<'_, DbPool>,
+    mut item: ShoppingListItem,
+) -> Result<ShoppingListItem, AppError> {
+    let conn = pool.get()?;
+
+    if item.quantity <= 0 {
+        return Err(AppError::Validation("Quantity must be positive".to_string()));
+    }
+    if !URGENCIES.contains(&item.urgency.as_str()) {
+        return Err(AppError::Validation(format!("Unknown urgency '{}'", item.urgency)));
+    }
+    if item.estimated_cost.is_some_and(|cost| cost.is_negative()) {
+        return Err(AppError::Validation("Estimated cost can't be negative".to_string()));
+    }
+    if let Some(supplier_id) = &item.supplier_id {
+        check_supplier(&conn, supplier_id)?;
+    }
+    if let Some(item_id) = &item.item_id {
+        let item_name: String = conn
+            .query_row("SELECT item_name FROM inventory_items WHERE id = ?1", params![item_id], |row| row.get(0))
+            .optional()?
+            .ok_or_else(|| AppError::NotFound(format!("Inventory item {} not found", item_id)))?;
+        if item.name.trim().is_empty() {
+            item.name = item_name;
+        }
+    }
+    item.name = item.name.trim().to_string();
+    if item.name.is_empty() {
+        return Err(AppError::Validation("Name is required".to_string()));
+    }
+
+    let now = Utc::now().to_rfc3339();
+    if item.id.is_empty() {
+        if item.status != "Draft" && item.status != "Pending" {
+            return Err(AppError::Validation("New items must be Draft or Pending".to_string()));
+        }
+        item.id = Uuid::new_v4().to_string();
+        conn.execute(
+            "INSERT INTO shopping_list_items (id, name, item_id, quantity, estimated_cost, supplier_id, notes, urgency, status, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?10)",
+            params![
+                item.id,
+                item.name,
+                item.item_id,
+                item.quantity,
+                item.estimated_cost,
+                item.supplier_id,
+                item.notes,
+                item.urgency,
+                item.status,
+                now,
+            ],
+        )?;
+    } else {
+        let existing = load_item(&conn, &item.id)?;
+        if existing.status != "Draft" && existing.status != "Pending" {
+            return Err(AppError::Conflict(format!(
+                "{} is {} and can no longer be edited",
+                existing.name,
+                existing.status.to_lowercase()
+            )));
+        }
+        conn.execute(
+            "UPDATE shopping_list_items SET name = ?2, item_id = ?3, quantity = ?4, estimated_cost = ?5, supplier_id = ?6,
+                notes = ?7, urgency = ?8, updated_at = ?9
+             WHERE id = ?1",
+            params![
+                item.id,
+                item.name,
+                item.item_id,
+                item.quantity,
+                item.estimated_cost,
+                item.supplier_id,
+                item.notes,
+                item.urgency,
+                now,
+            ],
+        )?;
+    }
+
+    load_item(&conn, &item.id)
+}
+
+/// Delete a shopping list item. Items waiting on a supplier order stay until the
+/// order is completed or they are taken off it.
+#[tauri::command]
+pub fn delete_shopping_list_item(pool: State<'_, DbPool>, id: String) -> Result<(), AppError> {
+    let conn = pool.get()?;
+    let item = load_item(&conn, &id)?;
+    if item.status == "Ordered" && item.order_id.is_some() {
+        return Err(AppError::Conflict(format!("{} is on a supplier order and can't be deleted", item.name)));
+    }
+    conn.execute("DELETE FROM shopping_list_items WHERE id = ?1", params![id])?;
+    Ok(())
+}
+
+/// Move an item to another status by hand, e.g. Draft to Pending or Ordered to
+/// Received for things bought without a supplier order.
+#[tauri::command]
+pub fn set_shopping_list_status(
+    pool: State<'_, DbPool>,
+    id: String,
+    status: String,
+) -> Result<ShoppingListItem, AppError> {
+    let conn = pool.get()?;
+    let item = load_item(&conn, &id)?;
+    if item.status == status {
+        return Ok(item);
+    }
+
+    if !TRANSITIONS.iter().any(|(known, _)| *known == status) {
+        return Err(AppError::Validation(format!("Unknown status '{}'", status)));
+    }
+    let allowed = TRANSITIONS
+        .iter()
+        .find(|(from, _)| *from == item.status)
+        .map_or(&[][..], |(_, to)| *to);
+    if !allowed.contains(&status.as_str()) {
+        return Err(AppError::Conflict(format!(
+            "{} can't go from {} to {}",
+            item.name, item.status, status
+        )));
+    }
+    if let Some(order_id) = &item.order_id {
+        if item.status == "Ordered" {
+            let order_number: String = conn
+                .query_row("SELECT order_number FROM orders WHERE id = ?1", params![order_id], |row| row.get(0))
+                .unwrap_or_default();
+            return Err(AppError::Conflict(format!(
+                "{} is on order {}; complete the order or remove it from the order instead",
+                item.name, order_number
+            )));
+        }
+    }
+
+    let now = Utc::now().to_rfc3339();
+    conn.execute(
+        "UPDATE shopping_list_items SET status = ?2, updated_at = ?3,
+            ordered_at = CASE WHEN ?2 = 'Ordered' THEN ?3 WHEN ?2 = 'Draft' THEN NULL ELSE ordered_at END,
+            received_at = CASE WHEN ?2 = 'Received' THEN ?3 ELSE NULL END,
+            order_id = CASE WHEN ?2 = 'Draft' THEN NULL ELSE order_id END,
+            order_item_id = CASE WHEN ?2 = 'Draft' THEN NULL ELSE order_item_id END
+         WHERE id = ?1",
+        params![id, status, now],
+    )?;
+    load_item(&conn, &id)
+}
+
+/// Put Draft or Pending items on a new draft order. The supplier is `supplier_id`
+/// or, when that is not given, the one supplier the items share. Each item becomes
+/// an order line at its estimated cost, else the supplier's price list price or the
+/// inventory item's buying price.
+#[tauri::command]
+pub fn convert_shopping_list_to_order(
+    pool: State<'_, DbPool>,
+    item_ids: Vec<String>,
+    supplier_id: Option<String>,
+    location_id: Option<String>,
+    created_by: Option<String>,
+) -> Result<Order, AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+
+    let mut items: Vec<ShoppingListItem> = Vec::new();
+    for id in item_ids {
+        if items.iter().any(|item| item.id == id) {
+            continue;
+        }
+        let item = load_item(&tx, &id)?;
+        if item.status != "Draft" && item.status != "Pending" {
+            return Err(AppError::Conflict(format!(
+                "{} is {} and can't be ordered again",
+                item.name,
+                item.status.to_lowercase()
+            )));
+        }
+        items.push(item);
+    }
+    if items.is_empty() {
+        return Err(AppError::Validation("Nothing to order".to_string()));
+    }
+
+    let supplier_id = match supplier_id {
+        Some(supplier_id) => supplier_id,
+        None => {
+            let mut suppliers = items.iter().map(|item| item.supplier_id.clone());
+            let first = suppliers.next().flatten();
+            match first {
+                Some(first) if suppliers.all(|other| other.as_deref() == Some(first.as_str())) => first,
+                _ => {
+                    return Err(AppError::Validation(
+                        "Choose a supplier; the selected items don't share one".to_string(),
+                    ))
+                }
+            }
+        }
+    };
+    check_supplier(&tx, &supplier_id)?;
+
+    let now = Utc::now().to_rfc3339();
+    let order = order::insert_order(
+        &tx,
+        Order {
+            id: Uuid::new_v4().to_string(),
+            order_number: String::new(),
+            supplier_id: supplier_id.clone(),
+            status: "draft".to_string(),
+            payment_status: "unpaid".to_string(),
+            total_amount: Money::ZERO,
+            paid_amount: Money::ZERO,
+            notes: Some("Created from the shopping list".to_string()),
+            created_at: now.clone(),
+            updated_at: now.clone(),
+            created_by,
+            location_id,
+        },
+    )?;
+
+    for item in items {
+        let unit_price = match (item.estimated_cost, &item.item_id) {
+            // The estimate is for the whole line
+            (Some(cost), _) => costing::per_unit(cost, item.quantity),
+            (None, Some(item_id)) => match price_list::supplier_price(&tx, &supplier_id, item_id)? {
+                Some(price) => price,
+                None => tx.query_row(
+                    "SELECT buying_price FROM inventory_items WHERE id = ?1",
+                    params![item_id],
+                    |row| row.get(0),
+                )?,
+            },
+            (None, None) => Money::ZERO,
+        };
+        let order_item_id = Uuid::new_v4().to_string();
+        order::insert_order_item(
+            &tx,
+            &OrderItem {
+                id: order_item_id.clone(),
+                order_id: order.id.clone(),
+                item_id: item.item_id.clone(),
+                item_name: item.name.clone(),
+                quantity: item.quantity as i32,
+                unit_price,
+                total_price: unit_price * item.quantity,
+                notes: item.notes.clone(),
+                received_quantity: 0,
+                cancelled_quantity: 0,
+            },
+        )?;
+        tx.execute(
+            "UPDATE shopping_list_items SET status = 'Ordered', supplier_id = ?2, order_id = ?3, order_item_id = ?4,
+                ordered_at = ?5, updated_at = ?5
+             WHERE id = ?1",
+            params![item.id, supplier_id, order.id, order_item_id, now],
+        )?;
+    }
+
+    let total_amount: Money = tx.query_row(
+        "SELECT total_amount FROM orders WHERE id = ?1",
+        params![order.id],
+        |row| row.get(0),
+    )?;
+    tx.commit()?;
+    Ok(Order { total_amount, ..order })
+}
+
+/// Mark the items on an order Received when it is completed, or Ordered again when
+/// a completed order goes back to draft.
+pub(crate) fn sync_order_status(conn: &Connection, order_id: &str, completed: bool) -> Result<(), AppError> {
+    let now = Utc::now().to_rfc3339();
+    if completed {
+        conn.execute(
+            "UPDATE shopping_list_items SET status = 'Received', received_at = ?2, updated_at = ?2
+             WHERE order_id = ?1 AND status = 'Ordered'",
+            params![order_id, now],
+        )?;
+    } else {
+        conn.execute(
+            "UPDATE shopping_list_items SET status = 'Ordered', received_at = NULL, updated_at = ?2
+             WHERE order_id = ?1 AND status = 'Received'",
+            params![order_id, now],
+        )?;
+    }
+    Ok(())
+}
+
+/// An order line was removed: the item it came from goes back to Pending.
+pub(crate) fn release_order_item(conn: &Connection, order_item_id: &str) -> Result<(), AppError> {
+    conn.execute(
+        "UPDATE shopping_list_items SET status = 'Pending', order_id = NULL, order_item_id = NULL,
+            ordered_at = NULL, received_at = NULL, updated_at = ?2
+         WHERE order_item_id = ?1",
+        params![order_item_id, Utc::now().to_rfc3339()],
+    )?;
+    Ok(())
+}
@@ -0,0 +1,370 @@
+use crate::db::costing;
+use crate::db::inventory;
+use crate::db::location;
+use crate::db::models::{StockTake, StockTakeLine, StockTakeVarianceReport, StockTakeWithLines};
+use crate::db::money::Money;
+use crate::db::DbPool;
+use crate::error::AppError;
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use tauri::State;
+use uuid::Uuid;
+
+const STOCK_TAKE_COLUMNS: &str = "s.id, s.name, s.status, s.phone_brand, s.item_type, s.notes, s.created_at, s.created_by, s.approved_at, s.approved_by,
+    (SELECT COUNT(*) FROM stock_take_lines l WHERE l.stock_take_id = s.id),
+    (SELECT COUNT(*) FROM stock_take_lines l WHERE l.stock_take_id = s.id AND l.counted_quantity IS NOT NULL),
+    s.location_id";
+
+/// Map a row selected with `STOCK_TAKE_COLUMNS`.
+fn stock_take_from_row(row: &rusqlite::Row) -> rusqlite::Result<StockTake> {
+    Ok(StockTake {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        status: row.get(2)?,
+        phone_brand: row.get(3)?,
+        item_type: row.get(4)?,
+        notes: row.get(5)?,
+        created_at: row.get(6)?,
+        created_by: row.get(7)?,
+        approved_at: row.get(8)?,
+        approved_by: row.get(9)?,
+        line_count: row.get(10)?,
+        counted_count: row.get(11)?,
+        location_id: row.get(12)?,
+    })
+}
+
+const LINE_COLUMNS: &str = "id, stock_take_id, item_id, item_name, barcode, expected_quantity, counted_quantity, unit_cost, counted_at, counted_by";
+
+/// Map a row selected with `LINE_COLUMNS`.
+fn line_from_row(row: &rusqlite::Row) -> rusqlite::Result<StockTakeLine> {
+    let expected_quantity: i64 = row.get(5)?;
+    let counted_quantity: Option<i64> = row.get(6)?;
+    let unit_cost: Money = row.get(7)?;
+    let variance = counted_quantity.map(|counted| counted - expected_quantity);
+    Ok(StockTakeLine {
+        id: row.get(0)?,
+        stock_take_id: row.get(1)?,
+        item_id: row.get(2)?,
+        item_name: row.get(3)?,
+        barcode: row.get(4)?,
+        expected_quantity,
+        counted_quantity,
+        variance,
+        unit_cost,
+        variance_value: variance.map(|units| unit_cost * units),
+        counted_at: row.get(8)?,
+        counted_by: row.get(9)?,
+    })
+}
+
+fn get_stock_take_internal(conn: &Connection, id: &str) -> Result<StockTake, AppError> {
+    conn.query_row(
+        &format!("SELECT {} FROM stock_takes s WHERE s.id = ?1", STOCK_TAKE_COLUMNS),
+        params![id],
+        stock_take_from_row,
+    )
+    .optional()?
+    .ok_or_else(|| AppError::NotFound(format!("Stock take {} not found", id)))
+}
+
+fn get_lines(conn: &Connection, stock_take_id: &str) -> Result<Vec<StockTakeLine>, AppError> {
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT {} FROM stock_take_lines WHERE stock_take_id = ?1 ORDER BY item_name",
+        LINE_COLUMNS
+    ))?;
+    let rows = stmt.query_map(params![stock_take_id], line_from_row)?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+fn get_line(conn: &Connection, line_id: &str) -> Result<StockTakeLine, AppError> {
+    Ok(conn.query_row(
+        &format!("SELECT {} FROM stock_take_lines WHERE id = ?1", LINE_COLUMNS),
+        params![line_id],
+        line_from_row,
+    )?)
+}
+
+/// Counts can only be recorded and approved while the stock take is open.
+fn ensure_open(stock_take: &StockTake) -> Result<(), AppError> {
+    if stock_take.status != "Open" {
+        return Err(AppError::Conflict(format!(
+            "Stock take '{}' is {} and can no longer be changed",
+            stock_take.name,
+            stock_take.status.to_lowercase()
+        )));
+    }
+    Ok(())
+}
+
+fn set_count(conn: &Connection, line_id: &str, counted_quantity: i64, counted_by: &Option<String>) -> Result<(), AppError> {
+    if counted_quantity < 0 {
+        return Err(AppError::Validation("A counted quantity can't be negative".to_string()));
+    }
+    conn.execute(
+        "UPDATE stock_take_lines SET counted_quantity = ?2, counted_at = ?3, counted_by = ?4 WHERE id = ?1",
+        params![line_id, counted_quantity, Utc::now().to_rfc3339(), counted_by],
+    )?;
+    Ok(())
+}
+
+fn variance_report(stock_take: StockTake, lines: Vec<StockTakeLine>) -> StockTakeVarianceReport {
+    let uncounted_items = lines.iter().filter(|line| line.counted_quantity.is_none()).count() as i64;
+    let lines: Vec<StockTakeLine> = lines
+        .into_iter()
+        .filter(|line| line.variance.unwrap_or(0) != 0)
+        .collect();
+
+    let mut report = StockTakeVarianceReport {
+        stock_take,
+        lines: Vec::new(),
+        uncounted_items,
+        units_over: 0,
+        units_short: 0,
+        value_over: Money::ZERO,
+        value_short: Money::ZERO,
+        net_value: Money::ZERO,
+    };
+    for line in &lines {
+        let units = line.variance.unwrap_or(0);
+        let value = line.variance_value.unwrap_or(Money::ZERO);
+        if units > 0 {
+            report.units_over += units;
+            report.value_over += value;
+        } else {
+            report.units_short -= units;
+            report.value_short -= value;
+        }
+        report.net_value += value;
+    }
+    report.lines = lines;
+    report
+}
+
+// ======================
+// STOCK TAKES
+// ======================
+
+/// Start counting every item at a location (the default one when `None`), or only
+/// those of `phone_brand` and/or `item_type`. Each item's stock there and its cost are
+/// snapshotted now; variances are measured against them.
+#[tauri::command]
+pub fn start_stock_take(
+    pool: State<'_, DbPool>,
+    name: Option<String>,
+    phone_brand: Option<String>,
+    item_type: Option<String>,
+    notes: Option<String>,
+    created_by: Option<String>,
+    location_id: Option<String>,
+) -> Result<StockTakeWithLines, AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    let location_id = location::resolve_location(&tx, location_id.as_deref())?;
+
+    let phone_brand = phone_brand.filter(|brand| !brand.trim().is_empty());
+    let item_type = item_type.filter(|kind| !kind.trim().is_empty());
+    let now = Utc::now().to_rfc3339();
+    let name = name
+        .filter(|name| !name.trim().is_empty())
+        .unwrap_or_else(|| format!("Stock count {}", &now[..10]));
+
+    let items: Vec<(String, String, Option<String>, i64, Money)> = {
+        let mut stmt = tx.prepare_cached(
+            "SELECT i.id, i.item_name, i.barcode, v.quantity, COALESCE(i.average_cost, i.buying_price, 0)
+             FROM inventory_items i
+             JOIN location_stock_levels v ON v.item_id = i.id AND v.location_id = ?3
+             WHERE (?1 IS NULL OR i.phone_brand = ?1) AND (?2 IS NULL OR i.item_type = ?2)
+             AND NOT i.is_bundle
+             ORDER BY i.item_name",
+        )?;
+        let rows = stmt.query_map(params![phone_brand, item_type, location_id], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?))
+        })?;
+        rows.collect::<Result<_, _>>()?
+    };
+    if items.is_empty() {
+        return Err(AppError::Validation("No inventory items match this stock take".to_string()));
+    }
+
+    let id = Uuid::new_v4().to_string();
+    tx.execute(
+        "INSERT INTO stock_takes (id, name, status, phone_brand, item_type, notes, created_at, created_by, location_id)
+         VALUES (?1, ?2, 'Open', ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![id, name.trim(), phone_brand, item_type, notes, now, created_by, location_id],
+    )?;
+    for (item_id, item_name, barcode, expected_quantity, unit_cost) in items {
+        tx.execute(
+            "INSERT INTO stock_take_lines (id, stock_take_id, item_id, item_name, barcode, expected_quantity, unit_cost)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![Uuid::new_v4().to_string(), id, item_id, item_name, barcode, expected_quantity, unit_cost],
+        )?;
+    }
+
+    let stock_take = get_stock_take_internal(&tx, &id)?;
+    let lines = get_lines(&tx, &id)?;
+    tx.commit()?;
+    Ok(StockTakeWithLines { stock_take, lines })
+}
+
+#[tauri::command]
+pub fn get_stock_takes(pool: State<'_, DbPool>, status: Option<String>) -> Result<Vec<StockTake>, AppError> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT {} FROM stock_takes s WHERE ?1 IS NULL OR s.status = ?1 ORDER BY s.created_at DESC",
+        STOCK_TAKE_COLUMNS
+    ))?;
+    let stock_takes = stmt
+        .query_map(params![status], stock_take_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(stock_takes)
+}
+
+#[tauri::command]
+pub fn get_stock_take(pool: State<'_, DbPool>, stock_take_id: String) -> Result<Option<StockTakeWithLines>, AppError> {
+    let conn = pool.get()?;
+    match get_stock_take_internal(&conn, &stock_take_id) {
+        Ok(stock_take) => Ok(Some(StockTakeWithLines {
+            lines: get_lines(&conn, &stock_take_id)?,
+            stock_take,
+        })),
+        Err(AppError::NotFound(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Set how many units of an item were counted, replacing an earlier count.
+#[tauri::command]
+pub fn record_stock_count(
+    pool: State<'_, DbPool>,
+    stock_take_id: String,
+    item_id: String,
+    counted_quantity: i64,
+    counted_by: Option<String>,
+) -> Result<StockTakeLine, AppError> {
+    let conn = pool.get()?;
+    ensure_open(&get_stock_take_internal(&conn, &stock_take_id)?)?;
+
+    let line_id: String = conn
+        .query_row(
+            "SELECT id FROM stock_take_lines WHERE stock_take_id = ?1 AND item_id = ?2",
+            params![stock_take_id, item_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound(format!("Item {} is not part of this stock take", item_id)))?;
+
+    set_count(&conn, &line_id, counted_quantity, &counted_by)?;
+    get_line(&conn, &line_id)
+}
+
+/// Count by barcode: each scan adds `quantity` (1 by default, negative to undo a scan)
+/// to what was counted for the item so far.
+#[tauri::command]
+pub fn scan_stock_count(
+    pool: State<'_, DbPool>,
+    stock_take_id: String,
+    barcode: String,
+    quantity: Option<i64>,
+    counted_by: Option<String>,
+) -> Result<StockTakeLine, AppError> {
+    let conn = pool.get()?;
+    ensure_open(&get_stock_take_internal(&conn, &stock_take_id)?)?;
+    let barcode = barcode.trim();
+
+    let line: Option<(String, Option<i64>)> = conn
+        .query_row(
+            "SELECT id, counted_quantity FROM stock_take_lines WHERE stock_take_id = ?1 AND barcode = ?2",
+            params![stock_take_id, barcode],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    let (line_id, counted) = match line {
+        Some(line) => line,
+        None => {
+            let item_name: Option<String> = conn
+                .query_row(
+                    "SELECT item_name FROM inventory_items WHERE barcode = ?1",
+                    params![barcode],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            return Err(match item_name {
+                Some(name) => AppError::Validation(format!("'{}' is not part of this stock take", name)),
+                None => AppError::NotFound(format!("No item with barcode {}", barcode)),
+            });
+        }
+    };
+
+    set_count(&conn, &line_id, counted.unwrap_or(0) + quantity.unwrap_or(1), &counted_by)?;
+    get_line(&conn, &line_id)
+}
+
+/// Counted lines that differ from the snapshot, valued at the cost snapshotted with them.
+#[tauri::command]
+pub fn get_stock_take_variance_report(
+    pool: State<'_, DbPool>,
+    stock_take_id: String,
+) -> Result<StockTakeVarianceReport, AppError> {
+    let conn = pool.get()?;
+    let stock_take = get_stock_take_internal(&conn, &stock_take_id)?;
+    let lines = get_lines(&conn, &stock_take_id)?;
+    Ok(variance_report(stock_take, lines))
+}
+
+/// Post the variances of the counted items as "Stock Count" inventory events. Each
+/// item moves by its variance, so sales made since the snapshot are kept. Items not
+/// counted are left alone.
+#[tauri::command]
+pub fn approve_stock_take(
+    pool: State<'_, DbPool>,
+    stock_take_id: String,
+    approved_by: Option<String>,
+) -> Result<StockTakeVarianceReport, AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+
+    let stock_take = get_stock_take_internal(&tx, &stock_take_id)?;
+    ensure_open(&stock_take)?;
+
+    for line in get_lines(&tx, &stock_take_id)? {
+        let (counted, variance) = match (line.counted_quantity, line.variance) {
+            (Some(counted), Some(variance)) if variance != 0 => (counted, variance),
+            _ => continue,
+        };
+        // The count is what is on the shelf, even if that dips into reserved stock
+        let location_id = stock_take.location_id.as_deref();
+        inventory::apply_stock_change(&tx, &line.item_id, location_id, variance, true)?;
+        inventory::log_stock_event(
+            &tx,
+            &line.item_id,
+            location_id,
+            "Stock Count",
+            variance,
+            &format!("{}: counted {}, expected {}", stock_take.name, counted, line.expected_quantity),
+            &stock_take_id,
+        )?;
+        costing::adjust_stock(&tx, &line.item_id, variance)?;
+    }
+
+    tx.execute(
+        "UPDATE stock_takes SET status = 'Approved', approved_at = ?2, approved_by = ?3 WHERE id = ?1",
+        params![stock_take_id, Utc::now().to_rfc3339(), approved_by],
+    )?;
+
+    let report = variance_report(get_stock_take_internal(&tx, &stock_take_id)?, get_lines(&tx, &stock_take_id)?);
+    tx.commit()?;
+    Ok(report)
+}
+
+/// Drop an open stock take without touching stock.
+#[tauri::command]
+pub fn cancel_stock_take(pool: State<'_, DbPool>, stock_take_id: String) -> Result<(), AppError> {
+    let conn = pool.get()?;
+    ensure_open(&get_stock_take_internal(&conn, &stock_take_id)?)?;
+    conn.execute(
+        "UPDATE stock_takes SET status = 'Cancelled' WHERE id = ?1",
+        params![stock_take_id],
+    )?;
+    Ok(())
+}
@@ -1,6 +1,10 @@
 // use crate::db::inventory::InventoryHistoryEvent;
 use rusqlite::{params, Result};
 use serde::{Deserialize, Serialize};
+use crate::db::DbPool;
+use crate::db::money::Money;
+use tauri::State;
+use crate::error::AppError;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Supplier {
@@ -12,7 +16,7 @@ pub struct Supplier {
     pub address: Option<String>,
     pub notes: Option<String>,
     pub preferred_payment_method: Option<String>,
-    pub credit_balance: Option<f64>,
+    pub credit_balance: Option<Money>,
     pub active: bool,
     pub created_at: String,
     pub updated_at: String,
@@ -29,18 +33,17 @@ pub struct SupplierFrontend {
     pub address: Option<String>,
     pub notes: Option<String>,
     pub preferred_payment_method: Option<String>,
-    pub outstanding_balance: f64, // Changed name and made non-optional to match frontend
+    pub outstanding_balance: Money, // Changed name and made non-optional to match frontend
     pub status: String, // Changed from boolean to string to match frontend ("active"/"inactive")
     pub created_at: String,
     pub updated_at: String,
 }
 
 #[tauri::command]
-pub fn get_suppliers() -> Result<Vec<SupplierFrontend>, String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+pub fn get_suppliers(pool: State<'_, DbPool>) -> Result<Vec<SupplierFrontend>, AppError> {
+    let conn = pool.get()?;
     let mut stmt = conn
-        .prepare("SELECT id, name, contact_name, email, phone, address, notes, preferred_payment_method, credit_balance, active, created_at, updated_at FROM suppliers")
-        .map_err(|e| e.to_string())?;
+        .prepare_cached("SELECT id, name, contact_name, email, phone, address, notes, preferred_payment_method, credit_balance, active, created_at, updated_at FROM suppliers")?;
     let suppliers = stmt
         .query_map([], |row| {
             // We need to handle errors properly within the closure
@@ -67,7 +70,7 @@ pub fn get_suppliers() -> Result<Vec<SupplierFrontend>, String> {
                     address: row.get(5).ok(),
                     notes: row.get(6).ok(),
                     preferred_payment_method: row.get(7).ok(),
-                    outstanding_balance: row.get(8).unwrap_or(0.0), // Map credit_balance to outstanding_balance
+                    outstanding_balance: row.get(8).unwrap_or(Money::ZERO), // Map credit_balance to outstanding_balance
                     status: if active {
                         "active".to_string()
                     } else {
@@ -80,23 +83,20 @@ pub fn get_suppliers() -> Result<Vec<SupplierFrontend>, String> {
                 // Return an error if we couldn't get required fields
                 Err(rusqlite::Error::InvalidQuery)
             }
-        })
-        .map_err(|e| e.to_string())?
+        })?
         .filter_map(|res| res.ok())
         .collect();
     Ok(suppliers)
 }
 
 #[tauri::command]
-pub fn get_supplier_by_id(supplier_id: String) -> Result<Option<SupplierFrontend>, String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+pub fn get_supplier_by_id(pool: State<'_, DbPool>, supplier_id: String) -> Result<Option<SupplierFrontend>, AppError> {
+    let conn = pool.get()?;
     let mut stmt = conn
-        .prepare("SELECT id, name, contact_name, email, phone, address, notes, preferred_payment_method, credit_balance, active, created_at, updated_at FROM suppliers WHERE id = ?1")
-        .map_err(|e| e.to_string())?;
+        .prepare_cached("SELECT id, name, contact_name, email, phone, address, notes, preferred_payment_method, credit_balance, active, created_at, updated_at FROM suppliers WHERE id = ?1")?;
     let mut rows = stmt
-        .query(params![supplier_id])
-        .map_err(|e| e.to_string())?;
-    if let Some(row) = rows.next().map_err(|e| e.to_string())? {
+        .query(params![supplier_id])?;
+    if let Some(row) = rows.next()? {
         // Handle errors properly within the closure
         let active_result: Result<bool, rusqlite::Error> = row.get(9);
         let id_result: Result<String, rusqlite::Error> = row.get(0);
@@ -121,7 +121,7 @@ pub fn get_supplier_by_id(supplier_id: String) -> Result<Option<SupplierFrontend
                 address: row.get(5).ok(),
                 notes: row.get(6).ok(),
                 preferred_payment_method: row.get(7).ok(),
-                outstanding_balance: row.get(8).unwrap_or(0.0), // Map credit_balance to outstanding_balance
+                outstanding_balance: row.get(8).unwrap_or(Money::ZERO), // Map credit_balance to outstanding_balance
                 status: if active {
                     "active".to_string()
                 } else {
@@ -132,7 +132,7 @@ pub fn get_supplier_by_id(supplier_id: String) -> Result<Option<SupplierFrontend
             }))
         } else {
             // Return an error if we couldn't get required fields
-            Err("Failed to retrieve supplier data".to_string())
+            Err(AppError::Database("Failed to retrieve supplier data".to_string()))
         }
     } else {
         Ok(None)
@@ -140,8 +140,8 @@ pub fn get_supplier_by_id(supplier_id: String) -> Result<Option<SupplierFrontend
 }
 
 #[tauri::command]
-pub fn insert_supplier(supplier: SupplierFrontend) -> Result<(), String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+pub fn insert_supplier(pool: State<'_, DbPool>, supplier: SupplierFrontend) -> Result<(), AppError> {
+    let conn = pool.get()?;
     conn.execute(
         "INSERT INTO suppliers (id, name, contact_name, email, phone, address, notes, preferred_payment_method, credit_balance, active, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
         params![
@@ -158,13 +158,13 @@ pub fn insert_supplier(supplier: SupplierFrontend) -> Result<(), String> {
             supplier.created_at,
             supplier.updated_at
         ],
-    ).map_err(|e| e.to_string())?;
+    )?;
     Ok(())
 }
 
 #[tauri::command]
-pub fn update_supplier(supplier: SupplierFrontend) -> Result<(), String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+pub fn update_supplier(pool: State<'_, DbPool>, supplier: SupplierFrontend) -> Result<(), AppError> {
+    let conn = pool.get()?;
     conn.execute(
         "UPDATE suppliers SET name = ?2, contact_name = ?3, email = ?4, phone = ?5, address = ?6, notes = ?7, preferred_payment_method = ?8, active = ?9, updated_at = ?10 WHERE id = ?1",
         params![
@@ -179,13 +179,13 @@ pub fn update_supplier(supplier: SupplierFrontend) -> Result<(), String> {
             supplier.status == "active", // Map status string to active boolean
             chrono::Utc::now().to_rfc3339() // Use server time for update
         ],
-    ).map_err(|e| e.to_string())?;
+    )?;
     Ok(())
 }
 
 #[tauri::command]
-pub fn delete_supplier(supplier_id: String) -> Result<(), String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+pub fn delete_supplier(pool: State<'_, DbPool>, supplier_id: String) -> Result<(), AppError> {
+    let conn = pool.get()?;
     
     // 1. Check if the supplier has any usage
     // Check orders
@@ -193,28 +193,28 @@ pub fn delete_supplier(supplier_id: String) -> Result<(), String> {
         "SELECT EXISTS(SELECT 1 FROM orders WHERE supplier_id = ?1)",
         params![supplier_id],
         |row| row.get(0)
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     // Check payments
     let has_payments: bool = conn.query_row(
         "SELECT EXISTS(SELECT 1 FROM supplier_payments WHERE supplier_id = ?1)",
         params![supplier_id],
         |row| row.get(0)
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     // Check transactions
     let has_transactions: bool = conn.query_row(
         "SELECT EXISTS(SELECT 1 FROM transactions WHERE party_id = ?1 AND party_type = 'Supplier')",
         params![supplier_id],
         |row| row.get(0)
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     if has_orders || has_payments || has_transactions {
         // 2. SOFT DELETE: Deactivate if used
         conn.execute(
             "UPDATE suppliers SET active = 0, updated_at = ?2 WHERE id = ?1",
             params![supplier_id, chrono::Utc::now().to_rfc3339()]
-        ).map_err(|e| e.to_string())?;
+        )?;
 
         // Log deactivation in history
         let history_id = uuid::Uuid::new_v4().to_string();
@@ -227,13 +227,12 @@ pub fn delete_supplier(supplier_id: String) -> Result<(), String> {
                 chrono::Utc::now().to_rfc3339(),
                 "Supplier Deactivated",
                 "Supplier marked as inactive due to existing financial history",
-                0.0
+                Money::ZERO
             ]
-        ).map_err(|e| e.to_string())?;
+        )?;
     } else {
         // 3. HARD DELETE: Physically remove if unused
-        conn.execute("DELETE FROM suppliers WHERE id = ?1", params![supplier_id])
-            .map_err(|e| e.to_string())?;
+        conn.execute("DELETE FROM suppliers WHERE id = ?1", params![supplier_id])?;
     }
 
     Ok(())
@@ -241,19 +240,19 @@ pub fn delete_supplier(supplier_id: String) -> Result<(), String> {
 
 #[tauri::command]
 pub fn add_supplier_payment(
+    pool: State<'_, DbPool>,
     id: String,
     supplier_id: String,
-    amount: f64,
+    amount: Money,
     method: String,
     notes: Option<String>,
     session_id: Option<String>,
-) -> Result<(), String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+) -> Result<(), AppError> {
+    let conn = pool.get()?;
     conn.execute(
         "INSERT INTO supplier_payments (id, supplier_id, amount, method, date, notes, session_id) VALUES (?1, ?2, ?3, ?4, datetime('now'), ?5, ?6)",
         params![id, supplier_id, amount, method, notes, session_id],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
 
     // Update balance
     conn.execute(
@@ -272,21 +271,21 @@ pub fn add_supplier_payment(
 }
 
 #[tauri::command]
-pub fn update_supplier_payment(id: String, amount: f64, method: String) -> Result<(), String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+pub fn update_supplier_payment(pool: State<'_, DbPool>, id: String, amount: Money, method: String) -> Result<(), AppError> {
+    let conn = pool.get()?;
 
     // Get old info
-    let (supplier_id, old_amount): (String, f64) = conn.query_row(
+    let (supplier_id, old_amount): (String, Money) = conn.query_row(
         "SELECT supplier_id, amount FROM supplier_payments WHERE id = ?1",
         params![id],
         |row| Ok((row.get(0)?, row.get(1)?))
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     // Update record
     conn.execute(
         "UPDATE supplier_payments SET amount = ?1, method = ?2 WHERE id = ?3",
         params![amount, method, id],
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     // Adjust balance: Refund old, apply new
     let balance_adj = old_amount - amount;
@@ -306,18 +305,18 @@ pub fn update_supplier_payment(id: String, amount: f64, method: String) -> Resul
 }
 
 #[tauri::command]
-pub fn delete_supplier_payment(id: String) -> Result<(), String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+pub fn delete_supplier_payment(pool: State<'_, DbPool>, id: String) -> Result<(), AppError> {
+    let conn = pool.get()?;
 
     // Get info
-    let (supplier_id, amount): (String, f64) = conn.query_row(
+    let (supplier_id, amount): (String, Money) = conn.query_row(
         "SELECT supplier_id, amount FROM supplier_payments WHERE id = ?1",
         params![id],
         |row| Ok((row.get(0)?, row.get(1)?))
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     // Delete record
-    conn.execute("DELETE FROM supplier_payments WHERE id = ?1", params![id]).map_err(|e| e.to_string())?;
+    conn.execute("DELETE FROM supplier_payments WHERE id = ?1", params![id])?;
 
     // Reverse balance: Refund everything
     conn.execute(
@@ -337,21 +336,21 @@ pub fn delete_supplier_payment(id: String) -> Result<(), String> {
 
 #[tauri::command]
 pub fn adjust_supplier_credit(
+    pool: State<'_, DbPool>,
     supplier_id: String,
-    amount: f64,
+    amount: Money,
     notes: Option<String>,
-) -> Result<(), String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+) -> Result<(), AppError> {
+    let conn = pool.get()?;
 
     // Update the credit balance in the database atomically
     conn.execute(
         "UPDATE suppliers SET credit_balance = COALESCE(credit_balance, 0) + ?1 WHERE id = ?2",
         params![amount, supplier_id],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
 
     // Log the manual adjustment in history if notes are provided or amount is significant
-    if amount.abs() > 0.001 {
+    if !amount.is_zero() {
         let history_id = uuid::Uuid::new_v4().to_string();
         conn.execute(
             "INSERT INTO supplier_history (id, supplier_id, date, type, notes, amount, changed_by)
@@ -365,8 +364,7 @@ pub fn adjust_supplier_credit(
                 amount,
                 None::<String>,
             ],
-        )
-        .map_err(|e| e.to_string())?;
+        )?;
     }
 
     Ok(())
@@ -375,11 +373,10 @@ pub fn adjust_supplier_credit(
 use crate::db::models::SupplierHistoryEvent;
 
 #[tauri::command]
-pub fn get_supplier_history(supplier_id: String) -> Result<Vec<SupplierHistoryEvent>, String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+pub fn get_supplier_history(pool: State<'_, DbPool>, supplier_id: String) -> Result<Vec<SupplierHistoryEvent>, AppError> {
+    let conn = pool.get()?;
     let mut stmt = conn
-        .prepare("SELECT id, supplier_id, date, type, notes, amount, changed_by FROM supplier_history WHERE supplier_id = ?1 ORDER BY date DESC")
-        .map_err(|e| e.to_string())?;
+        .prepare_cached("SELECT id, supplier_id, date, type, notes, amount, changed_by FROM supplier_history WHERE supplier_id = ?1 ORDER BY date DESC")?;
     let history = stmt
         .query_map(params![supplier_id], |row| {
             Ok(SupplierHistoryEvent {
@@ -388,11 +385,10 @@ pub fn get_supplier_history(supplier_id: String) -> Result<Vec<SupplierHistoryEv
                 date: row.get(2)?,
                 event_type: row.get(3)?,
                 notes: row.get(4).ok(),
-                amount: row.get::<_, f64>(5).unwrap_or(0.0),
+                amount: row.get::<_, Money>(5).unwrap_or(Money::ZERO),
                 changed_by: row.get(6).ok(),
             })
-        })
-        .map_err(|e| e.to_string())?
+        })?
         .filter_map(|res| res.ok())
         .collect();
     Ok(history)
@@ -400,8 +396,8 @@ pub fn get_supplier_history(supplier_id: String) -> Result<Vec<SupplierHistoryEv
 
 // Add the insert_supplier_history function
 #[tauri::command]
-pub fn insert_supplier_history(event: SupplierHistoryEvent) -> Result<(), String> {
-    let conn = crate::db::get_connection().map_err(|e| e.to_string())?;
+pub fn insert_supplier_history(pool: State<'_, DbPool>, event: SupplierHistoryEvent) -> Result<(), AppError> {
+    let conn = pool.get()?;
     conn.execute(
         "INSERT INTO supplier_history (id, supplier_id, date, type, notes, amount, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
         params![
@@ -413,6 +409,6 @@ pub fn insert_supplier_history(event: SupplierHistoryEvent) -> Result<(), String
             event.amount,
             event.changed_by,
         ],
-    ).map_err(|e| e.to_string())?;
+    )?;
     Ok(())
 }
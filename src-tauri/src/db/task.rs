@@ -1,16 +1,17 @@
-use crate::db;
+use crate::db::DbPool;
 use crate::db::models::Task;
 use rusqlite::{params, Result};
 use uuid::Uuid;
 use chrono::Utc;
+use tauri::State;
+use crate::error::AppError;
 
 #[tauri::command]
-pub fn get_tasks() -> Result<Vec<Task>, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn get_tasks(pool: State<'_, DbPool>) -> Result<Vec<Task>, AppError> {
+    let conn = pool.get()?;
     
     let mut stmt = conn
-        .prepare("SELECT id, title, description, priority, status, due_date, created_at, updated_at FROM tasks ORDER BY created_at DESC")
-        .map_err(|e| e.to_string())?;
+        .prepare_cached("SELECT id, title, description, priority, status, due_date, created_at, updated_at FROM tasks ORDER BY created_at DESC")?;
 
     let tasks = stmt
         .query_map([], |row| {
@@ -24,8 +25,7 @@ pub fn get_tasks() -> Result<Vec<Task>, String> {
                 created_at: row.get(6)?,
                 updated_at: row.get(7)?,
             })
-        })
-        .map_err(|e| e.to_string())?
+        })?
         .filter_map(|res| res.ok())
         .collect();
 
@@ -33,8 +33,8 @@ pub fn get_tasks() -> Result<Vec<Task>, String> {
 }
 
 #[tauri::command]
-pub fn insert_task(mut task: Task) -> Result<Task, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn insert_task(pool: State<'_, DbPool>, mut task: Task) -> Result<Task, AppError> {
+    let conn = pool.get()?;
 
     if task.id.is_empty() {
         task.id = Uuid::new_v4().to_string();
@@ -57,15 +57,14 @@ pub fn insert_task(mut task: Task) -> Result<Task, String> {
             task.created_at,
             task.updated_at,
         ],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
 
     Ok(task)
 }
 
 #[tauri::command]
-pub fn update_task(mut task: Task) -> Result<Task, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn update_task(pool: State<'_, DbPool>, mut task: Task) -> Result<Task, AppError> {
+    let conn = pool.get()?;
 
     task.updated_at = Utc::now().to_rfc3339();
 
@@ -80,18 +79,16 @@ pub fn update_task(mut task: Task) -> Result<Task, String> {
             task.updated_at,
             task.id,
         ],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
 
     Ok(task)
 }
 
 #[tauri::command]
-pub fn delete_task(id: String) -> Result<(), String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn delete_task(pool: State<'_, DbPool>, id: String) -> Result<(), AppError> {
+    let conn = pool.get()?;
 
-    conn.execute("DELETE FROM tasks WHERE id = ?1", params![id])
-        .map_err(|e| e.to_string())?;
+    conn.execute("DELETE FROM tasks WHERE id = ?1", params![id])?;
 
     Ok(())
 }
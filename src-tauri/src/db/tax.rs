@@ -0,0 +1,381 @@
+use crate::db::discount::discount_amount;
+use crate::db::models::{Repair, TaxLine, TaxRate, TaxSummaryRow, TransactionItem};
+use crate::db::money::Money;
+use crate::db::DbPool;
+use crate::error::AppError;
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use tauri::State;
+use uuid::Uuid;
+
+/// A line amount split into net and tax.
+pub(crate) struct LineTax {
+    pub net: Money,
+    pub tax: Money,
+    pub total: Money,
+}
+
+/// Split `amount` (price times quantity as entered, less discounts) at `rate` percent. An inclusive
+/// amount already holds the tax; an exclusive one has it added on top.
+pub(crate) fn compute_line_tax(amount: Money, rate: f64, inclusive: bool) -> LineTax {
+    if rate <= 0.0 {
+        return LineTax {
+            net: amount,
+            tax: Money::ZERO,
+            total: amount,
+        };
+    }
+    let minor = amount.minor() as f64;
+    if inclusive {
+        let tax = Money::from_minor((minor * rate / (100.0 + rate)).round() as i64);
+        LineTax {
+            net: amount - tax,
+            tax,
+            total: amount,
+        }
+    } else {
+        let tax = Money::from_minor((minor * rate / 100.0).round() as i64);
+        LineTax {
+            net: amount,
+            tax,
+            total: amount + tax,
+        }
+    }
+}
+
+fn tax_rate_from_row(row: &rusqlite::Row) -> rusqlite::Result<TaxRate> {
+    Ok(TaxRate {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        rate: row.get(2)?,
+        inclusive: row.get(3)?,
+        active: row.get(4)?,
+        created_at: row.get(5)?,
+    })
+}
+
+fn get_tax_rate_internal(conn: &Connection, id: &str) -> Result<TaxRate, AppError> {
+    conn.query_row(
+        "SELECT id, name, rate, inclusive, active, created_at FROM tax_rates WHERE id = ?1",
+        params![id],
+        tax_rate_from_row,
+    )
+    .optional()?
+    .ok_or_else(|| AppError::NotFound(format!("Tax rate {} not found", id)))
+}
+
+/// Copy the rate onto a transaction line: the line's own `tax_rate_id` (ad-hoc lines)
+/// or else the rate of its inventory item. Lines without a rate are untaxed.
+pub(crate) fn resolve_item_tax(conn: &Connection, item: &mut TransactionItem) -> Result<(), AppError> {
+    let rate_id = match (&item.tax_rate_id, &item.item_id) {
+        (Some(id), _) => Some(id.clone()),
+        (None, Some(item_id)) => conn
+            .query_row(
+                "SELECT tax_rate_id FROM inventory_items WHERE id = ?1",
+                params![item_id],
+                |row| row.get::<_, Option<String>>(0),
+            )
+            .optional()?
+            .flatten(),
+        (None, None) => None,
+    };
+
+    match rate_id {
+        Some(id) => {
+            let rate = get_tax_rate_internal(conn, &id)?;
+            item.tax_rate_id = Some(rate.id);
+            item.tax_rate = rate.rate;
+            item.tax_inclusive = rate.inclusive;
+        }
+        None => {
+            item.tax_rate_id = None;
+            item.tax_rate = 0.0;
+            item.tax_inclusive = true;
+        }
+    }
+    compute_item_tax(item);
+    Ok(())
+}
+
+/// Work out net, tax and total of a line from the rate already copied onto it, after
+/// its discounts.
+pub(crate) fn compute_item_tax(item: &mut TransactionItem) {
+    let amount = item.unit_price * item.quantity as i64 - item.discount_amount - item.document_discount_amount;
+    let line = compute_line_tax(amount, item.tax_rate, item.tax_inclusive);
+    item.net_amount = line.net;
+    item.tax_amount = line.tax;
+    item.total_price = line.total;
+}
+
+/// Discount the labor of a repair (its `estimated_cost`), tax the rest at the repair's
+/// own rate or else the default labor rate, and set what the customer owes.
+pub(crate) fn apply_repair_tax(conn: &Connection, repair: &mut Repair) -> Result<(), AppError> {
+    let rate_id = match &repair.tax_rate_id {
+        Some(id) => Some(id.clone()),
+        None => default_labor_tax_rate_id(conn)?,
+    };
+
+    match rate_id {
+        Some(id) => {
+            let rate = get_tax_rate_internal(conn, &id)?;
+            repair.tax_rate_id = Some(rate.id);
+            repair.tax_rate = rate.rate;
+            repair.tax_inclusive = rate.inclusive;
+        }
+        None => {
+            repair.tax_rate_id = None;
+            repair.tax_rate = 0.0;
+            repair.tax_inclusive = true;
+        }
+    }
+    repair.discount_amount = discount_amount(repair.discount.as_ref(), repair.estimated_cost)?;
+    let line = compute_line_tax(
+        repair.estimated_cost - repair.discount_amount,
+        repair.tax_rate,
+        repair.tax_inclusive,
+    );
+    repair.tax_amount = line.tax;
+    repair.total_amount = line.total;
+    Ok(())
+}
+
+fn default_labor_tax_rate_id(conn: &Connection) -> Result<Option<String>, AppError> {
+    let id = conn
+        .query_row(
+            "SELECT value FROM app_settings WHERE key = 'labor_tax_rate_id'",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(id)
+}
+
+/// Tax of a transaction per rate. Untaxed lines are left out.
+pub(crate) fn transaction_tax_lines(conn: &Connection, transaction_id: &str) -> Result<Vec<TaxLine>, AppError> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT i.tax_rate_id, COALESCE(r.name, 'Tax'), i.tax_rate, SUM(i.net_amount), SUM(i.tax_amount)
+         FROM transaction_items i
+         LEFT JOIN tax_rates r ON r.id = i.tax_rate_id
+         WHERE i.transaction_id = ?1 AND i.tax_rate_id IS NOT NULL
+         GROUP BY i.tax_rate_id, i.tax_rate
+         ORDER BY i.tax_rate DESC",
+    )?;
+    let lines = stmt
+        .query_map(params![transaction_id], |row| {
+            Ok(TaxLine {
+                tax_rate_id: row.get(0)?,
+                name: row.get(1)?,
+                rate: row.get(2)?,
+                net_amount: row.get(3)?,
+                tax_amount: row.get(4)?,
+            })
+        })?
+        .filter_map(|res| res.ok())
+        .collect();
+    Ok(lines)
+}
+
+// ======================
+// TAX RATES
+// ======================
+
+#[tauri::command]
+pub fn get_tax_rates(pool: State<'_, DbPool>) -> Result<Vec<TaxRate>, AppError> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare_cached(
+        "SELECT id, name, rate, inclusive, active, created_at FROM tax_rates ORDER BY active DESC, rate DESC, name",
+    )?;
+    let rates = stmt
+        .query_map([], tax_rate_from_row)?
+        .filter_map(|res| res.ok())
+        .collect();
+    Ok(rates)
+}
+
+/// Create a tax rate (empty `id`) or update one. Lines already taxed keep the rate
+/// and mode they were taxed with.
+#[tauri::command]
+pub fn save_tax_rate(pool: State<'_, DbPool>, mut tax_rate: TaxRate) -> Result<TaxRate, AppError> {
+    let conn = pool.get()?;
+    tax_rate.name = tax_rate.name.trim().to_string();
+    if tax_rate.name.is_empty() {
+        return Err(AppError::Validation("Tax rate name is required".to_string()));
+    }
+    if !tax_rate.rate.is_finite() || tax_rate.rate < 0.0 || tax_rate.rate > 100.0 {
+        return Err(AppError::Validation("Tax rate must be between 0 and 100 percent".to_string()));
+    }
+    if tax_rate.id.is_empty() {
+        tax_rate.id = Uuid::new_v4().to_string();
+    }
+    if tax_rate.created_at.is_empty() {
+        tax_rate.created_at = Utc::now().to_rfc3339();
+    }
+
+    conn.execute(
+        "INSERT INTO tax_rates (id, name, rate, inclusive, active, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+         ON CONFLICT(id) DO UPDATE SET name = ?2, rate = ?3, inclusive = ?4, active = ?5",
+        params![
+            tax_rate.id,
+            tax_rate.name,
+            tax_rate.rate,
+            tax_rate.inclusive,
+            tax_rate.active,
+            tax_rate.created_at,
+        ],
+    )?;
+    Ok(tax_rate)
+}
+
+/// Delete a tax rate nothing refers to. Rates used by items or documents can only be
+/// deactivated.
+#[tauri::command]
+pub fn delete_tax_rate(pool: State<'_, DbPool>, id: String) -> Result<(), AppError> {
+    let conn = pool.get()?;
+    let in_use: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM inventory_items WHERE tax_rate_id = ?1)
+             OR EXISTS(SELECT 1 FROM transaction_items WHERE tax_rate_id = ?1)
+             OR EXISTS(SELECT 1 FROM repairs WHERE tax_rate_id = ?1)",
+        params![id],
+        |row| row.get(0),
+    )?;
+    if in_use {
+        return Err(AppError::Conflict(
+            "This tax rate is in use; deactivate it instead".to_string(),
+        ));
+    }
+
+    conn.execute(
+        "DELETE FROM app_settings WHERE key = 'labor_tax_rate_id' AND value = ?1",
+        params![id],
+    )?;
+    conn.execute("DELETE FROM tax_rates WHERE id = ?1", params![id])?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_default_labor_tax_rate(pool: State<'_, DbPool>) -> Result<Option<TaxRate>, AppError> {
+    let conn = pool.get()?;
+    match default_labor_tax_rate_id(&conn)? {
+        Some(id) => Ok(Some(get_tax_rate_internal(&conn, &id)?)),
+        None => Ok(None),
+    }
+}
+
+/// Rate applied to repair labor when a repair doesn't name one. `None` leaves labor untaxed.
+#[tauri::command]
+pub fn set_default_labor_tax_rate(pool: State<'_, DbPool>, tax_rate_id: Option<String>) -> Result<(), AppError> {
+    let conn = pool.get()?;
+    match tax_rate_id {
+        Some(id) => {
+            get_tax_rate_internal(&conn, &id)?;
+            conn.execute(
+                "INSERT OR REPLACE INTO app_settings (key, value) VALUES ('labor_tax_rate_id', ?1)",
+                params![id],
+            )?;
+        }
+        None => {
+            conn.execute("DELETE FROM app_settings WHERE key = 'labor_tax_rate_id'", [])?;
+        }
+    }
+    Ok(())
+}
+
+// ======================
+// REPORTS
+// ======================
+
+/// Tax per rate for a period, for filing. Counts completed transactions by creation
+/// date and completed or delivered repairs by their last update, like the dashboard.
+#[tauri::command]
+pub fn get_tax_summary(
+    pool: State<'_, DbPool>,
+    start_date: String,
+    end_date: String,
+) -> Result<Vec<TaxSummaryRow>, AppError> {
+    let conn = pool.get()?;
+    // A bare end date covers the whole day
+    let end_date = if end_date.len() == 10 {
+        format!("{}T23:59:59.999", end_date)
+    } else {
+        end_date
+    };
+
+    let mut stmt = conn.prepare_cached(
+        "SELECT s.tax_rate_id, COALESCE(r.name, 'Tax'), s.tax_rate,
+                SUM(CASE WHEN s.side = 'sales' THEN s.sign * s.net ELSE 0 END),
+                SUM(CASE WHEN s.side = 'sales' THEN s.sign * s.tax ELSE 0 END),
+                SUM(CASE WHEN s.side = 'purchases' THEN s.sign * s.net ELSE 0 END),
+                SUM(CASE WHEN s.side = 'purchases' THEN s.sign * s.tax ELSE 0 END)
+         FROM (
+             SELECT i.tax_rate_id, i.tax_rate, i.net_amount AS net, i.tax_amount AS tax,
+                    CASE WHEN t.transaction_type IN ('Sale', 'Return') THEN 'sales' ELSE 'purchases' END AS side,
+                    CASE WHEN t.transaction_type IN ('Return', 'PurchaseReturn') THEN -1 ELSE 1 END AS sign
+             FROM transaction_items i
+             JOIN transactions t ON i.transaction_id = t.id
+             WHERE t.status = 'Completed' AND i.tax_rate_id IS NOT NULL
+               AND REPLACE(t.created_at, ' ', 'T') >= ?1 AND REPLACE(t.created_at, ' ', 'T') <= ?2
+             UNION ALL
+             SELECT tax_rate_id, tax_rate, total_amount - tax_amount, tax_amount, 'sales', 1
+             FROM repairs
+             WHERE status IN (SELECT name FROM repair_statuses WHERE kind = 'Done') AND tax_rate_id IS NOT NULL
+               AND REPLACE(updated_at, ' ', 'T') >= ?1 AND REPLACE(updated_at, ' ', 'T') <= ?2
+         ) s
+         LEFT JOIN tax_rates r ON r.id = s.tax_rate_id
+         GROUP BY s.tax_rate_id, s.tax_rate
+         ORDER BY s.tax_rate DESC, 2",
+    )?;
+    let rows = stmt
+        .query_map(params![start_date, end_date], |row| {
+            let sales_tax: Money = row.get(4)?;
+            let purchases_tax: Money = row.get(6)?;
+            Ok(TaxSummaryRow {
+                tax_rate_id: row.get(0)?,
+                name: row.get(1)?,
+                rate: row.get(2)?,
+                sales_net: row.get(3)?,
+                sales_tax,
+                purchases_net: row.get(5)?,
+                purchases_tax,
+                net_tax: sales_tax - purchases_tax,
+            })
+        })?
+        .filter_map(|res| res.ok())
+        .collect();
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(minor: i64, rate: f64, inclusive: bool) -> (i64, i64, i64) {
+        let line = compute_line_tax(Money::from_minor(minor), rate, inclusive);
+        (line.net.minor(), line.tax.minor(), line.total.minor())
+    }
+
+    #[test]
+    fn inclusive_amounts_already_hold_the_tax() {
+        assert_eq!(split(11000, 10.0, true), (10000, 1000, 11000));
+    }
+
+    #[test]
+    fn exclusive_amounts_get_the_tax_on_top() {
+        assert_eq!(split(10000, 10.0, false), (10000, 1000, 11000));
+    }
+
+    #[test]
+    fn tax_rounds_to_the_nearest_minor_unit() {
+        // 1999 * 7.5% = 149.925
+        assert_eq!(split(1999, 7.5, false), (1999, 150, 2149));
+        // 1999 * 7.5 / 107.5 = 139.46..., and net plus tax still make up the amount
+        assert_eq!(split(1999, 7.5, true), (1860, 139, 1999));
+        // Refund lines round the same way
+        assert_eq!(split(-1999, 7.5, false), (-1999, -150, -2149));
+    }
+
+    #[test]
+    fn a_zero_rate_leaves_the_amount_untaxed() {
+        assert_eq!(split(1234, 0.0, true), (1234, 0, 1234));
+        assert_eq!(split(1234, 0.0, false), (1234, 0, 1234));
+    }
+}
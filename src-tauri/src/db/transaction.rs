@@ -1,25 +1,256 @@
-use crate::db;
-use crate::db::models::{Transaction, TransactionItem, TransactionPayment, TransactionWithDetails};
+use crate::db::costing;
+use crate::db::currency::{edited_amount, tendered_amount};
+use crate::db::discount::{apply_discounts, discount_columns, discount_from_row};
+use crate::db::inventory;
+use crate::db::location;
+use crate::db::price_list;
+use crate::db::reservation;
+use crate::db::serial::{self, SerialSource};
+use crate::db::{contains_pattern, DbPool};
+use crate::db::money::{currency, Money};
+use crate::db::tax::{compute_item_tax, resolve_item_tax, transaction_tax_lines};
+use crate::db::models::{
+    Discount, ReturnItemRequest, Transaction, TransactionItem, TransactionPage, TransactionPayment,
+    TransactionQuery, TransactionWithDetails,
+};
 use chrono::Utc;
-use rusqlite::{params, Connection, Result};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Result};
 use uuid::Uuid;
+use tauri::State;
+use crate::error::AppError;
+
+const TRANSACTION_COLUMNS: &str = "id, transaction_number, transaction_type, party_id, party_type, status, payment_status, total_amount, paid_amount, notes, created_at, updated_at, created_by, original_transaction_id, subtotal, tax_amount, discount_type, discount_value, discount_reason, discount_by, discount_amount, location_id";
+
+/// Map a row selected with `TRANSACTION_COLUMNS`.
+fn transaction_from_row(row: &rusqlite::Row) -> rusqlite::Result<Transaction> {
+    Ok(Transaction {
+        id: row.get(0)?,
+        transaction_number: row.get(1)?,
+        transaction_type: row.get(2)?,
+        party_id: row.get(3)?,
+        party_type: row.get(4)?,
+        status: row.get(5)?,
+        payment_status: row.get(6)?,
+        total_amount: row.get(7)?,
+        paid_amount: row.get(8)?,
+        notes: row.get(9).ok(),
+        created_at: row.get(10)?,
+        updated_at: row.get(11)?,
+        created_by: row.get(12).ok(),
+        original_transaction_id: row.get(13).ok(),
+        subtotal: row.get(14)?,
+        tax_amount: row.get(15)?,
+        discount: discount_from_row(row, 16)?,
+        discount_amount: row.get(20)?,
+        location_id: row.get(21)?,
+    })
+}
 
-/// Generate a unique transaction number (e.g., TX-2025-001)
-fn generate_transaction_number(tx_type: &str) -> Result<String, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
-    generate_transaction_number_internal(&conn, tx_type)
+const ITEM_COLUMNS: &str = "id, transaction_id, item_id, item_name, quantity, unit_price, total_price, notes, original_item_id, tax_rate_id, tax_rate, tax_inclusive, net_amount, tax_amount, discount_type, discount_value, discount_reason, discount_by, discount_amount, document_discount_amount, unit_cost";
+
+/// Map a row selected with `ITEM_COLUMNS`.
+fn item_from_row(row: &rusqlite::Row) -> rusqlite::Result<TransactionItem> {
+    Ok(TransactionItem {
+        id: row.get(0)?,
+        transaction_id: row.get(1)?,
+        item_id: row.get(2).ok(),
+        item_name: row.get(3)?,
+        quantity: row.get(4)?,
+        unit_price: row.get(5)?,
+        total_price: row.get(6)?,
+        notes: row.get(7).ok(),
+        original_item_id: row.get(8).ok(),
+        tax_rate_id: row.get(9)?,
+        tax_rate: row.get(10)?,
+        tax_inclusive: row.get(11)?,
+        net_amount: row.get(12)?,
+        tax_amount: row.get(13)?,
+        discount: discount_from_row(row, 14)?,
+        discount_amount: row.get(18)?,
+        document_discount_amount: row.get(19)?,
+        unit_cost: row.get(20)?,
+        serial_numbers: Vec::new(),
+    })
+}
+
+/// Insert a line whose discounts and tax have already been worked out.
+fn insert_item(conn: &Connection, item: &TransactionItem) -> Result<(), AppError> {
+    let (discount_type, discount_value, discount_reason, discount_by) = discount_columns(&item.discount);
+    conn.execute(
+        &format!(
+            "INSERT INTO transaction_items ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21)",
+            ITEM_COLUMNS
+        ),
+        params![
+            item.id,
+            item.transaction_id,
+            item.item_id,
+            item.item_name,
+            item.quantity,
+            item.unit_price,
+            item.total_price,
+            item.notes,
+            item.original_item_id,
+            item.tax_rate_id,
+            item.tax_rate,
+            item.tax_inclusive,
+            item.net_amount,
+            item.tax_amount,
+            discount_type,
+            discount_value,
+            discount_reason,
+            discount_by,
+            item.discount_amount,
+            item.document_discount_amount,
+            item.unit_cost,
+        ],
+    )?;
+    serial::set_line_serials(conn, &item.id, item.item_id.as_deref(), item.quantity as i64, &item.serial_numbers)?;
+    Ok(())
+}
+
+/// Discount and tax every line and set the document totals from them.
+fn apply_items_tax(conn: &Connection, transaction: &mut Transaction, items: &mut [TransactionItem]) -> Result<(), AppError> {
+    for item in items.iter_mut() {
+        resolve_item_tax(conn, item)?;
+    }
+    transaction.discount_amount = apply_discounts(transaction.discount.as_ref(), items)?;
+    transaction.subtotal = items.iter().map(|item| item.net_amount).sum();
+    transaction.tax_amount = items.iter().map(|item| item.tax_amount).sum();
+    transaction.total_amount = items.iter().map(|item| item.total_price).sum();
+    Ok(())
+}
+
+/// Hold stock for the lines of a draft sale, so it can't be sold twice before the draft
+/// is completed. Other documents don't reserve.
+fn reserve_draft_lines(
+    conn: &Connection,
+    transaction: &Transaction,
+    items: &[TransactionItem],
+    allow_oversell: bool,
+) -> Result<(), AppError> {
+    if transaction.transaction_type != "Sale" || transaction.status != "Draft" {
+        return Ok(());
+    }
+    for item in items {
+        if let Some(item_id) = &item.item_id {
+            reservation::reserve_stock(
+                conn,
+                item_id,
+                item.quantity as i64,
+                "Transaction",
+                &transaction.id,
+                &item.id,
+                allow_oversell,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Move the cost layers with a line's stock, once the stock itself has moved. `reverse`
+/// undoes the movement the document made when it took effect. Sales and purchases
+/// record what one unit cost on the line; returns reuse the cost of the line they undo.
+fn cost_stock_movement(
+    conn: &Connection,
+    transaction: &Transaction,
+    item: &TransactionItem,
+    reverse: bool,
+) -> Result<(), AppError> {
+    let item_id = match &item.item_id {
+        Some(item_id) => item_id,
+        None => return Ok(()),
+    };
+    let qty = item.quantity as i64;
+    let line_cost = match item.unit_cost {
+        Some(cost) => cost,
+        None => costing::average_cost(conn, item_id)?,
+    };
+
+    match (transaction.transaction_type.as_str(), reverse) {
+        ("Sale", false) => {
+            let unit_cost = costing::issue_stock(conn, item_id, qty)?;
+            conn.execute(
+                "UPDATE transaction_items SET unit_cost = ?2 WHERE id = ?1",
+                params![item.id, unit_cost],
+            )?;
+        }
+        ("Sale", true) | ("PurchaseReturn", true) => {
+            costing::receive_stock(conn, item_id, qty, line_cost, "Restock", &transaction.id)?;
+        }
+        ("Return", false) => {
+            costing::receive_stock(conn, item_id, qty, line_cost, "Return", &transaction.id)?;
+        }
+        ("PurchaseReturn", false) => {
+            let purchase_id = transaction.original_transaction_id.as_deref().unwrap_or(&transaction.id);
+            costing::reverse_receipt(conn, item_id, qty, purchase_id)?;
+        }
+        (_, false) => {
+            let unit_cost = costing::per_unit(item.net_amount, qty);
+            costing::receive_stock(conn, item_id, qty, unit_cost, "Purchase", &transaction.id)?;
+            price_list::record_purchase(conn, &transaction.party_id, item_id, unit_cost)?;
+            conn.execute(
+                "UPDATE transaction_items SET unit_cost = ?2 WHERE id = ?1",
+                params![item.id, unit_cost],
+            )?;
+        }
+        (_, true) => {
+            costing::reverse_receipt(conn, item_id, qty, &transaction.id)?;
+        }
+    }
+    Ok(())
 }
 
+/// Move the serialized units of a line with its stock: sold units leave the shelf,
+/// purchased ones arrive, customer returns come back as "Returned" and purchase returns
+/// go to the supplier as "RMA". `reverse` puts the units back where they were.
+fn move_line_serials(
+    conn: &Connection,
+    transaction: &Transaction,
+    item: &TransactionItem,
+    reverse: bool,
+) -> Result<(), AppError> {
+    let item_id = match &item.item_id {
+        Some(item_id) => item_id,
+        None => return Ok(()),
+    };
+    if reverse {
+        return serial::reverse_line_units(
+            conn,
+            &item.id,
+            "Reversed",
+            &format!("{} {} reversed", transaction.transaction_type, transaction.transaction_number),
+        );
+    }
+
+    let source = SerialSource::line("Transaction", &transaction.id, &item.id, &transaction.transaction_number);
+    let qty = item.quantity as i64;
+    match transaction.transaction_type.as_str() {
+        "Sale" => serial::move_line_units(conn, item_id, qty, &serial::ON_HAND, "Sold", "Sold", &source),
+        "Return" => serial::move_line_units(conn, item_id, qty, &["Sold"], "Returned", "Returned", &source),
+        "PurchaseReturn" => {
+            serial::move_line_units(conn, item_id, qty, &serial::ON_HAND, "RMA", "Returned to Supplier", &source)
+        }
+        _ => serial::receive_line_units(conn, item_id, qty, "Purchased", &source),
+    }
+}
+
+/// Generate a unique transaction number (e.g., TX-2025-001)
 fn generate_transaction_number_internal(
     conn: &Connection,
     tx_type: &str,
-) -> Result<String, String> {
+) -> Result<String, AppError> {
     let year = Utc::now().format("%Y").to_string();
-    let prefix = if tx_type == "Sale" { "SALE" } else { "PUR" };
+    let prefix = match tx_type {
+        "Sale" => "SALE",
+        "Return" => "RET",
+        "PurchaseReturn" => "PRET",
+        _ => "PUR",
+    };
 
     let mut stmt = conn
-        .prepare("SELECT transaction_number FROM transactions WHERE transaction_number LIKE ?1 ORDER BY transaction_number DESC LIMIT 1")
-        .map_err(|e| e.to_string())?;
+        .prepare_cached("SELECT transaction_number FROM transactions WHERE transaction_number LIKE ?1 ORDER BY transaction_number DESC LIMIT 1")?;
 
     let next_number = match stmt.query_row(params![format!("{}-{}-%", prefix, year)], |row| {
         let tx_num: String = row.get(0)?;
@@ -43,17 +274,20 @@ fn generate_transaction_number_internal(
 }
 
 #[tauri::command]
-pub fn create_transaction(mut transaction: Transaction) -> Result<Transaction, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn create_transaction(pool: State<'_, DbPool>, mut transaction: Transaction) -> Result<Transaction, AppError> {
+    let conn = pool.get()?;
+    ensure_not_return(&transaction.transaction_type)?;
 
     if transaction.transaction_number.is_empty() {
         transaction.transaction_number =
-            generate_transaction_number(&transaction.transaction_type)?;
+            generate_transaction_number_internal(&conn, &transaction.transaction_type)?;
     }
+    transaction.location_id = Some(location::resolve_location(&conn, transaction.location_id.as_deref())?);
 
+    let (discount_type, discount_value, discount_reason, discount_by) = discount_columns(&transaction.discount);
     conn.execute(
-        "INSERT INTO transactions (id, transaction_number, transaction_type, party_id, party_type, status, payment_status, total_amount, paid_amount, notes, created_at, updated_at, created_by)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+        "INSERT INTO transactions (id, transaction_number, transaction_type, party_id, party_type, status, payment_status, total_amount, paid_amount, notes, created_at, updated_at, created_by, subtotal, tax_amount, discount_type, discount_value, discount_reason, discount_by, location_id)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20)",
         params![
             transaction.id,
             transaction.transaction_number,
@@ -68,9 +302,15 @@ pub fn create_transaction(mut transaction: Transaction) -> Result<Transaction, S
             transaction.created_at,
             transaction.updated_at,
             transaction.created_by,
+            transaction.subtotal,
+            transaction.tax_amount,
+            discount_type,
+            discount_value,
+            discount_reason,
+            discount_by,
+            transaction.location_id,
         ],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
 
     // Log history
     let history_id = Uuid::new_v4().to_string();
@@ -85,72 +325,186 @@ pub fn create_transaction(mut transaction: Transaction) -> Result<Transaction, S
             format!("{} {} created", transaction.transaction_type, transaction.transaction_number),
             transaction.created_by,
         ],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
 
     // Log party history
     if transaction.party_type == "Client" {
         let h_id = Uuid::new_v4().to_string();
         conn.execute(
             "INSERT INTO client_history (id, client_id, date, type, notes, amount, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-            params![h_id, transaction.party_id, Utc::now().to_rfc3339(), "Sale Created", format!("Sale {} created", transaction.transaction_number), 0.0, transaction.created_by],
-        ).map_err(|e| e.to_string())?;
+            params![h_id, transaction.party_id, Utc::now().to_rfc3339(), "Sale Created", format!("Sale {} created", transaction.transaction_number), Money::ZERO, transaction.created_by],
+        )?;
     } else {
         let h_id = Uuid::new_v4().to_string();
         conn.execute(
             "INSERT INTO supplier_history (id, supplier_id, date, type, notes, amount, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-            params![h_id, transaction.party_id, Utc::now().to_rfc3339(), "Purchase Order Created", format!("Order {} created", transaction.transaction_number), 0.0, transaction.created_by],
-        ).map_err(|e| e.to_string())?;
+            params![h_id, transaction.party_id, Utc::now().to_rfc3339(), "Purchase Order Created", format!("Order {} created", transaction.transaction_number), Money::ZERO, transaction.created_by],
+        )?;
     }
 
     Ok(transaction)
 }
 
-#[tauri::command]
-pub fn get_transactions(
-    type_filter: Option<String>,
-    status_filter: Option<String>,
-    party_filter: Option<String>,
-) -> Result<Vec<Transaction>, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
-
-    let mut query = "SELECT id, transaction_number, transaction_type, party_id, party_type, status, payment_status, total_amount, paid_amount, notes, created_at, updated_at, created_by FROM transactions WHERE 1=1".to_string();
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 500;
+
+/// Build the WHERE clause for a transaction query. Every value is bound as a
+/// parameter, never formatted into the SQL.
+fn transaction_filter_clause(query: &TransactionQuery) -> (String, Vec<Value>) {
+    let mut clause = String::from("WHERE 1=1");
+    let mut values: Vec<Value> = Vec::new();
+
+    let exact_filters = [
+        ("transaction_type", &query.transaction_type),
+        ("status", &query.status),
+        ("payment_status", &query.payment_status),
+        ("party_id", &query.party_id),
+    ];
+    for (column, filter) in exact_filters {
+        if let Some(v) = filter {
+            values.push(Value::Text(v.clone()));
+            clause.push_str(&format!(" AND {} = ?{}", column, values.len()));
+        }
+    }
 
-    if let Some(t) = type_filter {
-        query.push_str(&format!(" AND transaction_type = '{}'", t));
+    if let Some(start) = &query.start_date {
+        values.push(Value::Text(start.clone()));
+        clause.push_str(&format!(" AND created_at >= ?{}", values.len()));
+    }
+    if let Some(end) = &query.end_date {
+        let end_iso = if end.len() == 10 {
+            format!("{}T23:59:59.999", end)
+        } else {
+            end.clone()
+        };
+        values.push(Value::Text(end_iso));
+        clause.push_str(&format!(" AND created_at <= ?{}", values.len()));
     }
-    if let Some(s) = status_filter {
-        query.push_str(&format!(" AND status = '{}'", s));
+    if let Some(min) = query.min_amount {
+        values.push(Value::Integer(min.minor()));
+        clause.push_str(&format!(" AND total_amount >= ?{}", values.len()));
     }
-    if let Some(p) = party_filter {
-        query.push_str(&format!(" AND party_id = '{}'", p));
+    if let Some(max) = query.max_amount {
+        values.push(Value::Integer(max.minor()));
+        clause.push_str(&format!(" AND total_amount <= ?{}", values.len()));
+    }
+    if let Some(search) = query.search.as_deref().map(str::trim).filter(|s| !s.is_empty()) {
+        values.push(Value::Text(contains_pattern(search)));
+        let n = values.len();
+        clause.push_str(&format!(
+            " AND (transaction_number LIKE ?{n} ESCAPE '\\' OR notes LIKE ?{n} ESCAPE '\\')"
+        ));
     }
 
-    query.push_str(" ORDER BY created_at DESC");
+    (clause, values)
+}
 
-    let mut stmt = conn.prepare(&query).map_err(|e| e.to_string())?;
+/// Search transactions with bound filters, sorted and paged by keyset.
+///
+/// `next_cursor` is set when more rows follow; pass it back as `cursor` (with the
+/// same filters and sort) to get the next page.
+#[tauri::command]
+pub fn query_transactions(
+    pool: State<'_, DbPool>,
+    query: TransactionQuery,
+) -> Result<TransactionPage, AppError> {
+    let conn = pool.get()?;
+
+    let sort_column = match query.sort_by.as_deref().unwrap_or("created_at") {
+        "created_at" => "created_at",
+        "updated_at" => "updated_at",
+        "transaction_number" => "transaction_number",
+        "total_amount" => "total_amount",
+        other => return Err(AppError::Validation(format!("Cannot sort transactions by '{}'", other))),
+    };
+    let descending = query.sort_desc.unwrap_or(true);
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let (filter, mut values) = transaction_filter_clause(&query);
+
+    let total_count: i64 = conn.query_row(
+        &format!("SELECT COUNT(*) FROM transactions {}", filter),
+        params_from_iter(values.iter()),
+        |row| row.get(0),
+    )?;
+
+    // The cursor is "<sort value>|<id>" of the last row on the previous page; the id
+    // breaks ties so rows sharing a sort value are neither skipped nor repeated.
+    let mut page_filter = filter;
+    if let Some(cursor) = &query.cursor {
+        let (sort_value, last_id) = cursor
+            .rsplit_once('|')
+            .ok_or_else(|| AppError::Validation("Invalid transaction cursor".to_string()))?;
+        let sort_value = if sort_column == "total_amount" {
+            Value::Integer(sort_value.parse().map_err(|_| AppError::Validation("Invalid transaction cursor".to_string()))?)
+        } else {
+            Value::Text(sort_value.to_string())
+        };
+        let op = if descending { "<" } else { ">" };
+        values.push(sort_value);
+        values.push(Value::Text(last_id.to_string()));
+        let (v, i) = (values.len() - 1, values.len());
+        page_filter.push_str(&format!(
+            " AND ({col} {op} ?{v} OR ({col} = ?{v} AND id {op} ?{i}))",
+            col = sort_column
+        ));
+    }
 
-    let transactions = stmt
-        .query_map([], |row| {
-            Ok(Transaction {
-                id: row.get(0)?,
-                transaction_number: row.get(1)?,
-                transaction_type: row.get(2)?,
-                party_id: row.get(3)?,
-                party_type: row.get(4)?,
-                status: row.get(5)?,
-                payment_status: row.get(6)?,
-                total_amount: row.get(7)?,
-                paid_amount: row.get(8)?,
-                notes: row.get(9).ok(),
-                created_at: row.get(10)?,
-                updated_at: row.get(11)?,
-                created_by: row.get(12).ok(),
-            })
+    let direction = if descending { "DESC" } else { "ASC" };
+    let sql = format!(
+        "SELECT {} FROM transactions {} ORDER BY {} {}, id {} LIMIT {}",
+        TRANSACTION_COLUMNS, page_filter, sort_column, direction, direction, limit + 1
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let mut transactions: Vec<Transaction> = stmt
+        .query_map(params_from_iter(values.iter()), transaction_from_row)?
+        .collect::<rusqlite::Result<_>>()?;
+
+    let next_cursor = if transactions.len() as i64 > limit {
+        transactions.truncate(limit as usize);
+        transactions.last().map(|last| {
+            let sort_value = match sort_column {
+                "updated_at" => last.updated_at.clone(),
+                "transaction_number" => last.transaction_number.clone(),
+                "total_amount" => last.total_amount.minor().to_string(),
+                _ => last.created_at.clone(),
+            };
+            format!("{}|{}", sort_value, last.id)
         })
-        .map_err(|e| e.to_string())?
-        .filter_map(|res| res.ok())
-        .collect();
+    } else {
+        None
+    };
+
+    Ok(TransactionPage {
+        transactions,
+        total_count,
+        next_cursor,
+    })
+}
+
+#[tauri::command]
+pub fn get_transactions(
+    pool: State<'_, DbPool>,
+    type_filter: Option<String>,
+    status_filter: Option<String>,
+    party_filter: Option<String>,
+) -> Result<Vec<Transaction>, AppError> {
+    let conn = pool.get()?;
+
+    let (filter, values) = transaction_filter_clause(&TransactionQuery {
+        transaction_type: type_filter,
+        status: status_filter,
+        party_id: party_filter,
+        ..Default::default()
+    });
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM transactions {} ORDER BY created_at DESC",
+        TRANSACTION_COLUMNS, filter
+    ))?;
+
+    let transactions = stmt
+        .query_map(params_from_iter(values.iter()), transaction_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
 
     Ok(transactions)
 }
@@ -158,30 +512,13 @@ pub fn get_transactions(
 fn get_transaction_by_id_internal(
     conn: &Connection,
     tx_id: String,
-) -> Result<Option<TransactionWithDetails>, String> {
+) -> Result<Option<TransactionWithDetails>, AppError> {
     let mut stmt = conn
-        .prepare("SELECT id, transaction_number, transaction_type, party_id, party_type, status, payment_status, total_amount, paid_amount, notes, created_at, updated_at, created_by FROM transactions WHERE id = ?1")
-        .map_err(|e| e.to_string())?;
-
-    let transaction = match stmt.query_row(params![tx_id], |row| {
-        Ok(Transaction {
-            id: row.get(0)?,
-            transaction_number: row.get(1)?,
-            transaction_type: row.get(2)?,
-            party_id: row.get(3)?,
-            party_type: row.get(4)?,
-            status: row.get(5)?,
-            payment_status: row.get(6)?,
-            total_amount: row.get(7)?,
-            paid_amount: row.get(8)?,
-            notes: row.get(9).ok(),
-            created_at: row.get(10)?,
-            updated_at: row.get(11)?,
-            created_by: row.get(12).ok(),
-        })
-    }) {
-        Ok(tx) => tx,
-        Err(_) => return Ok(None),
+        .prepare_cached(&format!("SELECT {} FROM transactions WHERE id = ?1", TRANSACTION_COLUMNS))?;
+
+    let transaction = match stmt.query_row(params![tx_id], transaction_from_row).optional()? {
+        Some(tx) => tx,
+        None => return Ok(None),
     };
 
     let party_name: String = if transaction.party_type == "Client" {
@@ -190,40 +527,30 @@ fn get_transaction_by_id_internal(
             params![transaction.party_id],
             |row| row.get(0),
         )
-        .unwrap_or_else(|_| "Unknown Client".to_string())
+        .optional()?
+        .unwrap_or_else(|| "Unknown Client".to_string())
     } else {
         conn.query_row(
             "SELECT name FROM suppliers WHERE id = ?1",
             params![transaction.party_id],
             |row| row.get(0),
         )
-        .unwrap_or_else(|_| "Unknown Supplier".to_string())
+        .optional()?
+        .unwrap_or_else(|| "Unknown Supplier".to_string())
     };
 
     let mut items_stmt = conn
-        .prepare("SELECT id, transaction_id, item_id, item_name, quantity, unit_price, total_price, notes FROM transaction_items WHERE transaction_id = ?1")
-        .map_err(|e| e.to_string())?;
+        .prepare_cached(&format!("SELECT {} FROM transaction_items WHERE transaction_id = ?1", ITEM_COLUMNS))?;
 
-    let items = items_stmt
-        .query_map(params![transaction.id], |row| {
-            Ok(TransactionItem {
-                id: row.get(0)?,
-                transaction_id: row.get(1)?,
-                item_id: row.get(2).ok(),
-                item_name: row.get(3)?,
-                quantity: row.get(4)?,
-                unit_price: row.get(5)?,
-                total_price: row.get(6)?,
-                notes: row.get(7).ok(),
-            })
-        })
-        .map_err(|e| e.to_string())?
-        .filter_map(|res| res.ok())
-        .collect();
+    let mut items: Vec<TransactionItem> = items_stmt
+        .query_map(params![transaction.id], item_from_row)?
+        .collect::<Result<_, _>>()?;
+    for item in &mut items {
+        item.serial_numbers = serial::line_serials(conn, &item.id)?;
+    }
 
     let mut payments_stmt = conn
-        .prepare("SELECT id, transaction_id, amount, method, date, received_by, notes, session_id FROM transaction_payments WHERE transaction_id = ?1")
-        .map_err(|e| e.to_string())?;
+        .prepare_cached("SELECT id, transaction_id, amount, method, date, received_by, notes, session_id, currency_code, original_amount, exchange_rate FROM transaction_payments WHERE transaction_id = ?1")?;
 
     let payments = payments_stmt
         .query_map(params![transaction.id], |row| {
@@ -233,160 +560,174 @@ fn get_transaction_by_id_internal(
                 amount: row.get(2)?,
                 method: row.get(3)?,
                 date: row.get(4)?,
-                received_by: row.get(5).ok(),
-                notes: row.get(6).ok(),
-                session_id: row.get(7).ok(),
+                received_by: row.get(5)?,
+                notes: row.get(6)?,
+                session_id: row.get(7)?,
+                currency_code: row.get(8)?,
+                original_amount: row.get(9)?,
+                exchange_rate: row.get(10)?,
             })
-        })
-        .map_err(|e| e.to_string())?
-        .filter_map(|res| res.ok())
-        .collect();
+        })?
+        .collect::<Result<_, _>>()?;
+
+    let taxes = transaction_tax_lines(conn, &transaction.id)?;
 
     Ok(Some(TransactionWithDetails {
         transaction,
         items,
         payments,
         party_name,
+        taxes,
     }))
 }
 
 #[tauri::command]
-pub fn get_transaction_by_id(tx_id: String) -> Result<Option<TransactionWithDetails>, String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn get_transaction_by_id(pool: State<'_, DbPool>, tx_id: String) -> Result<Option<TransactionWithDetails>, AppError> {
+    let conn = pool.get()?;
     get_transaction_by_id_internal(&conn, tx_id)
 }
 
+/// Add a line to a transaction. On a completed one the stock moves right away, and a sale
+/// line exceeding `quantity_in_stock` fails unless `allow_oversell` is set.
 #[tauri::command]
-pub fn add_transaction_item(item: TransactionItem) -> Result<(), String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
-
-    conn.execute(
-        "INSERT INTO transaction_items (id, transaction_id, item_id, item_name, quantity, unit_price, total_price, notes) 
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
-        params![
-            item.id,
-            item.transaction_id,
-            item.item_id,
-            item.item_name,
-            item.quantity,
-            item.unit_price,
-            item.total_price,
-            item.notes,
-        ],
-    ).map_err(|e| e.to_string())?;
+pub fn add_transaction_item(
+    pool: State<'_, DbPool>,
+    mut item: TransactionItem,
+    allow_oversell: Option<bool>,
+) -> Result<(), AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+
+    let transaction: Transaction = tx.query_row(
+        &format!("SELECT {} FROM transactions WHERE id = ?1", TRANSACTION_COLUMNS),
+        params![item.transaction_id],
+        transaction_from_row,
+    )?;
+    let tx_type = &transaction.transaction_type;
+    ensure_not_return(tx_type)?;
+
+    resolve_item_tax(&tx, &mut item)?;
+    apply_discounts(None, std::slice::from_mut(&mut item))?;
+    insert_item(&tx, &item)?;
 
     // If transaction is already completed, update inventory immediately
-    let (status, tx_type, tx_num) = conn
-        .query_row(
-            "SELECT status, transaction_type, transaction_number FROM transactions WHERE id = ?1",
-            params![item.transaction_id],
-            |row| {
-                Ok((
-                    row.get::<_, String>(0)?,
-                    row.get::<_, String>(1)?,
-                    row.get::<_, String>(2)?,
-                ))
-            },
-        )
-        .map_err(|e| e.to_string())?;
+    reserve_draft_lines(&tx, &transaction, std::slice::from_ref(&item), false)?;
 
-    if status == "Completed" {
+    if transaction.status == "Completed" {
         if let Some(item_id) = &item.item_id {
-            let qty_change = if tx_type == "Sale" {
-                -(item.quantity as i64)
-            } else {
-                item.quantity as i64
-            };
-
-            conn.execute(
-                "UPDATE inventory_items SET quantity_in_stock = COALESCE(quantity_in_stock, 0) + ?1 WHERE id = ?2",
-                params![qty_change, item_id]
-            ).map_err(|e| e.to_string())?;
-
-            let history_id = Uuid::new_v4().to_string();
-            conn.execute(
-                "INSERT INTO inventory_history (id, item_id, date, event_type, quantity_change, notes, related_id) 
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-                params![
-                    history_id,
-                    item_id,
-                    Utc::now().to_rfc3339(),
-                    if tx_type == "Sale" { "Sold" } else { "Purchased" },
-                    qty_change,
-                    format!("Added item to completed {} {}", tx_type, tx_num),
-                    item.transaction_id,
-                ],
-            ).map_err(|e| e.to_string())?;
+            let qty_change = stock_direction(tx_type) * item.quantity as i64;
+
+            let location_id = transaction.location_id.as_deref();
+            inventory::apply_stock_change(&tx, item_id, location_id, qty_change, allow_oversell.unwrap_or(false))?;
+            inventory::log_stock_event(
+                &tx,
+                item_id,
+                location_id,
+                if tx_type == "Sale" { "Sold" } else { "Purchased" },
+                qty_change,
+                &format!("Added item to completed {} {}", tx_type, transaction.transaction_number),
+                &item.transaction_id,
+            )?;
+            cost_stock_movement(&tx, &transaction, &item, false)?;
+            move_line_serials(&tx, &transaction, &item, false)?;
         }
     }
 
-    recalculate_transaction_totals(&item.transaction_id)?;
+    recalculate_transaction_totals(&tx, &item.transaction_id)?;
 
+    tx.commit()?;
     Ok(())
 }
 
 #[tauri::command]
-pub fn remove_transaction_item(item_id: String, transaction_id: String) -> Result<(), String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn remove_transaction_item(pool: State<'_, DbPool>, item_id: String, transaction_id: String) -> Result<(), AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
 
-    let item_info: Option<(String, Option<String>, i32, String, String, String)> = conn.query_row(
+    let item_info: Option<(String, Option<String>, i32, String, String, String)> = tx.query_row(
         "SELECT i.item_name, i.item_id, i.quantity, t.status, t.transaction_type, t.transaction_number 
          FROM transaction_items i JOIN transactions t ON i.transaction_id = t.id WHERE i.id = ?1",
         params![item_id],
         |row| Ok((row.get(0)?, row.get(1).ok(), row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?))
     ).ok();
+    let line: Option<TransactionItem> = tx
+        .query_row(
+            &format!("SELECT {} FROM transaction_items WHERE id = ?1", ITEM_COLUMNS),
+            params![item_id],
+            item_from_row,
+        )
+        .optional()?;
+    if let Some((name, _, _, _, tx_type, _)) = &item_info {
+        ensure_not_return(tx_type)?;
+        if returned_quantity(&tx, &item_id)? > 0 {
+            return Err(AppError::Conflict(format!(
+                "{} has been returned; cancel its returns before removing it",
+                name
+            )));
+        }
+    }
 
-    conn.execute(
+    tx.execute(
         "DELETE FROM transaction_items WHERE id = ?1",
         params![item_id],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
+    reservation::release_line(&tx, &item_id)?;
+    serial::delete_line_serials(&tx, &item_id)?;
 
     if let Some((name, id_opt, qty, status, tx_type, tx_num)) = item_info {
         if status == "Completed" {
             if let Some(id) = id_opt {
                 // Reverse inventory
-                let qty_change = if tx_type == "Sale" {
-                    qty as i64
-                } else {
-                    -(qty as i64)
-                };
-
-                conn.execute(
-                    "UPDATE inventory_items SET quantity_in_stock = COALESCE(quantity_in_stock, 0) + ?1 WHERE id = ?2",
-                    params![qty_change, id]
-                ).map_err(|e| e.to_string())?;
-
-                let history_id = Uuid::new_v4().to_string();
-                conn.execute(
-                    "INSERT INTO inventory_history (id, item_id, date, event_type, quantity_change, notes, related_id) 
-                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-                    params![
-                        history_id,
-                        id,
-                        Utc::now().to_rfc3339(),
-                        "Adjustment",
-                        qty_change,
-                        format!("Removed item {} from completed {} {}", name, tx_type, tx_num),
-                        transaction_id,
-                    ],
-                ).ok();
+                let qty_change = -stock_direction(&tx_type) * qty as i64;
+
+                let transaction: Transaction = tx.query_row(
+                    &format!("SELECT {} FROM transactions WHERE id = ?1", TRANSACTION_COLUMNS),
+                    params![transaction_id],
+                    transaction_from_row,
+                )?;
+                let location_id = transaction.location_id.as_deref();
+                inventory::apply_stock_change(&tx, &id, location_id, qty_change, true)?;
+                inventory::log_stock_event(
+                    &tx,
+                    &id,
+                    location_id,
+                    "Adjustment",
+                    qty_change,
+                    &format!("Removed item {} from completed {} {}", name, tx_type, tx_num),
+                    &transaction_id,
+                )?;
+
+                if let Some(line) = &line {
+                    cost_stock_movement(&tx, &transaction, line, true)?;
+                    move_line_serials(&tx, &transaction, line, true)?;
+                }
             }
         }
     }
 
-    recalculate_transaction_totals(&transaction_id)?;
+    recalculate_transaction_totals(&tx, &transaction_id)?;
 
+    tx.commit()?;
     Ok(())
 }
 
 #[tauri::command]
-pub fn add_transaction_payment(payment: TransactionPayment) -> Result<(), String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn add_transaction_payment(pool: State<'_, DbPool>, mut payment: TransactionPayment) -> Result<(), AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+
+    let tendered = tendered_amount(
+        &tx,
+        payment.currency_code.as_deref(),
+        payment.original_amount,
+        payment.amount,
+        &payment.date,
+    )?;
+    payment.amount = tendered.amount;
 
-    conn.execute(
-        "INSERT INTO transaction_payments (id, transaction_id, amount, method, date, received_by, notes, session_id) 
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+    tx.execute(
+        "INSERT INTO transaction_payments (id, transaction_id, amount, method, date, received_by, notes, session_id, currency_code, original_amount, exchange_rate) 
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
         params![
             payment.id,
             payment.transaction_id,
@@ -396,337 +737,487 @@ pub fn add_transaction_payment(payment: TransactionPayment) -> Result<(), String
             payment.received_by,
             payment.notes,
             payment.session_id,
+            tendered.currency_code,
+            tendered.original_amount,
+            tendered.exchange_rate,
         ],
-    ).map_err(|e| e.to_string())?;
+    )?;
 
-    recalculate_transaction_totals(&payment.transaction_id)?;
+    recalculate_transaction_totals(&tx, &payment.transaction_id)?;
 
     // Adjust party balance
-    let tx_info: (String, String, String, String) = conn.query_row(
+    let tx_info: (String, String, String, String) = tx.query_row(
         "SELECT party_id, party_type, transaction_number, transaction_type FROM transactions WHERE id = ?1",
         params![payment.transaction_id],
         |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     let (party_id, party_type, tx_num, tx_type) = tx_info;
+    let balance_adj = payment.amount * payment_direction(&tx_type);
 
     if party_type == "Client" {
-        conn.execute(
-            "UPDATE clients SET credit_balance = COALESCE(credit_balance, 0) - ?1 WHERE id = ?2",
-            params![payment.amount, party_id],
-        )
-        .ok();
+        tx.execute(
+            "UPDATE clients SET credit_balance = COALESCE(credit_balance, 0) + ?1 WHERE id = ?2",
+            params![balance_adj, party_id],
+        )?;
 
+        let history_type = if is_return_type(&tx_type) { "Refund Paid" } else { "Payment Received" };
         let h_id = Uuid::new_v4().to_string();
-        conn.execute(
+        tx.execute(
             "INSERT INTO client_history (id, client_id, date, type, notes, amount, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-            params![h_id, party_id, Utc::now().to_rfc3339(), "Payment Received", format!("Payment for {} {}", tx_type, tx_num), -payment.amount, payment.received_by],
-        ).ok();
+            params![h_id, party_id, Utc::now().to_rfc3339(), history_type, format!("Payment for {} {}", tx_type, tx_num), balance_adj, payment.received_by],
+        )?;
     } else {
-        conn.execute(
-            "UPDATE suppliers SET credit_balance = COALESCE(credit_balance, 0) - ?1 WHERE id = ?2",
-            params![payment.amount, party_id],
-        )
-        .ok();
+        tx.execute(
+            "UPDATE suppliers SET credit_balance = COALESCE(credit_balance, 0) + ?1 WHERE id = ?2",
+            params![balance_adj, party_id],
+        )?;
 
+        let history_type = if is_return_type(&tx_type) { "Refund Received" } else { "Payment Made" };
         let h_id = Uuid::new_v4().to_string();
-        conn.execute(
+        tx.execute(
             "INSERT INTO supplier_history (id, supplier_id, date, type, notes, amount, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-            params![h_id, party_id, Utc::now().to_rfc3339(), "Payment Made", format!("Payment for {} {}", tx_type, tx_num), -payment.amount, payment.received_by],
-        ).ok();
+            params![h_id, party_id, Utc::now().to_rfc3339(), history_type, format!("Payment for {} {}", tx_type, tx_num), balance_adj, payment.received_by],
+        )?;
     }
 
+    tx.commit()?;
     Ok(())
 }
 
 #[tauri::command]
-pub fn update_transaction_payment(id: String, amount: f64, method: String) -> Result<(), String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn update_transaction_payment(pool: State<'_, DbPool>, id: String, amount: Money, method: String) -> Result<(), AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
 
     // Get old info for recalculation
-    let (tx_id, old_amount): (String, f64) = conn.query_row(
-        "SELECT transaction_id, amount FROM transaction_payments WHERE id = ?1",
+    let (tx_id, old_amount, currency_code, exchange_rate): (String, Money, Option<String>, Option<f64>) = tx.query_row(
+        "SELECT transaction_id, amount, currency_code, exchange_rate FROM transaction_payments WHERE id = ?1",
         params![id],
-        |row| Ok((row.get(0)?, row.get(1)?))
-    ).map_err(|e| e.to_string())?;
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+    )?;
+    let (amount, original_amount) = edited_amount(currency_code.as_deref(), exchange_rate, amount);
 
     // Update payment record
-    conn.execute(
-        "UPDATE transaction_payments SET amount = ?1, method = ?2 WHERE id = ?3",
-        params![amount, method, id],
-    ).map_err(|e| e.to_string())?;
+    tx.execute(
+        "UPDATE transaction_payments SET amount = ?1, method = ?2, original_amount = ?4 WHERE id = ?3",
+        params![amount, method, id, original_amount],
+    )?;
 
     // Recalculate transaction
-    recalculate_transaction_totals(&tx_id)?;
+    recalculate_transaction_totals(&tx, &tx_id)?;
 
     // Adjust party balance: Refund old, apply new
-    let tx_info: (String, String, String, String) = conn.query_row(
+    let tx_info: (String, String, String, String) = tx.query_row(
         "SELECT party_id, party_type, transaction_number, transaction_type FROM transactions WHERE id = ?1",
         params![tx_id],
         |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     let (party_id, party_type, tx_num, tx_type) = tx_info;
-    let balance_adj = old_amount - amount;
+    let balance_adj = (amount - old_amount) * payment_direction(&tx_type);
 
     if party_type == "Client" {
-        conn.execute(
+        tx.execute(
             "UPDATE clients SET credit_balance = COALESCE(credit_balance, 0) + ?1 WHERE id = ?2",
             params![balance_adj, party_id],
-        ).ok();
+        )?;
 
         let h_id = Uuid::new_v4().to_string();
-        conn.execute(
+        tx.execute(
             "INSERT INTO client_history (id, client_id, date, type, notes, amount, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
             params![h_id, party_id, Utc::now().to_rfc3339(), "Payment Updated", format!("Payment adjusted for {} {}: {} -> {}", tx_type, tx_num, old_amount, amount), balance_adj, None::<String>],
-        ).ok();
+        )?;
     } else {
-        conn.execute(
+        tx.execute(
             "UPDATE suppliers SET credit_balance = COALESCE(credit_balance, 0) + ?1 WHERE id = ?2",
             params![balance_adj, party_id],
-        ).ok();
+        )?;
 
         let h_id = Uuid::new_v4().to_string();
-        conn.execute(
+        tx.execute(
             "INSERT INTO supplier_history (id, supplier_id, date, type, notes, amount, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
             params![h_id, party_id, Utc::now().to_rfc3339(), "Payment Updated", format!("Payment adjusted for {} {}: {} -> {}", tx_type, tx_num, old_amount, amount), balance_adj, None::<String>],
-        ).ok();
+        )?;
     }
 
+    tx.commit()?;
     Ok(())
 }
 
 #[tauri::command]
-pub fn delete_transaction_payment(id: String) -> Result<(), String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn delete_transaction_payment(pool: State<'_, DbPool>, id: String) -> Result<(), AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
 
     // Get info before delete
-    let (tx_id, amount): (String, f64) = conn.query_row(
+    let (tx_id, amount): (String, Money) = tx.query_row(
         "SELECT transaction_id, amount FROM transaction_payments WHERE id = ?1",
         params![id],
         |row| Ok((row.get(0)?, row.get(1)?))
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     // Delete record
-    conn.execute("DELETE FROM transaction_payments WHERE id = ?1", params![id]).map_err(|e| e.to_string())?;
+    tx.execute("DELETE FROM transaction_payments WHERE id = ?1", params![id])?;
 
     // Recalculate transaction
-    recalculate_transaction_totals(&tx_id)?;
+    recalculate_transaction_totals(&tx, &tx_id)?;
 
     // Reverse party balance impact
-    let tx_info: (String, String, String, String) = conn.query_row(
+    let tx_info: (String, String, String, String) = tx.query_row(
         "SELECT party_id, party_type, transaction_number, transaction_type FROM transactions WHERE id = ?1",
         params![tx_id],
         |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     let (party_id, party_type, tx_num, tx_type) = tx_info;
+    let balance_adj = -amount * payment_direction(&tx_type);
 
     if party_type == "Client" {
-        conn.execute(
+        tx.execute(
             "UPDATE clients SET credit_balance = COALESCE(credit_balance, 0) + ?1 WHERE id = ?2",
-            params![amount, party_id],
-        ).ok();
+            params![balance_adj, party_id],
+        )?;
 
         let h_id = Uuid::new_v4().to_string();
-        conn.execute(
+        tx.execute(
             "INSERT INTO client_history (id, client_id, date, type, notes, amount, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-            params![h_id, party_id, Utc::now().to_rfc3339(), "Payment Deleted", format!("Payment of {} deleted for {} {}", amount, tx_type, tx_num), amount, None::<String>],
-        ).ok();
+            params![h_id, party_id, Utc::now().to_rfc3339(), "Payment Deleted", format!("Payment of {} deleted for {} {}", amount, tx_type, tx_num), balance_adj, None::<String>],
+        )?;
     } else {
-        conn.execute(
+        tx.execute(
             "UPDATE suppliers SET credit_balance = COALESCE(credit_balance, 0) + ?1 WHERE id = ?2",
-            params![amount, party_id],
-        ).ok();
+            params![balance_adj, party_id],
+        )?;
 
         let h_id = Uuid::new_v4().to_string();
-        conn.execute(
+        tx.execute(
             "INSERT INTO supplier_history (id, supplier_id, date, type, notes, amount, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-            params![h_id, party_id, Utc::now().to_rfc3339(), "Payment Deleted", format!("Payment of {} deleted for {} {}", amount, tx_type, tx_num), amount, None::<String>],
-        ).ok();
+            params![h_id, party_id, Utc::now().to_rfc3339(), "Payment Deleted", format!("Payment of {} deleted for {} {}", amount, tx_type, tx_num), balance_adj, None::<String>],
+        )?;
     }
 
+    tx.commit()?;
     Ok(())
 }
 
+/// Complete a draft transaction: move stock, update the party balance and mark it Completed.
+/// Everything runs in one SQL transaction, so any failure (including a sale line that
+/// exceeds `quantity_in_stock` when `allow_oversell` is not set) leaves the draft untouched.
 #[tauri::command]
-pub fn complete_transaction(tx_id: String) -> Result<(), String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+pub fn complete_transaction(
+    pool: State<'_, DbPool>,
+    tx_id: String,
+    allow_oversell: Option<bool>,
+) -> Result<(), AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
 
-    let tx: Transaction = conn.query_row(
-        "SELECT id, transaction_number, transaction_type, party_id, party_type, status, payment_status, total_amount, paid_amount, notes, created_at, updated_at, created_by 
-         FROM transactions WHERE id = ?1",
+    let transaction: Transaction = tx.query_row(
+        &format!("SELECT {} FROM transactions WHERE id = ?1", TRANSACTION_COLUMNS),
         params![tx_id],
-        |row| Ok(Transaction {
-            id: row.get(0)?,
-            transaction_number: row.get(1)?,
-            transaction_type: row.get(2)?,
-            party_id: row.get(3)?,
-            party_type: row.get(4)?,
-            status: row.get(5)?,
-            payment_status: row.get(6)?,
-            total_amount: row.get(7)?,
-            paid_amount: row.get(8)?,
-            notes: row.get(9).ok(),
-            created_at: row.get(10)?,
-            updated_at: row.get(11)?,
-            created_by: row.get(12).ok(),
-        })
-    ).map_err(|e| e.to_string())?;
+        transaction_from_row,
+    ).optional()?
+    .ok_or_else(|| AppError::NotFound(format!("Transaction {} not found", tx_id)))?;
 
-    if tx.status == "Completed" {
+    if transaction.status == "Completed" {
         return Ok(());
     }
+    if transaction.status == "Cancelled" {
+        return Err(AppError::Conflict(format!(
+            "Transaction {} is cancelled and cannot be completed",
+            transaction.transaction_number
+        )));
+    }
 
-    // 1. Update Inventory
-    let mut stmt = conn
-        .prepare("SELECT item_id, quantity FROM transaction_items WHERE transaction_id = ?1")
-        .map_err(|e| e.to_string())?;
-    let items: Vec<(Option<String>, i32)> = stmt
-        .query_map(params![tx_id], |row| Ok((row.get(0).ok(), row.get(1)?)))
-        .map_err(|e| e.to_string())?
-        .filter_map(|r| r.ok())
-        .collect();
-
-    for (item_id_opt, qty) in items {
-        if let Some(item_id) = item_id_opt {
-            let qty_change = if tx.transaction_type == "Sale" {
-                -(qty as i64)
-            } else {
-                qty as i64
-            };
-            conn.execute("UPDATE inventory_items SET quantity_in_stock = COALESCE(quantity_in_stock, 0) + ?1 WHERE id = ?2", params![qty_change, item_id]).ok();
+    // 1. Update Inventory, taking out the stock the draft was holding
+    reservation::convert_source(&tx, &tx_id)?;
+    let items: Vec<TransactionItem> = {
+        let mut stmt = tx
+            .prepare_cached(&format!("SELECT {} FROM transaction_items WHERE transaction_id = ?1", ITEM_COLUMNS))?;
+        let rows = stmt.query_map(params![tx_id], item_from_row)?;
+        rows.collect::<Result<_, _>>()?
+    };
 
-            let h_id = Uuid::new_v4().to_string();
-            conn.execute(
-                "INSERT INTO inventory_history (id, item_id, date, event_type, quantity_change, notes, related_id) 
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-                params![h_id, item_id, Utc::now().to_rfc3339(), if tx.transaction_type == "Sale" { "Sold" } else { "Purchased" }, qty_change, format!("{} {}", tx.transaction_type, tx.transaction_number), tx.id],
-            ).ok();
+    for item in &items {
+        if let Some(item_id) = &item.item_id {
+            let qty_change = stock_direction(&transaction.transaction_type) * item.quantity as i64;
+            let location_id = transaction.location_id.as_deref();
+            inventory::apply_stock_change(&tx, item_id, location_id, qty_change, allow_oversell.unwrap_or(false))?;
+            inventory::log_stock_event(
+                &tx,
+                item_id,
+                location_id,
+                if transaction.transaction_type == "Sale" { "Sold" } else { "Purchased" },
+                qty_change,
+                &format!("{} {}", transaction.transaction_type, transaction.transaction_number),
+                &transaction.id,
+            )?;
+            cost_stock_movement(&tx, &transaction, item, false)?;
+            move_line_serials(&tx, &transaction, item, false)?;
         }
     }
 
     // 2. Update Party Balance
-    if tx.party_type == "Client" {
-        conn.execute(
+    if transaction.party_type == "Client" {
+        tx.execute(
             "UPDATE clients SET credit_balance = COALESCE(credit_balance, 0) + ?1 WHERE id = ?2",
-            params![tx.total_amount, tx.party_id],
-        )
-        .ok();
+            params![transaction.total_amount, transaction.party_id],
+        )?;
         let h_id = Uuid::new_v4().to_string();
-        conn.execute("INSERT INTO client_history (id, client_id, date, type, notes, amount, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-            params![h_id, tx.party_id, Utc::now().to_rfc3339(), "Sale Completed", format!("Sale {}", tx.transaction_number), tx.total_amount, None::<String>]).ok();
+        tx.execute("INSERT INTO client_history (id, client_id, date, type, notes, amount, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![h_id, transaction.party_id, Utc::now().to_rfc3339(), "Sale Completed", format!("Sale {}", transaction.transaction_number), transaction.total_amount, None::<String>])?;
     } else {
-        conn.execute(
+        tx.execute(
             "UPDATE suppliers SET credit_balance = COALESCE(credit_balance, 0) + ?1 WHERE id = ?2",
-            params![tx.total_amount, tx.party_id],
-        )
-        .ok();
+            params![transaction.total_amount, transaction.party_id],
+        )?;
         let h_id = Uuid::new_v4().to_string();
-        conn.execute("INSERT INTO supplier_history (id, supplier_id, date, type, notes, amount, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-            params![h_id, tx.party_id, Utc::now().to_rfc3339(), "Purchase Order Completed", format!("Order {}", tx.transaction_number), tx.total_amount, None::<String>]).ok();
+        tx.execute("INSERT INTO supplier_history (id, supplier_id, date, type, notes, amount, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![h_id, transaction.party_id, Utc::now().to_rfc3339(), "Purchase Order Completed", format!("Order {}", transaction.transaction_number), transaction.total_amount, None::<String>])?;
     }
 
     // 3. Update Status
-    conn.execute(
+    tx.execute(
         "UPDATE transactions SET status = 'Completed', updated_at = ?2 WHERE id = ?1",
         params![tx_id, Utc::now().to_rfc3339()],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
+
+    tx.commit()?;
+    Ok(())
+}
+
+/// Cancel (void) a transaction. For a Completed transaction the stock movements and the
+/// party balance charge are reversed; the items, payments and history rows are kept for
+/// audit. Payments already taken stay on the client/supplier account until refunded.
+#[tauri::command]
+pub fn cancel_transaction(
+    pool: State<'_, DbPool>,
+    tx_id: String,
+    reason: String,
+    cancelled_by: Option<String>,
+) -> Result<(), AppError> {
+    if reason.trim().is_empty() {
+        return Err(AppError::Validation("A cancellation reason is required".to_string()));
+    }
+
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+
+    let details = get_transaction_by_id_internal(&tx, tx_id.clone())?
+        .ok_or_else(|| AppError::NotFound(format!("Transaction {} not found", tx_id)))?;
+    let transaction = &details.transaction;
+
+    if transaction.status == "Cancelled" {
+        return Err(AppError::Conflict(format!(
+            "Transaction {} is already cancelled",
+            transaction.transaction_number
+        )));
+    }
+    ensure_no_returns(&tx, &details)?;
+
+    let was_completed = transaction.status == "Completed";
+    let now = Utc::now().to_rfc3339();
+    reservation::release_source(&tx, &tx_id)?;
+
+    if was_completed {
+        // 1. Reverse Inventory
+        for item in &details.items {
+            if let Some(item_id) = &item.item_id {
+                let qty_change = -stock_direction(&transaction.transaction_type) * item.quantity as i64;
+                let location_id = transaction.location_id.as_deref();
+                inventory::apply_stock_change(&tx, item_id, location_id, qty_change, false)?;
+                inventory::log_stock_event(
+                    &tx,
+                    item_id,
+                    location_id,
+                    "Cancelled",
+                    qty_change,
+                    &format!("{} {} cancelled: {}", transaction.transaction_type, transaction.transaction_number, reason),
+                    &transaction.id,
+                )?;
+                cost_stock_movement(&tx, transaction, item, true)?;
+                move_line_serials(&tx, transaction, item, true)?;
+            }
+        }
+
+        // 2. Reverse Party Balance (sales and purchases charged the party, returns credited it)
+        let balance_change = if is_return_type(&transaction.transaction_type) {
+            transaction.total_amount
+        } else {
+            -transaction.total_amount
+        };
+        let label = match transaction.transaction_type.as_str() {
+            "Sale" => "Sale",
+            "Return" => "Return",
+            "PurchaseReturn" => "Purchase Return",
+            _ => "Purchase Order",
+        };
+        let h_id = Uuid::new_v4().to_string();
+        if transaction.party_type == "Client" {
+            tx.execute("UPDATE clients SET credit_balance = COALESCE(credit_balance, 0) + ?1 WHERE id = ?2", params![balance_change, transaction.party_id])?;
+            tx.execute("INSERT INTO client_history (id, client_id, date, type, notes, amount, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![h_id, transaction.party_id, now, format!("{} Cancelled", label), format!("{} {} cancelled: {}", label, transaction.transaction_number, reason), balance_change, cancelled_by])?;
+        } else {
+            tx.execute("UPDATE suppliers SET credit_balance = COALESCE(credit_balance, 0) + ?1 WHERE id = ?2", params![balance_change, transaction.party_id])?;
+            tx.execute("INSERT INTO supplier_history (id, supplier_id, date, type, notes, amount, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![h_id, transaction.party_id, now, format!("{} Cancelled", label), format!("{} {} cancelled: {}", label, transaction.transaction_number, reason), balance_change, cancelled_by])?;
+        }
+    }
+
+    // 3. Update Status
+    tx.execute(
+        "UPDATE transactions SET status = 'Cancelled', updated_at = ?2 WHERE id = ?1",
+        params![tx_id, now],
+    )?;
+
+    // 4. Log History
+    let h_id = Uuid::new_v4().to_string();
+    tx.execute(
+        "INSERT INTO transaction_history (id, transaction_id, date, event_type, details, changed_by)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            h_id,
+            tx_id,
+            now,
+            "cancelled",
+            format!(
+                "{} {} cancelled ({}): {}",
+                transaction.transaction_type,
+                transaction.transaction_number,
+                if was_completed { "stock and balance reversed" } else { "draft" },
+                reason
+            ),
+            cancelled_by,
+        ],
+    )?;
 
+    tx.commit()?;
     Ok(())
 }
 
-fn recalculate_transaction_totals(tx_id: &str) -> Result<(), String> {
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+/// Spread the document discount again over the lines, e.g. after one was added or removed.
+fn reprice_items(conn: &Connection, tx_id: &str, discount: &Discount) -> Result<Money, AppError> {
+    let mut items: Vec<TransactionItem> = {
+        let mut stmt = conn
+            .prepare_cached(&format!("SELECT {} FROM transaction_items WHERE transaction_id = ?1", ITEM_COLUMNS))?;
+        let rows = stmt.query_map(params![tx_id], item_from_row)?;
+        rows.collect::<Result<_, _>>()?
+    };
+    let document_discount = apply_discounts(Some(discount), &mut items)?;
+
+    for item in &items {
+        conn.execute(
+            "UPDATE transaction_items SET discount_amount = ?2, document_discount_amount = ?3, net_amount = ?4, tax_amount = ?5, total_price = ?6 WHERE id = ?1",
+            params![item.id, item.discount_amount, item.document_discount_amount, item.net_amount, item.tax_amount, item.total_price],
+        )?;
+    }
+    Ok(document_discount)
+}
 
-    let total_amount: f64 = conn
+fn recalculate_transaction_totals(conn: &Connection, tx_id: &str) -> Result<(), AppError> {
+    let discount: Option<Discount> = conn
         .query_row(
-            "SELECT COALESCE(SUM(total_price), 0) FROM transaction_items WHERE transaction_id = ?1",
+            "SELECT discount_type, discount_value, discount_reason, discount_by FROM transactions WHERE id = ?1",
             params![tx_id],
-            |row| row.get(0),
+            |row| discount_from_row(row, 0),
+        )
+        .optional()?
+        .flatten();
+    let discount_amount = match &discount {
+        Some(discount) => reprice_items(conn, tx_id, discount)?,
+        None => Money::ZERO,
+    };
+
+    let (total_amount, subtotal, tax_amount): (Money, Money, Money) = conn
+        .query_row(
+            "SELECT COALESCE(SUM(total_price), 0), COALESCE(SUM(net_amount), 0), COALESCE(SUM(tax_amount), 0) FROM transaction_items WHERE transaction_id = ?1",
+            params![tx_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
         )
-        .unwrap_or(0.0);
+        .unwrap_or((Money::ZERO, Money::ZERO, Money::ZERO));
 
-    let paid_amount: f64 = conn
+    let paid_amount: Money = conn
         .query_row(
             "SELECT COALESCE(SUM(amount), 0) FROM transaction_payments WHERE transaction_id = ?1",
             params![tx_id],
             |row| row.get(0),
         )
-        .unwrap_or(0.0);
+        .unwrap_or(Money::ZERO);
 
     let payment_status = if paid_amount >= total_amount {
         "Paid"
-    } else if paid_amount > 0.0 {
+    } else if paid_amount.is_positive() {
         "Partially"
     } else {
         "Unpaid"
     };
 
     conn.execute(
-        "UPDATE transactions SET total_amount = ?1, paid_amount = ?2, payment_status = ?3, updated_at = ?4 WHERE id = ?5",
-        params![total_amount, paid_amount, payment_status, Utc::now().to_rfc3339(), tx_id]
-    ).map_err(|e| e.to_string())?;
+        "UPDATE transactions SET total_amount = ?1, paid_amount = ?2, payment_status = ?3, updated_at = ?4, subtotal = ?6, tax_amount = ?7, discount_amount = ?8 WHERE id = ?5",
+        params![total_amount, paid_amount, payment_status, Utc::now().to_rfc3339(), tx_id, subtotal, tax_amount, discount_amount]
+    )?;
 
     Ok(())
 }
 
 fn apply_transaction_impact_internal(
-    tx: &rusqlite::Transaction,
+    tx: &Connection,
     transaction: &Transaction,
     items: &Vec<TransactionItem>,
     payments: &Vec<TransactionPayment>,
-) -> Result<(), String> {
+    allow_oversell: bool,
+) -> Result<(), AppError> {
     if transaction.status == "Completed" {
         // Inventory
         for item in items {
             if let Some(item_id) = &item.item_id {
-                let qty_change = if transaction.transaction_type == "Sale" {
-                    -(item.quantity as i64)
-                } else {
-                    item.quantity as i64
-                };
-                tx.execute("UPDATE inventory_items SET quantity_in_stock = COALESCE(quantity_in_stock, 0) + ?1 WHERE id = ?2", params![qty_change, item_id]).map_err(|e| e.to_string())?;
-
-                let h_id = Uuid::new_v4().to_string();
-                tx.execute(
-                    "INSERT INTO inventory_history (id, item_id, date, event_type, quantity_change, notes, related_id) 
-                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-                    params![h_id, item_id, Utc::now().to_rfc3339(), if transaction.transaction_type == "Sale" { "Sold" } else { "Purchased" }, qty_change, format!("{} {}", transaction.transaction_type, transaction.transaction_number), transaction.id],
-                ).map_err(|e| e.to_string())?;
+                let qty_change = stock_direction(&transaction.transaction_type) * item.quantity as i64;
+                let location_id = transaction.location_id.as_deref();
+                inventory::apply_stock_change(tx, item_id, location_id, qty_change, allow_oversell)?;
+                inventory::log_stock_event(
+                    tx,
+                    item_id,
+                    location_id,
+                    if transaction.transaction_type == "Sale" { "Sold" } else { "Purchased" },
+                    qty_change,
+                    &format!("{} {}", transaction.transaction_type, transaction.transaction_number),
+                    &transaction.id,
+                )?;
+                cost_stock_movement(tx, transaction, item, false)?;
+                move_line_serials(tx, transaction, item, false)?;
             }
         }
 
         // Party Balance
         if transaction.party_type == "Client" {
             // Increase client balance by total
-            tx.execute("UPDATE clients SET credit_balance = COALESCE(credit_balance, 0) + ?1 WHERE id = ?2", params![transaction.total_amount, transaction.party_id]).map_err(|e| e.to_string())?;
+            tx.execute("UPDATE clients SET credit_balance = COALESCE(credit_balance, 0) + ?1 WHERE id = ?2", params![transaction.total_amount, transaction.party_id])?;
             let h_id = Uuid::new_v4().to_string();
             tx.execute("INSERT INTO client_history (id, client_id, date, type, notes, amount, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-                params![h_id, transaction.party_id, Utc::now().to_rfc3339(), "Sale Completed", format!("Sale {}", transaction.transaction_number), transaction.total_amount, None::<String>]).map_err(|e| e.to_string())?;
+                params![h_id, transaction.party_id, Utc::now().to_rfc3339(), "Sale Completed", format!("Sale {}", transaction.transaction_number), transaction.total_amount, None::<String>])?;
 
             // Subtract payments from balance
             for payment in payments {
-                tx.execute("UPDATE clients SET credit_balance = COALESCE(credit_balance, 0) - ?1 WHERE id = ?2", params![payment.amount, transaction.party_id]).map_err(|e| e.to_string())?;
+                tx.execute("UPDATE clients SET credit_balance = COALESCE(credit_balance, 0) - ?1 WHERE id = ?2", params![payment.amount, transaction.party_id])?;
                 let p_h_id = Uuid::new_v4().to_string();
                 tx.execute("INSERT INTO client_history (id, client_id, date, type, notes, amount, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-                    params![p_h_id, transaction.party_id, Utc::now().to_rfc3339(), "Payment Received", format!("Payment for Sale {}", transaction.transaction_number), -payment.amount, payment.received_by]).map_err(|e| e.to_string())?;
+                    params![p_h_id, transaction.party_id, Utc::now().to_rfc3339(), "Payment Received", format!("Payment for Sale {}", transaction.transaction_number), -payment.amount, payment.received_by])?;
             }
         } else {
             // Supplier
-            tx.execute("UPDATE suppliers SET credit_balance = COALESCE(credit_balance, 0) + ?1 WHERE id = ?2", params![transaction.total_amount, transaction.party_id]).map_err(|e| e.to_string())?;
+            tx.execute("UPDATE suppliers SET credit_balance = COALESCE(credit_balance, 0) + ?1 WHERE id = ?2", params![transaction.total_amount, transaction.party_id])?;
             let h_id = Uuid::new_v4().to_string();
             tx.execute("INSERT INTO supplier_history (id, supplier_id, date, type, notes, amount, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
                 params![h_id, transaction.party_id, Utc::now().to_rfc3339(), "Purchase Order Completed", format!("Order {}", transaction.transaction_number), transaction.total_amount, None::<String>],
-            ).map_err(|e| e.to_string())?;
+            )?;
 
             // Subtract payments
             for payment in payments {
-                tx.execute("UPDATE suppliers SET credit_balance = COALESCE(credit_balance, 0) - ?1 WHERE id = ?2", params![payment.amount, transaction.party_id]).map_err(|e| e.to_string())?;
+                tx.execute("UPDATE suppliers SET credit_balance = COALESCE(credit_balance, 0) - ?1 WHERE id = ?2", params![payment.amount, transaction.party_id])?;
                 let p_h_id = Uuid::new_v4().to_string();
                 tx.execute("INSERT INTO supplier_history (id, supplier_id, date, type, notes, amount, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-                    params![p_h_id, transaction.party_id, Utc::now().to_rfc3339(), "Payment Made", format!("Payment for Purchase {}", transaction.transaction_number), -payment.amount, payment.received_by]).map_err(|e| e.to_string())?;
+                    params![p_h_id, transaction.party_id, Utc::now().to_rfc3339(), "Payment Made", format!("Payment for Purchase {}", transaction.transaction_number), -payment.amount, payment.received_by])?;
             }
         }
     }
@@ -735,69 +1226,88 @@ fn apply_transaction_impact_internal(
 
 #[tauri::command]
 pub fn update_transaction(
-    transaction: Transaction,
-    items: Vec<TransactionItem>,
-    payments: Vec<TransactionPayment>,
-) -> Result<(), String> {
-    let mut conn = db::get_connection().map_err(|e| e.to_string())?;
-    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    pool: State<'_, DbPool>,
+    mut transaction: Transaction,
+    mut items: Vec<TransactionItem>,
+    mut payments: Vec<TransactionPayment>,
+    allow_oversell: Option<bool>,
+) -> Result<(), AppError> {
+    ensure_not_return(&transaction.transaction_type)?;
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
 
     // 1. Get old transaction and items for reversal
     let old_tx_details = get_transaction_by_id_internal(&tx, transaction.id.clone())?;
 
     if let Some(details) = old_tx_details {
+        if details.transaction.status == "Cancelled" {
+            return Err(AppError::Conflict(format!(
+                "Transaction {} is cancelled and cannot be edited",
+                details.transaction.transaction_number
+            )));
+        }
+        ensure_not_return(&details.transaction.transaction_type)?;
+        ensure_no_returns(&tx, &details)?;
         if details.transaction.status == "Completed" {
             // Reverse Inventory
             for item in &details.items {
                 if let Some(item_id) = &item.item_id {
-                    let qty_change = if details.transaction.transaction_type == "Sale" {
-                        item.quantity as i64
-                    } else {
-                        -(item.quantity as i64)
-                    };
-                    tx.execute("UPDATE inventory_items SET quantity_in_stock = COALESCE(quantity_in_stock, 0) + ?1 WHERE id = ?2", params![qty_change, item_id]).map_err(|e| e.to_string())?;
-
-                    let h_id = Uuid::new_v4().to_string();
-                    tx.execute(
-                        "INSERT INTO inventory_history (id, item_id, date, event_type, quantity_change, notes, related_id) 
-                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-                        params![h_id, item_id, Utc::now().to_rfc3339(), "Adjustment", qty_change, format!("Reversing {} for edit", details.transaction.transaction_number), details.transaction.id],
-                    ).map_err(|e| e.to_string())?;
+                    let qty_change = -stock_direction(&details.transaction.transaction_type) * item.quantity as i64;
+                    let location_id = details.transaction.location_id.as_deref();
+                    inventory::apply_stock_change(&tx, item_id, location_id, qty_change, true)?;
+                    inventory::log_stock_event(
+                        &tx,
+                        item_id,
+                        location_id,
+                        "Adjustment",
+                        qty_change,
+                        &format!("Reversing {} for edit", details.transaction.transaction_number),
+                        &details.transaction.id,
+                    )?;
+                    cost_stock_movement(&tx, &details.transaction, item, true)?;
+                    move_line_serials(&tx, &details.transaction, item, true)?;
                 }
             }
 
             // Reverse Party Balance
             if details.transaction.party_type == "Client" {
-                tx.execute("UPDATE clients SET credit_balance = COALESCE(credit_balance, 0) - ?1 WHERE id = ?2", params![details.transaction.total_amount, details.transaction.party_id]).map_err(|e| e.to_string())?;
+                tx.execute("UPDATE clients SET credit_balance = COALESCE(credit_balance, 0) - ?1 WHERE id = ?2", params![details.transaction.total_amount, details.transaction.party_id])?;
                 for payment in &details.payments {
-                    tx.execute("UPDATE clients SET credit_balance = COALESCE(credit_balance, 0) + ?1 WHERE id = ?2", params![payment.amount, details.transaction.party_id]).map_err(|e| e.to_string())?;
+                    tx.execute("UPDATE clients SET credit_balance = COALESCE(credit_balance, 0) + ?1 WHERE id = ?2", params![payment.amount, details.transaction.party_id])?;
                 }
             } else {
-                tx.execute("UPDATE suppliers SET credit_balance = COALESCE(credit_balance, 0) - ?1 WHERE id = ?2", params![details.transaction.total_amount, details.transaction.party_id]).map_err(|e| e.to_string())?;
+                tx.execute("UPDATE suppliers SET credit_balance = COALESCE(credit_balance, 0) - ?1 WHERE id = ?2", params![details.transaction.total_amount, details.transaction.party_id])?;
                 for payment in &details.payments {
-                    tx.execute("UPDATE suppliers SET credit_balance = COALESCE(credit_balance, 0) + ?1 WHERE id = ?2", params![payment.amount, details.transaction.party_id]).map_err(|e| e.to_string())?;
+                    tx.execute("UPDATE suppliers SET credit_balance = COALESCE(credit_balance, 0) + ?1 WHERE id = ?2", params![payment.amount, details.transaction.party_id])?;
                 }
             }
         }
     } else {
-        return Err("Transaction not found".to_string());
+        return Err(AppError::NotFound("Transaction not found".to_string()));
     }
+    // The new lines take the place of whatever the draft was holding
+    reservation::release_source(&tx, &transaction.id)?;
 
-    // 2. Clear old items and payments
+    // 2. Clear old items, their serial numbers and payments
+    tx.execute(
+        "DELETE FROM line_serial_numbers WHERE line_id IN (SELECT id FROM transaction_items WHERE transaction_id = ?1)",
+        params![transaction.id],
+    )?;
     tx.execute(
         "DELETE FROM transaction_items WHERE transaction_id = ?1",
         params![transaction.id],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
     tx.execute(
         "DELETE FROM transaction_payments WHERE transaction_id = ?1",
         params![transaction.id],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
 
-    // 3. Update Header
+    // 3. Update Header, with totals taken from the discounted and taxed lines
+    apply_items_tax(&tx, &mut transaction, &mut items)?;
+    transaction.location_id = Some(location::resolve_location(&tx, transaction.location_id.as_deref())?);
+    let (discount_type, discount_value, discount_reason, discount_by) = discount_columns(&transaction.discount);
     tx.execute(
-        "UPDATE transactions SET transaction_type = ?1, party_id = ?2, party_type = ?3, status = ?4, payment_status = ?5, total_amount = ?6, paid_amount = ?7, notes = ?8, updated_at = ?9 WHERE id = ?10",
+        "UPDATE transactions SET transaction_type = ?1, party_id = ?2, party_type = ?3, status = ?4, payment_status = ?5, total_amount = ?6, paid_amount = ?7, notes = ?8, updated_at = ?9, subtotal = ?11, tax_amount = ?12, discount_type = ?13, discount_value = ?14, discount_reason = ?15, discount_by = ?16, discount_amount = ?17, location_id = ?18 WHERE id = ?10",
         params![
             transaction.transaction_type,
             transaction.party_id,
@@ -809,29 +1319,30 @@ pub fn update_transaction(
             transaction.notes,
             Utc::now().to_rfc3339(),
             transaction.id,
+            transaction.subtotal,
+            transaction.tax_amount,
+            discount_type,
+            discount_value,
+            discount_reason,
+            discount_by,
+            transaction.discount_amount,
+            transaction.location_id,
         ],
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     // 4. Insert New Items
     for item in &items {
-        tx.execute(
-            "INSERT INTO transaction_items (id, transaction_id, item_id, item_name, quantity, unit_price, total_price, notes) 
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
-            params![item.id, item.transaction_id, item.item_id, item.item_name, item.quantity, item.unit_price, item.total_price, item.notes],
-        ).map_err(|e| e.to_string())?;
+        insert_item(&tx, item)?;
     }
+    reserve_draft_lines(&tx, &transaction, &items, allow_oversell.unwrap_or(false))?;
 
     // 5. Insert New Payments
-    for payment in &payments {
-        tx.execute(
-            "INSERT INTO transaction_payments (id, transaction_id, amount, method, date, received_by, notes, session_id) 
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
-            params![payment.id, payment.transaction_id, payment.amount, payment.method, payment.date, payment.received_by, payment.notes, payment.session_id],
-        ).map_err(|e| e.to_string())?;
-    }
+    insert_payments(&tx, &mut payments)?;
+
+    recalculate_transaction_totals(&tx, &transaction.id)?;
 
     // 6. Apply Impact if Completed
-    apply_transaction_impact_internal(&tx, &transaction, &items, &payments)?;
+    apply_transaction_impact_internal(&tx, &transaction, &items, &payments, allow_oversell.unwrap_or(false))?;
 
     // 7. Log History
     let h_id = Uuid::new_v4().to_string();
@@ -839,33 +1350,106 @@ pub fn update_transaction(
         "INSERT INTO transaction_history (id, transaction_id, date, event_type, details, changed_by)
          VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
         params![h_id, transaction.id, Utc::now().to_rfc3339(), "updated", format!("Updated {} {}", transaction.transaction_type, transaction.transaction_number), transaction.created_by],
-    ).map_err(|e| e.to_string())?;
+    )?;
 
-    tx.commit().map_err(|e| e.to_string())?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Store the payments sent with a transaction, converting those tendered in another
+/// currency to the base one. A payment carried over by an edit keeps the rate it was
+/// taken at.
+fn insert_payments(conn: &Connection, payments: &mut [TransactionPayment]) -> Result<(), AppError> {
+    let base = currency().code;
+    for payment in payments.iter_mut() {
+        // The amount of a base-currency payment is what counts, even if it was edited
+        let foreign = payment
+            .currency_code
+            .as_deref()
+            .is_some_and(|code| !code.trim().eq_ignore_ascii_case(&base));
+        match (foreign, payment.exchange_rate, payment.original_amount) {
+            (true, Some(rate), Some(original)) => {
+                (payment.amount, _) = edited_amount(payment.currency_code.as_deref(), Some(rate), original);
+            }
+            _ => {
+                let tendered = tendered_amount(
+                    conn,
+                    payment.currency_code.as_deref(),
+                    payment.original_amount.filter(|_| foreign),
+                    payment.amount,
+                    &payment.date,
+                )?;
+                payment.amount = tendered.amount;
+                payment.currency_code = Some(tendered.currency_code);
+                payment.original_amount = Some(tendered.original_amount);
+                payment.exchange_rate = Some(tendered.exchange_rate);
+            }
+        }
+
+        conn.execute(
+            "INSERT INTO transaction_payments (id, transaction_id, amount, method, date, received_by, notes, session_id, currency_code, original_amount, exchange_rate)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                payment.id,
+                payment.transaction_id,
+                payment.amount,
+                payment.method,
+                payment.date,
+                payment.received_by,
+                payment.notes,
+                payment.session_id,
+                payment.currency_code,
+                payment.original_amount,
+                payment.exchange_rate,
+            ],
+        )?;
+    }
     Ok(())
 }
 
 #[tauri::command]
 pub fn submit_transaction(
-    mut transaction: Transaction,
+    pool: State<'_, DbPool>,
+    transaction: Transaction,
     items: Vec<TransactionItem>,
     payments: Vec<TransactionPayment>,
-) -> Result<(), String> {
-    let mut conn = db::get_connection().map_err(|e| e.to_string())?;
+    allow_oversell: Option<bool>,
+) -> Result<(), AppError> {
+    ensure_not_return(&transaction.transaction_type)?;
+    let mut conn = pool.get()?;
 
     // Start a manual SQL transaction
-    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let tx = conn.transaction()?;
+    let transaction = insert_submitted_transaction(&tx, transaction, items, payments, allow_oversell.unwrap_or(false))?;
+    tx.commit()?;
 
+    // Log party history (post-commit to ensure transaction exists)
+    log_party_submission(&conn, &transaction);
+    Ok(())
+}
+
+/// `submit_transaction` inside the caller's SQL transaction. Returns the transaction as
+/// stored, numbered and with its totals.
+pub(crate) fn insert_submitted_transaction(
+    tx: &Connection,
+    mut transaction: Transaction,
+    mut items: Vec<TransactionItem>,
+    mut payments: Vec<TransactionPayment>,
+    allow_oversell: bool,
+) -> Result<Transaction, AppError> {
     // 1. Generate number if needed
     if transaction.transaction_number.is_empty() {
         transaction.transaction_number =
-            generate_transaction_number_internal(&tx, &transaction.transaction_type)?;
+            generate_transaction_number_internal(tx, &transaction.transaction_type)?;
     }
 
-    // 2. Insert Header
+    // 2. Insert Header, with totals taken from the discounted and taxed lines
+    apply_items_tax(tx, &mut transaction, &mut items)?;
+    transaction.location_id = Some(location::resolve_location(tx, transaction.location_id.as_deref())?);
+    let (discount_type, discount_value, discount_reason, discount_by) = discount_columns(&transaction.discount);
     tx.execute(
-        "INSERT INTO transactions (id, transaction_number, transaction_type, party_id, party_type, status, payment_status, total_amount, paid_amount, notes, created_at, updated_at, created_by)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+        "INSERT INTO transactions (id, transaction_number, transaction_type, party_id, party_type, status, payment_status, total_amount, paid_amount, notes, created_at, updated_at, created_by, subtotal, tax_amount, discount_type, discount_value, discount_reason, discount_by, discount_amount, location_id)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21)",
         params![
             transaction.id,
             transaction.transaction_number,
@@ -880,29 +1464,30 @@ pub fn submit_transaction(
             transaction.created_at,
             transaction.updated_at,
             transaction.created_by,
+            transaction.subtotal,
+            transaction.tax_amount,
+            discount_type,
+            discount_value,
+            discount_reason,
+            discount_by,
+            transaction.discount_amount,
+            transaction.location_id,
         ],
-    ).map_err(|e| e.to_string())?;
+    )?;
 
     // 3. Insert Items
     for item in &items {
-        tx.execute(
-            "INSERT INTO transaction_items (id, transaction_id, item_id, item_name, quantity, unit_price, total_price, notes) 
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
-            params![item.id, item.transaction_id, item.item_id, item.item_name, item.quantity, item.unit_price, item.total_price, item.notes],
-        ).map_err(|e| e.to_string())?;
+        insert_item(tx, item)?;
     }
+    reserve_draft_lines(tx, &transaction, &items, allow_oversell)?;
 
     // 4. Insert Payments
-    for payment in &payments {
-        tx.execute(
-            "INSERT INTO transaction_payments (id, transaction_id, amount, method, date, received_by, notes, session_id) 
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
-            params![payment.id, payment.transaction_id, payment.amount, payment.method, payment.date, payment.received_by, payment.notes, payment.session_id],
-        ).map_err(|e| e.to_string())?;
-    }
+    insert_payments(tx, &mut payments)?;
+
+    recalculate_transaction_totals(tx, &transaction.id)?;
 
     // 5. If status is Completed, handle inventory and balance
-    apply_transaction_impact_internal(&tx, &transaction, &items, &payments)?;
+    apply_transaction_impact_internal(tx, &transaction, &items, &payments, allow_oversell)?;
 
     // 6. Log History
     let h_id = Uuid::new_v4().to_string();
@@ -910,12 +1495,13 @@ pub fn submit_transaction(
         "INSERT INTO transaction_history (id, transaction_id, date, event_type, details, changed_by)
          VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
         params![h_id, transaction.id, Utc::now().to_rfc3339(), if transaction.status == "Completed" { "completed" } else { "created_draft" }, format!("{} {} {}", transaction.transaction_type, transaction.transaction_number, transaction.status), transaction.created_by],
-    ).map_err(|e| e.to_string())?;
+    )?;
 
-    tx.commit().map_err(|e| e.to_string())?;
+    Ok(transaction)
+}
 
-    // Log party history (post-commit to ensure transaction exists)
-    let conn = db::get_connection().map_err(|e| e.to_string())?;
+/// Note a submitted transaction in the client's or supplier's history.
+pub(crate) fn log_party_submission(conn: &Connection, transaction: &Transaction) {
     let history_sql = if transaction.party_type == "Client" {
         "INSERT INTO client_history (id, client_id, date, type, notes, amount, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)"
     } else {
@@ -928,8 +1514,430 @@ pub fn submit_transaction(
     let h_id = Uuid::new_v4().to_string();
     conn.execute(
         history_sql,
-        params![h_id, transaction.party_id, Utc::now().to_rfc3339(), event_type, format!("{} {} submitted", party_type_label, transaction.transaction_number), 0.0, transaction.created_by],
+        params![h_id, transaction.party_id, Utc::now().to_rfc3339(), event_type, format!("{} {} submitted", party_type_label, transaction.transaction_number), Money::ZERO, transaction.created_by],
     ).ok();
+}
 
+// ======================
+// RETURNS
+// ======================
+
+/// `Return` (customer) and `PurchaseReturn` (to a supplier) documents undo part of an
+/// original Sale or Purchase.
+fn is_return_type(transaction_type: &str) -> bool {
+    transaction_type == "Return" || transaction_type == "PurchaseReturn"
+}
+
+/// Sign of what a payment does to the party's balance. Paying for a sale or purchase
+/// settles what the party owes; a refund on a return settles the credit the return gave.
+fn payment_direction(transaction_type: &str) -> i64 {
+    if is_return_type(transaction_type) {
+        1
+    } else {
+        -1
+    }
+}
+
+/// Sign of the stock movement a completed transaction of this type makes.
+fn stock_direction(transaction_type: &str) -> i64 {
+    match transaction_type {
+        "Sale" | "PurchaseReturn" => -1,
+        _ => 1,
+    }
+}
+
+/// Returns reference their original document and are only created through
+/// `create_return` / `create_purchase_return`.
+fn ensure_not_return(transaction_type: &str) -> Result<(), AppError> {
+    if is_return_type(transaction_type) {
+        return Err(AppError::Validation(
+            "Returns must be created with create_return or create_purchase_return".to_string(),
+        ));
+    }
     Ok(())
 }
+
+/// Units of a sale or purchase line already sent back by non-cancelled returns.
+fn returned_quantity(conn: &Connection, original_item_id: &str) -> Result<i64, AppError> {
+    let qty: i64 = conn.query_row(
+        "SELECT COALESCE(SUM(ti.quantity), 0)
+         FROM transaction_items ti
+         JOIN transactions t ON ti.transaction_id = t.id
+         WHERE ti.original_item_id = ?1 AND t.transaction_type IN ('Return', 'PurchaseReturn') AND t.status != 'Cancelled'",
+        params![original_item_id],
+        |row| row.get(0),
+    )?;
+    Ok(qty)
+}
+
+/// Goods sent back to a supplier, newest first.
+pub(crate) fn purchase_returns_for_supplier(conn: &Connection, supplier_id: &str) -> Result<Vec<Transaction>, AppError> {
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT {} FROM transactions
+         WHERE party_id = ?1 AND party_type = 'Supplier' AND transaction_type = 'PurchaseReturn'
+         ORDER BY created_at DESC",
+        TRANSACTION_COLUMNS
+    ))?;
+    let returns = stmt
+        .query_map(params![supplier_id], transaction_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(returns)
+}
+
+/// A sale or purchase can't be cancelled or rewritten while returns against it stand:
+/// they already gave back part of its stock and balance, and point at its lines.
+fn ensure_no_returns(conn: &Connection, details: &TransactionWithDetails) -> Result<(), AppError> {
+    for item in &details.items {
+        if returned_quantity(conn, &item.id)? > 0 {
+            return Err(AppError::Conflict(format!(
+                "{} {} has returns against it; cancel them first",
+                details.transaction.transaction_type, details.transaction.transaction_number
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Validate the requested lines against the original document and record the return:
+/// header, items, stock movements and `transaction_history` on both documents.
+/// Party balances are left to the caller.
+fn insert_return_document(
+    tx: &Connection,
+    original_transaction_id: &str,
+    return_type: &str,
+    items: &[ReturnItemRequest],
+    refunded: bool,
+    reason: &Option<String>,
+    created_by: &Option<String>,
+) -> Result<(Transaction, Transaction), AppError> {
+    if items.is_empty() {
+        return Err(AppError::Validation("A return needs at least one item".to_string()));
+    }
+
+    let (original_type, party_type, label, event_type, returnable_statuses) = if return_type == "Return" {
+        ("Sale", "Client", "sale", "Returned", &["Sold"][..])
+    } else {
+        ("Purchase", "Supplier", "purchase", "Returned to Supplier", &serial::ON_HAND[..])
+    };
+
+    let original = get_transaction_by_id_internal(tx, original_transaction_id.to_string())?
+        .ok_or_else(|| AppError::NotFound(format!("Transaction {} not found", original_transaction_id)))?;
+
+    if original.transaction.transaction_type != original_type || original.transaction.party_type != party_type {
+        return Err(AppError::Validation(format!(
+            "{} is a {}, only a {} can be returned here",
+            original.transaction.transaction_number, original.transaction.transaction_type, original_type
+        )));
+    }
+    if original.transaction.status != "Completed" {
+        return Err(AppError::Conflict(format!(
+            "{} {} is not completed and cannot be returned",
+            original_type, original.transaction.transaction_number
+        )));
+    }
+
+    // 1. Validate quantities against what is left to return on each original line
+    let mut lines: Vec<(&TransactionItem, i32, Vec<String>)> = Vec::new();
+    for request in items {
+        let line = original
+            .items
+            .iter()
+            .find(|item| item.id == request.original_item_id)
+            .ok_or_else(|| AppError::Validation(format!(
+                "Item {} is not part of {} {}",
+                request.original_item_id, label, original.transaction.transaction_number
+            )))?;
+
+        let already_returned = returned_quantity(tx, &line.id)?
+            + lines
+                .iter()
+                .filter(|(item, _, _)| item.id == line.id)
+                .map(|(_, qty, _)| *qty as i64)
+                .sum::<i64>();
+        let returnable = line.quantity as i64 - already_returned;
+        if request.quantity <= 0 || request.quantity as i64 > returnable {
+            return Err(AppError::Validation(format!(
+                "Cannot return {} of '{}': {} of {} can still be returned",
+                request.quantity, line.item_name, returnable.max(0), line.quantity
+            )));
+        }
+
+        // Serialized units must come from the original line. Without any named, the
+        // line's units still out are taken if that is exactly how many come back.
+        let mut serials: Vec<String> = request.serial_numbers.iter().map(|s| serial::normalize_serial(s)).collect();
+        if let Some(serial) = serials.iter().find(|s| !line.serial_numbers.contains(s)) {
+            return Err(AppError::Validation(format!(
+                "Serial number {} is not part of {} {}",
+                serial, label, original.transaction.transaction_number
+            )));
+        }
+        if serials.is_empty() {
+            let out = serial::line_units_in(tx, &line.id, returnable_statuses)?;
+            if out.len() as i64 == request.quantity as i64 {
+                serials = out;
+            }
+        }
+        lines.push((line, request.quantity, serials));
+    }
+
+    // 2. Insert Header; return lines are taxed at the rate of the line they undo and
+    // carry their share of its discounts, so the refund is what was paid
+    let now = Utc::now().to_rfc3339();
+    let return_id = Uuid::new_v4().to_string();
+    let return_items: Vec<TransactionItem> = lines
+        .iter()
+        .map(|(line, qty, serials)| {
+            let line_discount = line.discount_amount + line.document_discount_amount;
+            let returned_discount = Money::from_minor(
+                (line_discount.minor() as f64 * *qty as f64 / line.quantity as f64).round() as i64,
+            );
+            let discount = line
+                .discount
+                .as_ref()
+                .or(original.transaction.discount.as_ref())
+                .filter(|_| !returned_discount.is_zero())
+                .map(|d| Discount {
+                    kind: "Fixed".to_string(),
+                    value: returned_discount.to_major(),
+                    reason: d.reason.clone(),
+                    applied_by: d.applied_by.clone(),
+                });
+            let mut item = TransactionItem {
+                id: Uuid::new_v4().to_string(),
+                transaction_id: return_id.clone(),
+                item_id: line.item_id.clone(),
+                item_name: line.item_name.clone(),
+                quantity: *qty,
+                unit_price: line.unit_price,
+                total_price: Money::ZERO,
+                notes: reason.clone(),
+                original_item_id: Some(line.id.clone()),
+                tax_rate_id: line.tax_rate_id.clone(),
+                tax_rate: line.tax_rate,
+                tax_inclusive: line.tax_inclusive,
+                net_amount: Money::ZERO,
+                tax_amount: Money::ZERO,
+                discount,
+                discount_amount: returned_discount,
+                document_discount_amount: Money::ZERO,
+                unit_cost: line.unit_cost,
+                serial_numbers: serials.clone(),
+            };
+            compute_item_tax(&mut item);
+            item
+        })
+        .collect();
+    let total_amount: Money = return_items.iter().map(|item| item.total_price).sum();
+    let return_tx = Transaction {
+        id: return_id,
+        transaction_number: generate_transaction_number_internal(tx, return_type)?,
+        transaction_type: return_type.to_string(),
+        party_id: original.transaction.party_id.clone(),
+        party_type: party_type.to_string(),
+        status: "Completed".to_string(),
+        payment_status: if refunded { "Paid" } else { "Unpaid" }.to_string(),
+        total_amount,
+        paid_amount: if refunded { total_amount } else { Money::ZERO },
+        notes: reason.clone(),
+        created_at: now.clone(),
+        updated_at: now.clone(),
+        created_by: created_by.clone(),
+        original_transaction_id: Some(original.transaction.id.clone()),
+        subtotal: return_items.iter().map(|item| item.net_amount).sum(),
+        tax_amount: return_items.iter().map(|item| item.tax_amount).sum(),
+        discount: None,
+        discount_amount: Money::ZERO,
+        // Stock goes back to (or leaves) the location of the original document
+        location_id: original.transaction.location_id.clone(),
+    };
+
+    tx.execute(
+        "INSERT INTO transactions (id, transaction_number, transaction_type, party_id, party_type, status, payment_status, total_amount, paid_amount, notes, created_at, updated_at, created_by, original_transaction_id, subtotal, tax_amount, location_id)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)",
+        params![
+            return_tx.id,
+            return_tx.transaction_number,
+            return_tx.transaction_type,
+            return_tx.party_id,
+            return_tx.party_type,
+            return_tx.status,
+            return_tx.payment_status,
+            return_tx.total_amount,
+            return_tx.paid_amount,
+            return_tx.notes,
+            return_tx.created_at,
+            return_tx.updated_at,
+            return_tx.created_by,
+            return_tx.original_transaction_id,
+            return_tx.subtotal,
+            return_tx.tax_amount,
+            return_tx.location_id,
+        ],
+    )?;
+
+    // 3. Insert Items and move stock
+    for item in &return_items {
+        insert_item(tx, item)?;
+
+        if let Some(item_id) = &item.item_id {
+            let qty_change = stock_direction(return_type) * item.quantity as i64;
+            let location_id = return_tx.location_id.as_deref();
+            inventory::apply_stock_change(tx, item_id, location_id, qty_change, false)?;
+            inventory::log_stock_event(
+                tx,
+                item_id,
+                location_id,
+                event_type,
+                qty_change,
+                &format!("Returned from {} {} ({})", label, original.transaction.transaction_number, return_tx.transaction_number),
+                &return_tx.id,
+            )?;
+            cost_stock_movement(tx, &return_tx, item, false)?;
+            move_line_serials(tx, &return_tx, item, false)?;
+        }
+    }
+
+    // 4. Log History on both the return and the original document
+    for (history_tx_id, details) in [
+        (&return_tx.id, format!("Return {} of {} {}", return_tx.transaction_number, label, original.transaction.transaction_number)),
+        (&original.transaction.id, format!("Items returned in {}", return_tx.transaction_number)),
+    ] {
+        let h_id = Uuid::new_v4().to_string();
+        tx.execute(
+            "INSERT INTO transaction_history (id, transaction_id, date, event_type, details, changed_by)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![h_id, history_tx_id, now, "returned", details, created_by],
+        )?;
+    }
+
+    Ok((return_tx, original.transaction))
+}
+
+/// Take back (part of) a completed sale. Returned units are restocked with a "Returned"
+/// inventory event and their value is credited to the client. With a `refund_method`
+/// the money is paid back as a refund payment (a debit in the session); without one
+/// it stays on the client's `credit_balance` as store credit.
+#[tauri::command]
+pub fn create_return(
+    pool: State<'_, DbPool>,
+    original_transaction_id: String,
+    items: Vec<ReturnItemRequest>,
+    refund_method: Option<String>,
+    reason: Option<String>,
+    session_id: Option<String>,
+    created_by: Option<String>,
+) -> Result<TransactionWithDetails, AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+
+    let (return_tx, original) = insert_return_document(
+        &tx,
+        &original_transaction_id,
+        "Return",
+        &items,
+        refund_method.is_some(),
+        &reason,
+        &created_by,
+    )?;
+    let now = Utc::now().to_rfc3339();
+
+    // Credit the client, then pay the refund out if requested
+    tx.execute(
+        "UPDATE clients SET credit_balance = COALESCE(credit_balance, 0) - ?1 WHERE id = ?2",
+        params![return_tx.total_amount, return_tx.party_id],
+    )?;
+    let h_id = Uuid::new_v4().to_string();
+    tx.execute("INSERT INTO client_history (id, client_id, date, type, notes, amount, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            h_id,
+            return_tx.party_id,
+            now,
+            if refund_method.is_some() { "Return" } else { "Store Credit Issued" },
+            format!("Return {} for sale {}", return_tx.transaction_number, original.transaction_number),
+            -return_tx.total_amount,
+            created_by,
+        ],
+    )?;
+
+    if let Some(method) = &refund_method {
+        tx.execute(
+            "INSERT INTO transaction_payments (id, transaction_id, amount, method, date, received_by, notes, session_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                Uuid::new_v4().to_string(),
+                return_tx.id,
+                return_tx.total_amount,
+                method,
+                now,
+                created_by,
+                format!("Refund for sale {}", original.transaction_number),
+                session_id,
+            ],
+        )?;
+        tx.execute(
+            "UPDATE clients SET credit_balance = COALESCE(credit_balance, 0) + ?1 WHERE id = ?2",
+            params![return_tx.total_amount, return_tx.party_id],
+        )?;
+        let h_id = Uuid::new_v4().to_string();
+        tx.execute("INSERT INTO client_history (id, client_id, date, type, notes, amount, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![h_id, return_tx.party_id, now, "Refund Paid", format!("Refund for return {} ({})", return_tx.transaction_number, method), return_tx.total_amount, created_by],
+        )?;
+    }
+
+    let details = get_transaction_by_id_internal(&tx, return_tx.id.clone())?
+        .ok_or_else(|| AppError::NotFound(format!("Transaction {} not found", return_tx.id)))?;
+    tx.commit()?;
+    Ok(details)
+}
+
+/// Send (part of) a completed purchase back to the supplier. Returned units leave stock
+/// with a "Returned to Supplier" inventory event and their value is taken off the
+/// supplier's `credit_balance`.
+#[tauri::command]
+pub fn create_purchase_return(
+    pool: State<'_, DbPool>,
+    original_transaction_id: String,
+    items: Vec<ReturnItemRequest>,
+    reason: Option<String>,
+    created_by: Option<String>,
+) -> Result<TransactionWithDetails, AppError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+
+    let (return_tx, original) = insert_return_document(
+        &tx,
+        &original_transaction_id,
+        "PurchaseReturn",
+        &items,
+        false,
+        &reason,
+        &created_by,
+    )?;
+
+    tx.execute(
+        "UPDATE suppliers SET credit_balance = COALESCE(credit_balance, 0) - ?1 WHERE id = ?2",
+        params![return_tx.total_amount, return_tx.party_id],
+    )?;
+    let h_id = Uuid::new_v4().to_string();
+    tx.execute("INSERT INTO supplier_history (id, supplier_id, date, type, notes, amount, changed_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            h_id,
+            return_tx.party_id,
+            Utc::now().to_rfc3339(),
+            "Purchase Return",
+            format!(
+                "Return {} for purchase {}{}",
+                return_tx.transaction_number,
+                original.transaction_number,
+                reason.as_ref().map(|r| format!(": {}", r)).unwrap_or_default()
+            ),
+            -return_tx.total_amount,
+            created_by,
+        ],
+    )?;
+
+    let details = get_transaction_by_id_internal(&tx, return_tx.id.clone())?
+        .ok_or_else(|| AppError::NotFound(format!("Transaction {} not found", return_tx.id)))?;
+    tx.commit()?;
+    Ok(details)
+}
@@ -0,0 +1,104 @@
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::fmt;
+
+/// Error returned by every `db` and `printing` command.
+///
+/// Serialized to the frontend as `{ code, message }` (plus `details` for stock errors),
+/// where `code` is a stable identifier the UI can match on to show localized messages.
+#[derive(Debug, Clone)]
+pub enum AppError {
+    /// The requested record does not exist.
+    NotFound(String),
+    /// The input was rejected before touching the database.
+    Validation(String),
+    /// Not enough stock to fulfil the requested quantity of an item.
+    InsufficientStock {
+        item_id: String,
+        item_name: String,
+        requested: i64,
+        available: i64,
+    },
+    /// The operation clashes with existing data (constraint violation, wrong state, ...).
+    Conflict(String),
+    /// Any other SQLite failure.
+    Database(String),
+    /// Printer discovery, connection or I/O failure.
+    Printer(String),
+}
+
+impl AppError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::NotFound(_) => "NOT_FOUND",
+            AppError::Validation(_) => "VALIDATION",
+            AppError::InsufficientStock { .. } => "INSUFFICIENT_STOCK",
+            AppError::Conflict(_) => "CONFLICT",
+            AppError::Database(_) => "DATABASE",
+            AppError::Printer(_) => "PRINTER",
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::NotFound(msg)
+            | AppError::Validation(msg)
+            | AppError::Conflict(msg)
+            | AppError::Database(msg)
+            | AppError::Printer(msg) => write!(f, "{}", msg),
+            AppError::InsufficientStock {
+                item_name,
+                requested,
+                available,
+                ..
+            } => write!(
+                f,
+                "Not enough stock for '{}'. Available: {}, Requested: {}",
+                item_name, available, requested
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("AppError", 3)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", &self.to_string())?;
+        match self {
+            AppError::InsufficientStock {
+                item_id,
+                item_name,
+                requested,
+                available,
+            } => state.serialize_field(
+                "details",
+                &serde_json::json!({
+                    "item_id": item_id,
+                    "item_name": item_name,
+                    "requested": requested,
+                    "available": available,
+                }),
+            )?,
+            _ => state.skip_field("details")?,
+        }
+        state.end()
+    }
+}
+
+impl From<rusqlite::Error> for AppError {
+    fn from(err: rusqlite::Error) -> Self {
+        match err {
+            rusqlite::Error::QueryReturnedNoRows => AppError::NotFound("Record not found".to_string()),
+            rusqlite::Error::SqliteFailure(ref e, _)
+                if e.code == rusqlite::ErrorCode::ConstraintViolation =>
+            {
+                AppError::Conflict(err.to_string())
+            }
+            other => AppError::Database(other.to_string()),
+        }
+    }
+}
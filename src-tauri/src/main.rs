@@ -2,6 +2,7 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod db;
+mod error;
 mod printing;
 mod system;
 
@@ -20,16 +21,30 @@ use db::order::{
     get_order_payments, get_orders, get_orders_by_supplier, remove_order_item, update_order,
     update_order_item,
 };
+use db::goods_receipt::{cancel_order_remainder, get_goods_receipts, receive_order_goods};
+use db::price_list::{
+    compare_supplier_prices, delete_supplier_item, get_supplier_price_list, save_supplier_item,
+};
 use db::repair::{
     add_payment, add_used_part, delete_repair, delete_repair_payment, delete_used_part,
     get_history_for_repair, get_payments_for_repair, get_repair_by_id, get_repairs,
-    get_used_parts_for_repair, insert_repair, insert_repair_history, update_repair,
-    update_repair_payment, update_repair_status,
+    get_used_parts_for_repair, insert_repair, insert_repair_history, query_repairs,
+    update_repair, update_repair_payment, update_repair_status,
+};
+use db::repair_status::{
+    delete_repair_status, get_repair_status_timeline, get_repair_statuses, get_repair_turnaround,
+    save_repair_status,
 };
 use db::sale::{
     add_sale_item, add_sale_payment, complete_sale, create_sale, get_sale_by_id, get_sales,
     remove_sale_item, update_sale, update_sale_item,
 };
+use db::currency::{
+    convert_to_base, get_currencies, get_exchange_rates, get_session_currency_breakdown,
+    save_currency, set_exchange_rate,
+};
+use db::migrations;
+use db::money::{get_currency_settings, set_currency_settings};
 use db::schema;
 use db::session::{
     close_session, get_current_session, get_current_session_transactions,
@@ -45,11 +60,38 @@ use db::supplier::{
     insert_supplier_history, update_supplier, update_supplier_payment,
 };
 use db::transaction::{
-    add_transaction_item, add_transaction_payment, complete_transaction, create_transaction,
-    delete_transaction_payment, get_transaction_by_id, get_transactions, remove_transaction_item,
+    add_transaction_item, add_transaction_payment, cancel_transaction, complete_transaction,
+    create_purchase_return, create_return, create_transaction, delete_transaction_payment,
+    get_transaction_by_id, get_transactions, query_transactions, remove_transaction_item,
     submit_transaction, update_transaction, update_transaction_payment,
 };
 use db::task::{delete_task, get_tasks, insert_task, update_task};
+use db::tax::{
+    delete_tax_rate, get_default_labor_tax_rate, get_tax_rates, get_tax_summary, save_tax_rate,
+    set_default_labor_tax_rate,
+};
+use db::costing::{get_cost_layers, get_costing_method, set_costing_method};
+use db::reservation::get_stock_reservations;
+use db::stock_take::{
+    approve_stock_take, cancel_stock_take, get_stock_take, get_stock_take_variance_report,
+    get_stock_takes, record_stock_count, scan_stock_count, start_stock_take,
+};
+use db::location::{
+    create_stock_transfer, get_item_stock_by_location, get_location_stock, get_locations,
+    get_stock_transfer, get_stock_transfers, save_location, set_default_location,
+    set_location_low_stock_threshold,
+};
+use db::serial::{
+    get_serial_history, get_serial_units, register_serial_units, set_serial_unit_status,
+};
+use db::product::{delete_product, get_product_variants, get_products, save_product};
+use db::reorder::{
+    create_reorder_documents, get_reorder_settings, get_reorder_suggestions, set_reorder_settings,
+};
+use db::shopping_list::{
+    convert_shopping_list_to_order, delete_shopping_list_item, get_shopping_list, save_shopping_list_item,
+    set_shopping_list_status,
+};
 use db::payment::get_all_payments;
 use std::panic;
 
@@ -65,8 +107,62 @@ fn main() {
 
     // Use a more robust approach to start the application
     match tauri::Builder::default()
+        .manage(db::DbPool::new(db::current_db_path()))
         .invoke_handler(tauri::generate_handler![
             schema::init_database,
+            migrations::get_schema_status,
+            get_currency_settings,
+            set_currency_settings,
+            get_currencies,
+            save_currency,
+            set_exchange_rate,
+            get_exchange_rates,
+            convert_to_base,
+            get_session_currency_breakdown,
+            get_tax_rates,
+            save_tax_rate,
+            delete_tax_rate,
+            get_default_labor_tax_rate,
+            set_default_labor_tax_rate,
+            get_tax_summary,
+            get_costing_method,
+            set_costing_method,
+            get_cost_layers,
+            get_stock_reservations,
+            start_stock_take,
+            get_stock_takes,
+            get_stock_take,
+            record_stock_count,
+            scan_stock_count,
+            get_stock_take_variance_report,
+            approve_stock_take,
+            cancel_stock_take,
+            get_locations,
+            save_location,
+            set_default_location,
+            get_location_stock,
+            get_item_stock_by_location,
+            set_location_low_stock_threshold,
+            create_stock_transfer,
+            get_stock_transfers,
+            get_stock_transfer,
+            get_serial_units,
+            register_serial_units,
+            set_serial_unit_status,
+            get_serial_history,
+            get_products,
+            save_product,
+            delete_product,
+            get_product_variants,
+            get_reorder_settings,
+            set_reorder_settings,
+            get_reorder_suggestions,
+            create_reorder_documents,
+            get_shopping_list,
+            save_shopping_list_item,
+            delete_shopping_list_item,
+            set_shopping_list_status,
+            convert_shopping_list_to_order,
             // INVENTORY
             insert_item,
             get_items,
@@ -81,6 +177,7 @@ fn main() {
             // REPAIRS
             insert_repair,
             get_repairs,
+            query_repairs,
             get_repair_by_id,
             update_repair,
             update_repair_status,
@@ -95,6 +192,11 @@ fn main() {
             get_used_parts_for_repair,
             insert_repair_history,
             get_history_for_repair,
+            get_repair_statuses,
+            save_repair_status,
+            delete_repair_status,
+            get_repair_status_timeline,
+            get_repair_turnaround,
             // PAYMENT
             get_all_payments,
             // SUPPLIERS
@@ -120,6 +222,13 @@ fn main() {
             add_order_payment,
             get_order_payments,
             complete_order,
+            receive_order_goods,
+            get_goods_receipts,
+            cancel_order_remainder,
+            get_supplier_price_list,
+            save_supplier_item,
+            delete_supplier_item,
+            compare_supplier_prices,
             get_orders_by_supplier,
             // CLIENTS
             get_clients,
@@ -156,6 +265,7 @@ fn main() {
             // TRANSACTIONS
             create_transaction,
             get_transactions,
+            query_transactions,
             get_transaction_by_id,
             add_transaction_item,
             remove_transaction_item,
@@ -163,6 +273,9 @@ fn main() {
             update_transaction_payment,
             delete_transaction_payment,
             complete_transaction,
+            cancel_transaction,
+            create_return,
+            create_purchase_return,
             submit_transaction,
             update_transaction,
             // DASHBOARD
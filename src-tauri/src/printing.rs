@@ -4,6 +4,7 @@
 
 use serde::{Deserialize, Serialize};
 use std::process::Command;
+use crate::error::AppError;
 
 /// Printer configuration passed from the frontend.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,6 +44,23 @@ pub struct ReceiptItem {
     pub name: String,
     pub qty: i32,
     pub price: f64,
+    #[serde(default)]
+    pub discount: Option<f64>, // taken off this line, printed under it
+}
+
+/// A document discount, printed above the subtotal.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReceiptDiscount {
+    pub reason: String,
+    pub amount: f64,
+}
+
+/// Tax at one rate, printed between the subtotal and the total.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReceiptTax {
+    pub name: String,
+    pub rate: f64, // percent
+    pub amount: f64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -53,6 +71,12 @@ pub struct ReceiptData {
     pub device: Option<String>,
     pub issue: Option<String>,
     pub items: Vec<ReceiptItem>,
+    #[serde(default)]
+    pub discounts: Vec<ReceiptDiscount>,
+    #[serde(default)]
+    pub subtotal: Option<f64>, // before exclusive tax; printed with the tax lines
+    #[serde(default)]
+    pub taxes: Vec<ReceiptTax>,
     pub total: f64,
     pub shop_info: Option<ShopInfo>,
     pub date: Option<String>,
@@ -69,6 +93,8 @@ pub struct StickerData {
     pub issue: Option<String>,
     pub price: f64,
     pub currency_symbol: Option<String>,
+    #[serde(default)]
+    pub variant_label: Option<String>, // e.g. "iPhone 13 / Black / OEM" for a variant's sticker
 }
 
 /// Information about an available printer.
@@ -80,7 +106,7 @@ pub struct PrinterInfo {
 
 /// List available printers on the system.
 #[tauri::command]
-pub fn list_printers() -> Result<Vec<PrinterInfo>, String> {
+pub fn list_printers() -> Result<Vec<PrinterInfo>, AppError> {
     #[cfg(target_os = "windows")]
     {
         list_printers_windows()
@@ -94,7 +120,7 @@ pub fn list_printers() -> Result<Vec<PrinterInfo>, String> {
 }
 
 #[cfg(target_os = "windows")]
-fn list_printers_windows() -> Result<Vec<PrinterInfo>, String> {
+fn list_printers_windows() -> Result<Vec<PrinterInfo>, AppError> {
     // Use PowerShell to enumerate printers
     let output = Command::new("powershell")
         .args(&[
@@ -103,7 +129,7 @@ fn list_printers_windows() -> Result<Vec<PrinterInfo>, String> {
             "Get-Printer | Select-Object Name, @{N='IsDefault';E={$_.IsDefault}} | ConvertTo-Json",
         ])
         .output()
-        .map_err(|e| format!("Failed to run PowerShell: {}", e))?;
+        .map_err(|e| AppError::Printer(format!("Failed to run PowerShell: {}", e)))?;
 
     if !output.status.success() {
         // let stderr = String::from_utf8_lossy(&output.stderr);
@@ -122,7 +148,7 @@ fn list_printers_windows() -> Result<Vec<PrinterInfo>, String> {
     let printers: Vec<PrinterInfo> = if trimmed.starts_with('[') {
         // Array of printers
         let parsed: Vec<serde_json::Value> = serde_json::from_str(trimmed)
-            .map_err(|e| format!("Failed to parse printer list: {}", e))?;
+            .map_err(|e| AppError::Printer(format!("Failed to parse printer list: {}", e)))?;
         parsed
             .iter()
             .map(|p| PrinterInfo {
@@ -133,7 +159,7 @@ fn list_printers_windows() -> Result<Vec<PrinterInfo>, String> {
     } else {
         // Single printer object
         let parsed: serde_json::Value = serde_json::from_str(trimmed)
-            .map_err(|e| format!("Failed to parse printer: {}", e))?;
+            .map_err(|e| AppError::Printer(format!("Failed to parse printer: {}", e)))?;
         vec![PrinterInfo {
             name: parsed["Name"].as_str().unwrap_or("Unknown").to_string(),
             is_default: parsed["IsDefault"].as_bool().unwrap_or(false),
@@ -144,11 +170,11 @@ fn list_printers_windows() -> Result<Vec<PrinterInfo>, String> {
 }
 
 #[cfg(target_os = "windows")]
-fn list_printers_wmic() -> Result<Vec<PrinterInfo>, String> {
+fn list_printers_wmic() -> Result<Vec<PrinterInfo>, AppError> {
     let output = Command::new("wmic")
         .args(&["printer", "get", "Name,Default", "/format:csv"])
         .output()
-        .map_err(|e| format!("Failed to run wmic: {}", e))?;
+        .map_err(|e| AppError::Printer(format!("Failed to run wmic: {}", e)))?;
 
     let stdout = String::from_utf8_lossy(&output.stdout);
     let mut printers = Vec::new();
@@ -168,11 +194,11 @@ fn list_printers_wmic() -> Result<Vec<PrinterInfo>, String> {
 }
 
 #[cfg(not(target_os = "windows"))]
-fn list_printers_unix() -> Result<Vec<PrinterInfo>, String> {
+fn list_printers_unix() -> Result<Vec<PrinterInfo>, AppError> {
     let output = Command::new("lpstat")
         .args(&["-p", "-d"])
         .output()
-        .map_err(|e| format!("Failed to run lpstat: {}", e))?;
+        .map_err(|e| AppError::Printer(format!("Failed to run lpstat: {}", e)))?;
 
     let stdout = String::from_utf8_lossy(&output.stdout);
     let mut printers = Vec::new();
@@ -212,7 +238,7 @@ fn list_printers_unix() -> Result<Vec<PrinterInfo>, String> {
 
 /// Send raw bytes to a printer (for ESC/POS commands).
 #[tauri::command]
-pub fn print_raw(printer_name: String, data: Vec<u8>) -> Result<(), String> {
+pub fn print_raw(printer_name: String, data: Vec<u8>) -> Result<(), AppError> {
     #[cfg(target_os = "windows")]
     {
         print_raw_windows(&printer_name, &data)
@@ -225,7 +251,7 @@ pub fn print_raw(printer_name: String, data: Vec<u8>) -> Result<(), String> {
 }
 
 #[cfg(target_os = "windows")]
-fn print_raw_windows(printer_name: &str, data: &[u8]) -> Result<(), String> {
+fn print_raw_windows(printer_name: &str, data: &[u8]) -> Result<(), AppError> {
     use std::ptr;
     use std::ffi::OsStr;
     use std::os::windows::ffi::OsStrExt;
@@ -238,7 +264,7 @@ fn print_raw_windows(printer_name: &str, data: &[u8]) -> Result<(), String> {
 
     unsafe {
         if OpenPrinterW(printer_name_wide.as_mut_ptr(), &mut h_printer, ptr::null_mut()) == 0 {
-            return Err(format!("Failed to open printer: {}", printer_name));
+            return Err(AppError::Printer(format!("Failed to open printer: {}", printer_name)));
         }
 
         let doc_name: Vec<u16> = OsStr::new("FixTrack Direct Print").encode_wide().chain(Some(0)).collect();
@@ -253,13 +279,13 @@ fn print_raw_windows(printer_name: &str, data: &[u8]) -> Result<(), String> {
         let doc_id = StartDocPrinterW(h_printer, 1, &mut doc_info as *mut _ as *mut u8);
         if doc_id == 0 {
             ClosePrinter(h_printer);
-            return Err("Failed to start document printer".to_string());
+            return Err(AppError::Printer("Failed to start document printer".to_string()));
         }
 
         if StartPagePrinter(h_printer) == 0 {
             EndDocPrinter(h_printer);
             ClosePrinter(h_printer);
-            return Err("Failed to start page printer".to_string());
+            return Err(AppError::Printer("Failed to start page printer".to_string()));
         }
 
         let mut bytes_written: DWORD = 0;
@@ -275,7 +301,7 @@ fn print_raw_windows(printer_name: &str, data: &[u8]) -> Result<(), String> {
         ClosePrinter(h_printer);
 
         if write_result == 0 {
-            return Err("Failed to write to printer".to_string());
+            return Err(AppError::Printer("Failed to write to printer".to_string()));
         }
     }
 
@@ -283,14 +309,14 @@ fn print_raw_windows(printer_name: &str, data: &[u8]) -> Result<(), String> {
 }
 
 #[cfg(not(target_os = "windows"))]
-fn print_raw_unix(printer_name: &str, data: &[u8]) -> Result<(), String> {
+fn print_raw_unix(printer_name: &str, data: &[u8]) -> Result<(), AppError> {
     use std::io::Write;
 
     let temp_dir = std::env::temp_dir();
     let temp_file = temp_dir.join("fixary_print_raw.bin");
 
     std::fs::write(&temp_file, data)
-        .map_err(|e| format!("Failed to write temp file: {}", e))?;
+        .map_err(|e| AppError::Printer(format!("Failed to write temp file: {}", e)))?;
 
     let output = Command::new("lp")
         .args(&[
@@ -301,13 +327,13 @@ fn print_raw_unix(printer_name: &str, data: &[u8]) -> Result<(), String> {
             &temp_file.to_string_lossy(),
         ])
         .output()
-        .map_err(|e| format!("Failed to send to printer: {}", e))?;
+        .map_err(|e| AppError::Printer(format!("Failed to send to printer: {}", e)))?;
 
     let _ = std::fs::remove_file(&temp_file);
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("Printer error: {}", stderr));
+        return Err(AppError::Printer(format!("Printer error: {}", stderr)));
     }
 
     Ok(())
@@ -316,12 +342,12 @@ fn print_raw_unix(printer_name: &str, data: &[u8]) -> Result<(), String> {
 /// Print HTML content by saving to a temp file and sending to the OS printer.
 /// This acts as a PDF fallback path — the OS handles HTML-to-print rendering.
 #[tauri::command]
-pub fn print_html(html: String, printer_name: Option<String>) -> Result<(), String> {
+pub fn print_html(html: String, printer_name: Option<String>) -> Result<(), AppError> {
     let temp_dir = std::env::temp_dir();
     let temp_file = temp_dir.join("fixary_print_receipt.html");
 
     std::fs::write(&temp_file, &html)
-        .map_err(|e| format!("Failed to write temp HTML: {}", e))?;
+        .map_err(|e| AppError::Printer(format!("Failed to write temp HTML: {}", e)))?;
 
     #[cfg(target_os = "windows")]
     {
@@ -341,7 +367,7 @@ pub fn print_html(html: String, printer_name: Option<String>) -> Result<(), Stri
         Command::new("powershell")
             .args(&["-NoProfile", "-Command", &ps_command])
             .output()
-            .map_err(|e| format!("Failed to execute print command: {}", e))?;
+            .map_err(|e| AppError::Printer(format!("Failed to execute print command: {}", e)))?;
     }
 
     #[cfg(not(target_os = "windows"))]
@@ -350,7 +376,7 @@ pub fn print_html(html: String, printer_name: Option<String>) -> Result<(), Stri
         Command::new("xdg-open")
             .arg(&file_path)
             .output()
-            .map_err(|e| format!("Failed to open HTML: {}", e))?;
+            .map_err(|e| AppError::Printer(format!("Failed to open HTML: {}", e)))?;
     }
 
     Ok(())
@@ -358,7 +384,7 @@ pub fn print_html(html: String, printer_name: Option<String>) -> Result<(), Stri
 
 /// Send raw direct commands (ESC/POS) for a receipt
 #[tauri::command]
-pub fn print_receipt_direct(config: PrinterConfig, data: ReceiptData) -> Result<(), String> {
+pub fn print_receipt_direct(config: PrinterConfig, data: ReceiptData) -> Result<(), AppError> {
     println!("Printing receipt: data={:?}, config={:?}", data, config);
     let mut payload: Vec<u8> = Vec::new();
     
@@ -426,12 +452,31 @@ pub fn print_receipt_direct(config: PrinterConfig, data: ReceiptData) -> Result<
         let name = if item.name.len() > 15 { &item.name[0..15] } else { &item.name };
         let line = format!("{:<16} {:<8} {:>1}{:>8.2}\n", name, item.qty, symbol, item.price);
         payload.extend_from_slice(line.as_bytes());
+        if let Some(discount) = item.discount.filter(|d| *d > 0.0) {
+            let line = format!("{:<24} -{}{:>7.2}\n", "  Discount", symbol, discount);
+            payload.extend_from_slice(line.as_bytes());
+        }
     }
 
     payload.extend_from_slice(b"--------------------------------\n");
 
-    // 6. Total (Right align, Bold)
+    // 6. Discounts, subtotal, tax per rate and total (Right align, total Bold)
     payload.extend_from_slice(&[esc, 0x61, 0x02]); // Right align
+    for discount in &data.discounts {
+        payload.extend_from_slice(
+            format!("DISCOUNT ({}): -{}{:.2}\n", discount.reason, symbol, discount.amount).as_bytes(),
+        );
+    }
+    if !data.taxes.is_empty() {
+        let tax_total: f64 = data.taxes.iter().map(|t| t.amount).sum();
+        let subtotal = data.subtotal.unwrap_or(data.total - tax_total);
+        payload.extend_from_slice(format!("SUBTOTAL: {}{:.2}\n", symbol, subtotal).as_bytes());
+        for tax in &data.taxes {
+            payload.extend_from_slice(
+                format!("{} ({}%): {}{:.2}\n", tax.name, tax.rate, symbol, tax.amount).as_bytes(),
+            );
+        }
+    }
     payload.extend_from_slice(&[esc, 0x21, 0x08]); // Bold/Emphasized
     payload.extend_from_slice(format!("TOTAL: {}{:.2}\n", symbol, data.total).as_bytes());
     payload.extend_from_slice(&[esc, 0x21, 0x00]); // Back to normal
@@ -457,22 +502,22 @@ pub fn print_receipt_direct(config: PrinterConfig, data: ReceiptData) -> Result<
     println!("Connection type: {}", connection_type);
 
     if connection_type == "tcp" {
-        let ip = config.receipt_printer_ip.ok_or("Receipt printer IP address is required for TCP connection")?;
+        let ip = config.receipt_printer_ip.ok_or_else(|| AppError::Validation("Receipt printer IP address is required for TCP connection".to_string()))?;
         let port = config.receipt_printer_port.unwrap_or(9100);
         println!("Connecting to TCP printer: {}:{}", ip, port);
         
         let mut stream = std::net::TcpStream::connect_timeout(
-            &format!("{}:{}", ip, port).parse().map_err(|_| "Invalid IP/Port format")?,
+            &format!("{}:{}", ip, port).parse().map_err(|_| AppError::Validation("Invalid IP/Port format".to_string()))?,
             std::time::Duration::from_secs(3),
-        ).map_err(|e| format!("Failed to connect to network printer: {}", e))?;
+        ).map_err(|e| AppError::Printer(format!("Failed to connect to network printer: {}", e)))?;
 
         use std::io::Write;
-        stream.write_all(&payload).map_err(|e| format!("Failed to send data: {}", e))?;
-        stream.flush().map_err(|e| format!("Failed to flush: {}", e))?;
+        stream.write_all(&payload).map_err(|e| AppError::Printer(format!("Failed to send data: {}", e)))?;
+        stream.flush().map_err(|e| AppError::Printer(format!("Failed to flush: {}", e)))?;
         println!("TCP Print successful");
     } else {
         // Fallback to USB/OS spooler
-        let printer_name = config.receipt_printer_name.ok_or("Receipt printer name is required for USB connection")?;
+        let printer_name = config.receipt_printer_name.ok_or_else(|| AppError::Validation("Receipt printer name is required for USB connection".to_string()))?;
         println!("Sending to USB printer: {}", printer_name);
         print_raw(printer_name, payload)?;
         println!("USB Print successful");
@@ -483,24 +528,28 @@ pub fn print_receipt_direct(config: PrinterConfig, data: ReceiptData) -> Result<
 
 /// Send raw direct commands (TSPL) for a sticker
 #[tauri::command]
-pub fn print_sticker_direct(config: PrinterConfig, data: StickerData) -> Result<(), String> {
+pub fn print_sticker_direct(config: PrinterConfig, data: StickerData) -> Result<(), AppError> {
     let customer = data.customer_name.as_deref().unwrap_or("");
     let phone = data.customer_phone.as_deref().unwrap_or("");
     let issue = data.issue.as_deref().unwrap_or("");
 
     // Build the customer + phone line: "NAME-PHONE" or just "NAME"
-    let customer_line = if !phone.is_empty() {
+    let mut customer_line = if !phone.is_empty() {
         format!("{}-{}", customer, phone)
     } else {
         customer.to_string()
     };
+    // Inventory stickers have no customer: tell variants apart by their attributes instead
+    if customer_line.is_empty() {
+        customer_line = data.variant_label.as_deref().unwrap_or("").chars().take(32).collect();
+    }
 
     // TSPL template (often used for XPrinter XP-365B)
     // 50mm x 25mm label
     // Layout:
     //   Line 1: Repair code / Barcode
     //   Line 2: Issue or Item Name (Main display)
-    //   Line 3: Customer info
+    //   Line 3: Customer info, or the variant of an inventory item
     
     // Use item_name if issue is empty (e.g. for inventory stickers)
     let display_text = if issue.is_empty() { &data.item_name } else { issue };
@@ -539,20 +588,20 @@ pub fn print_sticker_direct(config: PrinterConfig, data: StickerData) -> Result<
     let connection_type = config.sticker_connection_type.as_deref().unwrap_or("usb");
 
     if connection_type == "tcp" {
-        let ip = config.sticker_printer_ip.ok_or("Sticker printer IP address is required for TCP connection")?;
+        let ip = config.sticker_printer_ip.ok_or_else(|| AppError::Validation("Sticker printer IP address is required for TCP connection".to_string()))?;
         let port = config.sticker_printer_port.unwrap_or(9100);
         
         let mut stream = std::net::TcpStream::connect_timeout(
-            &format!("{}:{}", ip, port).parse().map_err(|_| "Invalid IP/Port format")?,
+            &format!("{}:{}", ip, port).parse().map_err(|_| AppError::Validation("Invalid IP/Port format".to_string()))?,
             std::time::Duration::from_secs(3),
-        ).map_err(|e| format!("Failed to connect to network printer: {}", e))?;
+        ).map_err(|e| AppError::Printer(format!("Failed to connect to network printer: {}", e)))?;
 
         use std::io::Write;
-        stream.write_all(&payload).map_err(|e| format!("Failed to send data: {}", e))?;
-        stream.flush().map_err(|e| format!("Failed to flush: {}", e))?;
+        stream.write_all(&payload).map_err(|e| AppError::Printer(format!("Failed to send data: {}", e)))?;
+        stream.flush().map_err(|e| AppError::Printer(format!("Failed to flush: {}", e)))?;
     } else {
         // Fallback to USB/OS spooler
-        let printer_name = config.sticker_printer_name.ok_or("Sticker printer name is required for USB connection")?;
+        let printer_name = config.sticker_printer_name.ok_or_else(|| AppError::Validation("Sticker printer name is required for USB connection".to_string()))?;
         print_raw(printer_name, payload)?;
     }
 
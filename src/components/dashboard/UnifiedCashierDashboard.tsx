@@ -72,6 +72,7 @@ import {
 } from "@/lib/api/dashboard";
 import { format, startOfMonth, endOfMonth, subDays, startOfYear, startOfWeek, endOfWeek, parseISO, isWithinInterval } from "date-fns";
 import { useRouter } from "next/navigation";
+import { getErrorMessage } from "@/lib/errors";
 
 // Define transaction types
 interface Transaction {
@@ -490,7 +491,7 @@ export function UnifiedCashierDashboard() {
       // Emit event to notify other components of financial change
       emit("financial-data-change");
     } catch (error) {
-      alert(`Failed to start session: ${error}`);
+      alert(`Failed to start session: ${getErrorMessage(error)}`);
     }
   };
 
@@ -526,7 +527,7 @@ Carry Forward: ${formatCurrency(carryForward)}`);
         // Emit event to notify other components of financial change
         emit("financial-data-change");
       } catch (error) {
-        alert(`Failed to close session: ${error}`);
+        alert(`Failed to close session: ${getErrorMessage(error)}`);
       }
     }
   };
@@ -1,6 +1,7 @@
 "use client";
 
 import React, { useState, useEffect } from 'react';
+import { getErrorMessage } from "@/lib/errors";
 
 interface ContextInitializerProps {
   children: React.ReactNode;
@@ -44,7 +45,7 @@ export function ContextInitializer({
         await new Promise(resolve => setTimeout(resolve, 500));
         setLoading(false);
       } catch (err) {
-        const errorMessage = err instanceof Error ? err.message : 'Failed to initialize application';
+        const errorMessage = getErrorMessage(err, 'Failed to initialize application');
         setError(errorMessage);
         console.error('Context initialization error:', errorMessage);
       }
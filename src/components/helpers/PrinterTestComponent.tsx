@@ -30,6 +30,7 @@ import { toast } from "sonner";
 import { invoke } from "@tauri-apps/api/core"; // Import Tauri invoke directly
 
 import { useSettings } from "@/context/SettingsContext";
+import { getErrorMessage } from "@/lib/errors";
 
 interface PrinterTestComponentProps {
   className?: string;
@@ -100,7 +101,7 @@ export function PrinterTestComponent({ className }: PrinterTestComponentProps) {
       console.error("Error testing printer:", error);
       setTestResult({
         success: false,
-        message: error instanceof Error ? error.message : String(error),
+        message: getErrorMessage(error),
       });
     } finally {
       setIsTesting(false);
@@ -2,6 +2,7 @@
 "use client";
 import { invoke } from "@tauri-apps/api/core";
 import { toast } from "sonner";
+import { getErrorMessage } from "@/lib/errors";
 
 export const WorkingPrintButton: React.FC<{ repair: any }> = ({ repair }) => {
   const handlePrint = async () => {
@@ -16,7 +17,7 @@ export const WorkingPrintButton: React.FC<{ repair: any }> = ({ repair }) => {
 
       toast.success(result);
     } catch (error) {
-      toast.error(`Print failed: ${error}`);
+      toast.error(`Print failed: ${getErrorMessage(error)}`);
     }
   };
 
@@ -41,6 +41,7 @@ import {
 import { cn } from "@/lib";
 import { useSettings } from "@/context/SettingsContext";
 import { formatCurrency as formatCurrencyCentralized, formatNumber, getLocaleForIntl } from "@/lib/formatters";
+import { getErrorMessage } from "@/lib/errors";
 
 export function InventoryPageInner() {
   const {
@@ -131,7 +132,7 @@ export function InventoryPageInner() {
 
       setHistoryEvents(mapped);
     } catch (err) {
-      toast.error("Failed to load history: " + err);
+      toast.error("Failed to load history: " + getErrorMessage(err));
       setHistoryEvents([]);
     }
   };
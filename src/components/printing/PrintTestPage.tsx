@@ -17,6 +17,7 @@ import {
   FileText,
   CheckCircle2,
 } from "lucide-react";
+import { getErrorMessage } from "@/lib/errors";
 
 type ActiveTab = "config" | "calibrate" | "preview";
 
@@ -168,7 +169,7 @@ export function PrintTestPage() {
             });
             toast.success("Raw test receipt sent!");
           } catch (err) {
-             toast.error(`Print failed: ${err}`);
+             toast.error(`Print failed: ${getErrorMessage(err)}`);
           }
         }} variant="secondary">Test Raw Receipt (ESC/POS)</Button>
         <Button onClick={async () => {
@@ -187,7 +188,7 @@ export function PrintTestPage() {
              });
              toast.success("Raw test sticker sent!");
           } catch (err) {
-             toast.error(`Print failed: ${err}`);
+             toast.error(`Print failed: ${getErrorMessage(err)}`);
           }
         }} variant="secondary">Test Raw Sticker (TSPL)</Button>
       </div>
@@ -23,6 +23,7 @@ import { Printer, RefreshCw, Receipt, Tag } from "lucide-react";
 import { useTranslation } from "react-i18next";
 import { PrinterConfig } from "@/types/settings";
 import { Input } from "../ui/input";
+import { getErrorMessage } from "@/lib/errors";
 
 export function NativePrinterSettingsCard() {
   const { settings, updateSettings, availablePrinters, refreshPrinters } = useSettings();
@@ -238,7 +239,7 @@ export function NativePrinterSettingsCard() {
                   });
                   import('sonner').then(({ toast }) => toast.success("Test receipt sent!"));
                 } catch (err) {
-                  import('sonner').then(({ toast }) => toast.error(`Test failed: ${err}`));
+                  import('sonner').then(({ toast }) => toast.error(`Test failed: ${getErrorMessage(err)}`));
                 }
               }}
               className="h-9 text-[10px] font-black uppercase tracking-widest border-blue-200 hover:bg-blue-50 dark:border-blue-900/30 dark:hover:bg-blue-950/30"
@@ -267,7 +268,7 @@ export function NativePrinterSettingsCard() {
                   });
                   import('sonner').then(({ toast }) => toast.success("Test sticker sent!"));
                 } catch (err) {
-                  import('sonner').then(({ toast }) => toast.error(`Test failed: ${err}`));
+                  import('sonner').then(({ toast }) => toast.error(`Test failed: ${getErrorMessage(err)}`));
                 }
               }}
               className="h-9 text-[10px] font-black uppercase tracking-widest border-orange-200 hover:bg-orange-50 dark:border-orange-900/30 dark:hover:bg-orange-950/30"
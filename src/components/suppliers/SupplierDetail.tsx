@@ -95,7 +95,7 @@ export function SupplierDetail({ supplierId }: SupplierDetailProps) {
     try {
       setLoadingOrders(true);
       const data = await getOrdersBySupplier(supplierId);
-      setOrders(data);
+      setOrders(data.orders);
     } catch (error) {
       console.error("Failed to load supplier orders:", error);
     } finally {
@@ -20,6 +20,7 @@ import { useTransactions } from "@/context/TransactionContext";
 import { toast } from "sonner";
 import { useTranslation } from "react-i18next";
 import { useSettings } from "@/context/SettingsContext";
+import { getErrorMessage } from "@/lib/errors";
 
 interface SupplierTransactionListProps {
   supplierId: string;
@@ -50,7 +51,7 @@ export function SupplierTransactionList({ supplierId }: SupplierTransactionListP
       setTransactions(data);
     } catch (err) {
       console.error("Failed to load transactions:", err);
-      setError(String(err));
+      setError(getErrorMessage(err));
     } finally {
       setLoading(false);
     }
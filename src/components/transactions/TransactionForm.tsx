@@ -36,6 +36,7 @@ import { useTranslation } from "react-i18next";
 import { usePrintUtils } from "@/hooks/usePrintUtils";
 import { useSettings } from "@/context/SettingsContext";
 import { useClientContext } from "@/context/ClientContext";
+import { getErrorMessage } from "@/lib/errors";
 
 export function TransactionForm() {
   const router = useRouter();
@@ -249,7 +250,7 @@ export function TransactionForm() {
       toast.error(
         t("transactions_module.form.error", {
           type: t(`transactions_module.${type.toLowerCase()}`),
-          error: String(err),
+          error: getErrorMessage(err),
         })
       );
     } finally {
@@ -35,6 +35,7 @@ import { useInventory } from "@/context/InventoryContext";
 import { useTranslation } from "react-i18next";
 import { usePrintUtils } from "@/hooks/usePrintUtils";
 import { useSettings } from "@/context/SettingsContext";
+import { getErrorMessage } from "@/lib/errors";
 
 
 export function TransactionHistory() {
@@ -68,7 +69,7 @@ export function TransactionHistory() {
       setTransactions(data);
     } catch (err) {
       console.error("Failed to load transactions:", err);
-      setError(String(err));
+      setError(getErrorMessage(err));
     } finally {
       setLoading(false);
     }
@@ -22,6 +22,7 @@ import type {
   PhoneBrand,
   ItemType,
 } from "@/types/inventory";
+import { getErrorMessage } from "@/lib/errors";
 
 // ✅ State shape
 interface InventoryState {
@@ -132,7 +133,7 @@ export const InventoryProvider: React.FC<{ children: React.ReactNode }> = ({
       setInventoryItems(dbItems.map(mapItemFromDB));
     } catch (err) {
       console.error("Failed to fetch inventory items:", err);
-      setError(`Failed to load inventory: ${err}`);
+      setError(`Failed to load inventory: ${getErrorMessage(err)}`);
       setInventoryItems([]);
     }
   }, []);
@@ -150,8 +151,8 @@ export const InventoryProvider: React.FC<{ children: React.ReactNode }> = ({
       setInitialized(true);
     } catch (err) {
       console.error("Failed to initialize inventory:", err);
-      setError(`Failed to initialize inventory: ${err}`);
-      toast.error(`Failed to initialize inventory: ${err}`);
+      setError(`Failed to initialize inventory: ${getErrorMessage(err)}`);
+      toast.error(`Failed to initialize inventory: ${getErrorMessage(err)}`);
     } finally {
       setLoading(false);
     }
@@ -208,7 +209,7 @@ export const InventoryProvider: React.FC<{ children: React.ReactNode }> = ({
         // Emit event to notify dashboard
         emit("financial-data-change");
       } catch (err) {
-        toast.error(`Failed to add item: ${err}`);
+        toast.error(`Failed to add item: ${getErrorMessage(err)}`);
       }
     },
     [fetchItems]
@@ -259,7 +260,7 @@ export const InventoryProvider: React.FC<{ children: React.ReactNode }> = ({
         // Emit event to notify dashboard
         emit("financial-data-change");
       } catch (err) {
-        toast.error(`Failed to update item: ${err}`);
+        toast.error(`Failed to update item: ${getErrorMessage(err)}`);
       }
     },
     [fetchItems, inventoryItems]
@@ -274,7 +275,7 @@ export const InventoryProvider: React.FC<{ children: React.ReactNode }> = ({
         // Emit event to notify dashboard
         emit("financial-data-change");
       } catch (err) {
-        toast.error(`Failed to delete item: ${err}`);
+        toast.error(`Failed to delete item: ${getErrorMessage(err)}`);
       }
     },
     [fetchItems]
@@ -321,7 +322,7 @@ export const InventoryProvider: React.FC<{ children: React.ReactNode }> = ({
         // Emit event to notify dashboard
         emit("financial-data-change");
       } catch (err) {
-        toast.error(`Failed to update quantity: ${err}`);
+        toast.error(`Failed to update quantity: ${getErrorMessage(err)}`);
       }
     },
     [fetchItems, inventoryItems]
@@ -335,7 +336,7 @@ export const InventoryProvider: React.FC<{ children: React.ReactNode }> = ({
       return dbItems.map(mapItemFromDB);
     } catch (err) {
       console.error("Failed to search items:", err);
-      toast.error(`Search failed: ${err}`);
+      toast.error(`Search failed: ${getErrorMessage(err)}`);
       return [];
     }
   }, []);
@@ -26,6 +26,7 @@ import type {
   PaymentStatus,
   RepairStatus,
 } from "@/types/repair";
+import { getErrorMessage } from "@/lib/errors";
 
 // ✅ State shape
 interface RepairState {
@@ -86,7 +87,7 @@ async function withAsync<T>(
     return result;
   } catch (err) {
     const errorMessage =
-      err instanceof Error ? err.message : "Unexpected error";
+      getErrorMessage(err, "Unexpected error");
     onError?.(errorMessage);
     toast.error(errorMessage);
     throw err;
@@ -233,8 +234,8 @@ export const RepairProvider: React.FC<{ children: React.ReactNode }> = ({
       setInitialized(true);
     } catch (err) {
       console.error("Failed to initialize repairs:", err);
-      setError(`Failed to initialize repairs: ${err}`);
-      toast.error(`Failed to initialize repairs: ${err}`);
+      setError(`Failed to initialize repairs: ${getErrorMessage(err)}`);
+      toast.error(`Failed to initialize repairs: ${getErrorMessage(err)}`);
     } finally {
       setLoading(false);
     }
@@ -295,7 +296,7 @@ export const RepairProvider: React.FC<{ children: React.ReactNode }> = ({
         } catch (error) {
           console.error("❌ Error fetching related repair data:", error);
           setError(
-            error instanceof Error ? error.message : "Failed to fetch related data"
+            getErrorMessage(error, "Failed to fetch related data")
           );
         }
       }
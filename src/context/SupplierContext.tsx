@@ -25,6 +25,7 @@ import type {
   PaymentMethod,
   SupplierHistoryEventType,
 } from "@/types/supplier";
+import { getErrorMessage } from "@/lib/errors";
 
 // ✅ Interface for backend SupplierFrontend struct
 interface SupplierFrontend {
@@ -155,8 +156,8 @@ export const SupplierProvider: React.FC<{ children: React.ReactNode }> = ({
       setInitialized(true);
     } catch (err) {
       console.error("Failed to initialize suppliers:", err);
-      setError(`Failed to initialize suppliers: ${err}`);
-      toast.error(`Failed to initialize suppliers: ${err}`);
+      setError(`Failed to initialize suppliers: ${getErrorMessage(err)}`);
+      toast.error(`Failed to initialize suppliers: ${getErrorMessage(err)}`);
     } finally {
       setLoading(false);
     }
@@ -691,7 +692,7 @@ async function withAsync<T>(
     return result;
   } catch (err) {
     const errorMessage =
-      err instanceof Error ? err.message : "Unexpected error";
+      getErrorMessage(err, "Unexpected error");
     onError?.(errorMessage);
     toast.error(errorMessage);
     throw err;
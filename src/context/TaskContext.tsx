@@ -12,6 +12,7 @@ import React, {
 import { toast } from "sonner";
 import { v4 as uuidv4 } from "uuid";
 import type { Task, TaskStatus } from "@/types/task";
+import { getErrorMessage } from "@/lib/errors";
 
 interface TaskState {
   tasks: Task[];
@@ -44,7 +45,7 @@ export const TaskProvider: React.FC<{ children: React.ReactNode }> = ({
       const data = await invoke<Task[]>("get_tasks");
       setTasks(data);
     } catch (err) {
-      const msg = err instanceof Error ? err.message : String(err);
+      const msg = getErrorMessage(err);
       setError(msg);
       console.error("Failed to fetch tasks:", msg);
     } finally {
@@ -1,4 +1,5 @@
 import { useState, useEffect, useCallback } from "react";
+import { getErrorMessage } from "@/lib/errors";
 
 interface AsyncInitializeState<T> {
   data: T | null;
@@ -36,7 +37,7 @@ export function useAsyncInitialize<T>(
       setData(result);
       setInitialized(true);
     } catch (err) {
-      const errorMessage = err instanceof Error ? err.message : "Unknown error";
+      const errorMessage = getErrorMessage(err);
       setError(errorMessage);
     } finally {
       setLoading(false);
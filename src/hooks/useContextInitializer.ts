@@ -1,4 +1,5 @@
 import { useState, useCallback, useEffect } from "react";
+import { getErrorMessage } from "@/lib/errors";
 
 interface ContextInitializerState {
   loading: boolean;
@@ -33,7 +34,7 @@ export function useContextInitializer(
       setInitialized(true);
     } catch (err) {
       const errorMessage =
-        err instanceof Error ? err.message : "Failed to initialize";
+        getErrorMessage(err, "Failed to initialize");
       setError(errorMessage);
       console.error("Context initialization error:", errorMessage);
     } finally {
@@ -19,6 +19,7 @@ import { useSettings } from "@/context/SettingsContext";
 import { CURRENCY_SYMBOLS } from "@/types/settings";
 import { useRouter } from "next/navigation";
 import { LOGO_DATA_URI } from "@/lib/logoDataUri";
+import { getErrorMessage } from "@/lib/errors";
 
 interface PrintOptions {
   includePayments?: boolean;
@@ -205,7 +206,7 @@ export const usePrintUtils = () => {
         return true;
 
       } catch (error) {
-        const errorMsg = error instanceof Error ? error.message : String(error);
+        const errorMsg = getErrorMessage(error);
         toast.error(`Native Print Error: ${errorMsg}`);
         addToPrintHistory(item, type, false, errorMsg);
         return false;
@@ -230,7 +231,7 @@ export const usePrintUtils = () => {
         return printDocument(content, data, "sticker");
       } catch (error) {
         const errorMsg =
-          error instanceof Error ? error.message : "Unknown error";
+          getErrorMessage(error);
         toast.error(`Failed to generate sticker: ${errorMsg}`);
         addToPrintHistory(data, "sticker", false, errorMsg);
         return false;
@@ -259,7 +260,7 @@ export const usePrintUtils = () => {
         return printDocument(content, repair, "receipt");
       } catch (error) {
         const errorMsg =
-          error instanceof Error ? error.message : "Unknown error";
+          getErrorMessage(error);
         toast.error(`Failed to generate receipt: ${errorMsg}`);
         addToPrintHistory(repair, "receipt", false, errorMsg);
         return false;
@@ -290,7 +291,7 @@ export const usePrintUtils = () => {
         return printDocument(content, { id: payment.id } as any, "receipt");
       } catch (error) {
         const errorMsg =
-          error instanceof Error ? error.message : "Unknown error";
+          getErrorMessage(error);
         toast.error(`Failed to generate payment receipt: ${errorMsg}`);
         return false;
       }
@@ -324,7 +325,7 @@ export const usePrintUtils = () => {
         return printDocument(content, transaction as any, "receipt");
       } catch (error) {
         const errorMsg =
-          error instanceof Error ? error.message : "Unknown error";
+          getErrorMessage(error);
         toast.error(`Failed to generate transaction receipt: ${errorMsg}`);
         return false;
       }
@@ -1,5 +1,5 @@
 import { invoke } from '@tauri-apps/api/core';
-import { Order, OrderItem, OrderPayment, OrderWithDetails } from '@/types/order';
+import { Order, OrderItem, OrderPayment, OrderWithDetails, SupplierOrders } from '@/types/order';
 
 /**
  * Order Management API - Tauri command wrappers
@@ -33,10 +33,10 @@ export async function getOrderById(orderId: string): Promise<OrderWithDetails |
 }
 
 /**
- * Get all orders for a specific supplier
+ * Get all orders for a specific supplier, with the goods sent back to it
  */
-export async function getOrdersBySupplier(supplierId: string): Promise<Order[]> {
-    return await invoke<Order[]>('get_orders_by_supplier', { supplierId });
+export async function getOrdersBySupplier(supplierId: string): Promise<SupplierOrders> {
+    return await invoke<SupplierOrders>('get_orders_by_supplier', { supplierId });
 }
 
 /**
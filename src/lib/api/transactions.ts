@@ -13,8 +13,8 @@ export async function getTransactionById(txId: string): Promise<TransactionWithD
     return await invoke("get_transaction_by_id", { txId });
 }
 
-export async function addTransactionItem(item: TransactionItem): Promise<void> {
-    await invoke("add_transaction_item", { item });
+export async function addTransactionItem(item: TransactionItem, allowOversell?: boolean): Promise<void> {
+    await invoke("add_transaction_item", { item, allowOversell });
 }
 
 export async function removeTransactionItem(itemId: string, transactionId: string): Promise<void> {
@@ -0,0 +1,45 @@
+// Errors returned by the Tauri `db` and `printing` commands
+
+export type AppErrorCode =
+  | "NOT_FOUND"
+  | "VALIDATION"
+  | "INSUFFICIENT_STOCK"
+  | "CONFLICT"
+  | "DATABASE"
+  | "PRINTER";
+
+export interface AppError {
+  code: AppErrorCode;
+  message: string;
+  // Only sent with INSUFFICIENT_STOCK
+  details?: {
+    item_id: string;
+    item_name: string;
+    requested: number;
+    available: number;
+  };
+}
+
+export function isAppError(error: unknown): error is AppError {
+  return (
+    typeof error === "object" &&
+    error !== null &&
+    typeof (error as AppError).code === "string" &&
+    typeof (error as AppError).message === "string"
+  );
+}
+
+// A readable message for whatever `invoke` rejected with: an AppError, an Error or a plain string
+export function getErrorMessage(error: unknown, fallback = "Unknown error"): string {
+  if (isAppError(error) || error instanceof Error) {
+    return error.message || fallback;
+  }
+  if (typeof error === "string") {
+    return error || fallback;
+  }
+  return fallback;
+}
+
+export function getErrorCode(error: unknown): AppErrorCode | undefined {
+  return isAppError(error) ? error.code : undefined;
+}
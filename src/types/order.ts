@@ -1,4 +1,5 @@
 // Order Management Types - matches Rust models
+import { Transaction } from './transaction';
 
 export interface Order {
     id: string;
@@ -50,3 +51,8 @@ export interface OrderWithDetails {
     payments: OrderPayment[];
     supplier_name: string;
 }
+
+export interface SupplierOrders {
+    orders: Order[];
+    returns: Transaction[]; // PurchaseReturn documents, newest first
+}